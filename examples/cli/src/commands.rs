@@ -0,0 +1,395 @@
+//! The commands, each returning the JSON document printed on success.
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::key::UntweakedKeypair;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, Network, XOnlyPublicKey};
+use clap::ValueEnum;
+use log::info;
+use serde_json::{json, Value};
+use zeroize::Zeroizing;
+
+use common::cluster::Cluster;
+use common::config::load_profile;
+use common::decoder::{self, Decoder};
+use common::deploy::{ProgramDeployer, RpcNode};
+use common::helper::{
+    call_rpc, get_processed_transaction, get_transaction_status, read_account_info,
+    send_transaction, sign_transaction,
+};
+use common::keystore::{bip86_path, generate_mnemonic, parse_mnemonic, Keystore};
+use sdk::arch_program::account::AccountMeta;
+use sdk::arch_program::instruction::Instruction;
+use sdk::arch_program::pubkey::Pubkey;
+use sdk::arch_program::system_instruction::SystemInstruction;
+
+use crate::scaffold::scaffold;
+use crate::{output, AccountCommand, Cli, Command, Target, TxCommand};
+
+pub fn run(cli: &Cli) -> Result<Value> {
+    let profile = load_profile(cli.profile.as_deref())?;
+    let network = cli.network.unwrap_or(profile.network);
+    // Only the commands talking to a node need its URL
+    let node_url = || match &cli.rpc_url {
+        Some(url) => Ok(url.clone()),
+        None => profile.leader_url().map(str::to_string),
+    };
+
+    match &cli.command {
+        Command::Keygen {
+            path,
+            force,
+            mnemonic,
+            recover,
+            account,
+            index,
+        } => {
+            let source = match (mnemonic, recover) {
+                (true, _) => KeySource::NewMnemonic,
+                (_, true) => KeySource::Recover,
+                _ => KeySource::Random,
+            };
+            keygen(path, *force, source, *account, *index, network)
+        }
+        Command::Address(target) => {
+            let pubkey = target.pubkey()?;
+            Ok(json!({
+                "pubkey": output::pubkey(&pubkey),
+                "network": network.to_string(),
+                "address": taproot_address(&pubkey, network)?,
+            }))
+        }
+        Command::Account(AccountCommand::Show(target)) => {
+            let pubkey = target.pubkey()?;
+            Ok(output::account(
+                &pubkey,
+                &read_account_info(&node_url()?, pubkey)?,
+            ))
+        }
+        Command::Deploy {
+            elf,
+            key,
+            utxo,
+            max_retries,
+            skip_validation,
+            truncate,
+            fallback_key,
+        } => {
+            let url = &node_url()?;
+            let elf = fs::read(elf).with_context(|| format!("Unable to read {}", elf.display()))?;
+            let (keypair, pubkey) = load_key(key)?;
+
+            let created_with = match utxo {
+                Some(utxo) => Some(create_account(url, keypair, pubkey, utxo)?),
+                None => None,
+            };
+
+            let mut deployer = ProgramDeployer::new(RpcNode::new(url), keypair)
+                .with_max_retries(*max_retries)
+                .on_progress(|progress| info!("{:?}", progress));
+            if *skip_validation {
+                deployer = deployer.without_validation();
+            }
+            if *truncate {
+                deployer = deployer.with_truncate();
+            }
+            if let Some(fallback_key) = fallback_key {
+                deployer = deployer.with_fallback_account(load_key(fallback_key)?.0);
+            }
+            let report = deployer.deploy(&elf)?;
+
+            Ok(json!({
+                "program_id": output::pubkey(&report.program_id),
+                "create_account_txid": created_with,
+                "hash": report.hash,
+                "bytes_written": report.bytes_written,
+                "chunks": report.chunks.len(),
+                "txids": report.txids,
+            }))
+        }
+        Command::Tx(TxCommand::Send {
+            keys,
+            program_id,
+            accounts,
+            data,
+        }) => {
+            let url = &node_url()?;
+            let signers = keys
+                .iter()
+                .map(|key| load_key(key).map(|(keypair, _)| keypair))
+                .collect::<Result<Vec<_>>>()?;
+            let instruction = Instruction {
+                program_id: parse_pubkey(program_id)?,
+                accounts: accounts
+                    .iter()
+                    .map(|account| parse_account_meta(account))
+                    .collect::<Result<Vec<_>>>()?,
+                data: hex::decode(data).context("Instruction data isn't valid hex")?,
+            };
+            let instruction_hash = instruction.hash();
+
+            let txid = send_transaction(url, sign_transaction(vec![instruction], signers))?;
+            Ok(json!({ "txid": txid, "instruction_hash": instruction_hash }))
+        }
+        Command::Tx(TxCommand::Decode { hex, idls, text }) => {
+            let decoded = decode(hex, idls, network)?;
+            if *text {
+                Ok(Value::String(decoded.to_string()))
+            } else {
+                Ok(serde_json::to_value(&decoded)?)
+            }
+        }
+        Command::Tx(TxCommand::Status { txid, wait }) => {
+            let url = &node_url()?;
+            let processed_tx = if *wait {
+                Some(get_processed_transaction(url, txid.clone())?)
+            } else {
+                get_transaction_status(url, txid)?
+            };
+            match processed_tx {
+                Some(processed_tx) => Ok(output::processed_transaction(&processed_tx)),
+                None => Ok(json!({ "txid": txid, "status": "unknown" })),
+            }
+        }
+        Command::KeyExchange => Ok(json!({
+            "result": call_rpc(&node_url()?, "start_key_exchange")?
+        })),
+        Command::Dkg => Ok(json!({ "result": call_rpc(&node_url()?, "start_dkg")? })),
+        Command::Nodes => {
+            let cluster = match &cli.rpc_url {
+                Some(url) => Cluster::new([url.clone()])?,
+                None => Cluster::from_profile(&profile)?,
+            };
+            Ok(serde_json::to_value(cluster.check_health())?)
+        }
+        Command::New {
+            name,
+            template,
+            dir,
+            arch_root,
+        } => {
+            let project = scaffold(name, *template, dir, arch_root)?;
+            Ok(json!({
+                "path": project.path.display().to_string(),
+                "template": template.to_possible_value().map(|value| value.get_name().to_string()),
+                "program": project.program,
+                "files": project.files,
+            }))
+        }
+    }
+}
+
+impl Target {
+    fn pubkey(&self) -> Result<Pubkey> {
+        match (&self.pubkey, &self.key) {
+            (Some(pubkey), _) => parse_pubkey(pubkey),
+            (None, Some(key)) => Ok(load_key(key)?.1),
+            (None, None) => bail!("Either a pubkey or a key file is required"),
+        }
+    }
+}
+
+enum KeySource {
+    Random,
+    NewMnemonic,
+    Recover,
+}
+
+fn keygen(
+    path: &Path,
+    force: bool,
+    source: KeySource,
+    account: u32,
+    index: u32,
+    network: Network,
+) -> Result<Value> {
+    if path.exists() {
+        if !force {
+            bail!(
+                "{} already exists, use --force to overwrite it",
+                path.display()
+            );
+        }
+        fs::remove_file(path)?;
+    }
+
+    let keystore = Keystore::from_env();
+    let mut generated = json!({
+        "path": path.display().to_string(),
+        "encrypted": keystore.is_encrypting(),
+    });
+
+    let mnemonic = match source {
+        KeySource::Random => None,
+        KeySource::NewMnemonic => {
+            let mnemonic = generate_mnemonic(12)?;
+            generated["mnemonic"] = mnemonic.to_string().into();
+            Some(mnemonic)
+        }
+        KeySource::Recover => {
+            let mut phrase = Zeroizing::new(String::new());
+            io::stdin()
+                .read_to_string(&mut phrase)
+                .context("Unable to read the mnemonic from stdin")?;
+            Some(parse_mnemonic(&phrase)?)
+        }
+    };
+
+    let (_, pubkey) = match mnemonic {
+        Some(mnemonic) => {
+            let derivation_path = bip86_path(network, account, index)?;
+            generated["derivation_path"] = derivation_path.to_string().into();
+            keystore.import_mnemonic(path, &mnemonic, "", &derivation_path)?
+        }
+        None => keystore.create(path)?,
+    };
+
+    generated["pubkey"] = output::pubkey(&pubkey);
+    generated["address"] = taproot_address(&pubkey, network)?.into();
+    Ok(generated)
+}
+
+fn load_key(path: &Path) -> Result<(UntweakedKeypair, Pubkey)> {
+    Keystore::from_env().load(path)
+}
+
+/// Creates the account of `pubkey` from a `<txid>:<vout>` UTXO and waits for
+/// it to be processed.
+fn create_account(
+    url: &str,
+    keypair: UntweakedKeypair,
+    pubkey: Pubkey,
+    utxo: &str,
+) -> Result<String> {
+    let (txid, vout) = utxo
+        .split_once(':')
+        .ok_or_else(|| anyhow!("UTXO should be given as <txid>:<vout>"))?;
+    let txid: [u8; 32] = hex::decode(txid)
+        .ok()
+        .and_then(|txid| txid.try_into().ok())
+        .ok_or_else(|| anyhow!("UTXO txid should be 32 hex encoded bytes"))?;
+    let vout = vout.parse().context("UTXO vout should be a number")?;
+
+    let transaction = sign_transaction(
+        vec![SystemInstruction::new_create_account_instruction(
+            txid, vout, pubkey,
+        )],
+        vec![keypair],
+    );
+    let txid = send_transaction(url, transaction)?;
+    get_processed_transaction(url, txid.clone())?;
+    info!("Created program account in {}", txid);
+
+    Ok(txid)
+}
+
+pub fn taproot_address(pubkey: &Pubkey, network: Network) -> Result<String> {
+    let secp = Secp256k1::new();
+    let public_key = XOnlyPublicKey::from_slice(&pubkey.serialize()).map_err(|e| {
+        anyhow!(
+            "{} isn't a valid x-only public key: {}",
+            output::pubkey(pubkey),
+            e
+        )
+    })?;
+    Ok(Address::p2tr(&secp, public_key, None, network).to_string())
+}
+
+pub fn parse_pubkey(pubkey: &str) -> Result<Pubkey> {
+    let bytes = hex::decode(pubkey).context("Pubkey isn't valid hex")?;
+    if bytes.len() != 32 {
+        bail!("Pubkey should be 32 bytes, got {}", bytes.len());
+    }
+    Ok(Pubkey::from_slice(&bytes))
+}
+
+/// Parses `<pubkey>[:flags]`, with `s` for signer and `w` for writable.
+pub fn parse_account_meta(account: &str) -> Result<AccountMeta> {
+    let (pubkey, flags) = account.split_once(':').unwrap_or((account, ""));
+    if let Some(flag) = flags.chars().find(|flag| !matches!(flag, 's' | 'w')) {
+        bail!("Unknown account flag '{}' in {}", flag, account);
+    }
+
+    Ok(AccountMeta {
+        pubkey: parse_pubkey(pubkey)?,
+        is_signer: flags.contains('s'),
+        is_writable: flags.contains('w'),
+    })
+}
+
+/// Decodes a hex transaction, with the IDLs given as `<program_id>:<path>`.
+pub fn decode(data: &str, idls: &[String], network: Network) -> Result<decoder::Value> {
+    let mut decoder = Decoder::new(network);
+    for idl in idls {
+        let (program_id, path) = idl
+            .split_once(':')
+            .ok_or_else(|| anyhow!("IDL should be given as <program_id>:<path>, got {}", idl))?;
+        let json = fs::read_to_string(path).with_context(|| format!("Can't read IDL {}", path))?;
+        let idl = serde_json::from_str(&json).with_context(|| format!("Invalid IDL {}", path))?;
+        decoder = decoder.with_idl(parse_pubkey(program_id)?, idl);
+    }
+    decoder.decode_hex(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_account_meta() {
+        let pubkey = "01".repeat(32);
+
+        let meta = parse_account_meta(&format!("{}:sw", pubkey)).unwrap();
+        assert_eq!(meta.pubkey, Pubkey::from([1; 32]));
+        assert!(meta.is_signer && meta.is_writable);
+
+        let meta = parse_account_meta(&pubkey).unwrap();
+        assert!(!meta.is_signer && !meta.is_writable);
+
+        assert!(parse_account_meta(&format!("{}:x", pubkey)).is_err());
+        assert!(parse_account_meta("0101").is_err());
+    }
+
+    #[test]
+    fn test_decode_transaction() {
+        let secp = Secp256k1::new();
+        let (secret_key, _) = secp.generate_keypair(&mut bitcoin::secp256k1::rand::thread_rng());
+        let keypair = UntweakedKeypair::from_secret_key(&secp, &secret_key);
+        let pubkey = Pubkey::from_slice(&XOnlyPublicKey::from_keypair(&keypair).0.serialize());
+
+        let transaction = sign_transaction(
+            vec![SystemInstruction::new_make_executable_instruction(pubkey)],
+            vec![keypair],
+        );
+        let encoded = hex::encode(transaction.serialize());
+        let decoded =
+            serde_json::to_value(decode(&encoded, &[], Network::Regtest).unwrap()).unwrap();
+        assert_eq!(decoded["txid"], transaction.txid());
+        assert_eq!(decoded["signers"][0]["pubkey"], output::pubkey(&pubkey));
+        assert_eq!(decoded["signers"][0]["signature_status"], "valid");
+        assert_eq!(decoded["instructions"][0]["data"], "02");
+        assert_eq!(
+            decoded["instructions"][0]["decoded"]["instruction"],
+            "make_executable"
+        );
+
+        assert!(decode(&encoded[..40], &[], Network::Regtest).is_err());
+        assert!(decode("zz", &[], Network::Regtest).is_err());
+        assert!(decode(&encoded, &["missing.json".to_string()], Network::Regtest).is_err());
+    }
+
+    #[test]
+    fn test_taproot_address() {
+        let pubkey =
+            parse_pubkey("cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115")
+                .unwrap();
+        assert_eq!(
+            taproot_address(&pubkey, Network::Bitcoin).unwrap(),
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
+        assert!(taproot_address(&Pubkey::from([0; 32]), Network::Regtest).is_err());
+    }
+}
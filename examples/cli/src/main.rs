@@ -0,0 +1,228 @@
+//! `arch`, a command line tool for working with an Arch node.
+//!
+//! Every command prints a single JSON document on stdout so scripts can
+//! consume it, while logs go to stderr. A failing command prints
+//! `{"error": "..."}` instead and exits with status 1. The only exception is
+//! `arch tx decode --text`, printing text for people to read.
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use bitcoin::Network;
+use clap::{Args, Parser, Subcommand};
+use serde_json::json;
+
+mod commands;
+mod output;
+mod scaffold;
+
+#[derive(Parser, Debug)]
+#[command(name = "arch", version, about = "Interact with an Arch node")]
+struct Cli {
+    /// Network profile giving the node URL and Bitcoin network, see
+    /// `arch.json`
+    #[arg(long, global = true, env = "ARCH_PROFILE")]
+    profile: Option<String>,
+
+    /// RPC endpoint of the node, the leader of the profile by default
+    #[arg(long, global = true, env = "ARCH_RPC_URL")]
+    rpc_url: Option<String>,
+
+    /// Bitcoin network used to derive addresses, the one of the profile by
+    /// default
+    #[arg(long, global = true, value_parser = parse_network)]
+    network: Option<Network>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate a new keypair and write it to a key file, encrypted with the
+    /// password in PRIVATE_KEY_PASSWORD
+    Keygen {
+        /// File the key is written to
+        path: PathBuf,
+        /// Overwrite the file if it already exists
+        #[arg(long)]
+        force: bool,
+        /// Derive the key from a new BIP39 mnemonic, printed once
+        #[arg(long, conflicts_with = "recover")]
+        mnemonic: bool,
+        /// Derive the key from a BIP39 mnemonic read from stdin
+        #[arg(long)]
+        recover: bool,
+        /// BIP86 account the key is derived from
+        #[arg(long, default_value_t = 0)]
+        account: u32,
+        /// BIP86 index of the derived key
+        #[arg(long, default_value_t = 0)]
+        index: u32,
+    },
+    /// Show the taproot address of a pubkey
+    Address(Target),
+    /// Inspect accounts
+    #[command(subcommand)]
+    Account(AccountCommand),
+    /// Deploy a program ELF to the account of the given key
+    Deploy {
+        /// Path to the program ELF
+        elf: PathBuf,
+        /// Secret key file of the program account
+        #[arg(long)]
+        key: PathBuf,
+        /// Create the program account from this UTXO first, as `<txid>:<vout>`
+        #[arg(long)]
+        utxo: Option<String>,
+        /// Number of times failed chunks are resent
+        #[arg(long, default_value_t = common::deploy::DEFAULT_MAX_RETRIES)]
+        max_retries: usize,
+        /// Deploy without validating the ELF first
+        #[arg(long)]
+        skip_validation: bool,
+        /// Shrink the program account when the ELF is shorter, for nodes
+        /// supporting truncation
+        #[arg(long)]
+        truncate: bool,
+        /// Secret key file of an existing account the ELF is deployed to
+        /// instead when it is shorter than the deployed program and isn't
+        /// truncated
+        #[arg(long)]
+        fallback_key: Option<PathBuf>,
+    },
+    /// Send, decode and inspect transactions
+    #[command(subcommand)]
+    Tx(TxCommand),
+    /// Start the key exchange between the nodes
+    KeyExchange,
+    /// Start a distributed key generation round
+    Dkg,
+    /// Check the readiness and latency of the nodes of the profile
+    Nodes,
+    /// Create a new project with a program, its client and starter tests
+    New {
+        /// Name of the project, also naming its crates
+        name: String,
+        /// Program the project starts from
+        #[arg(long, value_enum, default_value_t = scaffold::Template::Counter)]
+        template: scaffold::Template,
+        /// Directory the project is created in
+        #[arg(long, default_value = "examples")]
+        dir: PathBuf,
+        /// Root of the arch-local checkout providing the sdk crates
+        #[arg(long, default_value = ".")]
+        arch_root: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AccountCommand {
+    /// Show the owner, data and UTXO of an account
+    Show(Target),
+}
+
+#[derive(Subcommand, Debug)]
+enum TxCommand {
+    /// Sign and send a transaction made of a single instruction
+    Send {
+        /// Secret key files of the signers
+        #[arg(long = "key", required = true)]
+        keys: Vec<PathBuf>,
+        /// Program the instruction is sent to
+        #[arg(long)]
+        program_id: String,
+        /// Instruction account as `<pubkey>[:flags]`, where flags is any of
+        /// `s` (signer) and `w` (writable)
+        #[arg(long = "account")]
+        accounts: Vec<String>,
+        /// Instruction data as hex
+        #[arg(long, default_value = "")]
+        data: String,
+    },
+    /// Decode a hex serialized transaction, or transaction to sign, checking
+    /// its signatures and decoding its instructions
+    Decode {
+        /// The serialized transaction
+        hex: String,
+        /// IDL decoding the instructions of a program, as
+        /// `<program_id>:<path>`
+        #[arg(long = "idl")]
+        idls: Vec<String>,
+        /// Print indented text instead of JSON
+        #[arg(long)]
+        text: bool,
+    },
+    /// Show the status of a transaction
+    Status {
+        txid: String,
+        /// Wait until the node has processed the transaction
+        #[arg(long)]
+        wait: bool,
+    },
+}
+
+/// The pubkey a command is about, given directly or through a key file.
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+struct Target {
+    /// Hex encoded pubkey
+    pubkey: Option<String>,
+    /// Secret key file to take the pubkey from
+    #[arg(long)]
+    key: Option<PathBuf>,
+}
+
+fn parse_network(network: &str) -> Result<Network, String> {
+    network.parse::<Network>().map_err(|e| e.to_string())
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+    let cli = Cli::parse();
+
+    match commands::run(&cli) {
+        Ok(serde_json::Value::String(text)) => {
+            print!("{}", text);
+            ExitCode::SUCCESS
+        }
+        Ok(output) => {
+            println!("{}", serde_json::to_string_pretty(&output).unwrap());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json!({ "error": format!("{:#}", err) })).unwrap()
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_target_needs_pubkey_or_key() {
+        assert!(Cli::try_parse_from(["arch", "address"]).is_err());
+        assert!(Cli::try_parse_from(["arch", "address", "00", "--key", "k.json"]).is_err());
+
+        let cli =
+            Cli::try_parse_from(["arch", "--network", "testnet", "account", "show", "00"]).unwrap();
+        assert_eq!(cli.network, Some(Network::Testnet));
+        assert!(matches!(
+            cli.command,
+            Command::Account(AccountCommand::Show(Target {
+                pubkey: Some(_),
+                key: None
+            }))
+        ));
+    }
+}
@@ -0,0 +1,78 @@
+//! JSON renderings of the sdk types, with keys and bytes as hex.
+use serde_json::{json, Value};
+
+use common::helper::AccountInfoResult;
+use sdk::arch_program::instruction::Instruction;
+use sdk::arch_program::pubkey::Pubkey;
+use sdk::processed_transaction::{ProcessedTransaction, Status};
+use sdk::runtime_transaction::RuntimeTransaction;
+
+pub fn pubkey(pubkey: &Pubkey) -> Value {
+    json!(format!("{:x}", pubkey))
+}
+
+pub fn instruction(instruction: &Instruction) -> Value {
+    json!({
+        "program_id": pubkey(&instruction.program_id),
+        "accounts": instruction.accounts.iter().map(|meta| json!({
+            "pubkey": pubkey(&meta.pubkey),
+            "is_signer": meta.is_signer,
+            "is_writable": meta.is_writable,
+        })).collect::<Vec<_>>(),
+        "data": hex::encode(&instruction.data),
+    })
+}
+
+pub fn transaction(transaction: &RuntimeTransaction) -> Value {
+    json!({
+        "txid": transaction.txid(),
+        "version": transaction.version,
+        "signatures": transaction
+            .signatures
+            .iter()
+            .map(|signature| hex::encode(&signature.0))
+            .collect::<Vec<_>>(),
+        "signers": transaction.message.signers.iter().map(pubkey).collect::<Vec<_>>(),
+        "instructions": transaction
+            .message
+            .instructions
+            .iter()
+            .map(instruction)
+            .collect::<Vec<_>>(),
+    })
+}
+
+pub fn processed_transaction(processed_tx: &ProcessedTransaction) -> Value {
+    let status = match processed_tx.status {
+        Status::Processing => "processing",
+        Status::Processed => "processed",
+        Status::Failed(_) => "failed",
+    };
+    json!({
+        "txid": processed_tx.txid(),
+        "status": status,
+        "error": processed_tx.error().map(|error| json!({
+            "message": error.to_string(),
+            "details": error,
+        })),
+        "bitcoin_txids": processed_tx.bitcoin_txids,
+        "logs": processed_tx.logs,
+        "compute_units_consumed": processed_tx.compute_units_consumed,
+        "return_data": processed_tx.return_data.as_ref().map(|return_data| json!({
+            "program_id": pubkey(&return_data.program_id),
+            "data": hex::encode(&return_data.data),
+        })),
+        "transaction": transaction(&processed_tx.runtime_transaction),
+    })
+}
+
+pub fn account(key: &Pubkey, account: &AccountInfoResult) -> Value {
+    json!({
+        "pubkey": pubkey(key),
+        "owner": pubkey(&account.owner),
+        "utxo": account.utxo,
+        "is_executable": account.is_executable,
+        "data_len": account.data.len(),
+        "data": hex::encode(&account.data),
+    })
+}
@@ -0,0 +1,287 @@
+//! `arch new`, rendering a new project from the templates in `templates/`.
+//!
+//! A project is a workspace holding a client crate, whose `build.rs`
+//! generates typed instruction builders from the program IDL, and the
+//! program crate in `program/`. The program is written with
+//! `#[arch_program::program]`, which declares its `entrypoint!` when built for
+//! SBF, and comes with an IDL golden test. The client crate has starter tests
+//! running the instructions against the local stack.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+
+/// The kinds of program a project can start from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Template {
+    /// A counter stored in a program owned account
+    Counter,
+    /// A profile account with variable length state that grows as it changes
+    AccountState,
+    /// A program calling another program and passing its return data back
+    CpiCaller,
+}
+
+type TemplateFile = (&'static str, &'static str);
+
+const COMMON: &[TemplateFile] = &[
+    (
+        "Cargo.toml",
+        include_str!("../templates/common/Cargo.toml.tmpl"),
+    ),
+    (
+        ".gitignore",
+        include_str!("../templates/common/gitignore.tmpl"),
+    ),
+    (
+        "build.rs",
+        include_str!("../templates/common/build.rs.tmpl"),
+    ),
+    (
+        "src/harness.rs",
+        include_str!("../templates/common/src/harness.rs.tmpl"),
+    ),
+    (
+        "program/Cargo.toml",
+        include_str!("../templates/common/program/Cargo.toml.tmpl"),
+    ),
+    (
+        "program/tests/idl.rs",
+        include_str!("../templates/common/program/tests/idl.rs.tmpl"),
+    ),
+];
+
+const COUNTER: &[TemplateFile] = &[
+    (
+        "src/lib.rs",
+        include_str!("../templates/counter/src/lib.rs.tmpl"),
+    ),
+    (
+        "program/src/lib.rs",
+        include_str!("../templates/counter/program/src/lib.rs.tmpl"),
+    ),
+    (
+        "program/idl.json",
+        include_str!("../templates/counter/program/idl.json.tmpl"),
+    ),
+];
+
+const ACCOUNT_STATE: &[TemplateFile] = &[
+    (
+        "src/lib.rs",
+        include_str!("../templates/account-state/src/lib.rs.tmpl"),
+    ),
+    (
+        "program/src/lib.rs",
+        include_str!("../templates/account-state/program/src/lib.rs.tmpl"),
+    ),
+    (
+        "program/idl.json",
+        include_str!("../templates/account-state/program/idl.json.tmpl"),
+    ),
+];
+
+const CPI_CALLER: &[TemplateFile] = &[
+    (
+        "src/lib.rs",
+        include_str!("../templates/cpi-caller/src/lib.rs.tmpl"),
+    ),
+    (
+        "program/src/lib.rs",
+        include_str!("../templates/cpi-caller/program/src/lib.rs.tmpl"),
+    ),
+    (
+        "program/idl.json",
+        include_str!("../templates/cpi-caller/program/idl.json.tmpl"),
+    ),
+];
+
+impl Template {
+    fn files(self) -> impl Iterator<Item = &'static TemplateFile> {
+        let files = match self {
+            Template::Counter => COUNTER,
+            Template::AccountState => ACCOUNT_STATE,
+            Template::CpiCaller => CPI_CALLER,
+        };
+        COMMON.iter().chain(files)
+    }
+}
+
+/// A project rendered by [`scaffold`].
+#[derive(Debug)]
+pub struct Project {
+    pub path: PathBuf,
+    pub program: String,
+    pub files: Vec<String>,
+}
+
+/// Render the `template` project called `name` in `dir`, with the crates of
+/// the arch-local checkout at `arch_root` as path dependencies.
+pub fn scaffold(name: &str, template: Template, dir: &Path, arch_root: &Path) -> Result<Project> {
+    check_name(name)?;
+    if !arch_root.join("program/Cargo.toml").is_file() {
+        bail!("{} isn't an arch-local checkout", arch_root.display());
+    }
+
+    let path = dir.join(name);
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+    fs::create_dir_all(dir).with_context(|| format!("Unable to create {}", dir.display()))?;
+    let relative_root = relative_path(
+        &fs::canonicalize(dir)?.join(name),
+        &fs::canonicalize(arch_root)?,
+    );
+
+    let module = name.replace('-', "_");
+    let program = format!("{}program", module);
+    let vars = [
+        ("name", name.to_string()),
+        ("module", module.clone()),
+        ("program", program.clone()),
+        ("pascal", pascal_case(&module)),
+        ("arch_root", relative_root),
+    ];
+
+    let mut files = vec![];
+    for (file, contents) in template.files() {
+        let target = path.join(file);
+        fs::create_dir_all(target.parent().expect("template files are in the project"))?;
+        fs::write(&target, render(contents, &vars))
+            .with_context(|| format!("Unable to write {}", target.display()))?;
+        files.push(file.to_string());
+    }
+
+    Ok(Project {
+        path,
+        program,
+        files,
+    })
+}
+
+/// Replace the `{{var}}` placeholders of `template`.
+fn render(template: &str, vars: &[(&str, String)]) -> String {
+    vars.iter()
+        .fold(template.to_string(), |rendered, (var, value)| {
+            rendered.replace(&format!("{{{{{}}}}}", var), value)
+        })
+}
+
+fn check_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+    if !valid {
+        return Err(anyhow!(
+            "Invalid project name {:?}, use lowercase letters, digits, '_' and '-' starting with a letter",
+            name
+        ));
+    }
+    Ok(())
+}
+
+fn pascal_case(module: &str) -> String {
+    module
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+/// Path leading from the directory `from` to `to`, both absolute.
+fn relative_path(from: &Path, to: &Path) -> String {
+    let from = from.components().collect::<Vec<_>>();
+    let to = to.components().collect::<Vec<_>>();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let parts = std::iter::repeat_n("..", from.len() - common)
+        .map(str::to_string)
+        .chain(
+            to[common..]
+                .iter()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned()),
+        )
+        .collect::<Vec<_>>();
+
+    if parts.is_empty() {
+        ".".to_string()
+    } else {
+        parts.join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arch_root() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../..")
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("arch-new-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_scaffold_every_template() {
+        for template in Template::value_variants() {
+            let dir = temp_dir("templates");
+            let project = scaffold("my-app", *template, &dir, &arch_root()).unwrap();
+            assert_eq!(project.program, "my_appprogram");
+
+            for file in &project.files {
+                let contents = fs::read_to_string(project.path.join(file)).unwrap();
+                assert!(!contents.contains("{{"), "{} has placeholders left", file);
+            }
+
+            let manifest = fs::read_to_string(project.path.join("Cargo.toml")).unwrap();
+            let root = manifest
+                .lines()
+                .find_map(|line| line.strip_prefix("sdk = { path = \""))
+                .and_then(|line| line.strip_suffix("/sdk\" }"))
+                .unwrap();
+            assert!(project.path.join(root).join("sdk/Cargo.toml").is_file());
+
+            let program = fs::read_to_string(project.path.join("program/src/lib.rs")).unwrap();
+            assert!(program.contains("pub mod my_app {"));
+            let idl = fs::read_to_string(project.path.join("program/idl.json")).unwrap();
+            assert!(idl.contains("\"name\": \"my_app\""));
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_scaffold_refuses_existing_projects_and_bad_names() {
+        let dir = temp_dir("existing");
+        scaffold("counter", Template::Counter, &dir, &arch_root()).unwrap();
+        assert!(scaffold("counter", Template::Counter, &dir, &arch_root()).is_err());
+
+        for name in ["", "Counter", "1counter", "my app", "../up"] {
+            assert!(scaffold(name, Template::Counter, &dir, &arch_root()).is_err());
+        }
+        assert!(scaffold("other", Template::Counter, &dir, &dir).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(pascal_case("my_app_2"), "MyApp2");
+        assert_eq!(
+            relative_path(Path::new("/a/b/examples/app"), Path::new("/a/b")),
+            "../.."
+        );
+        assert_eq!(
+            relative_path(Path::new("/a/app"), Path::new("/a/b/arch")),
+            "../b/arch"
+        );
+    }
+}
@@ -0,0 +1,493 @@
+//! Access to the Bitcoin network the examples fund their accounts from.
+//!
+//! [`BitcoinBackend`] covers the calls the helpers make: sending to an
+//! address, looking up raw transactions, the block count, the UTXOs of an
+//! address and broadcasting. [`CoreRpcBackend`] talks to a bitcoind wallet and
+//! [`EsploraBackend`] to an Esplora HTTP API, such as mempool.space, paying
+//! from a key of its own as Esplora has no wallet.
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::absolute::LockTime;
+use bitcoin::consensus::encode::{deserialize, serialize_hex};
+use bitcoin::key::{TapTweak, UntweakedKeypair};
+use bitcoin::secp256k1::{self, Secp256k1};
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Address, Amount, Network, OutPoint, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn,
+    TxOut, Txid, Witness, XOnlyPublicKey,
+};
+use bitcoincore_rpc::json::ScanTxOutRequest;
+use bitcoincore_rpc::{jsonrpc, Auth, Client, RpcApi};
+use serde::Deserialize;
+
+use crate::config::active_profile;
+use crate::transport::{self, BitcoindTransport};
+
+/// Confirmations a coinbase output needs before it can be spent
+pub const COINBASE_MATURITY: u64 = 100;
+
+/// An unspent output paying to an address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AddressUtxo {
+    pub txid: Txid,
+    pub vout: u32,
+    pub value: Amount,
+    /// Height of the block holding the output, `None` while in the mempool
+    pub block_height: Option<u64>,
+}
+
+impl AddressUtxo {
+    pub fn outpoint(&self) -> OutPoint {
+        OutPoint {
+            txid: self.txid,
+            vout: self.vout,
+        }
+    }
+
+    /// Number of blocks confirming the output at `block_count`.
+    pub fn confirmations(&self, block_count: u64) -> u64 {
+        self.block_height
+            .map_or(0, |height| block_count.saturating_sub(height) + 1)
+    }
+}
+
+/// The Bitcoin calls the helpers need.
+pub trait BitcoinBackend {
+    /// Pay `amount` to `address` from the backend's funds.
+    fn send_to_address(&self, address: &Address, amount: Amount) -> Result<Txid>;
+
+    fn get_raw_transaction(&self, txid: &Txid) -> Result<Transaction>;
+
+    /// Height of the chain tip.
+    fn get_block_count(&self) -> Result<u64>;
+
+    fn get_address_utxos(&self, address: &Address) -> Result<Vec<AddressUtxo>>;
+
+    fn send_raw_transaction(&self, transaction: &Transaction) -> Result<Txid>;
+}
+
+/// [`BitcoinBackend`] using the RPC interface and wallet of a bitcoind node.
+pub struct CoreRpcBackend {
+    client: Client,
+}
+
+impl CoreRpcBackend {
+    /// Connect to the node at `url`, a wallet URL such as
+    /// `http://127.0.0.1:18443/wallet/testwallet` to send from that wallet.
+    /// Requests go through the recording or replaying session if the
+    /// environment sets one up, see [`crate::transport`].
+    pub fn new(url: &str, username: &str, password: &str) -> Result<Self> {
+        if let Some(session) = transport::session()? {
+            let auth = Some((username.to_string(), password.to_string()));
+            let transport = BitcoindTransport::new(url, auth, session);
+            return Ok(Self::from_client(Client::from_jsonrpc(
+                jsonrpc::Client::with_transport(transport),
+            )));
+        }
+
+        let auth = Auth::UserPass(username.to_string(), password.to_string());
+        let client = Client::new(url, auth)
+            .map_err(|e| anyhow!("Unable to create an RPC client for {}: {}", url, e))?;
+        Ok(Self { client })
+    }
+
+    pub fn from_client(client: Client) -> Self {
+        Self { client }
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl BitcoinBackend for CoreRpcBackend {
+    fn send_to_address(&self, address: &Address, amount: Amount) -> Result<Txid> {
+        self.client
+            .send_to_address(address, amount, None, None, None, None, None, None)
+            .with_context(|| format!("Unable to send {} to {}", amount, address))
+    }
+
+    fn get_raw_transaction(&self, txid: &Txid) -> Result<Transaction> {
+        self.client
+            .get_raw_transaction(txid, None)
+            .with_context(|| format!("Unable to get transaction {}", txid))
+    }
+
+    fn get_block_count(&self) -> Result<u64> {
+        Ok(self.client.get_block_count()?)
+    }
+
+    /// Scans the UTXO set, so outputs still in the mempool aren't returned.
+    fn get_address_utxos(&self, address: &Address) -> Result<Vec<AddressUtxo>> {
+        let result = self
+            .client
+            .scan_tx_out_set_blocking(&[ScanTxOutRequest::Single(format!("addr({})", address))])
+            .with_context(|| format!("Unable to scan the UTXOs of {}", address))?;
+        Ok(result
+            .unspents
+            .into_iter()
+            .map(|utxo| AddressUtxo {
+                txid: utxo.txid,
+                vout: utxo.vout,
+                value: utxo.amount,
+                block_height: Some(utxo.height),
+            })
+            .collect())
+    }
+
+    fn send_raw_transaction(&self, transaction: &Transaction) -> Result<Txid> {
+        Ok(self.client.send_raw_transaction(transaction)?)
+    }
+}
+
+impl<T: BitcoinBackend + ?Sized> BitcoinBackend for Box<T> {
+    fn send_to_address(&self, address: &Address, amount: Amount) -> Result<Txid> {
+        (**self).send_to_address(address, amount)
+    }
+
+    fn get_raw_transaction(&self, txid: &Txid) -> Result<Transaction> {
+        (**self).get_raw_transaction(txid)
+    }
+
+    fn get_block_count(&self) -> Result<u64> {
+        (**self).get_block_count()
+    }
+
+    fn get_address_utxos(&self, address: &Address) -> Result<Vec<AddressUtxo>> {
+        (**self).get_address_utxos(address)
+    }
+
+    fn send_raw_transaction(&self, transaction: &Transaction) -> Result<Txid> {
+        (**self).send_raw_transaction(transaction)
+    }
+}
+
+/// The backend of the active profile, see [`crate::config`].
+pub fn default_backend() -> Result<Box<dyn BitcoinBackend>> {
+    active_profile()?.bitcoin_backend()
+}
+
+/// [`BitcoinBackend`] using an Esplora HTTP API.
+///
+/// Payments are made from the UTXOs of the taproot address of the wallet key
+/// set with [`EsploraBackend::with_wallet`].
+pub struct EsploraBackend {
+    url: String,
+    client: reqwest::blocking::Client,
+    wallet: Option<(UntweakedKeypair, Address)>,
+    fee_rate: u64,
+}
+
+/// Fee rate used by default for payments, in sat/vB
+pub const DEFAULT_FEE_RATE: u64 = 2;
+
+#[derive(Deserialize)]
+struct EsploraUtxo {
+    txid: Txid,
+    vout: u32,
+    value: u64,
+    status: EsploraStatus,
+}
+
+#[derive(Deserialize)]
+struct EsploraStatus {
+    block_height: Option<u64>,
+}
+
+impl EsploraBackend {
+    /// Use the API at `url`, such as `https://mempool.space/testnet/api`.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            client: reqwest::blocking::Client::new(),
+            wallet: None,
+            fee_rate: DEFAULT_FEE_RATE,
+        }
+    }
+
+    /// Pay from the taproot address of `keypair` on `network`.
+    pub fn with_wallet(mut self, keypair: UntweakedKeypair, network: Network) -> Self {
+        let address = Address::p2tr(
+            &Secp256k1::new(),
+            XOnlyPublicKey::from_keypair(&keypair).0,
+            None,
+            network,
+        );
+        self.wallet = Some((keypair, address));
+        self
+    }
+
+    /// Set the fee rate of payments, in sat/vB.
+    pub fn with_fee_rate(mut self, fee_rate: u64) -> Self {
+        self.fee_rate = fee_rate;
+        self
+    }
+
+    fn get(&self, path: &str) -> Result<String> {
+        let url = format!("{}{}", self.url, path);
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .map_err(|e| anyhow!("Unable to reach {}: {}", url, e))?;
+        read_response(&url, response)
+    }
+}
+
+fn read_response(url: &str, response: reqwest::blocking::Response) -> Result<String> {
+    let status = response.status();
+    let body = response
+        .text()
+        .map_err(|e| anyhow!("Unable to read response from {}: {}", url, e))?;
+    if !status.is_success() {
+        bail!("{} answered {}: {}", url, status, body);
+    }
+    Ok(body)
+}
+
+impl BitcoinBackend for EsploraBackend {
+    fn send_to_address(&self, address: &Address, amount: Amount) -> Result<Txid> {
+        let (keypair, wallet) = self
+            .wallet
+            .as_ref()
+            .ok_or_else(|| anyhow!("Esplora has no wallet, set one to send to addresses"))?;
+
+        let utxos = self.get_address_utxos(wallet)?;
+        let transaction = build_payment(keypair, wallet, &utxos, address, amount, self.fee_rate)?;
+        self.send_raw_transaction(&transaction)
+    }
+
+    fn get_raw_transaction(&self, txid: &Txid) -> Result<Transaction> {
+        let hex = self.get(&format!("/tx/{}/hex", txid))?;
+        hex::decode(hex.trim())
+            .ok()
+            .and_then(|bytes| deserialize(&bytes).ok())
+            .ok_or_else(|| anyhow!("Invalid transaction {}", txid))
+    }
+
+    fn get_block_count(&self) -> Result<u64> {
+        self.get("/blocks/tip/height")?
+            .trim()
+            .parse()
+            .context("Invalid tip height")
+    }
+
+    fn get_address_utxos(&self, address: &Address) -> Result<Vec<AddressUtxo>> {
+        let utxos: Vec<EsploraUtxo> =
+            serde_json::from_str(&self.get(&format!("/address/{}/utxo", address))?)
+                .with_context(|| format!("Invalid UTXOs of {}", address))?;
+        Ok(utxos
+            .into_iter()
+            .map(|utxo| AddressUtxo {
+                txid: utxo.txid,
+                vout: utxo.vout,
+                value: Amount::from_sat(utxo.value),
+                block_height: utxo.status.block_height,
+            })
+            .collect())
+    }
+
+    fn send_raw_transaction(&self, transaction: &Transaction) -> Result<Txid> {
+        let url = format!("{}/tx", self.url);
+        let response = self
+            .client
+            .post(&url)
+            .body(serialize_hex(transaction))
+            .send()
+            .map_err(|e| anyhow!("Unable to reach {}: {}", url, e))?;
+        read_response(&url, response)?
+            .trim()
+            .parse()
+            .context("Invalid txid returned for the broadcast transaction")
+    }
+}
+
+// Virtual sizes of the parts of a transaction spending taproot key paths
+const TX_OVERHEAD_VBYTES: u64 = 11;
+const P2TR_KEY_SPEND_INPUT_VBYTES: u64 = 58;
+const P2TR_OUTPUT_VBYTES: u64 = 43;
+
+/// Build and sign a transaction paying `amount` to `to` from the `utxos` of
+/// the taproot `wallet` address of `keypair`, sending the change back to it.
+pub fn build_payment(
+    keypair: &UntweakedKeypair,
+    wallet: &Address,
+    utxos: &[AddressUtxo],
+    to: &Address,
+    amount: Amount,
+    fee_rate: u64,
+) -> Result<Transaction> {
+    let fee = |inputs: u64| {
+        Amount::from_sat(
+            fee_rate
+                * (TX_OVERHEAD_VBYTES
+                    + inputs * P2TR_KEY_SPEND_INPUT_VBYTES
+                    + 2 * P2TR_OUTPUT_VBYTES),
+        )
+    };
+
+    // Spend the largest outputs first, confirmed ones before the others
+    let mut candidates = utxos.iter().collect::<Vec<_>>();
+    candidates.sort_by_key(|utxo| (utxo.block_height.is_none(), std::cmp::Reverse(utxo.value)));
+
+    let mut selected = vec![];
+    let mut total = Amount::ZERO;
+    for utxo in candidates {
+        if total >= amount + fee(selected.len() as u64) {
+            break;
+        }
+        selected.push(utxo);
+        total += utxo.value;
+    }
+    let fee = fee(selected.len() as u64);
+    if total < amount + fee {
+        bail!(
+            "{} holds {}, not enough to pay {} and a {} fee",
+            wallet,
+            total,
+            amount,
+            fee
+        );
+    }
+
+    let mut output = vec![TxOut {
+        value: amount,
+        script_pubkey: to.script_pubkey(),
+    }];
+    let change = total - amount - fee;
+    if change >= wallet.script_pubkey().dust_value() {
+        output.push(TxOut {
+            value: change,
+            script_pubkey: wallet.script_pubkey(),
+        });
+    }
+
+    let mut transaction = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: selected
+            .iter()
+            .map(|utxo| TxIn {
+                previous_output: utxo.outpoint(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output,
+    };
+
+    let prevouts = selected
+        .iter()
+        .map(|utxo| TxOut {
+            value: utxo.value,
+            script_pubkey: wallet.script_pubkey(),
+        })
+        .collect::<Vec<_>>();
+    let secp = Secp256k1::new();
+    let tweaked = keypair.tap_tweak(&secp, None).to_inner();
+
+    let mut sighasher = SighashCache::new(&mut transaction);
+    for index in 0..prevouts.len() {
+        let sighash = sighasher.taproot_key_spend_signature_hash(
+            index,
+            &Prevouts::All(&prevouts),
+            TapSighashType::Default,
+        )?;
+        let signature = bitcoin::taproot::Signature {
+            sig: secp.sign_schnorr(&secp256k1::Message::from(sighash), &tweaked),
+            hash_ty: TapSighashType::Default,
+        };
+        *sighasher
+            .witness_mut(index)
+            .expect("the input being signed exists") = Witness::from_slice(&[signature.to_vec()]);
+    }
+
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    fn utxo(byte: u8, sats: u64, block_height: Option<u64>) -> AddressUtxo {
+        AddressUtxo {
+            txid: Txid::from_byte_array([byte; 32]),
+            vout: 0,
+            value: Amount::from_sat(sats),
+            block_height,
+        }
+    }
+
+    #[test]
+    fn test_build_payment() {
+        let secp = Secp256k1::new();
+        let (secret_key, _) = secp.generate_keypair(&mut secp256k1::rand::thread_rng());
+        let keypair = UntweakedKeypair::from_secret_key(&secp, &secret_key);
+        let (internal_key, _) = XOnlyPublicKey::from_keypair(&keypair);
+        let wallet = Address::p2tr(&secp, internal_key, None, Network::Regtest);
+        let to = Address::p2tr(
+            &secp,
+            internal_key
+                .add_tweak(&secp, &secp256k1::Scalar::ONE)
+                .unwrap()
+                .0,
+            None,
+            Network::Regtest,
+        );
+
+        let utxos = [
+            utxo(1, 2_000, Some(10)),
+            utxo(2, 5_000, None),
+            utxo(3, 4_000, Some(12)),
+        ];
+        let transaction =
+            build_payment(&keypair, &wallet, &utxos, &to, Amount::from_sat(3_000), 2).unwrap();
+
+        // The confirmed outputs are spent first, the largest one leaving
+        // enough for the fee alone
+        let fee = 2 * (11 + 58 + 2 * 43);
+        assert_eq!(transaction.input.len(), 1);
+        assert_eq!(transaction.input[0].previous_output, utxos[2].outpoint());
+        assert_eq!(transaction.output[0].value, Amount::from_sat(3_000));
+        assert_eq!(transaction.output[0].script_pubkey, to.script_pubkey());
+        assert_eq!(
+            transaction.output[1].value,
+            Amount::from_sat(4_000 - 3_000 - fee)
+        );
+
+        // The witness is a valid key path spend signature
+        let prevouts = [TxOut {
+            value: utxos[2].value,
+            script_pubkey: wallet.script_pubkey(),
+        }];
+        let sighash = SighashCache::new(&transaction)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), TapSighashType::Default)
+            .unwrap();
+        let signature =
+            bitcoin::taproot::Signature::from_slice(&transaction.input[0].witness[0]).unwrap();
+        let (output_key, _) = internal_key.tap_tweak(&secp, None);
+        secp.verify_schnorr(
+            &signature.sig,
+            &secp256k1::Message::from(sighash),
+            &output_key.to_inner(),
+        )
+        .unwrap();
+
+        // Larger payments take more inputs, and too large ones fail
+        let transaction =
+            build_payment(&keypair, &wallet, &utxos, &to, Amount::from_sat(7_000), 2).unwrap();
+        assert_eq!(transaction.input.len(), 3);
+        assert!(
+            build_payment(&keypair, &wallet, &utxos, &to, Amount::from_sat(11_000), 2).is_err()
+        );
+    }
+
+    #[test]
+    fn test_confirmations() {
+        assert_eq!(utxo(1, 1, None).confirmations(200), 0);
+        assert_eq!(utxo(1, 1, Some(200)).confirmations(200), 1);
+        assert_eq!(utxo(1, 1, Some(100)).confirmations(200), 101);
+    }
+}
@@ -0,0 +1,606 @@
+//! A client for a set of nodes, like the leader and validators of
+//! `compose.yaml`.
+//!
+//! [`Cluster`] sends writes, the transactions, to the leader and reads to the
+//! healthy node answering the fastest. A node that can't be reached, answers
+//! with something other than JSON-RPC or says it isn't ready is marked
+//! unhealthy and the request moves on to the next node, so callers only see
+//! an error once no node could answer. Unhealthy nodes are tried again after
+//! [`Cluster::with_retry_after`], or at the next [`Cluster::check_health`].
+//!
+//! The nodes are given leader first, and writes go to the first healthy node
+//! in that order while the leader is down. A write only moves on when no
+//! connection to the node could be made: a node that timed out or answered
+//! garbage may have received the transaction anyway, and sending it to the
+//! next would submit it twice, so the error is returned for the caller to
+//! check the txid before sending again. An error returned by a node over
+//! JSON-RPC is the answer to the request and doesn't fail over, since any
+//! other node would answer the same.
+//!
+//! Reads may be served by a validator that hasn't caught up with a write to
+//! the leader yet, so a transaction just sent can be unknown for a moment,
+//! which [`ConfirmationTracker`] already waits out.
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use log::{debug, warn};
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+
+use sdk::arch_program::pubkey::Pubkey;
+use sdk::processed_transaction::{ProcessedTransaction, Status};
+use sdk::runtime_transaction::RuntimeTransaction;
+
+use crate::config::Profile;
+use crate::confirmation::{ConfirmationTracker, StatusSource};
+use crate::constants::{
+    ALL_NODES_READY, GET_BEST_BLOCK_HASH, GET_BLOCK, GET_PROCESSED_TRANSACTION,
+    GET_PROGRAM_ACCOUNTS, IS_NODE_READY, READ_ACCOUNT_INFO, SEND_TRANSACTION, SEND_TRANSACTIONS,
+    TRANSACTION_NOT_FOUND_CODE,
+};
+use crate::deploy::DeployNode;
+use crate::helper::{AccountInfoResult, Block, ProgramAccount};
+use crate::transport::{self, Call, ConnectError, Transport};
+
+/// Time after which a request to a node is abandoned by default
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time after which an unhealthy node is tried again by default
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Leader,
+    Validator,
+}
+
+/// What the cluster knows of a node, for monitoring.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct NodeStatus {
+    pub url: String,
+    pub role: Role,
+    /// Whether the last request to the node was answered, and it didn't say
+    /// it isn't ready
+    pub healthy: bool,
+    /// Answer of `is_node_ready` at the last health check, `None` before
+    /// the first or if the node doesn't have the method
+    pub ready: Option<bool>,
+    /// Round trip time of the last answered request
+    #[serde(rename = "latency_ms", serialize_with = "serialize_millis")]
+    pub latency: Option<Duration>,
+    /// Number of requests sent to the node, and of those left unanswered
+    pub requests: u64,
+    pub failures: u64,
+    pub last_error: Option<String>,
+}
+
+/// Health of the whole cluster, as returned by [`Cluster::check_health`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ClusterStatus {
+    /// Answer of `all_nodes_ready`, `None` if no node answered it
+    pub all_nodes_ready: Option<bool>,
+    pub nodes: Vec<NodeStatus>,
+}
+
+struct Node {
+    status: NodeStatus,
+    /// When the node was last marked unhealthy
+    failed_at: Option<Instant>,
+}
+
+/// A client routing requests over a set of nodes, see the
+/// [module documentation](self). Its clones share the status of the nodes.
+#[derive(Clone)]
+pub struct Cluster {
+    transport: Arc<dyn Transport>,
+    timeout: Duration,
+    retry_after: Duration,
+    nodes: Arc<Mutex<Vec<Node>>>,
+}
+
+/// Why a node didn't answer a request.
+#[derive(Debug)]
+enum Unanswered {
+    /// No connection to the node could be made, it never got the request
+    NotConnected(anyhow::Error),
+    /// The node didn't answer in time or with JSON-RPC, after maybe getting
+    /// the request
+    Unreachable(anyhow::Error),
+    /// The node answered but said it isn't ready
+    NotReady,
+}
+
+impl Cluster {
+    /// A cluster of the nodes at `urls`, the leader first.
+    pub fn new<S: Into<String>>(urls: impl IntoIterator<Item = S>) -> Result<Self> {
+        let nodes = urls
+            .into_iter()
+            .enumerate()
+            .map(|(index, url)| Node {
+                status: NodeStatus {
+                    url: url.into(),
+                    role: if index == 0 {
+                        Role::Leader
+                    } else {
+                        Role::Validator
+                    },
+                    healthy: true,
+                    ready: None,
+                    latency: None,
+                    requests: 0,
+                    failures: 0,
+                    last_error: None,
+                },
+                failed_at: None,
+            })
+            .collect::<Vec<_>>();
+        if nodes.is_empty() {
+            bail!("A cluster needs at least one node URL");
+        }
+
+        Ok(Self {
+            transport: transport::current()?,
+            timeout: DEFAULT_TIMEOUT,
+            retry_after: DEFAULT_RETRY_AFTER,
+            nodes: Arc::new(Mutex::new(nodes)),
+        })
+    }
+
+    /// A cluster of the nodes of the profile.
+    pub fn from_profile(profile: &Profile) -> Result<Self> {
+        if profile.node_urls.is_empty() {
+            bail!("Profile {} has no node URL", profile.name);
+        }
+        Self::new(profile.node_urls.iter().cloned())
+    }
+
+    /// Abandon requests to a node after `timeout`, moving on to the next.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send the requests through `transport` instead of the current one,
+    /// see [`crate::transport`].
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Try unhealthy nodes again once `retry_after` has passed since they
+    /// failed.
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// URL of the leader node
+    pub fn leader_url(&self) -> String {
+        self.nodes.lock().unwrap()[0].status.url.clone()
+    }
+
+    /// The status of each node, the leader first, as of the last request.
+    pub fn status(&self) -> Vec<NodeStatus> {
+        let nodes = self.nodes.lock().unwrap();
+        nodes.iter().map(|node| node.status.clone()).collect()
+    }
+
+    /// Asks every node whether it is ready, measuring its latency, and the
+    /// cluster whether all its nodes are.
+    pub fn check_health(&self) -> ClusterStatus {
+        let count = self.nodes.lock().unwrap().len();
+        thread::scope(|scope| {
+            for index in 0..count {
+                scope.spawn(move || {
+                    let _ = self.request(index, IS_NODE_READY, None);
+                });
+            }
+        });
+
+        ClusterStatus {
+            all_nodes_ready: self
+                .write::<()>(ALL_NODES_READY, None)
+                .ok()
+                .and_then(|ready| ready.as_bool()),
+            nodes: self.status(),
+        }
+    }
+
+    /// Asks the leader whether all the nodes are ready.
+    pub fn all_nodes_ready(&self) -> Result<bool> {
+        let result = self.write::<()>(ALL_NODES_READY, None)?;
+        result
+            .as_bool()
+            .ok_or_else(|| anyhow!("Unexpected all_nodes_ready result: {}", result))
+    }
+
+    /// Calls a method changing the state of the network on the leader,
+    /// returning its result.
+    pub fn write<T: Serialize>(&self, method: &str, params: Option<T>) -> Result<Value> {
+        rpc_result(self.call(true, method, params)?)
+    }
+
+    /// Calls a method reading the state of the network on the fastest
+    /// healthy node, returning its result.
+    pub fn read<T: Serialize>(&self, method: &str, params: Option<T>) -> Result<Value> {
+        rpc_result(self.call(false, method, params)?)
+    }
+
+    /// Sends a signed transaction, returning its txid
+    pub fn send_transaction(&self, transaction: RuntimeTransaction) -> Result<String> {
+        let result = self.write(SEND_TRANSACTION, Some(transaction))?;
+        result
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("send_transaction didn't return a txid"))
+    }
+
+    /// Sends the transactions in one batch, returning their txids in order
+    pub fn send_transactions(&self, transactions: Vec<RuntimeTransaction>) -> Result<Vec<String>> {
+        let result = self.write(SEND_TRANSACTIONS, Some(transactions))?;
+        serde_json::from_value(result)
+            .map_err(|e| anyhow!("Unable to decode send_transactions result: {:?}", e))
+    }
+
+    pub fn read_account_info(&self, pubkey: Pubkey) -> Result<AccountInfoResult> {
+        serde_json::from_value(self.read(READ_ACCOUNT_INFO, Some(pubkey))?)
+            .map_err(|e| anyhow!("Unable to decode read_account_info result: {:?}", e))
+    }
+
+    pub fn get_program_accounts(&self, program_id: Pubkey) -> Result<Vec<ProgramAccount>> {
+        serde_json::from_value(self.read(GET_PROGRAM_ACCOUNTS, Some(program_id))?)
+            .map_err(|e| anyhow!("Unable to decode get_program_accounts result: {:?}", e))
+    }
+
+    /// Returns the processed transaction, or `None` if the node asked
+    /// doesn't know it yet
+    pub fn get_transaction_status(&self, txid: &str) -> Result<Option<ProcessedTransaction>> {
+        let response = self.call(false, GET_PROCESSED_TRANSACTION, Some(txid))?;
+        if response["error"]["code"].as_i64() == Some(TRANSACTION_NOT_FOUND_CODE) {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_value(rpc_result(response)?)?))
+    }
+
+    pub fn get_best_block_hash(&self) -> Result<String> {
+        let result = self.read::<()>(GET_BEST_BLOCK_HASH, None)?;
+        result
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Unexpected get_best_block_hash result: {}", result))
+    }
+
+    pub fn get_block(&self, hash: &str) -> Result<Block> {
+        // Some nodes return the block serialized into a string
+        let block = match self.read(GET_BLOCK, Some(hash))? {
+            Value::String(block) => serde_json::from_str(&block),
+            block => serde_json::from_value(block),
+        };
+        block.map_err(|e| anyhow!("Unable to decode get_block result: {:?}", e))
+    }
+
+    /// Indices of the nodes to try in turn: the healthy ones first, by
+    /// configured order for writes and by latency for reads, then the
+    /// unhealthy ones due for a retry. When none is left, every node.
+    fn route(&self, write: bool) -> Vec<usize> {
+        let nodes = self.nodes.lock().unwrap();
+        let mut order = (0..nodes.len())
+            .filter(|&index| {
+                let node = &nodes[index];
+                node.status.healthy
+                    || node
+                        .failed_at
+                        .is_none_or(|at| at.elapsed() >= self.retry_after)
+            })
+            .collect::<Vec<_>>();
+        if order.is_empty() {
+            return (0..nodes.len()).collect();
+        }
+
+        order.sort_by_key(|&index| {
+            let status = &nodes[index].status;
+            let latency = match write {
+                true => None,
+                false => Some(status.latency.unwrap_or(Duration::MAX)),
+            };
+            (!status.healthy, latency, index)
+        });
+        order
+    }
+
+    /// Sends the request to the nodes in the order of [`Cluster::route`]
+    /// until one answers, returning its JSON-RPC response. A write stops at
+    /// the first node that may have received it.
+    fn call<T: Serialize>(&self, write: bool, method: &str, params: Option<T>) -> Result<Value> {
+        let params = params.map(serde_json::to_value).transpose()?;
+        let mut errors = vec![];
+        for index in self.route(write) {
+            match self.request(index, method, params.as_ref()) {
+                Ok(response) => return Ok(response),
+                Err((error, Unanswered::Unreachable(_))) if write => {
+                    errors.push(error);
+                    bail!(
+                        "{} may have reached a node without an answer, not sending it to another: {}",
+                        method,
+                        errors.join("; ")
+                    )
+                }
+                Err((error, _)) => errors.push(error),
+            }
+        }
+        bail!("No node answered {}: {}", method, errors.join("; "))
+    }
+
+    /// Sends the request to a node, updating its status with the outcome.
+    fn request(
+        &self,
+        index: usize,
+        method: &str,
+        params: Option<&Value>,
+    ) -> Result<Value, (String, Unanswered)> {
+        let url = {
+            let mut nodes = self.nodes.lock().unwrap();
+            nodes[index].status.requests += 1;
+            nodes[index].status.url.clone()
+        };
+
+        let start = Instant::now();
+        let answer = self.post(&url, method, params);
+        let latency = start.elapsed();
+
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = &mut nodes[index];
+        let answer = answer.and_then(|response| {
+            if method == IS_NODE_READY {
+                node.status.ready = response["result"].as_bool();
+                if node.status.ready == Some(false) {
+                    return Err(Unanswered::NotReady);
+                }
+            }
+            Ok(response)
+        });
+        match answer {
+            Ok(response) => {
+                if !node.status.healthy {
+                    debug!("Node {} is healthy again", url);
+                }
+                node.status.healthy = true;
+                node.status.latency = Some(latency);
+                node.failed_at = None;
+                Ok(response)
+            }
+            Err(unanswered) => {
+                let error = match &unanswered {
+                    Unanswered::NotConnected(e) | Unanswered::Unreachable(e) => format!("{:#}", e),
+                    Unanswered::NotReady => format!("{} isn't ready", url),
+                };
+                if node.status.healthy {
+                    warn!("Node {} is unhealthy: {}", url, error);
+                }
+                node.status.healthy = false;
+                node.status.failures += 1;
+                node.status.last_error = Some(error.clone());
+                node.failed_at = Some(Instant::now());
+                Err((error, unanswered))
+            }
+        }
+    }
+
+    fn post(&self, url: &str, method: &str, params: Option<&Value>) -> Result<Value, Unanswered> {
+        let mut request = json!({
+            "jsonrpc": "2.0",
+            "id": "curlycurl",
+            "method": method,
+        });
+        if let Some(params) = params {
+            request["params"] = params.clone();
+        }
+
+        let mut call = Call::new(url, &request);
+        call.timeout = Some(self.timeout);
+        let response =
+            self.transport
+                .call(&call)
+                .map_err(|e| match e.downcast_ref::<ConnectError>() {
+                    Some(_) => Unanswered::NotConnected(e),
+                    None => Unanswered::Unreachable(e),
+                })?;
+        if !response.is_object() {
+            return Err(Unanswered::Unreachable(anyhow!(
+                "Invalid response from {}: not an object",
+                url
+            )));
+        }
+        Ok(response)
+    }
+}
+
+impl StatusSource for Cluster {
+    fn transaction_status(&self, txid: &str) -> Result<Option<ProcessedTransaction>> {
+        self.get_transaction_status(txid)
+    }
+}
+
+impl DeployNode for Cluster {
+    fn read_account(&self, pubkey: Pubkey) -> Result<AccountInfoResult> {
+        self.read_account_info(pubkey)
+    }
+
+    fn send_transactions(&self, transactions: Vec<RuntimeTransaction>) -> Result<Vec<String>> {
+        Cluster::send_transactions(self, transactions)
+    }
+
+    fn confirm(&self, txid: &str) -> Result<()> {
+        ConfirmationTracker::new(self.clone()).wait(txid, Status::Processed)?;
+        Ok(())
+    }
+
+    fn confirm_all(&self, txids: &[String]) -> Vec<Result<()>> {
+        ConfirmationTracker::new(self.clone())
+            .wait_all(txids, Status::Processed)
+            .into_iter()
+            .map(|result| result.map(|_| ()))
+            .collect()
+    }
+}
+
+/// The result of a JSON-RPC response, or its error
+fn rpc_result(mut response: Value) -> Result<Value> {
+    if let Some(err) = response.get("error") {
+        return Err(anyhow!("{:?}", err));
+    }
+    Ok(response["result"].take())
+}
+
+fn serialize_millis<S: Serializer>(
+    latency: &Option<Duration>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    latency
+        .map(|latency| latency.as_secs_f64() * 1000.0)
+        .serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::MockServer;
+    use sdk::arch_program::message::Message;
+
+    fn transaction(nonce: u8) -> RuntimeTransaction {
+        RuntimeTransaction {
+            version: 0,
+            signatures: vec![],
+            message: Message {
+                signers: vec![Pubkey::from_slice(&[nonce; 32])],
+                instructions: vec![],
+            },
+        }
+    }
+
+    /// A URL nothing listens on
+    fn dead_url() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}/", listener.local_addr().unwrap())
+    }
+
+    #[test]
+    fn test_routing_and_failover() {
+        let leader = MockServer::start().unwrap();
+        let validator = MockServer::start().unwrap();
+        let dead = dead_url();
+        let cluster = Cluster::new([leader.url(), dead.clone(), validator.url()]).unwrap();
+
+        let status = cluster.check_health();
+        assert_eq!(status.all_nodes_ready, Some(true));
+        let healthy = status
+            .nodes
+            .iter()
+            .map(|node| node.healthy)
+            .collect::<Vec<_>>();
+        assert_eq!(healthy, vec![true, false, true]);
+        assert_eq!(status.nodes[0].role, Role::Leader);
+        assert_eq!(status.nodes[1].role, Role::Validator);
+        assert!(status.nodes[1].last_error.is_some());
+        assert!(status.nodes[2].latency.is_some());
+
+        // Writes go to the leader, reads to any healthy node
+        let txid = cluster.send_transaction(transaction(1)).unwrap();
+        assert_eq!(leader.received_transactions(), vec![txid.clone()]);
+        assert!(validator.received_transactions().is_empty());
+        validator.add_block(vec![]);
+        let best_block_hash = leader.add_block(vec![]);
+        assert_eq!(cluster.get_best_block_hash().unwrap(), best_block_hash);
+        assert_eq!(cluster.status()[1].requests, 1);
+
+        // The leader stops answering: writes fail over to the next node
+        drop(leader);
+        let txid = cluster.send_transaction(transaction(2)).unwrap();
+        assert_eq!(validator.received_transactions(), vec![txid]);
+        let status = cluster.status();
+        assert!(!status[0].healthy);
+        assert_eq!(status[0].failures, 1);
+
+        // The unhealthy nodes aren't tried again until the retry delay
+        assert!(cluster.get_transaction_status("00").unwrap().is_none());
+        assert_eq!(cluster.status()[0].requests, status[0].requests);
+        assert_eq!(cluster.status()[1].requests, status[1].requests);
+
+        // A JSON-RPC error is an answer, not a failure
+        assert!(cluster
+            .read_account_info(Pubkey::from_slice(&[9; 32]))
+            .is_err());
+        assert!(cluster.status()[2].healthy);
+    }
+
+    #[test]
+    fn test_writes_dont_fail_over_after_timeout() {
+        // Connections to a listener that never accepts are made, and the
+        // request sent, but never answered
+        let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let validator = MockServer::start().unwrap();
+        let cluster = Cluster::new([
+            format!("http://{}/", silent.local_addr().unwrap()),
+            validator.url(),
+        ])
+        .unwrap()
+        .with_timeout(Duration::from_millis(200));
+
+        // The leader may have the transaction, so it isn't sent again
+        let err = cluster
+            .send_transaction(transaction(1))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("may have reached a node"), "{}", err);
+        assert!(validator.received_transactions().is_empty());
+        assert!(!cluster.status()[0].healthy);
+
+        // Reads move on to the validator
+        let best_block_hash = validator.add_block(vec![]);
+        assert_eq!(cluster.get_best_block_hash().unwrap(), best_block_hash);
+    }
+
+    #[test]
+    fn test_not_ready_and_recovery() {
+        let leader = MockServer::start().unwrap();
+        let validator = MockServer::start().unwrap();
+        let cluster = Cluster::new([leader.url(), validator.url()])
+            .unwrap()
+            .with_retry_after(Duration::ZERO);
+
+        validator.set_ready(false);
+        leader.set_all_nodes_ready(false);
+        let status = cluster.check_health();
+        assert_eq!(status.all_nodes_ready, Some(false));
+        assert!(!cluster.all_nodes_ready().unwrap());
+        assert_eq!(status.nodes[1].ready, Some(false));
+        assert!(!status.nodes[1].healthy);
+
+        // Reads avoid the unready node while a healthy one is left
+        let txid = cluster.send_transaction(transaction(1)).unwrap();
+        let processed_tx = cluster.get_transaction_status(&txid).unwrap().unwrap();
+        assert_eq!(processed_tx.status, Status::Processing);
+
+        validator.set_ready(true);
+        leader.set_all_nodes_ready(true);
+        let status = cluster.check_health();
+        assert_eq!(status.all_nodes_ready, Some(true));
+        assert!(status.nodes.iter().all(|node| node.healthy));
+
+        // With every node down the request fails, naming each node
+        drop(leader);
+        drop(validator);
+        let err = cluster.get_best_block_hash().unwrap_err().to_string();
+        assert!(
+            err.starts_with("No node answered get_best_block_hash"),
+            "{}",
+            err
+        );
+        assert_eq!(err.matches("Unable to reach").count(), 2, "{}", err);
+
+        assert!(Cluster::new(Vec::<String>::new()).is_err());
+    }
+}
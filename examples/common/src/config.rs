@@ -0,0 +1,436 @@
+//! Network profiles telling the helpers which Arch nodes and which Bitcoin
+//! network to use.
+//!
+//! The built-in profiles are `localnet`, the stack of `compose.yaml`,
+//! `devnet`, `testnet` and `mainnet`. Only `localnet` knows its node URLs, the
+//! others need them from the configuration file or the environment. The file,
+//! `arch.json` in the working directory or the one named by `ARCH_CONFIG`,
+//! overrides the fields of built-in profiles and can add new ones:
+//!
+//! ```json
+//! {
+//!   "default_profile": "testnet",
+//!   "profiles": {
+//!     "testnet": { "node_urls": ["https://my-arch-node.example:9002/"] },
+//!     "my-regtest": {
+//!       "network": "regtest",
+//!       "node_urls": ["http://127.0.0.1:9002/"],
+//!       "bitcoin": {
+//!         "backend": "core_rpc",
+//!         "url": "http://127.0.0.1:18443/wallet/testwallet",
+//!         "username": "bitcoin",
+//!         "password": "bitcoin"
+//!       }
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! The profile is picked by `ARCH_PROFILE`, then the file's
+//! `default_profile`, then `localnet`. Its fields are then overridden by
+//! `ARCH_NODE_URLS` (comma separated), `ARCH_BITCOIN_NETWORK`,
+//! `ARCH_BITCOIN_RPC_URL`, `ARCH_BITCOIN_RPC_USERNAME`,
+//! `ARCH_BITCOIN_RPC_PASSWORD`, `ARCH_ESPLORA_URL` and `ARCH_ESPLORA_WALLET`.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Context, Result};
+use bitcoin::Network;
+use serde::{Deserialize, Serialize};
+
+use crate::bitcoin_backend::{BitcoinBackend, CoreRpcBackend, EsploraBackend};
+use crate::constants::{
+    BITCOIN_NODE_ENDPOINT, BITCOIN_NODE_PASSWORD, BITCOIN_NODE_USERNAME, NODE1_ADDRESS,
+    NODE2_ADDRESS, NODE3_ADDRESS,
+};
+use crate::keystore::Keystore;
+
+/// Configuration file read from the working directory
+pub const CONFIG_FILE: &str = "arch.json";
+
+/// Profile used when none is selected
+pub const DEFAULT_PROFILE: &str = "localnet";
+
+/// The Bitcoin backend of a profile.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case", deny_unknown_fields)]
+pub enum BitcoinConfig {
+    /// A bitcoind node, sending from the wallet of its URL
+    CoreRpc {
+        url: String,
+        username: String,
+        password: String,
+    },
+    /// An Esplora API, sending from the key file `wallet`
+    Esplora {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        wallet: Option<PathBuf>,
+    },
+}
+
+/// The nodes and Bitcoin network a client talks to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Profile {
+    pub name: String,
+    pub network: Network,
+    /// RPC URLs of the nodes, the leader first
+    pub node_urls: Vec<String>,
+    pub bitcoin: BitcoinConfig,
+}
+
+impl Profile {
+    /// The built-in profile called `name`.
+    pub fn builtin(name: &str) -> Option<Profile> {
+        let dev_bitcoin_node = BitcoinConfig::CoreRpc {
+            url: BITCOIN_NODE_ENDPOINT.to_string(),
+            username: BITCOIN_NODE_USERNAME.to_string(),
+            password: BITCOIN_NODE_PASSWORD.to_string(),
+        };
+        let (network, node_urls, bitcoin) = match name {
+            "localnet" => (
+                Network::Regtest,
+                vec![
+                    NODE1_ADDRESS.to_string(),
+                    NODE2_ADDRESS.to_string(),
+                    NODE3_ADDRESS.to_string(),
+                ],
+                dev_bitcoin_node,
+            ),
+            "devnet" => (Network::Regtest, vec![], dev_bitcoin_node),
+            "testnet" => (
+                Network::Testnet,
+                vec![],
+                BitcoinConfig::Esplora {
+                    url: "https://mempool.space/testnet/api".to_string(),
+                    wallet: None,
+                },
+            ),
+            "mainnet" => (
+                Network::Bitcoin,
+                vec![],
+                BitcoinConfig::Esplora {
+                    url: "https://mempool.space/api".to_string(),
+                    wallet: None,
+                },
+            ),
+            _ => return None,
+        };
+        Some(Profile {
+            name: name.to_string(),
+            network,
+            node_urls,
+            bitcoin,
+        })
+    }
+
+    /// RPC URL of the leader node.
+    pub fn leader_url(&self) -> Result<&str> {
+        self.node_urls.first().map(String::as_str).ok_or_else(|| {
+            anyhow!(
+                "Profile {} has no node URLs, set them in {} or ARCH_NODE_URLS",
+                self.name,
+                CONFIG_FILE
+            )
+        })
+    }
+
+    /// Connect to the Bitcoin backend of the profile.
+    pub fn bitcoin_backend(&self) -> Result<Box<dyn BitcoinBackend>> {
+        Ok(match &self.bitcoin {
+            BitcoinConfig::CoreRpc {
+                url,
+                username,
+                password,
+            } => Box::new(CoreRpcBackend::new(url, username, password)?),
+            BitcoinConfig::Esplora { url, wallet } => {
+                let mut backend = EsploraBackend::new(url);
+                if let Some(wallet) = wallet {
+                    let (keypair, _) = Keystore::from_env().load(wallet)?;
+                    backend = backend.with_wallet(keypair, self.network);
+                }
+                Box::new(backend)
+            }
+        })
+    }
+}
+
+/// The fields of a profile set by the configuration file or the environment.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileOverrides {
+    network: Option<Network>,
+    node_urls: Option<Vec<String>>,
+    bitcoin: Option<BitcoinConfig>,
+}
+
+impl ProfileOverrides {
+    fn apply(self, name: &str, profile: Option<Profile>) -> Result<Profile> {
+        let missing = |field: &str| anyhow!("Profile {} doesn't set {}", name, field);
+        Ok(match profile {
+            Some(profile) => Profile {
+                name: profile.name,
+                network: self.network.unwrap_or(profile.network),
+                node_urls: self.node_urls.unwrap_or(profile.node_urls),
+                bitcoin: self.bitcoin.unwrap_or(profile.bitcoin),
+            },
+            None => Profile {
+                name: name.to_string(),
+                network: self.network.ok_or_else(|| missing("network"))?,
+                node_urls: self.node_urls.unwrap_or_default(),
+                bitcoin: self.bitcoin.ok_or_else(|| missing("bitcoin"))?,
+            },
+        })
+    }
+
+    fn from_env(profile: &Profile, var: &dyn Fn(&str) -> Option<String>) -> Result<Self> {
+        let network = var("ARCH_BITCOIN_NETWORK")
+            .map(|network| {
+                network
+                    .parse::<Network>()
+                    .map_err(|e| anyhow!("Invalid ARCH_BITCOIN_NETWORK: {}", e))
+            })
+            .transpose()?;
+        let node_urls = var("ARCH_NODE_URLS").map(|urls| {
+            urls.split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(str::to_string)
+                .collect()
+        });
+
+        let (rpc_username, rpc_password, esplora_wallet) = match &profile.bitcoin {
+            BitcoinConfig::CoreRpc {
+                username, password, ..
+            } => (Some(username.clone()), Some(password.clone()), None),
+            BitcoinConfig::Esplora { wallet, .. } => (None, None, wallet.clone()),
+        };
+        let rpc_username = var("ARCH_BITCOIN_RPC_USERNAME").or(rpc_username);
+        let rpc_password = var("ARCH_BITCOIN_RPC_PASSWORD").or(rpc_password);
+        let esplora_wallet = var("ARCH_ESPLORA_WALLET")
+            .map(PathBuf::from)
+            .or(esplora_wallet);
+
+        let bitcoin = match (var("ARCH_BITCOIN_RPC_URL"), var("ARCH_ESPLORA_URL")) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!(
+                    "Set either ARCH_BITCOIN_RPC_URL or ARCH_ESPLORA_URL, not both"
+                ))
+            }
+            (Some(url), None) => Some(BitcoinConfig::CoreRpc {
+                url,
+                username: rpc_username.unwrap_or_default(),
+                password: rpc_password.unwrap_or_default(),
+            }),
+            (None, Some(url)) => Some(BitcoinConfig::Esplora {
+                url,
+                wallet: esplora_wallet,
+            }),
+            (None, None) => match &profile.bitcoin {
+                BitcoinConfig::CoreRpc { url, .. } => Some(BitcoinConfig::CoreRpc {
+                    url: url.clone(),
+                    username: rpc_username.unwrap_or_default(),
+                    password: rpc_password.unwrap_or_default(),
+                }),
+                BitcoinConfig::Esplora { url, .. } => Some(BitcoinConfig::Esplora {
+                    url: url.clone(),
+                    wallet: esplora_wallet,
+                }),
+            },
+        };
+
+        Ok(ProfileOverrides {
+            network,
+            node_urls,
+            bitcoin,
+        })
+    }
+}
+
+/// The contents of a configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    default_profile: Option<String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, ProfileOverrides>,
+}
+
+impl ConfigFile {
+    pub fn parse(contents: &str) -> Result<Self> {
+        serde_json::from_str(contents).context("Invalid configuration file")
+    }
+
+    /// Resolve the profile called `name`, or the default one, reading the
+    /// environment overrides with `var`.
+    pub fn profile(
+        &self,
+        name: Option<&str>,
+        var: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Profile> {
+        let name = name
+            .map(str::to_string)
+            .or_else(|| var("ARCH_PROFILE"))
+            .or_else(|| self.default_profile.clone())
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+
+        let builtin = Profile::builtin(&name);
+        let profile = match self.profiles.get(&name) {
+            Some(overrides) => overrides.clone().apply(&name, builtin)?,
+            None => builtin.ok_or_else(|| anyhow!("Unknown profile {}", name))?,
+        };
+
+        let overrides = ProfileOverrides::from_env(&profile, var)?;
+        overrides.apply(&name, Some(profile))
+    }
+}
+
+/// Load the profile called `name`, or the selected one if `None`, from the
+/// configuration file and the environment.
+pub fn load_profile(name: Option<&str>) -> Result<Profile> {
+    let path = std::env::var("ARCH_CONFIG").unwrap_or_else(|_| CONFIG_FILE.to_string());
+    let file = match fs::read_to_string(&path) {
+        Ok(contents) => {
+            ConfigFile::parse(&contents).with_context(|| format!("Unable to load {}", path))?
+        }
+        Err(_) if std::env::var("ARCH_CONFIG").is_err() => ConfigFile::default(),
+        Err(e) => return Err(anyhow!("Unable to read {}: {}", path, e)),
+    };
+
+    file.profile(name, &|var| std::env::var(var).ok())
+}
+
+static ACTIVE_PROFILE: OnceLock<Profile> = OnceLock::new();
+
+/// The selected profile, loaded once, used by the helpers that don't take a
+/// node URL or a network.
+pub fn active_profile() -> Result<&'static Profile> {
+    if let Some(profile) = ACTIVE_PROFILE.get() {
+        return Ok(profile);
+    }
+    let profile = load_profile(None)?;
+    Ok(ACTIVE_PROFILE.get_or_init(|| profile))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn test_builtin_profiles() {
+        let file = ConfigFile::default();
+
+        let localnet = file.profile(None, &env(&[])).unwrap();
+        assert_eq!(localnet, Profile::builtin("localnet").unwrap());
+        assert_eq!(localnet.leader_url().unwrap(), NODE1_ADDRESS);
+
+        let mainnet = file.profile(Some("mainnet"), &env(&[])).unwrap();
+        assert_eq!(mainnet.network, Network::Bitcoin);
+        assert!(mainnet.leader_url().is_err());
+
+        let testnet = file
+            .profile(None, &env(&[("ARCH_PROFILE", "testnet")]))
+            .unwrap();
+        assert_eq!(testnet.network, Network::Testnet);
+
+        assert!(file.profile(Some("nowhere"), &env(&[])).is_err());
+    }
+
+    #[test]
+    fn test_file_profiles() {
+        let file = ConfigFile::parse(
+            r#"{
+                "default_profile": "testnet",
+                "profiles": {
+                    "testnet": { "node_urls": ["http://10.0.0.1:9002/"] },
+                    "custom": {
+                        "network": "signet",
+                        "bitcoin": { "backend": "esplora", "url": "http://127.0.0.1:3000" }
+                    },
+                    "partial": { "node_urls": [] }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let testnet = file.profile(None, &env(&[])).unwrap();
+        assert_eq!(testnet.node_urls, vec!["http://10.0.0.1:9002/"]);
+        assert_eq!(
+            testnet.bitcoin,
+            Profile::builtin("testnet").unwrap().bitcoin
+        );
+
+        let custom = file.profile(Some("custom"), &env(&[])).unwrap();
+        assert_eq!(custom.network, Network::Signet);
+        assert!(custom.node_urls.is_empty());
+
+        assert!(file.profile(Some("partial"), &env(&[])).is_err());
+        assert!(ConfigFile::parse(r#"{ "profiles": { "x": { "nodes": [] } } }"#).is_err());
+    }
+
+    #[test]
+    fn test_environment_overrides() {
+        let file = ConfigFile::default();
+
+        let profile = file
+            .profile(
+                None,
+                &env(&[
+                    ("ARCH_NODE_URLS", "http://a:9002/, http://b:9003/"),
+                    ("ARCH_BITCOIN_NETWORK", "signet"),
+                    ("ARCH_BITCOIN_RPC_PASSWORD", "secret"),
+                ]),
+            )
+            .unwrap();
+        assert_eq!(profile.node_urls, vec!["http://a:9002/", "http://b:9003/"]);
+        assert_eq!(profile.network, Network::Signet);
+        assert_eq!(
+            profile.bitcoin,
+            BitcoinConfig::CoreRpc {
+                url: BITCOIN_NODE_ENDPOINT.to_string(),
+                username: BITCOIN_NODE_USERNAME.to_string(),
+                password: "secret".to_string(),
+            }
+        );
+
+        let profile = file
+            .profile(
+                Some("localnet"),
+                &env(&[
+                    ("ARCH_ESPLORA_URL", "http://127.0.0.1:3000"),
+                    ("ARCH_ESPLORA_WALLET", "wallet.json"),
+                ]),
+            )
+            .unwrap();
+        assert_eq!(
+            profile.bitcoin,
+            BitcoinConfig::Esplora {
+                url: "http://127.0.0.1:3000".to_string(),
+                wallet: Some(PathBuf::from("wallet.json")),
+            }
+        );
+
+        assert!(file
+            .profile(None, &env(&[("ARCH_BITCOIN_NETWORK", "moon")]))
+            .is_err());
+        assert!(file
+            .profile(
+                None,
+                &env(&[
+                    ("ARCH_ESPLORA_URL", "http://127.0.0.1:3000"),
+                    ("ARCH_BITCOIN_RPC_URL", "http://127.0.0.1:18443"),
+                ])
+            )
+            .is_err());
+    }
+}
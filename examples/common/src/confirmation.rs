@@ -0,0 +1,395 @@
+//! Waiting for transactions to reach a status.
+//!
+//! [`ConfirmationTracker`] polls the node for many transactions at once, each
+//! with its own exponential backoff, so confirming a batch takes about as long
+//! as its slowest transaction instead of the sum of fixed sleeps.
+//!
+//! The futures returned by [`ConfirmationTracker::wait_for`] and
+//! [`ConfirmationTracker::wait_for_all`] must run on a tokio runtime. The
+//! blocking [`ConfirmationTracker::wait`] and [`ConfirmationTracker::wait_all`]
+//! start their own and can't be called from inside one.
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use log::debug;
+use tokio::runtime::{Builder, Runtime};
+use tokio::sync::Semaphore;
+
+use sdk::processed_transaction::{ProcessedTransaction, Status};
+
+use crate::helper::get_transaction_status;
+
+/// Number of status requests in flight at once by default
+pub const DEFAULT_MAX_CONCURRENT_POLLS: usize = 16;
+
+/// Where the tracker reads transaction statuses from.
+pub trait StatusSource: Send + Sync {
+    /// Returns the processed transaction, or `None` if it isn't known yet.
+    fn transaction_status(&self, txid: &str) -> Result<Option<ProcessedTransaction>>;
+}
+
+/// [`StatusSource`] asking a node over RPC.
+pub struct RpcStatusSource {
+    url: String,
+}
+
+impl RpcStatusSource {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+        }
+    }
+}
+
+impl StatusSource for RpcStatusSource {
+    fn transaction_status(&self, txid: &str) -> Result<Option<ProcessedTransaction>> {
+        get_transaction_status(&self.url, txid)
+    }
+}
+
+/// Delays between the polls of one transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    /// Delay after the first poll
+    pub initial: Duration,
+    /// Factor applied to the delay after each poll
+    pub multiplier: u32,
+    /// Longest delay between two polls
+    pub max_delay: Duration,
+    /// Time after which the wait fails
+    pub timeout: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(250),
+            multiplier: 2,
+            max_delay: Duration::from_secs(5),
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay after the poll numbered `attempt`, starting from 0.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.initial
+            .saturating_mul(self.multiplier.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+}
+
+/// Whether a transaction with `status` satisfies a wait for `target`, failing
+/// once it never will.
+fn reached(txid: &str, status: &Status, target: &Status) -> Result<bool> {
+    match (status, target) {
+        (_, Status::Processing) => Ok(true),
+        (Status::Failed(error), _) => bail!("transaction {} failed: {}", txid, error),
+        (status, target) => Ok(status == target),
+    }
+}
+
+pub struct ConfirmationTracker<S> {
+    source: Arc<S>,
+    backoff: Backoff,
+    polls: Arc<Semaphore>,
+}
+
+impl ConfirmationTracker<RpcStatusSource> {
+    /// Tracker polling the node at `url`.
+    pub fn rpc(url: &str) -> Self {
+        Self::new(RpcStatusSource::new(url))
+    }
+}
+
+impl<S: StatusSource + 'static> ConfirmationTracker<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source: Arc::new(source),
+            backoff: Backoff::default(),
+            polls: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_POLLS)),
+        }
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_max_concurrent_polls(mut self, max: usize) -> Self {
+        self.polls = Arc::new(Semaphore::new(max.max(1)));
+        self
+    }
+
+    /// Resolves with the transaction once its status reaches `target`, or
+    /// fails when it failed, the backoff times out or the source returns an
+    /// error. Any status reaches [`Status::Processing`].
+    pub fn wait_for(
+        &self,
+        txid: &str,
+        target: Status,
+    ) -> impl Future<Output = Result<ProcessedTransaction>> + Send + 'static {
+        let source = self.source.clone();
+        let polls = self.polls.clone();
+        let backoff = self.backoff;
+        let txid = txid.to_string();
+
+        async move {
+            let deadline = Instant::now() + backoff.timeout;
+            let mut attempt = 0;
+            loop {
+                let permit = polls.clone().acquire_owned().await?;
+                let (poll_source, poll_txid) = (source.clone(), txid.clone());
+                let status = tokio::task::spawn_blocking(move || {
+                    let _permit = permit;
+                    poll_source.transaction_status(&poll_txid)
+                })
+                .await??;
+
+                match status {
+                    Some(processed_tx) => {
+                        if reached(&txid, &processed_tx.status, &target)? {
+                            return Ok(processed_tx);
+                        }
+                        debug!(
+                            "Transaction {} is {:?}, waiting for {:?}",
+                            txid, processed_tx.status, target
+                        );
+                    }
+                    None => debug!("Transaction {} isn't known yet", txid),
+                }
+
+                let now = Instant::now();
+                if now >= deadline {
+                    bail!(
+                        "Timeout: transaction {} wasn't {:?} after {:?}",
+                        txid,
+                        target,
+                        backoff.timeout
+                    );
+                }
+                tokio::time::sleep(backoff.delay(attempt).min(deadline - now)).await;
+                attempt += 1;
+            }
+        }
+    }
+
+    /// Waits for every transaction concurrently, with the results in the
+    /// order of `txids`.
+    pub fn wait_for_all(
+        &self,
+        txids: &[String],
+        target: Status,
+    ) -> impl Future<Output = Vec<Result<ProcessedTransaction>>> + Send + 'static {
+        let waits = txids
+            .iter()
+            .map(|txid| self.wait_for(txid, target.clone()))
+            .collect::<Vec<_>>();
+
+        async move {
+            let tasks = waits.into_iter().map(tokio::spawn).collect::<Vec<_>>();
+            let mut results = Vec::with_capacity(tasks.len());
+            for task in tasks {
+                results.push(
+                    task.await
+                        .unwrap_or_else(|e| Err(anyhow!("confirmation task failed: {}", e))),
+                );
+            }
+            results
+        }
+    }
+
+    /// Blocking version of [`Self::wait_for`].
+    pub fn wait(&self, txid: &str, target: Status) -> Result<ProcessedTransaction> {
+        runtime()?.block_on(self.wait_for(txid, target))
+    }
+
+    /// Blocking version of [`Self::wait_for_all`].
+    pub fn wait_all(&self, txids: &[String], target: Status) -> Vec<Result<ProcessedTransaction>> {
+        match runtime() {
+            Ok(runtime) => runtime.block_on(self.wait_for_all(txids, target)),
+            Err(e) => txids.iter().map(|_| Err(anyhow!("{}", e))).collect(),
+        }
+    }
+}
+
+fn runtime() -> Result<Runtime> {
+    Ok(Builder::new_current_thread().enable_time().build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Mutex;
+
+    use sdk::arch_program::message::Message;
+    use sdk::arch_program::program_error::ProgramError;
+    use sdk::processed_transaction::TransactionError;
+    use sdk::runtime_transaction::RuntimeTransaction;
+
+    /// Transactions known after a number of polls, processed after more.
+    #[derive(Default)]
+    struct MockSource {
+        /// txid to (polls until Processing, polls until Processed)
+        schedule: HashMap<String, (usize, usize)>,
+        /// Transactions failing instead of being processed
+        failing: HashSet<String>,
+        polls: Mutex<HashMap<String, usize>>,
+        latency: Duration,
+    }
+
+    impl MockSource {
+        fn with(mut self, txid: &str, processing: usize, processed: usize) -> Self {
+            self.schedule
+                .insert(txid.to_string(), (processing, processed));
+            self
+        }
+
+        fn polls(&self, txid: &str) -> usize {
+            self.polls.lock().unwrap().get(txid).copied().unwrap_or(0)
+        }
+    }
+
+    impl StatusSource for MockSource {
+        fn transaction_status(&self, txid: &str) -> Result<Option<ProcessedTransaction>> {
+            std::thread::sleep(self.latency);
+            let polls = {
+                let mut polls = self.polls.lock().unwrap();
+                let count = polls.entry(txid.to_string()).or_default();
+                *count += 1;
+                *count
+            };
+            let Some(&(processing, processed)) = self.schedule.get(txid) else {
+                return Ok(None);
+            };
+            let status = match polls {
+                polls if polls >= processed && self.failing.contains(txid) => {
+                    Status::Failed(ProgramError::Custom(1).into())
+                }
+                polls if polls >= processed => Status::Processed,
+                polls if polls >= processing => Status::Processing,
+                _ => return Ok(None),
+            };
+            Ok(Some(ProcessedTransaction {
+                runtime_transaction: RuntimeTransaction {
+                    version: 0,
+                    signatures: vec![],
+                    message: Message {
+                        signers: vec![],
+                        instructions: vec![],
+                    },
+                },
+                status,
+                bitcoin_txids: vec![txid.to_string()],
+                logs: vec![],
+                compute_units_consumed: 0,
+                return_data: None,
+            }))
+        }
+    }
+
+    fn fast_backoff() -> Backoff {
+        Backoff {
+            initial: Duration::from_millis(1),
+            multiplier: 2,
+            max_delay: Duration::from_millis(8),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn test_backoff_delays() {
+        let backoff = Backoff::default();
+        let delays = (0..7)
+            .map(|attempt| backoff.delay(attempt))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            [250, 500, 1000, 2000, 4000, 5000, 5000].map(Duration::from_millis)
+        );
+        assert_eq!(backoff.delay(u32::MAX), backoff.max_delay);
+    }
+
+    #[test]
+    fn test_waits_for_target_status() {
+        let source = MockSource::default().with("a", 2, 4);
+        let tracker = ConfirmationTracker::new(source).with_backoff(fast_backoff());
+
+        let processing = tracker.wait("a", Status::Processing).unwrap();
+        assert_eq!(processing.status, Status::Processing);
+        assert_eq!(tracker.source.polls("a"), 2);
+
+        let processed = tracker.wait("a", Status::Processed).unwrap();
+        assert_eq!(processed.status, Status::Processed);
+        assert_eq!(processed.bitcoin_txids, vec!["a".to_string()]);
+        assert_eq!(tracker.source.polls("a"), 4);
+    }
+
+    #[test]
+    fn test_waits_for_all_concurrently() {
+        let txids = (0..40).map(|i| format!("tx{}", i)).collect::<Vec<_>>();
+        let source = txids.iter().enumerate().fold(
+            MockSource {
+                latency: Duration::from_millis(25),
+                ..Default::default()
+            },
+            |source, (i, txid)| source.with(txid, 1, 1 + i % 3),
+        );
+        let tracker = ConfirmationTracker::new(source)
+            .with_backoff(fast_backoff())
+            .with_max_concurrent_polls(40);
+
+        let started = Instant::now();
+        let results = tracker.wait_all(&txids, Status::Processed);
+        // Polled one after the other this takes 80 polls of 25ms each
+        assert!(started.elapsed() < Duration::from_millis(1000));
+
+        for (txid, result) in txids.iter().zip(results) {
+            assert_eq!(result.unwrap().bitcoin_txids, vec![txid.clone()]);
+        }
+    }
+
+    #[test]
+    fn test_times_out() {
+        let source = MockSource::default().with("stuck", 1, usize::MAX);
+        let tracker = ConfirmationTracker::new(source).with_backoff(Backoff {
+            timeout: Duration::from_millis(50),
+            ..fast_backoff()
+        });
+
+        let results = tracker.wait_all(
+            &["stuck".to_string(), "unknown".to_string()],
+            Status::Processed,
+        );
+        for result in results {
+            assert!(result.unwrap_err().to_string().starts_with("Timeout"));
+        }
+        // Polls slow down to the capped delay instead of spinning
+        assert!(tracker.source.polls("stuck") < 20);
+        assert!(tracker.source.polls("unknown") > 1);
+    }
+
+    #[test]
+    fn test_fails_with_the_transaction() {
+        let mut source = MockSource::default().with("failing", 1, 2);
+        source.failing.insert("failing".to_string());
+        let tracker = ConfirmationTracker::new(source).with_backoff(fast_backoff());
+
+        let error = tracker.wait("failing", Status::Processed).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "transaction failing failed: Custom program error: 0x1"
+        );
+        assert_eq!(tracker.source.polls("failing"), 2);
+
+        let failed = tracker.wait("failing", Status::Processing).unwrap();
+        assert!(matches!(
+            failed.error(),
+            Some(TransactionError::Program(ProgramError::Custom(1)))
+        ));
+    }
+}
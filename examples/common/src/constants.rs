@@ -4,8 +4,11 @@
 pub const CALLER_FILE_PATH: &str = "caller.json";
 pub const PROGRAM_FILE_PATH: &str = "program.json";
 
-/// Local address for node 1
+/// Local address for node 1, the leader
 pub const NODE1_ADDRESS: &str = "http://127.0.0.1:9002/";
+/// Local addresses for the validators
+pub const NODE2_ADDRESS: &str = "http://127.0.0.1:9003/";
+pub const NODE3_ADDRESS: &str = "http://127.0.0.1:9004/";
 
 /// Arbitrary example names for HelloWorld program
 pub const NAME1: &str = "Amine";
@@ -16,11 +19,24 @@ pub const ASSIGN_AUTHORITY: &str = "assign_authority";
 pub const READ_ACCOUNT_INFO: &str = "read_account_info";
 pub const DEPLOY_PROGRAM: &str = "deploy_program";
 pub const SEND_TRANSACTION: &str = "send_transaction";
+pub const SEND_TRANSACTIONS: &str = "send_transactions";
 pub const GET_PROGRAM: &str = "get_program";
 pub const GET_BLOCK: &str = "get_block";
 pub const GET_BEST_BLOCK_HASH: &str = "get_best_block_hash";
 pub const GET_PROCESSED_TRANSACTION: &str = "get_processed_transaction";
 pub const GET_ACCOUNT_ADDRESS: &str = "get_account_address";
+pub const GET_PROGRAM_ACCOUNTS: &str = "get_program_accounts";
+pub const IS_NODE_READY: &str = "is_node_ready";
+pub const ALL_NODES_READY: &str = "all_nodes_ready";
+
+/// Subscription methods and their notifications
+pub const ACCOUNT_SUBSCRIBE: &str = "account_subscribe";
+pub const TRANSACTION_SUBSCRIBE: &str = "transaction_subscribe";
+pub const PROGRAM_SUBSCRIBE: &str = "program_subscribe";
+pub const UNSUBSCRIBE: &str = "unsubscribe";
+pub const ACCOUNT_NOTIFICATION: &str = "account_notification";
+pub const TRANSACTION_NOTIFICATION: &str = "transaction_notification";
+pub const PROGRAM_NOTIFICATION: &str = "program_notification";
 
 /// Data
 pub const BITCOIN_NODE_ENDPOINT: &str =
@@ -0,0 +1,894 @@
+//! Human readable decoding of transactions, for debugging.
+//!
+//! [`Decoder`] turns a [`RuntimeTransaction`] or a serialized
+//! [`TransactionToSign`] into a structure rendered as JSON through serde or
+//! as indented text through `Display`. It checks the signature of every
+//! signer, names the accounts and decodes the data of system instructions
+//! and of programs whose [`Idl`] it was given, and pretty prints the Bitcoin
+//! transactions it finds, including those passed as `Vec<u8>` instruction
+//! arguments.
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::secp256k1::{schnorr, Message as SecpMessage, Secp256k1, XOnlyPublicKey};
+use bitcoin::{Address, Network, Transaction};
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Serialize, Serializer};
+
+use sdk::arch_program::idl::{Idl, IdlFields, IdlType, IdlTypeDef, IdlTypeDefTy};
+use sdk::arch_program::pubkey::Pubkey;
+use sdk::arch_program::system_instruction::SystemInstruction;
+use sdk::runtime_transaction::RuntimeTransaction;
+use sdk::transaction_to_sign::TransactionToSign;
+
+/// Nesting of IDL types beyond which data is considered malformed, so
+/// recursive types can't recurse forever
+const MAX_DEPTH: usize = 32;
+
+/// A decoded value, keeping the order of fields for display.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Int(i128),
+    UInt(u128),
+    String(String),
+    /// Bytes, shown as hex
+    Bytes(Vec<u8>),
+    Pubkey(Pubkey),
+    None,
+    List(Vec<Value>),
+    Fields(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn fields<'a>(fields: impl IntoIterator<Item = (&'a str, Value)>) -> Self {
+        Self::Fields(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn is_scalar(&self) -> bool {
+        match self {
+            Self::List(values) => values.is_empty(),
+            Self::Fields(fields) => fields.is_empty(),
+            _ => true,
+        }
+    }
+
+    fn write_scalar(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Int(value) => write!(f, "{}", value),
+            Self::UInt(value) => write!(f, "{}", value),
+            Self::String(value) if value.is_empty() => write!(f, "\"\""),
+            Self::String(value) => write!(f, "{}", value),
+            Self::Bytes(bytes) if bytes.is_empty() => write!(f, "(empty)"),
+            Self::Bytes(bytes) => write!(f, "{}", hex::encode(bytes)),
+            Self::Pubkey(pubkey) => write!(f, "{:x}", pubkey),
+            Self::None => write!(f, "none"),
+            Self::List(_) => write!(f, "[]"),
+            Self::Fields(_) => write!(f, "{{}}"),
+        }
+    }
+
+    /// Writes the value below a line ending with its name, indented.
+    fn write_nested(&self, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        if self.is_scalar() {
+            write!(f, " ")?;
+            self.write_scalar(f)?;
+            return writeln!(f);
+        }
+        writeln!(f)?;
+        let pad = "  ".repeat(indent);
+        match self {
+            Self::List(values) => {
+                for value in values {
+                    write!(f, "{}-", pad)?;
+                    value.write_nested(f, indent + 1)?;
+                }
+            }
+            Self::Fields(fields) => {
+                for (name, value) in fields {
+                    write!(f, "{}{}:", pad, name)?;
+                    value.write_nested(f, indent + 1)?;
+                }
+            }
+            _ => unreachable!("scalars are written inline"),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Fields(fields) => {
+                for (name, value) in fields {
+                    write!(f, "{}:", name)?;
+                    value.write_nested(f, 1)?;
+                }
+                Ok(())
+            }
+            value => value.write_nested(f, 0),
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Bool(value) => serializer.serialize_bool(*value),
+            Self::Int(value) => match i64::try_from(*value) {
+                Ok(value) => serializer.serialize_i64(value),
+                Err(_) => serializer.serialize_str(&value.to_string()),
+            },
+            Self::UInt(value) => match u64::try_from(*value) {
+                Ok(value) => serializer.serialize_u64(value),
+                Err(_) => serializer.serialize_str(&value.to_string()),
+            },
+            Self::String(value) => serializer.serialize_str(value),
+            Self::Bytes(bytes) => serializer.serialize_str(&hex::encode(bytes)),
+            Self::Pubkey(pubkey) => serializer.serialize_str(&format!("{:x}", pubkey)),
+            Self::None => serializer.serialize_none(),
+            Self::List(values) => {
+                let mut seq = serializer.serialize_seq(Some(values.len()))?;
+                for value in values {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            Self::Fields(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (name, value) in fields {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// Decodes transactions, knowing the interface of some programs.
+pub struct Decoder {
+    network: Network,
+    idls: HashMap<Pubkey, Idl>,
+}
+
+impl Decoder {
+    /// A decoder showing Bitcoin addresses of `network`.
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            idls: HashMap::new(),
+        }
+    }
+
+    /// Decodes the instructions of `program_id` with its IDL.
+    pub fn with_idl(mut self, program_id: Pubkey, idl: Idl) -> Self {
+        self.idls.insert(program_id, idl);
+        self
+    }
+
+    /// Decodes a hex serialized [`RuntimeTransaction`], or failing that a
+    /// [`TransactionToSign`].
+    pub fn decode_hex(&self, data: &str) -> Result<Value> {
+        let bytes = hex::decode(data.trim()).context("Transaction isn't valid hex")?;
+        let transaction_error = match RuntimeTransaction::try_from_slice(&bytes) {
+            Ok(transaction) => return Ok(self.decode_transaction(&transaction)),
+            Err(e) => e,
+        };
+        match TransactionToSign::try_from_slice(&bytes) {
+            Ok(transaction_to_sign) => self.decode_transaction_to_sign(&transaction_to_sign),
+            Err(e) => Err(anyhow!(
+                "Data is neither a runtime transaction ({}) nor a transaction to sign ({})",
+                transaction_error,
+                e
+            )),
+        }
+    }
+
+    pub fn decode_transaction(&self, transaction: &RuntimeTransaction) -> Value {
+        let message = &transaction.message;
+        let message_hash = message.hash();
+
+        let mut signers = message
+            .signers
+            .iter()
+            .enumerate()
+            .map(|(index, signer)| {
+                let signature = transaction.signatures.get(index);
+                Value::fields([
+                    ("pubkey", Value::Pubkey(*signer)),
+                    (
+                        "signature",
+                        signature
+                            .map_or(Value::None, |signature| Value::Bytes(signature.0.clone())),
+                    ),
+                    (
+                        "signature_status",
+                        Value::String(
+                            signature_status(signer, signature.map(|s| &s.0[..]), &message_hash)
+                                .to_string(),
+                        ),
+                    ),
+                ])
+            })
+            .collect::<Vec<_>>();
+        // Signatures without a signer to check them against
+        signers.extend(
+            transaction
+                .signatures
+                .iter()
+                .skip(message.signers.len())
+                .map(|signature| {
+                    Value::fields([
+                        ("pubkey", Value::None),
+                        ("signature", Value::Bytes(signature.0.clone())),
+                        ("signature_status", Value::String("no signer".to_string())),
+                    ])
+                }),
+        );
+
+        let instructions = message
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(index, instruction)| {
+                let idl = self.idls.get(&instruction.program_id);
+                let idl_instruction = idl.and_then(|idl| {
+                    let variant = *instruction.data.first()?;
+                    idl.instructions.get(variant as usize)
+                });
+                let program = if instruction.program_id == Pubkey::system_program() {
+                    Value::String("system".to_string())
+                } else {
+                    idl.map_or(Value::None, |idl| Value::String(idl.name.clone()))
+                };
+
+                let accounts = instruction
+                    .accounts
+                    .iter()
+                    .enumerate()
+                    .map(|(position, meta)| {
+                        let name = idl_instruction
+                            .and_then(|idl_instruction| idl_instruction.accounts.get(position))
+                            .map_or(Value::None, |account| Value::String(account.name.clone()));
+                        Value::fields([
+                            ("pubkey", Value::Pubkey(meta.pubkey)),
+                            ("name", name),
+                            ("is_signer", Value::Bool(meta.is_signer)),
+                            ("is_writable", Value::Bool(meta.is_writable)),
+                        ])
+                    })
+                    .collect();
+
+                let decoded = if instruction.program_id == Pubkey::system_program() {
+                    Some(decode_system_instruction(&instruction.data))
+                } else {
+                    idl.map(|idl| self.decode_idl_instruction(idl, &instruction.data))
+                };
+                let decoded = match decoded {
+                    None => Value::None,
+                    Some(Ok(decoded)) => decoded,
+                    Some(Err(e)) => Value::fields([("error", Value::String(e.to_string()))]),
+                };
+
+                Value::fields([
+                    ("index", Value::UInt(index as u128)),
+                    ("program_id", Value::Pubkey(instruction.program_id)),
+                    ("program", program),
+                    ("accounts", Value::List(accounts)),
+                    ("data", Value::Bytes(instruction.data.clone())),
+                    ("decoded", decoded),
+                ])
+            })
+            .collect();
+
+        Value::fields([
+            ("txid", Value::String(transaction.txid())),
+            ("version", Value::UInt(transaction.version as u128)),
+            ("message_hash", Value::String(message_hash)),
+            ("signers", Value::List(signers)),
+            ("instructions", Value::List(instructions)),
+        ])
+    }
+
+    pub fn decode_transaction_to_sign(
+        &self,
+        transaction_to_sign: &TransactionToSign,
+    ) -> Result<Value> {
+        let transaction =
+            bitcoin::consensus::deserialize::<Transaction>(&transaction_to_sign.tx_bytes)
+                .context("Transaction to sign doesn't hold a Bitcoin transaction")?;
+
+        let inputs_to_sign = transaction_to_sign
+            .inputs_to_sign
+            .iter()
+            .map(|input| {
+                let mut fields = vec![
+                    ("index".to_string(), Value::UInt(input.index as u128)),
+                    ("signer".to_string(), Value::Pubkey(input.signer)),
+                ];
+                if input.index as usize >= transaction.input.len() {
+                    fields.push((
+                        "error".to_string(),
+                        Value::String("the transaction has no such input".to_string()),
+                    ));
+                }
+                Value::Fields(fields)
+            })
+            .collect();
+
+        Ok(Value::fields([
+            (
+                "bitcoin_transaction",
+                self.bitcoin_transaction(&transaction),
+            ),
+            ("inputs_to_sign", Value::List(inputs_to_sign)),
+        ]))
+    }
+
+    pub fn bitcoin_transaction(&self, transaction: &Transaction) -> Value {
+        let inputs = transaction
+            .input
+            .iter()
+            .map(|input| {
+                Value::fields([
+                    (
+                        "previous_output",
+                        Value::String(input.previous_output.to_string()),
+                    ),
+                    ("sequence", Value::UInt(input.sequence.0 as u128)),
+                    ("script_sig", Value::Bytes(input.script_sig.to_bytes())),
+                    (
+                        "witness",
+                        Value::List(
+                            input
+                                .witness
+                                .iter()
+                                .map(|item| Value::Bytes(item.to_vec()))
+                                .collect(),
+                        ),
+                    ),
+                ])
+            })
+            .collect();
+        let outputs = transaction
+            .output
+            .iter()
+            .map(|output| {
+                let address = Address::from_script(&output.script_pubkey, self.network)
+                    .map_or(Value::None, |address| Value::String(address.to_string()));
+                Value::fields([
+                    ("value", Value::UInt(output.value.to_sat() as u128)),
+                    ("address", address),
+                    (
+                        "script_pubkey",
+                        Value::Bytes(output.script_pubkey.to_bytes()),
+                    ),
+                ])
+            })
+            .collect();
+
+        Value::fields([
+            ("txid", Value::String(transaction.txid().to_string())),
+            ("version", Value::Int(transaction.version.0 as i128)),
+            (
+                "lock_time",
+                Value::String(transaction.lock_time.to_string()),
+            ),
+            ("inputs", Value::List(inputs)),
+            ("outputs", Value::List(outputs)),
+        ])
+    }
+
+    /// Decodes instruction data made of the index of the instruction in the
+    /// IDL and the Borsh encoding of its arguments.
+    fn decode_idl_instruction(&self, idl: &Idl, data: &[u8]) -> Result<Value> {
+        let (&variant, mut args) = data
+            .split_first()
+            .ok_or_else(|| anyhow!("instruction data is empty"))?;
+        let instruction = idl
+            .instructions
+            .get(variant as usize)
+            .ok_or_else(|| anyhow!("{} has no instruction {}", idl.name, variant))?;
+
+        let reader = IdlReader { decoder: self, idl };
+        let values = instruction
+            .args
+            .iter()
+            .map(|arg| Ok((arg.name.clone(), reader.read(&arg.ty, &mut args, 0)?)))
+            .collect::<Result<Vec<_>>>()?;
+        if !args.is_empty() {
+            bail!("{} trailing bytes after the arguments", args.len());
+        }
+
+        Ok(Value::fields([
+            ("instruction", Value::String(instruction.name.clone())),
+            ("args", Value::Fields(values)),
+        ]))
+    }
+}
+
+fn signature_status(signer: &Pubkey, signature: Option<&[u8]>, message_hash: &str) -> &'static str {
+    let Some(signature) = signature else {
+        return "missing";
+    };
+    let (Ok(pubkey), Ok(signature), Ok(digest)) = (
+        XOnlyPublicKey::from_slice(&signer.serialize()),
+        schnorr::Signature::from_slice(signature),
+        hex::decode(message_hash),
+    ) else {
+        return "malformed";
+    };
+    let Ok(message) = SecpMessage::from_digest_slice(&digest) else {
+        return "malformed";
+    };
+    match Secp256k1::verification_only().verify_schnorr(&signature, &message, &pubkey) {
+        Ok(()) => "valid",
+        Err(_) => "invalid",
+    }
+}
+
+fn decode_system_instruction(data: &[u8]) -> Result<Value> {
+    let instruction = SystemInstruction::try_from_slice(data)
+        .map_err(|e| anyhow!("invalid system instruction: {}", e))?;
+    let (name, args) = match instruction {
+        SystemInstruction::CreateAccount(utxo) => (
+            "create_account",
+            vec![
+                ("txid", Value::String(hex::encode(utxo.txid()))),
+                ("vout", Value::UInt(utxo.vout() as u128)),
+            ],
+        ),
+        // Never decoded, `try_from_slice` reads its bytes as `WriteBytes`
+        #[allow(deprecated)]
+        SystemInstruction::ExtendBytes(data) => {
+            ("extend_bytes", vec![("data", Value::Bytes(data))])
+        }
+        SystemInstruction::WriteBytes { offset, data } => (
+            "write_bytes",
+            vec![
+                ("offset", Value::UInt(offset as u128)),
+                ("len", Value::UInt(data.len() as u128)),
+                ("data", Value::Bytes(data)),
+            ],
+        ),
+        SystemInstruction::MakeExecutable => ("make_executable", vec![]),
+        SystemInstruction::AssignOwnership(owner) => {
+            ("assign_ownership", vec![("owner", Value::Pubkey(owner))])
+        }
+        SystemInstruction::Truncate(len) => ("truncate", vec![("len", Value::UInt(len as u128))]),
+    };
+    Ok(Value::fields([
+        ("instruction", Value::String(name.to_string())),
+        ("args", Value::fields(args)),
+    ]))
+}
+
+/// Reads Borsh encoded values laid out by the types of an IDL.
+struct IdlReader<'a> {
+    decoder: &'a Decoder,
+    idl: &'a Idl,
+}
+
+impl IdlReader<'_> {
+    fn read(&self, ty: &IdlType, data: &mut &[u8], depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("types are nested too deeply");
+        }
+        let depth = depth + 1;
+
+        Ok(match ty {
+            IdlType::Bool => match take::<1>(data)? {
+                [0] => Value::Bool(false),
+                [1] => Value::Bool(true),
+                [byte] => bail!("invalid bool {}", byte),
+            },
+            IdlType::U8 => Value::UInt(u8::from_le_bytes(take(data)?) as u128),
+            IdlType::U16 => Value::UInt(u16::from_le_bytes(take(data)?) as u128),
+            IdlType::U32 => Value::UInt(u32::from_le_bytes(take(data)?) as u128),
+            IdlType::U64 => Value::UInt(u64::from_le_bytes(take(data)?) as u128),
+            IdlType::U128 => Value::UInt(u128::from_le_bytes(take(data)?)),
+            IdlType::I8 => Value::Int(i8::from_le_bytes(take(data)?) as i128),
+            IdlType::I16 => Value::Int(i16::from_le_bytes(take(data)?) as i128),
+            IdlType::I32 => Value::Int(i32::from_le_bytes(take(data)?) as i128),
+            IdlType::I64 => Value::Int(i64::from_le_bytes(take(data)?) as i128),
+            IdlType::I128 => Value::Int(i128::from_le_bytes(take(data)?)),
+            IdlType::String => {
+                let len = read_len(data)?;
+                let bytes = take_slice(data, len)?;
+                Value::String(String::from_utf8(bytes.to_vec()).context("invalid string")?)
+            }
+            IdlType::Pubkey => Value::Pubkey(Pubkey(take(data)?)),
+            IdlType::Option(ty) => match take::<1>(data)? {
+                [0] => Value::None,
+                [1] => self.read(ty, data, depth)?,
+                [tag] => bail!("invalid option tag {}", tag),
+            },
+            IdlType::Vec(ty) if **ty == IdlType::U8 => {
+                let len = read_len(data)?;
+                self.bytes(take_slice(data, len)?)
+            }
+            IdlType::Vec(ty) => {
+                let len = read_len(data)?;
+                Value::List(self.read_many(ty, len, data, depth)?)
+            }
+            IdlType::Array(ty, len) if **ty == IdlType::U8 => {
+                Value::Bytes(take_slice(data, *len)?.to_vec())
+            }
+            IdlType::Array(ty, len) => Value::List(self.read_many(ty, *len, data, depth)?),
+            IdlType::Tuple(tys) => Value::List(
+                tys.iter()
+                    .map(|ty| self.read(ty, data, depth))
+                    .collect::<Result<_>>()?,
+            ),
+            IdlType::Defined(name) => self.read_defined(self.definition(name)?, data, depth)?,
+        })
+    }
+
+    fn read_many(
+        &self,
+        ty: &IdlType,
+        len: usize,
+        data: &mut &[u8],
+        depth: usize,
+    ) -> Result<Vec<Value>> {
+        // Every element takes at least a byte, except empty tuples and structs
+        // no program sends in bulk
+        if len > data.len() {
+            bail!(
+                "{} elements don't fit in the {} bytes left",
+                len,
+                data.len()
+            );
+        }
+        (0..len).map(|_| self.read(ty, data, depth)).collect()
+    }
+
+    fn read_defined(
+        &self,
+        definition: &IdlTypeDef,
+        data: &mut &[u8],
+        depth: usize,
+    ) -> Result<Value> {
+        match &definition.ty {
+            IdlTypeDefTy::Struct { fields } => self.read_fields(fields, data, depth),
+            IdlTypeDefTy::Enum { variants } => {
+                let [index] = take::<1>(data)?;
+                let variant = variants
+                    .get(index as usize)
+                    .ok_or_else(|| anyhow!("{} has no variant {}", definition.name, index))?;
+                let fields = self.read_fields(&variant.fields, data, depth)?;
+                Ok(match fields {
+                    Value::Fields(fields) if fields.is_empty() => {
+                        Value::String(variant.name.clone())
+                    }
+                    fields => Value::Fields(vec![(variant.name.clone(), fields)]),
+                })
+            }
+        }
+    }
+
+    fn read_fields(&self, fields: &IdlFields, data: &mut &[u8], depth: usize) -> Result<Value> {
+        match fields {
+            IdlFields::Named(fields) => Ok(Value::Fields(
+                fields
+                    .iter()
+                    .map(|field| Ok((field.name.clone(), self.read(&field.ty, data, depth)?)))
+                    .collect::<Result<_>>()?,
+            )),
+            IdlFields::Tuple(tys) => Ok(Value::List(
+                tys.iter()
+                    .map(|ty| self.read(ty, data, depth))
+                    .collect::<Result<_>>()?,
+            )),
+        }
+    }
+
+    fn definition(&self, name: &str) -> Result<&IdlTypeDef> {
+        self.idl
+            .types
+            .iter()
+            .chain(&self.idl.accounts)
+            .chain(&self.idl.events)
+            .find(|definition| definition.name == name)
+            .ok_or_else(|| anyhow!("{} isn't defined in the IDL of {}", name, self.idl.name))
+    }
+
+    /// Bytes, with the Bitcoin transaction they hold if they do
+    fn bytes(&self, bytes: &[u8]) -> Value {
+        match bitcoin::consensus::deserialize::<Transaction>(bytes) {
+            Ok(transaction) => Value::fields([
+                ("hex", Value::Bytes(bytes.to_vec())),
+                (
+                    "bitcoin_transaction",
+                    self.decoder.bitcoin_transaction(&transaction),
+                ),
+            ]),
+            Err(_) => Value::Bytes(bytes.to_vec()),
+        }
+    }
+}
+
+fn take<const N: usize>(data: &mut &[u8]) -> Result<[u8; N]> {
+    Ok(take_slice(data, N)?.try_into().unwrap())
+}
+
+fn take_slice<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if data.len() < len {
+        bail!("data is truncated");
+    }
+    let (taken, rest) = data.split_at(len);
+    *data = rest;
+    Ok(taken)
+}
+
+fn read_len(data: &mut &[u8]) -> Result<usize> {
+    Ok(u32::from_le_bytes(take(data)?) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::absolute::LockTime;
+    use bitcoin::key::UntweakedKeypair;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, TxIn, TxOut, Witness};
+    use sdk::arch_program::account::AccountMeta;
+    use sdk::arch_program::idl::{IdlAccount, IdlField, IdlInstruction, IdlVariant};
+    use sdk::arch_program::input_to_sign::InputToSign;
+    use sdk::arch_program::instruction::Instruction;
+    use sdk::arch_program::transaction_to_sign::TransactionToSign as ProgramTransactionToSign;
+    use sdk::signature::Signature;
+
+    use super::*;
+    use crate::helper::sign_transaction;
+
+    fn bitcoin_transaction() -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(3000),
+                script_pubkey: ScriptBuf::new_op_return([1, 2, 3]),
+            }],
+        }
+    }
+
+    fn idl() -> Idl {
+        let mut idl = Idl::new("pool", "0.1.0");
+        idl.instructions.push(IdlInstruction {
+            name: "swap".to_string(),
+            accounts: vec![
+                IdlAccount {
+                    name: "pool".to_string(),
+                    is_signer: false,
+                    is_writable: true,
+                },
+                IdlAccount {
+                    name: "trader".to_string(),
+                    is_signer: true,
+                    is_writable: false,
+                },
+            ],
+            args: vec![
+                IdlField::new("amount", IdlType::U64),
+                IdlField::new("side", IdlType::Defined("Side".to_string())),
+                IdlField::new("memo", IdlType::Option(Box::new(IdlType::String))),
+                IdlField::new("tx_hex", IdlType::Vec(Box::new(IdlType::U8))),
+            ],
+        });
+        idl.types.push(IdlTypeDef {
+            name: "Side".to_string(),
+            discriminator: None,
+            ty: IdlTypeDefTy::Enum {
+                variants: vec![
+                    IdlVariant {
+                        name: "Buy".to_string(),
+                        fields: IdlFields::Named(vec![]),
+                    },
+                    IdlVariant {
+                        name: "Sell".to_string(),
+                        fields: IdlFields::Named(vec![IdlField::new("limit", IdlType::I64)]),
+                    },
+                ],
+            },
+        });
+        idl
+    }
+
+    fn field<'a>(value: &'a Value, path: &[&str]) -> &'a Value {
+        path.iter().fold(value, |value, name| match value {
+            Value::Fields(fields) => {
+                &fields
+                    .iter()
+                    .find(|(field, _)| field == name)
+                    .unwrap_or_else(|| panic!("no field {}", name))
+                    .1
+            }
+            Value::List(values) => &values[name.parse::<usize>().unwrap()],
+            value => panic!("{:?} has no field {}", value, name),
+        })
+    }
+
+    #[test]
+    fn test_decode_transaction() {
+        let secp = Secp256k1::new();
+        let keypair = UntweakedKeypair::from_secret_key(
+            &secp,
+            &bitcoin::secp256k1::SecretKey::from_slice(&[3; 32]).unwrap(),
+        );
+        let signer = Pubkey::from_slice(&XOnlyPublicKey::from_keypair(&keypair).0.serialize());
+        let (program_id, pool) = (Pubkey::from_slice(&[9; 32]), Pubkey::from_slice(&[5; 32]));
+
+        let mut data = vec![0];
+        data.extend(1500u64.to_le_bytes());
+        data.push(1);
+        data.extend((-7i64).to_le_bytes());
+        data.push(0);
+        let tx_bytes = bitcoin::consensus::serialize(&bitcoin_transaction());
+        data.extend((tx_bytes.len() as u32).to_le_bytes());
+        data.extend(&tx_bytes);
+
+        let transaction = sign_transaction(
+            vec![
+                SystemInstruction::new_create_account_instruction([1; 32], 2, signer),
+                Instruction {
+                    program_id,
+                    accounts: vec![
+                        AccountMeta {
+                            pubkey: pool,
+                            is_signer: false,
+                            is_writable: true,
+                        },
+                        AccountMeta {
+                            pubkey: signer,
+                            is_signer: true,
+                            is_writable: false,
+                        },
+                    ],
+                    data,
+                },
+            ],
+            vec![keypair],
+        );
+        let decoder = Decoder::new(Network::Regtest).with_idl(program_id, idl());
+        let decoded = decoder
+            .decode_hex(&hex::encode(transaction.serialize()))
+            .unwrap();
+
+        assert_eq!(
+            field(&decoded, &["signers", "0", "signature_status"]),
+            &Value::String("valid".to_string())
+        );
+        assert_eq!(
+            field(&decoded, &["instructions", "0", "decoded", "instruction"]),
+            &Value::String("create_account".to_string())
+        );
+        assert_eq!(
+            field(&decoded, &["instructions", "0", "decoded", "args", "vout"]),
+            &Value::UInt(2)
+        );
+
+        let swap = field(&decoded, &["instructions", "1"]);
+        assert_eq!(
+            field(swap, &["program"]),
+            &Value::String("pool".to_string())
+        );
+        assert_eq!(
+            field(swap, &["accounts", "1", "name"]),
+            &Value::String("trader".to_string())
+        );
+        let args = field(swap, &["decoded", "args"]);
+        assert_eq!(field(args, &["amount"]), &Value::UInt(1500));
+        assert_eq!(field(args, &["side", "Sell", "limit"]), &Value::Int(-7));
+        assert_eq!(field(args, &["memo"]), &Value::None);
+        assert_eq!(
+            field(
+                args,
+                &["tx_hex", "bitcoin_transaction", "outputs", "0", "value"]
+            ),
+            &Value::UInt(3000)
+        );
+
+        // JSON keeps the order of the fields
+        let json = serde_json::to_string(&decoded).unwrap();
+        assert!(json.starts_with(&format!(r#"{{"txid":"{}","version":0"#, transaction.txid())));
+        assert!(json.contains(r#""args":{"amount":1500,"side":{"Sell":{"limit":-7}},"memo":null"#));
+
+        let text = decoded.to_string();
+        assert!(text.contains("\n    signature_status: valid\n"));
+        assert!(text.contains("\n      instruction: swap\n"));
+        assert!(text.contains("\n        side:\n          Sell:\n            limit: -7\n"));
+
+        // A tampered signature, missing one and undecodable data
+        let mut tampered = transaction.clone();
+        tampered.signatures[0].0[0] ^= 1;
+        tampered.message.signers.push(signer);
+        tampered.message.instructions[1].data = vec![0, 1];
+        let decoded = decoder.decode_transaction(&tampered);
+        assert_eq!(
+            field(&decoded, &["signers", "0", "signature_status"]),
+            &Value::String("invalid".to_string())
+        );
+        assert_eq!(
+            field(&decoded, &["signers", "1", "signature_status"]),
+            &Value::String("missing".to_string())
+        );
+        assert_eq!(
+            field(&decoded, &["instructions", "1", "decoded", "error"]),
+            &Value::String("data is truncated".to_string())
+        );
+
+        tampered.signatures.push(Signature(vec![0; 64]));
+        tampered.signatures.push(Signature(vec![0; 63]));
+        let decoded = decoder.decode_transaction(&tampered);
+        assert_eq!(
+            field(&decoded, &["signers", "1", "signature_status"]),
+            &Value::String("invalid".to_string())
+        );
+        assert_eq!(
+            field(&decoded, &["signers", "2", "signature_status"]),
+            &Value::String("no signer".to_string())
+        );
+
+        tampered.signatures[1] = Signature(vec![0; 63]);
+        let decoded = decoder.decode_transaction(&tampered);
+        assert_eq!(
+            field(&decoded, &["signers", "1", "signature_status"]),
+            &Value::String("malformed".to_string())
+        );
+    }
+
+    #[test]
+    fn test_decode_transaction_to_sign() {
+        let tx_bytes = bitcoin::consensus::serialize(&bitcoin_transaction());
+        let inputs_to_sign = [
+            InputToSign {
+                index: 0,
+                signer: Pubkey::from_slice(&[4; 32]),
+            },
+            InputToSign {
+                index: 1,
+                signer: Pubkey::from_slice(&[4; 32]),
+            },
+        ];
+        let serialized = ProgramTransactionToSign {
+            tx_bytes: &tx_bytes,
+            inputs_to_sign: &inputs_to_sign,
+        }
+        .serialise();
+
+        let decoded = Decoder::new(Network::Regtest)
+            .decode_hex(&hex::encode(serialized))
+            .unwrap();
+        assert_eq!(
+            field(&decoded, &["bitcoin_transaction", "txid"]),
+            &Value::String(bitcoin_transaction().txid().to_string())
+        );
+        assert_eq!(
+            field(
+                &decoded,
+                &["bitcoin_transaction", "inputs", "0", "previous_output"]
+            ),
+            &Value::String(OutPoint::null().to_string())
+        );
+        assert_eq!(
+            field(&decoded, &["inputs_to_sign", "1", "error"]),
+            &Value::String("the transaction has no such input".to_string())
+        );
+
+        assert!(Decoder::new(Network::Regtest).decode_hex("0102").is_err());
+        assert!(Decoder::new(Network::Regtest).decode_hex("zz").is_err());
+    }
+}
@@ -0,0 +1,851 @@
+//! Resumable and verified deployment of program ELFs.
+//!
+//! The ELF is written to the program account in chunks of
+//! [`SystemInstruction::WriteBytes`]. Each chunk is tracked with its offset
+//! and status so only the failed ones are resent, and chunks already holding
+//! the right bytes are skipped, which turns a redeploy into a write of the
+//! changed ranges. Once every chunk is confirmed the account data is read back
+//! and compared with the ELF by hash before the program is made executable.
+//!
+//! Nodes drop a transaction whose txid they have already seen, so a chunk
+//! resent as the same bytes would never be applied again. Each attempt signs
+//! with its own auxiliary randomness instead, which makes every resent chunk
+//! a new transaction. Writing a chunk twice, when an attempt thought failed
+//! lands after all, leaves the same bytes at the same offset.
+//!
+//! Shrinking the account to a shorter program takes a
+//! [`SystemInstruction::Truncate`], which nodes without truncation fail, so it
+//! is only sent after [`ProgramDeployer::with_truncate`]. Otherwise a program
+//! shorter than the deployed one is written to the fresh account given with
+//! [`ProgramDeployer::with_fallback_account`], and without one the deployment
+//! fails before sending anything.
+//!
+//! The ELF is checked with [`validate_program`] first so a program the
+//! runtime can't load is rejected before any chunk is paid for.
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::key::UntweakedKeypair;
+use bitcoin::secp256k1::{self, Secp256k1};
+use bitcoin::XOnlyPublicKey;
+use log::{debug, info, warn};
+
+use sdk::arch_program::instruction::Instruction;
+use sdk::arch_program::message::Message;
+use sdk::arch_program::pubkey::Pubkey;
+use sdk::arch_program::system_instruction::SystemInstruction;
+use sdk::elf::validate_program;
+use sdk::processed_transaction::Status;
+use sdk::runtime_transaction::{RuntimeTransaction, RUNTIME_TX_SIZE_LIMIT};
+use sdk::signature::Signature;
+
+use crate::confirmation::ConfirmationTracker;
+use crate::constants::SEND_TRANSACTIONS;
+use crate::helper::{post_data, process_result, read_account_info, AccountInfoResult};
+
+/// Number of times failed chunks are resent by default
+pub const DEFAULT_MAX_RETRIES: usize = 3;
+
+/// The node calls needed to deploy a program.
+pub trait DeployNode {
+    fn read_account(&self, pubkey: Pubkey) -> Result<AccountInfoResult>;
+
+    /// Send the transactions in one batch, returning their ids in order.
+    fn send_transactions(&self, transactions: Vec<RuntimeTransaction>) -> Result<Vec<String>>;
+
+    /// Wait for the transaction and fail unless it was processed.
+    fn confirm(&self, txid: &str) -> Result<()>;
+
+    /// [`DeployNode::confirm`] every transaction, with the results in order.
+    fn confirm_all(&self, txids: &[String]) -> Vec<Result<()>> {
+        txids.iter().map(|txid| self.confirm(txid)).collect()
+    }
+}
+
+/// [`DeployNode`] talking to a node over RPC.
+pub struct RpcNode {
+    url: String,
+}
+
+impl RpcNode {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+        }
+    }
+}
+
+impl DeployNode for RpcNode {
+    fn read_account(&self, pubkey: Pubkey) -> Result<AccountInfoResult> {
+        read_account_info(&self.url, pubkey)
+    }
+
+    fn send_transactions(&self, transactions: Vec<RuntimeTransaction>) -> Result<Vec<String>> {
+        process_result(post_data(&self.url, SEND_TRANSACTIONS, transactions))?
+            .as_array()
+            .ok_or_else(|| anyhow!("send_transactions didn't return an array"))?
+            .iter()
+            .map(|txid| {
+                txid.as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| anyhow!("send_transactions returned a non string txid"))
+            })
+            .collect()
+    }
+
+    fn confirm(&self, txid: &str) -> Result<()> {
+        ConfirmationTracker::rpc(&self.url).wait(txid, Status::Processed)?;
+        Ok(())
+    }
+
+    fn confirm_all(&self, txids: &[String]) -> Vec<Result<()>> {
+        ConfirmationTracker::rpc(&self.url)
+            .wait_all(txids, Status::Processed)
+            .into_iter()
+            .map(|result| result.map(|_| ()))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChunkStatus {
+    /// The account already holds these bytes
+    Unchanged,
+    Pending,
+    Sent(String),
+    Confirmed(String),
+    Failed(String),
+}
+
+/// A range of the ELF written by one transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    pub offset: u32,
+    pub len: u32,
+    pub status: ChunkStatus,
+}
+
+impl Chunk {
+    fn range(&self) -> std::ops::Range<usize> {
+        self.offset as usize..(self.offset + self.len) as usize
+    }
+
+    fn needs_write(&self) -> bool {
+        matches!(self.status, ChunkStatus::Pending | ChunkStatus::Failed(_))
+    }
+}
+
+/// Progress reported to the callback set with [`ProgramDeployer::on_progress`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeployProgress {
+    Planned { total: usize, changed: usize },
+    ChunkSent { offset: u32, txid: String },
+    ChunkConfirmed { offset: u32 },
+    ChunkFailed { offset: u32, error: String },
+    Retrying { attempt: usize, chunks: usize },
+    FallbackAccount { program_id: Pubkey },
+    Truncated { len: u32 },
+    Verified { hash: String },
+    MadeExecutable,
+}
+
+/// Outcome of a successful deployment.
+#[derive(Clone, Debug)]
+pub struct DeployReport {
+    /// Account the program was deployed to, the fallback one if it was used
+    pub program_id: Pubkey,
+    pub chunks: Vec<Chunk>,
+    /// Ids of every transaction sent, including failed ones
+    pub txids: Vec<String>,
+    /// Number of ELF bytes written, counting retries
+    pub bytes_written: usize,
+    /// sha256 of the deployed program
+    pub hash: String,
+}
+
+/// Largest chunk of data a single `WriteBytes` transaction can carry.
+pub fn write_bytes_max_len() -> usize {
+    let message = Message {
+        signers: vec![Pubkey::system_program()],
+        instructions: vec![SystemInstruction::new_write_bytes_instruction(
+            0,
+            vec![],
+            Pubkey::system_program(),
+        )],
+    };
+
+    RUNTIME_TX_SIZE_LIMIT
+        - RuntimeTransaction {
+            version: 0,
+            signatures: vec![Signature([0_u8; 64].to_vec())],
+            message,
+        }
+        .serialize()
+        .len()
+}
+
+type ProgressCallback<'a> = Box<dyn FnMut(&DeployProgress) + 'a>;
+
+pub struct ProgramDeployer<'a, N: DeployNode> {
+    node: N,
+    keypair: UntweakedKeypair,
+    pubkey: Pubkey,
+    chunk_size: usize,
+    max_retries: usize,
+    validate: bool,
+    truncate: bool,
+    fallback: Option<UntweakedKeypair>,
+    on_progress: Option<ProgressCallback<'a>>,
+    /// Auxiliary randomness of the signatures, varied by `attempt`
+    aux_rand: [u8; 32],
+    attempt: usize,
+}
+
+impl<'a, N: DeployNode> ProgramDeployer<'a, N> {
+    pub fn new(node: N, program_keypair: UntweakedKeypair) -> Self {
+        Self {
+            node,
+            keypair: program_keypair,
+            pubkey: pubkey_of(&program_keypair),
+            chunk_size: write_bytes_max_len(),
+            max_retries: DEFAULT_MAX_RETRIES,
+            validate: true,
+            truncate: false,
+            fallback: None,
+            on_progress: None,
+            aux_rand: secp256k1::rand::random(),
+            attempt: 0,
+        }
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.clamp(1, write_bytes_max_len());
+        self
+    }
+
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Deploy the ELF without checking it can be loaded first.
+    pub fn without_validation(mut self) -> Self {
+        self.validate = false;
+        self
+    }
+
+    /// Shrink the program account with [`SystemInstruction::Truncate`], for
+    /// nodes known to support it.
+    pub fn with_truncate(mut self) -> Self {
+        self.truncate = true;
+        self
+    }
+
+    /// Deploy to the account of `keypair`, which must already exist, when the
+    /// program account holds a longer program and can't be truncated.
+    pub fn with_fallback_account(mut self, keypair: UntweakedKeypair) -> Self {
+        self.fallback = Some(keypair);
+        self
+    }
+
+    pub fn on_progress(mut self, callback: impl FnMut(&DeployProgress) + 'a) -> Self {
+        self.on_progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Write `elf` to the program account, which must already exist, verify
+    /// it and make the program executable.
+    pub fn deploy(&mut self, elf: &[u8]) -> Result<DeployReport> {
+        if self.validate {
+            let report = validate_program(elf)?;
+            for warning in &report.warnings {
+                warn!("{}", warning);
+            }
+            if !report.is_valid() {
+                let errors = report
+                    .errors
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+                bail!("invalid program ELF: {}", errors.join(", "));
+            }
+        }
+
+        let mut account = self
+            .node
+            .read_account(self.pubkey)
+            .context("the program account should be created before deploying")?;
+        if account.data.len() > elf.len() && !self.truncate {
+            let Some(keypair) = self.fallback.take() else {
+                bail!(
+                    "the program account holds {} bytes, more than the {} of the ELF, and truncating it \
+                     isn't enabled: deploy to a fresh account or enable truncation for nodes supporting it",
+                    account.data.len(),
+                    elf.len()
+                );
+            };
+            self.keypair = keypair;
+            self.pubkey = pubkey_of(&keypair);
+            account = self
+                .node
+                .read_account(self.pubkey)
+                .context("the fallback account should be created before deploying")?;
+            if account.data.len() > elf.len() {
+                bail!(
+                    "the fallback account holds {} bytes, more than the {} of the ELF",
+                    account.data.len(),
+                    elf.len()
+                );
+            }
+            self.report(DeployProgress::FallbackAccount {
+                program_id: self.pubkey,
+            });
+        }
+
+        let mut chunks = elf
+            .chunks(self.chunk_size)
+            .enumerate()
+            .map(|(i, bytes)| Chunk {
+                offset: (i * self.chunk_size) as u32,
+                len: bytes.len() as u32,
+                status: ChunkStatus::Pending,
+            })
+            .collect::<Vec<_>>();
+        mark_unchanged(&mut chunks, &account.data, elf);
+        self.report(DeployProgress::Planned {
+            total: chunks.len(),
+            changed: chunks.iter().filter(|chunk| chunk.needs_write()).count(),
+        });
+
+        let hash = sha256::digest(elf);
+        let mut txids = vec![];
+        let mut bytes_written = 0;
+        let mut deployed = None;
+        let mut truncate_error = None;
+
+        for attempt in 0..=self.max_retries {
+            self.attempt = attempt;
+            let pending = chunks.iter().filter(|chunk| chunk.needs_write()).count();
+            if attempt > 0 {
+                self.report(DeployProgress::Retrying {
+                    attempt,
+                    chunks: pending,
+                });
+            }
+
+            if pending > 0 {
+                bytes_written += self.write_chunks(elf, &mut chunks, &mut txids);
+                if chunks.iter().any(Chunk::needs_write) {
+                    continue;
+                }
+            }
+
+            let mut account = self.node.read_account(self.pubkey)?;
+            if account.data.len() > elf.len() {
+                let len = elf.len() as u32;
+                let txid = self.send_and_confirm(SystemInstruction::new_truncate_instruction(
+                    len,
+                    self.pubkey,
+                ));
+                match txid {
+                    Ok(txid) => {
+                        txids.push(txid);
+                        self.report(DeployProgress::Truncated { len });
+                    }
+                    Err(e) => {
+                        debug!("Failed to truncate program account: {:?}", e);
+                        truncate_error = Some(e);
+                        continue;
+                    }
+                }
+                account = self.node.read_account(self.pubkey)?;
+            }
+
+            if sha256::digest(account.data.as_slice()) == hash {
+                self.report(DeployProgress::Verified { hash: hash.clone() });
+                deployed = Some(account);
+                break;
+            }
+
+            // Chunks were confirmed but the account doesn't hold the ELF,
+            // write the ranges that differ again.
+            for chunk in &mut chunks {
+                if account.data.get(chunk.range()) != Some(&elf[chunk.range()]) {
+                    chunk.status = ChunkStatus::Failed("deployed bytes differ".to_string());
+                    self.report(DeployProgress::ChunkFailed {
+                        offset: chunk.offset,
+                        error: "deployed bytes differ".to_string(),
+                    });
+                }
+            }
+        }
+
+        let Some(account) = deployed else {
+            if let Some(e) = truncate_error {
+                return Err(e.context(
+                    "failed to shrink the program account, the node may not support truncating accounts",
+                ));
+            }
+            bail!(
+                "program deployment wasn't verified after {} retries",
+                self.max_retries
+            );
+        };
+
+        if !account.is_executable {
+            let txid = self
+                .send_and_confirm(SystemInstruction::new_make_executable_instruction(
+                    self.pubkey,
+                ))
+                .context("failed to make the program executable")?;
+            txids.push(txid);
+            self.report(DeployProgress::MadeExecutable);
+        }
+
+        info!(
+            "Deployed program {} ({} bytes written, sha256 {})",
+            hex::encode(self.pubkey.serialize()),
+            bytes_written,
+            hash
+        );
+
+        Ok(DeployReport {
+            program_id: self.pubkey,
+            chunks,
+            txids,
+            bytes_written,
+            hash,
+        })
+    }
+
+    /// Send the chunks needing a write in one batch and confirm them,
+    /// returning the number of bytes sent.
+    fn write_chunks(&mut self, elf: &[u8], chunks: &mut [Chunk], txids: &mut Vec<String>) -> usize {
+        let mut pending = chunks
+            .iter_mut()
+            .filter(|chunk| chunk.needs_write())
+            .collect::<Vec<_>>();
+
+        let transactions = pending
+            .iter()
+            .map(|chunk| {
+                self.sign(SystemInstruction::new_write_bytes_instruction(
+                    chunk.offset,
+                    elf[chunk.range()].to_vec(),
+                    self.pubkey,
+                ))
+            })
+            .collect::<Vec<_>>();
+        let bytes = pending.iter().map(|chunk| chunk.len as usize).sum();
+
+        let sent = self.node.send_transactions(transactions).and_then(|sent| {
+            match sent.len() == pending.len() {
+                true => Ok(sent),
+                false => Err(anyhow!(
+                    "node returned {} txids for {} transactions",
+                    sent.len(),
+                    pending.len()
+                )),
+            }
+        });
+        let sent = match sent {
+            Ok(sent) => sent,
+            Err(e) => {
+                for chunk in pending {
+                    chunk.status = ChunkStatus::Failed(e.to_string());
+                    self.report(DeployProgress::ChunkFailed {
+                        offset: chunk.offset,
+                        error: e.to_string(),
+                    });
+                }
+                return bytes;
+            }
+        };
+
+        for (chunk, txid) in pending.iter_mut().zip(&sent) {
+            chunk.status = ChunkStatus::Sent(txid.clone());
+            self.report(DeployProgress::ChunkSent {
+                offset: chunk.offset,
+                txid: txid.clone(),
+            });
+        }
+        txids.extend(sent.iter().cloned());
+
+        let confirmed = self.node.confirm_all(&sent);
+        for ((chunk, txid), result) in pending.into_iter().zip(sent).zip(confirmed) {
+            match result {
+                Ok(()) => {
+                    chunk.status = ChunkStatus::Confirmed(txid);
+                    self.report(DeployProgress::ChunkConfirmed {
+                        offset: chunk.offset,
+                    });
+                }
+                Err(e) => {
+                    chunk.status = ChunkStatus::Failed(e.to_string());
+                    self.report(DeployProgress::ChunkFailed {
+                        offset: chunk.offset,
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+
+        bytes
+    }
+
+    fn send_and_confirm(&self, instruction: Instruction) -> Result<String> {
+        let txid = self
+            .node
+            .send_transactions(vec![self.sign(instruction)])?
+            .pop()
+            .ok_or_else(|| anyhow!("node didn't return a txid"))?;
+        self.node.confirm(&txid)?;
+        Ok(txid)
+    }
+
+    fn sign(&self, instruction: Instruction) -> RuntimeTransaction {
+        let message = Message {
+            signers: vec![self.pubkey],
+            instructions: vec![instruction],
+        };
+        let digest_slice = hex::decode(message.hash()).expect("hashed message should be decodable");
+        let sig_message = secp256k1::Message::from_digest_slice(&digest_slice)
+            .expect("signed message should be gotten from digest slice");
+        let secp = Secp256k1::new();
+        let mut aux_rand = self.aux_rand;
+        for (byte, attempt) in aux_rand.iter_mut().zip(self.attempt.to_le_bytes()) {
+            *byte ^= attempt;
+        }
+
+        RuntimeTransaction {
+            version: 0,
+            signatures: vec![Signature(
+                secp.sign_schnorr_with_aux_rand(&sig_message, &self.keypair, &aux_rand)
+                    .serialize()
+                    .to_vec(),
+            )],
+            message,
+        }
+    }
+
+    fn report(&mut self, progress: DeployProgress) {
+        debug!("{:?}", progress);
+        if let Some(callback) = self.on_progress.as_mut() {
+            callback(&progress);
+        }
+    }
+}
+
+fn pubkey_of(keypair: &UntweakedKeypair) -> Pubkey {
+    Pubkey::from_slice(&XOnlyPublicKey::from_keypair(keypair).0.serialize())
+}
+
+/// Mark the chunks the account data already matches as unchanged.
+fn mark_unchanged(chunks: &mut [Chunk], data: &[u8], elf: &[u8]) {
+    for chunk in chunks {
+        if data.get(chunk.range()) == Some(&elf[chunk.range()]) {
+            chunk.status = ChunkStatus::Unchanged;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::{HashMap, HashSet};
+
+    use anyhow::{anyhow, Result};
+    use bitcoin::key::UntweakedKeypair;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use sdk::arch_program::pubkey::Pubkey;
+    use sdk::arch_program::system_instruction::SystemInstruction;
+    use sdk::runtime_transaction::RuntimeTransaction;
+
+    use super::{pubkey_of, ChunkStatus, DeployNode, DeployProgress, ProgramDeployer};
+    use crate::helper::AccountInfoResult;
+
+    #[derive(Clone, Default)]
+    struct MockAccount {
+        data: Vec<u8>,
+        is_executable: bool,
+    }
+
+    /// Applies system instructions to the accounts of their signers, failing
+    /// the first write at each offset listed in `fail_offsets`, and every
+    /// truncation when `without_truncate`. Like a node, it ignores a
+    /// transaction whose txid it has seen, which keeps the status of the
+    /// first.
+    #[derive(Default)]
+    struct MockNode {
+        accounts: RefCell<HashMap<Pubkey, MockAccount>>,
+        fail_offsets: RefCell<HashSet<u32>>,
+        without_truncate: bool,
+        sent: RefCell<Vec<SystemInstruction>>,
+        failed: RefCell<HashSet<String>>,
+        seen: RefCell<HashSet<String>>,
+    }
+
+    impl DeployNode for &MockNode {
+        fn read_account(&self, pubkey: Pubkey) -> Result<AccountInfoResult> {
+            let account = self.account(pubkey);
+            Ok(AccountInfoResult {
+                owner: pubkey,
+                data: account.data,
+                utxo: String::new(),
+                is_executable: account.is_executable,
+            })
+        }
+
+        fn send_transactions(&self, transactions: Vec<RuntimeTransaction>) -> Result<Vec<String>> {
+            Ok(transactions
+                .into_iter()
+                .map(|transaction| {
+                    let txid = transaction.txid();
+                    let instruction = SystemInstruction::try_from_slice(
+                        &transaction.message.instructions[0].data,
+                    )
+                    .expect("a system instruction");
+                    if !self.seen.borrow_mut().insert(txid.clone()) {
+                        return txid;
+                    }
+                    self.sent.borrow_mut().push(instruction.clone());
+
+                    let mut accounts = self.accounts.borrow_mut();
+                    let account = accounts.entry(transaction.message.signers[0]).or_default();
+                    match instruction {
+                        SystemInstruction::WriteBytes { offset, .. }
+                            if self.fail_offsets.borrow_mut().remove(&offset) =>
+                        {
+                            self.failed.borrow_mut().insert(txid.clone());
+                        }
+                        SystemInstruction::Truncate(_) if self.without_truncate => {
+                            self.failed.borrow_mut().insert(txid.clone());
+                        }
+                        SystemInstruction::WriteBytes { offset, data } => {
+                            let end = offset as usize + data.len();
+                            if account.data.len() < end {
+                                account.data.resize(end, 0);
+                            }
+                            account.data[offset as usize..end].copy_from_slice(&data);
+                        }
+                        SystemInstruction::Truncate(len) => account.data.truncate(len as usize),
+                        SystemInstruction::MakeExecutable => account.is_executable = true,
+                        _ => {}
+                    }
+                    txid
+                })
+                .collect())
+        }
+
+        fn confirm(&self, txid: &str) -> Result<()> {
+            match self.failed.borrow().contains(txid) {
+                true => Err(anyhow!("transaction failed")),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl MockNode {
+        fn account(&self, pubkey: Pubkey) -> MockAccount {
+            self.accounts
+                .borrow()
+                .get(&pubkey)
+                .cloned()
+                .unwrap_or_default()
+        }
+
+        fn insert(&self, keypair: &UntweakedKeypair, data: Vec<u8>, is_executable: bool) {
+            self.accounts.borrow_mut().insert(
+                pubkey_of(keypair),
+                MockAccount {
+                    data,
+                    is_executable,
+                },
+            );
+        }
+    }
+
+    fn keypair() -> UntweakedKeypair {
+        keypair_from(3)
+    }
+
+    fn keypair_from(byte: u8) -> UntweakedKeypair {
+        let secret_key = SecretKey::from_slice(&[byte; 32]).unwrap();
+        UntweakedKeypair::from_secret_key(&Secp256k1::new(), &secret_key)
+    }
+
+    fn program(node: &MockNode) -> MockAccount {
+        node.account(pubkey_of(&keypair()))
+    }
+
+    fn writes(node: &MockNode) -> Vec<u32> {
+        node.sent
+            .borrow()
+            .iter()
+            .filter_map(|instruction| match instruction {
+                SystemInstruction::WriteBytes { offset, .. } => Some(*offset),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_retries_failed_chunks_only() {
+        let node = MockNode::default();
+        node.fail_offsets.borrow_mut().insert(100);
+        let elf = (0..250).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut progress = vec![];
+        let report = ProgramDeployer::new(&node, keypair())
+            .without_validation()
+            .with_chunk_size(100)
+            .on_progress(|event| progress.push(event.clone()))
+            .deploy(&elf)
+            .unwrap();
+
+        assert_eq!(program(&node).data, elf);
+        assert!(program(&node).is_executable);
+        assert_eq!(writes(&node), vec![0, 100, 200, 100]);
+        // The resent chunk is a new transaction rather than one the node
+        // ignores
+        let txids = report.txids.iter().collect::<HashSet<_>>();
+        assert_eq!(txids.len(), report.txids.len());
+        assert_eq!(report.bytes_written, 350);
+        assert!(report
+            .chunks
+            .iter()
+            .all(|chunk| matches!(chunk.status, ChunkStatus::Confirmed(_))));
+        assert_eq!(report.hash, sha256::digest(elf.as_slice()));
+
+        assert_eq!(
+            progress[0],
+            DeployProgress::Planned {
+                total: 3,
+                changed: 3
+            }
+        );
+        assert!(progress.contains(&DeployProgress::Retrying {
+            attempt: 1,
+            chunks: 1
+        }));
+        assert_eq!(progress.last(), Some(&DeployProgress::MadeExecutable));
+    }
+
+    #[test]
+    fn test_redeploy_writes_changed_ranges() {
+        let node = MockNode::default();
+        node.insert(&keypair(), vec![1; 300], true);
+
+        let mut elf = vec![1; 250];
+        elf[150] = 2;
+
+        let report = ProgramDeployer::new(&node, keypair())
+            .without_validation()
+            .with_chunk_size(100)
+            .with_truncate()
+            .deploy(&elf)
+            .unwrap();
+
+        assert_eq!(program(&node).data, elf);
+        assert_eq!(writes(&node), vec![100]);
+        assert_eq!(report.chunks[0].status, ChunkStatus::Unchanged);
+        assert_eq!(report.chunks[2].status, ChunkStatus::Unchanged);
+        assert!(node
+            .sent
+            .borrow()
+            .contains(&SystemInstruction::Truncate(250)));
+        assert!(!node
+            .sent
+            .borrow()
+            .contains(&SystemInstruction::MakeExecutable));
+    }
+
+    #[test]
+    fn test_redeploy_shorter_without_truncate() {
+        let node = MockNode::default();
+        node.insert(&keypair(), vec![1; 300], true);
+
+        let error = ProgramDeployer::new(&node, keypair())
+            .without_validation()
+            .deploy(&[1; 250])
+            .unwrap_err();
+
+        assert!(error.to_string().contains("deploy to a fresh account"));
+        assert!(node.sent.borrow().is_empty());
+        assert_eq!(program(&node).data.len(), 300);
+    }
+
+    #[test]
+    fn test_redeploy_shorter_to_fallback_account() {
+        let node = MockNode::default();
+        node.insert(&keypair(), vec![1; 300], true);
+        let fallback = keypair_from(4);
+        node.insert(&fallback, vec![], false);
+
+        let mut progress = vec![];
+        let report = ProgramDeployer::new(&node, keypair())
+            .without_validation()
+            .with_chunk_size(100)
+            .with_fallback_account(fallback)
+            .on_progress(|event| progress.push(event.clone()))
+            .deploy(&[2; 250])
+            .unwrap();
+
+        assert_eq!(report.program_id, pubkey_of(&fallback));
+        assert_eq!(
+            progress[0],
+            DeployProgress::FallbackAccount {
+                program_id: pubkey_of(&fallback),
+            }
+        );
+        assert_eq!(node.account(pubkey_of(&fallback)).data, vec![2; 250]);
+        assert!(node.account(pubkey_of(&fallback)).is_executable);
+        assert_eq!(program(&node).data, vec![1; 300]);
+        assert!(!node
+            .sent
+            .borrow()
+            .iter()
+            .any(|instruction| matches!(instruction, SystemInstruction::Truncate(_))));
+    }
+
+    #[test]
+    fn test_truncate_rejected_by_node() {
+        let node = MockNode {
+            without_truncate: true,
+            ..MockNode::default()
+        };
+        node.insert(&keypair(), vec![1; 300], true);
+
+        let error = ProgramDeployer::new(&node, keypair())
+            .without_validation()
+            .with_max_retries(1)
+            .with_truncate()
+            .deploy(&[1; 250])
+            .unwrap_err();
+
+        assert!(error.to_string().contains("shrink the program account"));
+        assert_eq!(program(&node).data.len(), 300);
+    }
+
+    #[test]
+    fn test_gives_up_after_max_retries() {
+        let node = MockNode::default();
+        node.fail_offsets.borrow_mut().insert(0);
+
+        let result = ProgramDeployer::new(&node, keypair())
+            .without_validation()
+            .with_max_retries(0)
+            .deploy(&[1, 2, 3]);
+
+        assert!(result.is_err());
+        assert!(!program(&node).is_executable);
+    }
+
+    #[test]
+    fn test_rejects_invalid_elf() {
+        let node = MockNode::default();
+
+        let result = ProgramDeployer::new(&node, keypair()).deploy(b"not an elf");
+
+        assert!(result.is_err());
+        assert!(node.sent.borrow().is_empty());
+    }
+}
@@ -0,0 +1,516 @@
+//! Fixture files of recorded JSON-RPC sessions, see [`crate::transport`].
+//!
+//! A fixture holds one exchange per line, as JSON: the URL, the request and
+//! either the response or the error the request failed with. Credentials
+//! aren't recorded. Lines can be edited, removed or added by hand, as long as
+//! each stays a single JSON object.
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::transport::{Call, ConnectError, Transport};
+
+/// What normalized params are replaced with
+pub const PLACEHOLDER: &str = "<normalized>";
+
+/// A request and what came back.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    pub url: String,
+    pub request: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+    /// Why the request went unanswered, when it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Whether the error is a [`ConnectError`], the request never delivered
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub not_connected: bool,
+}
+
+/// [`Transport`] recording every exchange of another to a fixture file.
+pub struct Recorder<T> {
+    inner: T,
+    file: Mutex<File>,
+}
+
+impl<T: Transport> Recorder<T> {
+    /// Records the exchanges of `inner` to the file at `path`, replacing it.
+    pub fn create(path: impl AsRef<Path>, inner: T) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("Unable to create fixture {}", path.display()))?;
+        Ok(Self {
+            inner,
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl<T: Transport> Transport for Recorder<T> {
+    fn call(&self, call: &Call) -> Result<Value> {
+        let result = self.inner.call(call);
+        let exchange = Exchange {
+            url: call.url.to_string(),
+            request: call.body.clone(),
+            response: result.as_ref().ok().cloned(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            not_connected: result
+                .as_ref()
+                .is_err_and(|e| e.downcast_ref::<ConnectError>().is_some()),
+        };
+
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", serde_json::to_string(&exchange)?)
+            .and_then(|_| file.flush())
+            .context("Unable to record the exchange")?;
+        result
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Target {
+    /// Every value of an object key with this name
+    Field(String),
+    /// The values at a JSON pointer, where `*` matches every element
+    Pointer(Vec<String>),
+    /// Every string of 64 hex digits
+    Txids,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Rule {
+    /// Method the rule is limited to
+    method: Option<String>,
+    target: Target,
+}
+
+/// The params ignored when matching a request with a recorded one, such as
+/// signatures made with fresh randomness or timestamps.
+///
+/// Rules are written as a comma separated list of `[<method>:]<target>`,
+/// where the target is a field name, replacing the value of that key in any
+/// object, a JSON pointer into the params such as `/0` or `/signers/*`, where
+/// `*` matches every element and `/` alone the whole params, or `@txids`,
+/// replacing every string of 64 hex digits. A rule naming a method only
+/// applies to its requests. The default is `signatures,timestamp`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Normalizer {
+    rules: Vec<Rule>,
+}
+
+impl Normalizer {
+    /// A normalizer matching params exactly
+    pub fn none() -> Self {
+        Self { rules: vec![] }
+    }
+
+    /// Adds a rule, written like one of the list parsed by [`FromStr`].
+    pub fn with_rule(mut self, rule: &str) -> Result<Self> {
+        let (method, target) = match rule.starts_with(['/', '@']) {
+            true => (None, rule),
+            false => match rule.split_once(':') {
+                Some((method, target)) => (Some(method.to_string()), target),
+                None => (None, rule),
+            },
+        };
+        let target = match target {
+            "@txids" => Target::Txids,
+            "/" => Target::Pointer(vec![]),
+            pointer if pointer.starts_with('/') => Target::Pointer(
+                pointer[1..]
+                    .split('/')
+                    .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+                    .collect(),
+            ),
+            name if name.starts_with('@') => bail!("Unknown normalization {}", name),
+            "" => bail!("Empty normalization rule in {:?}", rule),
+            name => Target::Field(name.to_string()),
+        };
+        self.rules.push(Rule { method, target });
+        Ok(self)
+    }
+
+    /// The params of a request to `method` with the ignored values replaced
+    /// by [`PLACEHOLDER`].
+    pub fn normalize(&self, method: &str, params: &Value) -> Value {
+        let mut params = params.clone();
+        for rule in &self.rules {
+            if rule.method.as_deref().is_some_and(|only| only != method) {
+                continue;
+            }
+            match &rule.target {
+                Target::Field(name) => replace_field(&mut params, name),
+                Target::Pointer(segments) => replace_pointer(&mut params, segments),
+                Target::Txids => replace_txids(&mut params),
+            }
+        }
+        params
+    }
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        "signatures,timestamp"
+            .parse()
+            .expect("the default rules should parse")
+    }
+}
+
+impl FromStr for Normalizer {
+    type Err = anyhow::Error;
+
+    fn from_str(rules: &str) -> Result<Self> {
+        rules
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .try_fold(Self::none(), |normalizer, rule| normalizer.with_rule(rule))
+    }
+}
+
+fn replace_field(value: &mut Value, name: &str) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if key == name {
+                    *value = json!(PLACEHOLDER);
+                } else {
+                    replace_field(value, name);
+                }
+            }
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| replace_field(value, name)),
+        _ => {}
+    }
+}
+
+fn replace_pointer(value: &mut Value, segments: &[String]) {
+    let Some((segment, rest)) = segments.split_first() else {
+        *value = json!(PLACEHOLDER);
+        return;
+    };
+    match value {
+        Value::Object(object) => object
+            .iter_mut()
+            .filter(|(key, _)| segment == "*" || *key == segment)
+            .for_each(|(_, value)| replace_pointer(value, rest)),
+        Value::Array(values) => values
+            .iter_mut()
+            .enumerate()
+            .filter(|(index, _)| segment == "*" || index.to_string() == *segment)
+            .for_each(|(_, value)| replace_pointer(value, rest)),
+        _ => {}
+    }
+}
+
+fn replace_txids(value: &mut Value) {
+    match value {
+        Value::String(string)
+            if string.len() == 64 && string.bytes().all(|byte| byte.is_ascii_hexdigit()) =>
+        {
+            *value = json!(PLACEHOLDER)
+        }
+        Value::Object(object) => object.values_mut().for_each(replace_txids),
+        Value::Array(values) => values.iter_mut().for_each(replace_txids),
+        _ => {}
+    }
+}
+
+/// [`Transport`] answering requests from a fixture.
+///
+/// A request is answered by the first exchange not replayed yet whose
+/// request has the same method and params, or else the same params once
+/// normalized. Requests are matched whatever their URL and id, and the id of
+/// the response is set to the one of the request. Once the matching
+/// exchanges have all been replayed, the last one answers again, so a
+/// transaction polled more often than when it was recorded keeps its final
+/// status.
+pub struct Replayer {
+    exchanges: Vec<Exchange>,
+    normalizer: Normalizer,
+    /// Exact and normalized keys of the exchanges
+    keys: Vec<(Value, Value)>,
+    replayed: Mutex<Vec<bool>>,
+}
+
+impl Replayer {
+    /// Replays the fixture file at `path`.
+    pub fn open(path: impl AsRef<Path>, normalizer: Normalizer) -> Result<Self> {
+        let path = path.as_ref();
+        let fixture = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read fixture {}", path.display()))?;
+        let exchanges = fixture
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).with_context(|| {
+                    format!("Line {} of {} isn't an exchange", index + 1, path.display())
+                })
+            })
+            .collect::<Result<Vec<Exchange>>>()?;
+        Ok(Self::new(exchanges, normalizer))
+    }
+
+    pub fn new(exchanges: Vec<Exchange>, normalizer: Normalizer) -> Self {
+        let keys = exchanges
+            .iter()
+            .map(|exchange| {
+                (
+                    key(&exchange.request, None),
+                    key(&exchange.request, Some(&normalizer)),
+                )
+            })
+            .collect();
+        Self {
+            replayed: Mutex::new(vec![false; exchanges.len()]),
+            exchanges,
+            normalizer,
+            keys,
+        }
+    }
+
+    /// The exchanges that haven't been replayed, in the order of the fixture
+    pub fn unused(&self) -> Vec<&Exchange> {
+        let replayed = self.replayed.lock().unwrap();
+        self.exchanges
+            .iter()
+            .zip(replayed.iter())
+            .filter(|(_, replayed)| !**replayed)
+            .map(|(exchange, _)| exchange)
+            .collect()
+    }
+}
+
+impl Transport for Replayer {
+    fn call(&self, call: &Call) -> Result<Value> {
+        let exact = key(call.body, None);
+        let normalized = key(call.body, Some(&self.normalizer));
+
+        let mut replayed = self.replayed.lock().unwrap();
+        let find = |replayed: &Vec<bool>, done: bool, normalize: bool| {
+            let matches = |index: &usize| {
+                let (exact_key, normalized_key) = &self.keys[*index];
+                replayed[*index] == done
+                    && match normalize {
+                        false => *exact_key == exact,
+                        true => *normalized_key == normalized,
+                    }
+            };
+            match done {
+                false => (0..self.exchanges.len()).find(matches),
+                true => (0..self.exchanges.len()).rev().find(matches),
+            }
+        };
+        let index = [(false, false), (false, true), (true, false), (true, true)]
+            .into_iter()
+            .find_map(|(done, normalize)| find(&replayed, done, normalize))
+            .ok_or_else(|| NoRecording {
+                method: call.method().to_string(),
+                params: normalized.clone(),
+            })?;
+        replayed[index] = true;
+
+        let exchange = &self.exchanges[index];
+        let mut response = match (&exchange.response, &exchange.error) {
+            (Some(response), _) => response.clone(),
+            (None, Some(error)) if exchange.not_connected => {
+                return Err(ConnectError(error.clone()).into())
+            }
+            (None, Some(error)) => bail!("{}", error),
+            (None, None) => bail!("The recorded {} has no response", call.method()),
+        };
+        match (&mut response, call.body) {
+            (Value::Array(responses), Value::Array(requests)) => {
+                for (response, request) in responses.iter_mut().zip(requests) {
+                    response["id"] = request["id"].clone();
+                }
+            }
+            (response @ Value::Object(_), request) => response["id"] = request["id"].clone(),
+            _ => {}
+        }
+        Ok(response)
+    }
+}
+
+/// A request missing from the fixture, which usually means the flow changed
+/// since it was recorded.
+#[derive(Debug)]
+pub struct NoRecording {
+    pub method: String,
+    /// The params once normalized
+    pub params: Value,
+}
+
+impl fmt::Display for NoRecording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "No recorded response to {} with params {}",
+            self.method, self.params
+        )
+    }
+}
+
+impl std::error::Error for NoRecording {}
+
+/// What a request is matched on: its method and params, or those of each
+/// request of a batch
+fn key(body: &Value, normalizer: Option<&Normalizer>) -> Value {
+    let single = |request: &Value| {
+        let method = request["method"].as_str().unwrap_or_default();
+        let params = match normalizer {
+            Some(normalizer) => normalizer.normalize(method, &request["params"]),
+            None => request["params"].clone(),
+        };
+        json!([method, params])
+    };
+    match body {
+        Value::Array(requests) => Value::Array(requests.iter().map(single).collect()),
+        request => single(request),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::MockServer;
+    use crate::transport::HttpTransport;
+    use sdk::arch_program::message::Message;
+    use sdk::arch_program::pubkey::Pubkey;
+    use sdk::processed_transaction::{ProcessedTransaction, Status};
+    use sdk::runtime_transaction::RuntimeTransaction;
+    use sdk::signature::Signature;
+
+    fn request(method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": "curlycurl", "method": method, "params": params })
+    }
+
+    #[test]
+    fn test_normalizer() {
+        let normalizer = "timestamp, send:/0, /signers/*, @txids"
+            .parse::<Normalizer>()
+            .unwrap();
+        let params = json!({
+            "signers": [[1, 2], [3]],
+            "nested": [{ "timestamp": 17, "other": 1 }],
+            "txid": "ab".repeat(32),
+            "short": "ab",
+        });
+        assert_eq!(
+            normalizer.normalize("read", &params),
+            json!({
+                "signers": [PLACEHOLDER, PLACEHOLDER],
+                "nested": [{ "timestamp": PLACEHOLDER, "other": 1 }],
+                "txid": PLACEHOLDER,
+                "short": "ab",
+            })
+        );
+        assert_eq!(normalizer.normalize("read", &json!([1, 2])), json!([1, 2]));
+        assert_eq!(
+            normalizer.normalize("send", &json!([1, 2])),
+            json!([PLACEHOLDER, 2])
+        );
+        let whole = Normalizer::none().with_rule("status:/").unwrap();
+        assert_eq!(whole.normalize("status", &json!("00")), json!(PLACEHOLDER));
+
+        assert!("@unknown".parse::<Normalizer>().is_err());
+        assert!("send:".parse::<Normalizer>().is_err());
+        assert_eq!("".parse::<Normalizer>().unwrap(), Normalizer::none());
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let server = MockServer::start().unwrap();
+        let best_block_hash = server.add_block(vec![]);
+        let processed_tx = ProcessedTransaction {
+            runtime_transaction: RuntimeTransaction {
+                version: 0,
+                signatures: vec![Signature(vec![1; 64])],
+                message: Message {
+                    signers: vec![Pubkey::from_slice(&[2; 32])],
+                    instructions: vec![],
+                },
+            },
+            status: Status::Processing,
+            bitcoin_txids: vec![],
+            logs: vec![],
+            compute_units_consumed: 0,
+            return_data: None,
+        };
+        let txid = processed_tx.txid();
+        server.set_transaction(processed_tx.clone());
+
+        let path = std::env::temp_dir().join(format!("arch-fixture-{}.jsonl", std::process::id()));
+        let recorder = Recorder::create(&path, HttpTransport::default()).unwrap();
+        let url = server.url();
+        let call = |transport: &dyn Transport, body: &Value| {
+            transport.call(&Call::new(&url, body)).unwrap()
+        };
+        let status = request("get_processed_transaction", json!(txid));
+        let processing = call(&recorder, &status);
+        server.set_transaction(ProcessedTransaction {
+            status: Status::Processed,
+            ..processed_tx.clone()
+        });
+        let processed = call(&recorder, &status);
+        let send = request("send_transaction", json!(processed_tx.runtime_transaction));
+        let sent = call(&recorder, &send);
+        drop(server);
+        let err = recorder
+            .call(&Call::new(
+                &url,
+                &request("get_best_block_hash", Value::Null),
+            ))
+            .unwrap_err();
+        assert!(err.downcast_ref::<ConnectError>().is_some(), "{}", err);
+
+        let replayer = Replayer::open(&path, Normalizer::default()).unwrap();
+        assert_eq!(replayer.unused().len(), 4);
+        // Polls get the statuses in the recorded order, then the last again
+        assert_eq!(call(&replayer, &status), processing);
+        assert_eq!(call(&replayer, &status), processed);
+        assert_eq!(call(&replayer, &status), processed);
+
+        // A transaction with other signatures matches once normalized, and
+        // the response takes the id of the request
+        let mut resigned = processed_tx.runtime_transaction.clone();
+        resigned.signatures = vec![Signature(vec![3; 64])];
+        let mut resend = request("send_transaction", json!(resigned));
+        resend["id"] = json!(7);
+        let replayed = call(&replayer, &resend);
+        assert_eq!(replayed["result"], sent["result"]);
+        assert_eq!(replayed["id"], json!(7));
+
+        // Failures replay as failures, still telling the request wasn't
+        // delivered
+        let err = replayer
+            .call(&Call::new(
+                "http://elsewhere/",
+                &request("get_best_block_hash", Value::Null),
+            ))
+            .unwrap_err();
+        assert!(err.to_string().contains("Unable to reach"), "{}", err);
+        assert!(err.downcast_ref::<ConnectError>().is_some(), "{}", err);
+        assert!(replayer.unused().is_empty());
+
+        let err = replayer
+            .call(&Call::new(
+                &url,
+                &request("get_block", json!(best_block_hash)),
+            ))
+            .unwrap_err();
+        assert!(err.downcast_ref::<NoRecording>().is_some(), "{}", err);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -9,7 +9,7 @@ use bitcoin::{
     transaction::Version,
     Amount, OutPoint, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, Witness,
 };
-use bitcoincore_rpc::{Auth, Client, RawTx, RpcApi};
+use bitcoincore_rpc::RawTx;
 use env_logger;
 use log::{debug, error, info, warn};
 use serde::Deserialize;
@@ -21,20 +21,26 @@ use std::process::Child;
 use std::process::Command;
 use std::str::FromStr;
 
-use sdk::processed_transaction::ProcessedTransaction;
+use sdk::processed_transaction::{ProcessedTransaction, Status};
 
+use crate::bitcoin_backend::{default_backend, AddressUtxo, BitcoinBackend, COINBASE_MATURITY};
+use crate::config::active_profile;
+use crate::confirmation::ConfirmationTracker;
 use crate::constants::{
-    BITCOIN_NODE_ENDPOINT, BITCOIN_NODE_PASSWORD, BITCOIN_NODE_USERNAME, CALLER_FILE_PATH,
-    GET_ACCOUNT_ADDRESS, GET_BEST_BLOCK_HASH, GET_BLOCK, GET_PROCESSED_TRANSACTION, GET_PROGRAM,
-    NODE1_ADDRESS, READ_ACCOUNT_INFO, TRANSACTION_NOT_FOUND_CODE,
+    CALLER_FILE_PATH, GET_ACCOUNT_ADDRESS, GET_BEST_BLOCK_HASH, GET_BLOCK,
+    GET_PROCESSED_TRANSACTION, GET_PROGRAM, GET_PROGRAM_ACCOUNTS, READ_ACCOUNT_INFO, SEND_TRANSACTION,
+    TRANSACTION_NOT_FOUND_CODE,
 };
+use crate::deploy::{ProgramDeployer, RpcNode};
+use crate::keystore::Keystore;
 use crate::models::{BitcoinRpcInfo, CallerInfo};
+use crate::transport::{self, Call};
 use sdk::arch_program::message::Message;
 use sdk::arch_program::pubkey::Pubkey;
 use sdk::runtime_transaction::RuntimeTransaction;
 use sdk::signature::Signature;
 
-fn process_result(response: String) -> Result<Value> {
+pub(crate) fn process_result(response: String) -> Result<Value> {
     let result = from_str::<Value>(&response).expect("result should be Value parseable");
 
     let result = match result {
@@ -70,37 +76,38 @@ fn process_get_transaction_result(response: String) -> Result<Value> {
 }
 
 fn post(url: &str, method: &str) -> String {
-    let client = reqwest::blocking::Client::new();
-    let res = client
-        .post(url)
-        .header("content-type", "application/json")
-        .json(&json!({
-            "jsonrpc": "2.0",
-            "id": "curlycurl",
-            "method": method,
-        }))
-        .send()
-        .expect("post method should not fail");
-
-    res.text().expect("result should be text decodable")
-}
-
-fn post_data<T: Serialize + std::fmt::Debug>(url: &str, method: &str, params: T) -> String {
-    let client = reqwest::blocking::Client::new();
-    let res = client
-        .post(url)
-        .header("content-type", "application/json")
-        .json(&json!({
-            "jsonrpc": "2.0",
-            "id": "curlycurl",
-            "method": method,
-            "params": params,
-        }))
-        .send();
-
-    res.expect("post method should not fail")
-        .text()
-        .expect("result should be text decodable")
+    try_post(url, method, None::<()>).expect("post method should not fail")
+}
+
+pub(crate) fn post_data<T: Serialize + std::fmt::Debug>(url: &str, method: &str, params: T) -> String {
+    try_post(url, method, Some(params)).expect("post method should not fail")
+}
+
+/// Posts a JSON-RPC request through the current [`transport`], returning
+/// the raw response body
+fn try_post<T: Serialize>(url: &str, method: &str, params: Option<T>) -> Result<String> {
+    let mut request = json!({
+        "jsonrpc": "2.0",
+        "id": "curlycurl",
+        "method": method,
+    });
+    if let Some(params) = params {
+        request["params"] = serde_json::to_value(params)?;
+    }
+
+    let response = transport::current()?.call(&Call::new(url, &request))?;
+    Ok(response.to_string())
+}
+
+/// Calls an RPC method without params and returns its result
+pub fn call_rpc(url: &str, method: &str) -> Result<Value> {
+    let response = try_post(url, method, None::<()>)?;
+    let result = from_str::<Value>(&response)
+        .map_err(|e| anyhow!("Invalid response from {}: {}", url, e))?;
+    if let Some(err) = result.get("error") {
+        return Err(anyhow!("{:?}", err));
+    }
+    Ok(result["result"].clone())
 }
 
 /// Returns a caller information using the secret key file specified
@@ -109,47 +116,13 @@ fn get_trader(trader_id: u64) -> Result<CallerInfo> {
     Ok(CallerInfo::with_secret_key_file(file_path)?)
 }
 
-use crate::helper::secp256k1::SecretKey;
 use bitcoin::key::UntweakedKeypair;
 use bitcoin::XOnlyPublicKey;
-use rand_core::OsRng;
 
+/// Loads the key file at `file_path`, decrypting it with the password in
+/// `PRIVATE_KEY_PASSWORD`. See [`Keystore`] to create key files.
 pub fn with_secret_key_file(file_path: &str) -> Result<(UntweakedKeypair, Pubkey)> {
-    let secp = Secp256k1::new();
-    let secret_key = match fs::read_to_string(file_path) {
-        Ok(key) => SecretKey::from_str(&key).unwrap(),
-        Err(_) => {
-            let (key, _) = secp.generate_keypair(&mut OsRng);
-            fs::write(file_path, &key.display_secret().to_string())
-                .map_err(|_| anyhow!("Unable to write file"))?;
-            key
-        }
-    };
-    let keypair = UntweakedKeypair::from_secret_key(&secp, &secret_key);
-    let pubkey = Pubkey::from_slice(&XOnlyPublicKey::from_keypair(&keypair).0.serialize());
-    Ok((keypair, pubkey))
-}
-
-use sdk::arch_program::system_instruction::SystemInstruction;
-use sdk::runtime_transaction::RUNTIME_TX_SIZE_LIMIT;
-
-fn extend_bytes_max_len() -> usize {
-    let message = Message {
-        signers: vec![Pubkey::system_program()],
-        instructions: vec![SystemInstruction::new_extend_bytes_instruction(
-            vec![0_u8; 8],
-            Pubkey::system_program(),
-        )],
-    };
-
-    RUNTIME_TX_SIZE_LIMIT
-        - RuntimeTransaction {
-            version: 0,
-            signatures: vec![Signature([0_u8; 64].to_vec())],
-            message,
-        }
-        .serialize()
-        .len()
+    Keystore::from_env().load(file_path)
 }
 
 /// Creates an instruction, signs it as a message
@@ -191,7 +164,7 @@ pub fn sign_and_send_instruction(
 
     debug!("RuntimeTransaction Params: {:?}", params);
 
-    let result = process_result(post_data(NODE1_ADDRESS, "send_transaction", params))
+    let result = process_result(post_data(leader_url()?, "send_transaction", params))
         .expect("send_transaction should not fail")
         .as_str()
         .expect("cannot convert result to string")
@@ -207,6 +180,17 @@ pub fn sign_and_send_transaction(
     instructions: Vec<Instruction>,
     signers: Vec<UntweakedKeypair>,
 ) -> Result<String> {
+    let result = send_transaction(leader_url()?, sign_transaction(instructions, signers))
+        .expect("send_transaction should not fail");
+
+    Ok(result)
+}
+
+/// Builds a transaction from the instructions, signed by every signer
+pub fn sign_transaction(
+    instructions: Vec<Instruction>,
+    signers: Vec<UntweakedKeypair>,
+) -> RuntimeTransaction {
     let pubkeys = signers
         .iter()
         .map(|signer| Pubkey::from_slice(&XOnlyPublicKey::from_keypair(signer).0.serialize()))
@@ -226,102 +210,40 @@ pub fn sign_and_send_transaction(
         .map(|signer| Signature(secp.sign_schnorr(&sig_message, signer).serialize().to_vec()))
         .collect::<Vec<Signature>>();
 
-    let params = RuntimeTransaction {
+    RuntimeTransaction {
         version: 0,
         signatures,
         message,
-    };
-    let result = process_result(post_data(NODE1_ADDRESS, "send_transaction", params))
-        .expect("send_transaction should not fail")
-        .as_str()
-        .expect("cannot convert result to string")
-        .to_string();
+    }
+}
 
-    Ok(result)
+/// Sends a signed transaction to the node at `url`, returning its txid
+pub fn send_transaction(url: &str, transaction: RuntimeTransaction) -> Result<String> {
+    let response = try_post(url, SEND_TRANSACTION, Some(transaction))?;
+    process_result(response)?
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("send_transaction didn't return a txid"))
 }
 
-/// Deploys the HelloWorld program using the compiled ELF
+/// Deploys the program at `elf_path` to the program account with a
+/// [`ProgramDeployer`], returning the ids of the transactions sent
 pub fn deploy_program_txs(program_keypair: UntweakedKeypair, elf_path: &str) -> Vec<String> {
     info!("Starting program deployment");
-    let program_pubkey =
-        Pubkey::from_slice(&XOnlyPublicKey::from_keypair(&program_keypair).0.serialize());
     let elf = fs::read(elf_path).expect("Failed to read ELF file");
     info!("ELF file size: {} bytes", elf.len());
-    let txs = elf
-        .chunks(extend_bytes_max_len())
-        .enumerate()
-        .map(|(i, chunk)| {
-            let mut bytes = vec![];
-            let offset: u32 = (i * extend_bytes_max_len()) as u32;
-            let len: u32 = chunk.len() as u32;
-            bytes.extend(offset.to_le_bytes());
-            bytes.extend(len.to_le_bytes());
-            bytes.extend(chunk);
-            let message = Message {
-                signers: vec![program_pubkey.clone()],
-                instructions: vec![SystemInstruction::new_extend_bytes_instruction(
-                    bytes,
-                    program_pubkey.clone(),
-                )],
-            };
-            let digest_slice =
-                hex::decode(message.hash()).expect("hashed message should be decodable");
-            let sig_message = secp256k1::Message::from_digest_slice(&digest_slice)
-                .expect("signed message should be gotten from digest slice");
-            let secp = Secp256k1::new();
-            RuntimeTransaction {
-                version: 0,
-                signatures: vec![Signature(
-                    secp.sign_schnorr(&sig_message, &program_keypair)
-                        .serialize()
-                        .to_vec(),
-                )],
-                message,
-            }
-        })
-        .collect::<Vec<RuntimeTransaction>>();
-
-    info!("Deploying program with {} transactions", txs.len());
-
-    let txids = process_result(post_data(NODE1_ADDRESS, "send_transactions", txs))
-        .expect("send_transaction should not fail")
-        .as_array()
-        .expect("cannot convert result to array")
-        .iter()
-        .map(|r| {
-            r.as_str()
-                .expect("cannot convert object to string")
-                .to_string()
-        })
-        .collect::<Vec<String>>();
-
-    info!(
-        "Successfully sent {} transactions for program deployment",
-        txids.len()
-    );
 
-    for (i, txid) in txids.iter().enumerate() {
-        match get_processed_transaction(NODE1_ADDRESS, txid.clone()) {
-            Ok(_) => debug!(
-                "Transaction {} (ID: {}) processed successfully",
-                i + 1,
-                txid
-            ),
-            Err(e) => warn!(
-                "Failed to process transaction {} (ID: {}): {:?}",
-                i + 1,
-                txid,
-                e
-            ),
-        }
-    }
-
-    txids
+    let url = leader_url().expect("the profile should have a node URL");
+    ProgramDeployer::new(RpcNode::new(url), program_keypair)
+        .deploy(&elf)
+        .expect("program deployment should not fail")
+        .txids
 }
 
 /// Starts Key Exchange by calling the RPC method
 pub fn start_key_exchange() {
-    match process_result(post(NODE1_ADDRESS, "start_key_exchange")) {
+    let url = leader_url().expect("the profile should have a node URL");
+    match process_result(post(url, "start_key_exchange")) {
         Err(err) => error!("Failed to initiate Key Exchange: {:?}", err),
         Ok(val) => {
             if val.as_bool().unwrap_or(false) {
@@ -335,13 +257,14 @@ pub fn start_key_exchange() {
 
 /// Starts a Distributed Key Generation round by calling the RPC method
 pub fn start_dkg() {
-    match process_result(post(NODE1_ADDRESS, "start_dkg")) {
+    let url = leader_url().expect("the profile should have a node URL");
+    match process_result(post(url, "start_dkg")) {
         Ok(_) => info!("Distributed Key Generation (DKG) initiated successfully"),
         Err(err) => error!("Failed to initiate Distributed Key Generation: {:?}", err),
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AccountInfoResult {
     pub owner: Pubkey,
     pub data: Vec<u8>,
@@ -352,7 +275,7 @@ pub struct AccountInfoResult {
 /// Read Utxo given the utxo ID
 pub fn read_account_info(url: &str, pubkey: Pubkey) -> Result<AccountInfoResult> {
     // Perform the POST request and get the raw response
-    let raw_response = post_data(url, READ_ACCOUNT_INFO, pubkey);
+    let raw_response = try_post(url, READ_ACCOUNT_INFO, Some(pubkey))?;
 
     // Process the result
     let result = process_result(raw_response.clone())
@@ -373,6 +296,57 @@ pub fn read_account_info(url: &str, pubkey: Pubkey) -> Result<AccountInfoResult>
     Ok(account_info)
 }
 
+/// An account owned by a program, as returned by `get_program_accounts`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProgramAccount {
+    pub pubkey: Pubkey,
+    pub account: AccountInfoResult,
+}
+
+/// Returns the accounts owned by a program
+pub fn get_program_accounts(url: &str, program_id: Pubkey) -> Result<Vec<ProgramAccount>> {
+    let result = process_result(try_post(url, GET_PROGRAM_ACCOUNTS, Some(program_id))?)?;
+    serde_json::from_value(result)
+        .map_err(|e| anyhow!("Unable to decode get_program_accounts result: {:?}", e))
+}
+
+/// A block of the node, linking to its parent and listing the ids of the
+/// transactions it contains
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Block {
+    #[serde(default)]
+    pub previous_block_hash: String,
+    #[serde(default)]
+    pub transactions: Vec<String>,
+}
+
+impl Block {
+    /// Returns true if `hash` is the parent of the first block
+    pub fn is_genesis_parent(hash: &str) -> bool {
+        hash.bytes().all(|byte| byte == b'0')
+    }
+}
+
+/// Returns the hash of the last block of the node
+pub fn get_best_block_hash(url: &str) -> Result<String> {
+    let result = process_result(try_post(url, GET_BEST_BLOCK_HASH, None::<()>)?)?;
+    result
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Unexpected get_best_block_hash result: {}", result))
+}
+
+/// Returns the block with the given hash
+pub fn get_block(url: &str, hash: &str) -> Result<Block> {
+    let result = process_result(try_post(url, GET_BLOCK, Some(hash))?)?;
+    // Some nodes return the block serialized into a string
+    let block = match result {
+        Value::String(block) => serde_json::from_str(&block),
+        block => serde_json::from_value(block),
+    };
+    block.map_err(|e| anyhow!("Unable to decode get_block result: {:?}", e))
+}
+
 /// Returns a program given the program ID
 pub fn get_program(url: &str, program_id: String) -> String {
     process_result(post_data(url, GET_PROGRAM, program_id))
@@ -384,87 +358,59 @@ pub fn get_program(url: &str, program_id: String) -> String {
 
 /// Returns the best block
 fn get_best_block() -> String {
-    let best_block_hash = process_result(post(NODE1_ADDRESS, GET_BEST_BLOCK_HASH))
+    let url = leader_url().expect("the profile should have a node URL");
+    let best_block_hash = process_result(post(url, GET_BEST_BLOCK_HASH))
         .expect("best_block_hash should not fail")
         .as_str()
         .expect("cannot convert result to string")
         .to_string();
-    process_result(post_data(NODE1_ADDRESS, GET_BLOCK, best_block_hash))
+    process_result(post_data(url, GET_BLOCK, best_block_hash))
         .expect("get_block should not fail")
         .as_str()
         .expect("cannot convert result to string")
         .to_string()
 }
 
-/// Returns a processed transaction given the txid
-/// Keeps trying for a maximum of 60 seconds if the processed transaction is not available
-pub fn get_processed_transaction(url: &str, tx_id: String) -> Result<ProcessedTransaction> {
-    let mut processed_tx =
-        process_get_transaction_result(post_data(url, GET_PROCESSED_TRANSACTION, tx_id.clone()));
-    if let Err(e) = processed_tx {
-        return Err(anyhow!("{}", e));
+/// Returns the processed transaction given the txid, or `None` if the node
+/// doesn't know it yet
+pub fn get_transaction_status(url: &str, tx_id: &str) -> Result<Option<ProcessedTransaction>> {
+    let response = try_post(url, GET_PROCESSED_TRANSACTION, Some(tx_id))?;
+    match process_get_transaction_result(response)? {
+        Value::Null => Ok(None),
+        processed_tx => Ok(Some(serde_json::from_value(processed_tx)?)),
     }
+}
 
-    let mut wait_time = 10;
-    while let Ok(Value::Null) = processed_tx {
-        debug!(
-            "Transaction not yet processed. Retrying in {} seconds...",
-            wait_time
-        );
-        std::thread::sleep(std::time::Duration::from_secs(wait_time));
-        processed_tx = process_get_transaction_result(post_data(
-            url,
-            GET_PROCESSED_TRANSACTION,
-            tx_id.clone(),
-        ));
-        wait_time += 10;
-        if wait_time >= 60 {
-            error!("Failed to retrieve processed transaction after 60 seconds");
-            return Err(anyhow!("Timeout: Failed to retrieve processed transaction"));
-        }
-    }
+/// Returns a processed transaction given the txid
+/// Polls with a growing delay for a maximum of 60 seconds if the processed transaction is not available
+pub fn get_processed_transaction(url: &str, tx_id: String) -> Result<ProcessedTransaction> {
+    let processed_tx = ConfirmationTracker::rpc(url)
+        .wait(&tx_id, Status::Processing)
+        .inspect_err(|e| error!("Failed to retrieve processed transaction: {}", e))?;
 
     info!(
         "Successfully retrieved and processed transaction: {}",
         tx_id
     );
-    Ok(serde_json::from_value(processed_tx?).unwrap())
+    Ok(processed_tx)
 }
 
+/// Funds the caller with a UTXO on the default backend and returns a signed
+/// transaction spending it, for programs to add their outputs to
 pub fn prepare_fees() -> String {
-    let userpass = Auth::UserPass(
-        BITCOIN_NODE_USERNAME.to_string(),
-        BITCOIN_NODE_PASSWORD.to_string(),
-    );
-    let rpc =
-        Client::new(BITCOIN_NODE_ENDPOINT, userpass).expect("rpc shouldn not fail to be initiated");
-
+    let backend = default_backend().expect("the bitcoin backend should be reachable");
     let caller = CallerInfo::with_secret_key_file(CALLER_FILE_PATH)
         .expect("getting caller info should not fail");
+    prepare_fees_with(&backend, &caller).expect("fees should be prepared")
+}
 
-    let txid = rpc
-        .send_to_address(
-            &caller.address,
-            Amount::from_sat(3000),
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-        )
-        .expect("SATs should be sent to address");
-
-    let sent_tx = rpc
-        .get_raw_transaction(&txid, None)
-        .expect("should get raw transaction");
-    let mut vout = 0;
-
-    for (index, output) in sent_tx.output.iter().enumerate() {
-        if output.script_pubkey == caller.address.script_pubkey() {
-            vout = index as u32;
-        }
-    }
+/// Funds `caller` with a UTXO from `backend` and returns a transaction
+/// spending it, signed with `NONE|ANYONECANPAY` so programs can add their
+/// inputs and outputs to it
+pub fn prepare_fees_with(backend: &impl BitcoinBackend, caller: &CallerInfo) -> Result<String> {
+    let txid = backend.send_to_address(&caller.address, Amount::from_sat(3000))?;
+    let sent_tx = backend.get_raw_transaction(&txid)?;
+    let vout = find_output(&sent_tx, &caller.address)?;
 
     let mut tx = Transaction {
         version: Version::TWO,
@@ -479,16 +425,13 @@ pub fn prepare_fees() -> String {
     };
 
     let sighash_type = TapSighashType::NonePlusAnyoneCanPay;
-    let raw_tx = rpc
-        .get_raw_transaction(&txid, None)
-        .expect("raw transaction should not fail");
-    let prevouts = vec![raw_tx.output[vout as usize].clone()];
+    let prevouts = vec![sent_tx.output[vout as usize].clone()];
     let prevouts = Prevouts::All(&prevouts);
 
     let mut sighasher = SighashCache::new(&mut tx);
     let sighash = sighasher
         .taproot_key_spend_signature_hash(0, &prevouts, sighash_type)
-        .expect("should not fail to construct sighash");
+        .map_err(|e| anyhow!("Unable to construct the sighash: {}", e))?;
 
     // Sign the sighash using the secp256k1 library (exported by rust-bitcoin).
     let secp = Secp256k1::new();
@@ -503,92 +446,75 @@ pub fn prepare_fees() -> String {
     };
     tx.input[0].witness.push(signature.to_vec());
 
-    tx.raw_hex()
+    Ok(tx.raw_hex())
 }
 
+/// Sends a UTXO to the account of `pubkey` on the default backend
 pub fn send_utxo(pubkey: Pubkey) -> (String, u32) {
-    let userpass = Auth::UserPass(
-        BITCOIN_NODE_USERNAME.to_string(),
-        BITCOIN_NODE_PASSWORD.to_string(),
-    );
-    let rpc =
-        Client::new(BITCOIN_NODE_ENDPOINT, userpass).expect("rpc shouldn not fail to be initiated");
+    let backend = default_backend().expect("the bitcoin backend should be reachable");
+    send_utxo_with(&backend, pubkey).expect("UTXO should be sent")
+}
 
-    let caller = CallerInfo::with_secret_key_file(CALLER_FILE_PATH)
-        .expect("getting caller info should not fail");
+/// Sends a UTXO from `backend` to the address of the account of `pubkey`,
+/// returning its txid and output index
+pub fn send_utxo_with(backend: &impl BitcoinBackend, pubkey: Pubkey) -> Result<(String, u32)> {
+    let address = get_account_address(pubkey)?;
 
-    let address = get_account_address(pubkey);
+    let account_address =
+        Address::from_str(&address)?.require_network(active_profile()?.network)?;
 
-    let account_address = Address::from_str(&address)
-        .unwrap()
-        .require_network(bitcoin::Network::Regtest)
-        .unwrap();
+    send_utxo_to(backend, &account_address)
+}
 
+/// Sends a UTXO from `backend` to `address`, returning its txid and output
+/// index
+pub fn send_utxo_to(backend: &impl BitcoinBackend, address: &Address) -> Result<(String, u32)> {
     info!("Sending UTXO to account address: {}", address);
 
-    let txid = rpc
-        .send_to_address(
-            &account_address,
-            Amount::from_sat(3000),
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-        )
-        .expect("Failed to send SATs to address");
-
-    let sent_tx = rpc
-        .get_raw_transaction(&txid, None)
-        .expect("should get raw transaction");
-    let mut vout = 0;
-
-    for (index, output) in sent_tx.output.iter().enumerate() {
-        if output.script_pubkey == account_address.script_pubkey() {
-            vout = index as u32;
-            println!("Found a matching UTXO")
-        }
-    }
+    let txid = backend.send_to_address(address, Amount::from_sat(3000))?;
+    let sent_tx = backend.get_raw_transaction(&txid)?;
+    let vout = find_output(&sent_tx, address)?;
 
     info!(
         "UTXO sent successfully. Transaction ID: {}, Output Index: {}",
         txid, vout
     );
-    (txid.to_string(), vout)
+    Ok((txid.to_string(), vout))
 }
 
-fn get_account_address(pubkey: Pubkey) -> String {
-    process_result(post_data(NODE1_ADDRESS, GET_ACCOUNT_ADDRESS, pubkey))
-        .expect("get_account_address should not fail")
-        .as_str()
-        .expect("cannot convert result to string")
-        .to_string()
+/// Index of the output of `tx` paying to `address`
+fn find_output(tx: &Transaction, address: &Address) -> Result<u32> {
+    tx.output
+        .iter()
+        .position(|output| output.script_pubkey == address.script_pubkey())
+        .map(|index| index as u32)
+        .ok_or_else(|| anyhow!("{} has no output paying to {}", tx.txid(), address))
 }
 
-fn get_address_utxos(rpc: &Client, address: String) -> Vec<Value> {
-    let client = reqwest::blocking::Client::new();
-
-    let res = client
-        .get(format!(
-            "https://mempool.dev.aws.archnetwork.xyz/api/address/{}/utxo",
-            address
-        ))
-        .header("Accept", "application/json")
-        .send()
-        .unwrap();
+fn get_account_address(pubkey: Pubkey) -> Result<String> {
+    process_result(post_data(leader_url()?, GET_ACCOUNT_ADDRESS, pubkey))?
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("get_account_address didn't return an address"))
+}
 
-    let utxos = from_str::<Value>(&res.text().unwrap()).unwrap();
+/// RPC URL of the leader node of the active profile
+fn leader_url() -> Result<&'static str> {
+    active_profile()?.leader_url()
+}
 
-    utxos
-        .as_array()
-        .unwrap()
-        .iter()
-        .filter(|utxo| {
-            utxo["status"]["block_height"].as_u64().unwrap() <= rpc.get_block_count().unwrap() - 100
-        })
-        .map(|utxo| utxo.clone())
-        .collect()
+/// Returns the UTXOs of `address` that are deep enough to be spent even if
+/// they are coinbase outputs
+pub fn get_address_utxos(
+    backend: &impl BitcoinBackend,
+    address: &Address,
+) -> Result<Vec<AddressUtxo>> {
+    let block_count = backend.get_block_count()?;
+    Ok(backend
+        .get_address_utxos(address)?
+        .into_iter()
+        .filter(|utxo| utxo.confirmations(block_count) > COINBASE_MATURITY)
+        .collect())
 }
 
 pub fn start_boot_node(port: u16, arch_nodes: &str, bitcoin_rpc_info: &BitcoinRpcInfo) -> Child {
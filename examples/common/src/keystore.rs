@@ -0,0 +1,466 @@
+//! Password protected key files and keys derived from BIP39 mnemonics.
+//!
+//! Key files are JSON documents holding the x-only pubkey and the secret key,
+//! encrypted with AES-256-GCM under a key stretched from the password with
+//! scrypt. The password is read from `PRIVATE_KEY_PASSWORD`, the variable the
+//! nodes use for their own keys, and an empty password stores the secret key
+//! unencrypted as the nodes do. Key files holding a bare hex secret key, as
+//! written before this module existed, are still read.
+//!
+//! Keys derived from a mnemonic follow BIP86, `m/86'/<coin>'/<account>'/0/<index>`,
+//! whose internal key is the x-only pubkey used as the account [`Pubkey`].
+//!
+//! Passwords, seeds and decrypted secrets are zeroized once used.
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{anyhow, bail, Context, Result};
+use bip39::Mnemonic;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Xpriv};
+use bitcoin::key::UntweakedKeypair;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::{Network, XOnlyPublicKey};
+use log::warn;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use sdk::arch_program::pubkey::Pubkey;
+
+/// Environment variable holding the key file password
+pub const PASSWORD_ENV: &str = "PRIVATE_KEY_PASSWORD";
+
+/// Version of the key file format
+const KEY_FILE_VERSION: u32 = 1;
+
+/// scrypt cost parameters used for new key files
+pub const DEFAULT_SCRYPT_LOG_N: u8 = 15;
+pub const DEFAULT_SCRYPT_R: u32 = 8;
+pub const DEFAULT_SCRYPT_P: u32 = 1;
+
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+struct KeyFile {
+    version: u32,
+    pubkey: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    derivation_path: Option<String>,
+    #[serde(flatten)]
+    secret: StoredSecret,
+}
+
+#[derive(Serialize, Deserialize, Zeroize)]
+#[serde(tag = "encryption", rename_all = "snake_case")]
+enum StoredSecret {
+    None {
+        secret_key: String,
+    },
+    Aes256Gcm {
+        kdf: ScryptKdf,
+        nonce: String,
+        ciphertext: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Zeroize)]
+struct ScryptKdf {
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: String,
+}
+
+/// Reads and writes key files with a password.
+pub struct Keystore {
+    password: Zeroizing<String>,
+    log_n: u8,
+    r: u32,
+    p: u32,
+}
+
+impl Keystore {
+    /// Keystore using the password in [`PASSWORD_ENV`], storing keys
+    /// unencrypted if it is unset or empty.
+    pub fn from_env() -> Self {
+        Self::with_password(&Zeroizing::new(
+            std::env::var(PASSWORD_ENV).unwrap_or_default(),
+        ))
+    }
+
+    pub fn with_password(password: &str) -> Self {
+        Self {
+            password: Zeroizing::new(password.to_string()),
+            log_n: DEFAULT_SCRYPT_LOG_N,
+            r: DEFAULT_SCRYPT_R,
+            p: DEFAULT_SCRYPT_P,
+        }
+    }
+
+    /// Set the scrypt cost of the key files written from now on.
+    pub fn with_scrypt_params(mut self, log_n: u8, r: u32, p: u32) -> Self {
+        self.log_n = log_n;
+        self.r = r;
+        self.p = p;
+        self
+    }
+
+    pub fn is_encrypting(&self) -> bool {
+        !self.password.is_empty()
+    }
+
+    /// Load the key stored at `path`, failing if there is none.
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<(UntweakedKeypair, Pubkey)> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            bail!("Key file {} doesn't exist", path.display());
+        }
+        let contents = Zeroizing::new(
+            fs::read_to_string(path)
+                .with_context(|| format!("Unable to read key file {}", path.display()))?,
+        );
+
+        let secret_key = match serde_json::from_str::<KeyFile>(&contents) {
+            Ok(key_file) => self
+                .decrypt(&key_file)
+                .with_context(|| format!("Unable to load key file {}", path.display()))?,
+            Err(_) => {
+                let secret_key = SecretKey::from_str(contents.trim()).map_err(|_| {
+                    anyhow!(
+                        "{} is neither a key file nor a hex secret key",
+                        path.display()
+                    )
+                })?;
+                warn!(
+                    "{} holds an unencrypted secret key in the legacy format",
+                    path.display()
+                );
+                secret_key
+            }
+        };
+
+        Ok(keypair_from_secret_key(secret_key))
+    }
+
+    /// Generate a new key and store it at `path`, failing if a file is
+    /// already there.
+    pub fn create(&self, path: impl AsRef<Path>) -> Result<(UntweakedKeypair, Pubkey)> {
+        let (mut secret_key, _) = Secp256k1::new().generate_keypair(&mut OsRng);
+        let keypair = keypair_from_secret_key(secret_key);
+        secret_key.non_secure_erase();
+
+        self.save(path, &keypair.0, None)?;
+        Ok(keypair)
+    }
+
+    /// Load the key stored at `path`, generating and storing a new one if
+    /// there is none.
+    pub fn load_or_create(&self, path: impl AsRef<Path>) -> Result<(UntweakedKeypair, Pubkey)> {
+        if path.as_ref().exists() {
+            self.load(path)
+        } else {
+            self.create(path)
+        }
+    }
+
+    /// Derive the BIP86 key of `account` and `index` from `mnemonic` and store
+    /// it at `path`, failing if a file is already there.
+    pub fn import_mnemonic(
+        &self,
+        path: impl AsRef<Path>,
+        mnemonic: &Mnemonic,
+        passphrase: &str,
+        derivation_path: &DerivationPath,
+    ) -> Result<(UntweakedKeypair, Pubkey)> {
+        let keypair = derive_keypair(mnemonic, passphrase, derivation_path)?;
+        self.save(path, &keypair.0, Some(derivation_path))?;
+        Ok(keypair)
+    }
+
+    /// Store `keypair` at `path`, failing if a file is already there.
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        keypair: &UntweakedKeypair,
+        derivation_path: Option<&DerivationPath>,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let secret = Zeroizing::new(keypair.secret_bytes());
+
+        let secret = if self.is_encrypting() {
+            let mut salt = [0u8; 16];
+            let mut nonce = [0u8; 12];
+            OsRng.fill_bytes(&mut salt);
+            OsRng.fill_bytes(&mut nonce);
+
+            let kdf = ScryptKdf {
+                log_n: self.log_n,
+                r: self.r,
+                p: self.p,
+                salt: hex::encode(salt),
+            };
+            let ciphertext = self
+                .cipher(&kdf)?
+                .encrypt(
+                    Nonce::from_slice(&nonce),
+                    Payload {
+                        msg: secret.as_slice(),
+                        aad: &keypair.x_only_public_key().0.serialize(),
+                    },
+                )
+                .map_err(|_| anyhow!("Unable to encrypt the secret key"))?;
+
+            StoredSecret::Aes256Gcm {
+                kdf,
+                nonce: hex::encode(nonce),
+                ciphertext: hex::encode(ciphertext),
+            }
+        } else {
+            warn!(
+                "{} is empty, storing {} unencrypted",
+                PASSWORD_ENV,
+                path.display()
+            );
+            StoredSecret::None {
+                secret_key: hex::encode(secret.as_slice()),
+            }
+        };
+
+        let key_file = KeyFile {
+            version: KEY_FILE_VERSION,
+            pubkey: hex::encode(keypair.x_only_public_key().0.serialize()),
+            derivation_path: derivation_path.map(|path| path.to_string()),
+            secret,
+        };
+        let contents = Zeroizing::new(serde_json::to_string_pretty(&key_file)?);
+        write_new_file(path, contents.as_bytes())
+    }
+
+    fn decrypt(&self, key_file: &KeyFile) -> Result<SecretKey> {
+        let pubkey = hex::decode(&key_file.pubkey).context("Invalid pubkey")?;
+
+        let secret_key = match &key_file.secret {
+            StoredSecret::None { secret_key } => {
+                SecretKey::from_str(secret_key).context("Invalid secret key")?
+            }
+            StoredSecret::Aes256Gcm {
+                kdf,
+                nonce,
+                ciphertext,
+            } => {
+                if !self.is_encrypting() {
+                    bail!("the key is encrypted, set {} to decrypt it", PASSWORD_ENV);
+                }
+                let nonce = hex::decode(nonce).context("Invalid nonce")?;
+                if nonce.len() != 12 {
+                    bail!("Invalid nonce");
+                }
+                let secret = Zeroizing::new(
+                    self.cipher(kdf)?
+                        .decrypt(
+                            Nonce::from_slice(&nonce),
+                            Payload {
+                                msg: &hex::decode(ciphertext).context("Invalid ciphertext")?,
+                                aad: &pubkey,
+                            },
+                        )
+                        .map_err(|_| anyhow!("wrong password or corrupted key file"))?,
+                );
+                SecretKey::from_slice(&secret).context("Invalid secret key")?
+            }
+        };
+
+        let (_, stored_pubkey) = keypair_from_secret_key(secret_key);
+        if stored_pubkey.serialize().as_slice() != pubkey.as_slice() {
+            bail!("the secret key doesn't match the pubkey of the key file");
+        }
+        Ok(secret_key)
+    }
+
+    fn cipher(&self, kdf: &ScryptKdf) -> Result<Aes256Gcm> {
+        let params = scrypt::Params::new(kdf.log_n, kdf.r, kdf.p, 32)
+            .map_err(|e| anyhow!("Invalid scrypt parameters: {}", e))?;
+        let salt = hex::decode(&kdf.salt).context("Invalid salt")?;
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        scrypt::scrypt(self.password.as_bytes(), &salt, &params, key.as_mut())
+            .map_err(|e| anyhow!("Unable to derive the encryption key: {}", e))?;
+        Aes256Gcm::new_from_slice(key.as_ref()).map_err(|e| anyhow!("{}", e))
+    }
+}
+
+/// Write a file readable only by its owner, failing if it exists.
+fn write_new_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Unable to create key file {}", path.display()))?;
+    file.write_all(contents)?;
+    Ok(())
+}
+
+fn keypair_from_secret_key(secret_key: SecretKey) -> (UntweakedKeypair, Pubkey) {
+    let keypair = UntweakedKeypair::from_secret_key(&Secp256k1::new(), &secret_key);
+    let pubkey = Pubkey::from_slice(&XOnlyPublicKey::from_keypair(&keypair).0.serialize());
+    (keypair, pubkey)
+}
+
+/// Generate a new English mnemonic of `word_count` words.
+pub fn generate_mnemonic(word_count: usize) -> Result<Mnemonic> {
+    Mnemonic::generate(word_count).map_err(|e| anyhow!("Unable to generate a mnemonic: {}", e))
+}
+
+/// Parse an English mnemonic, checking its checksum.
+pub fn parse_mnemonic(phrase: &str) -> Result<Mnemonic> {
+    Mnemonic::parse_normalized(phrase.trim()).map_err(|e| anyhow!("Invalid mnemonic: {}", e))
+}
+
+/// The BIP86 derivation path of the key `index` of `account`, on the coin
+/// type of `network`.
+pub fn bip86_path(network: Network, account: u32, index: u32) -> Result<DerivationPath> {
+    let coin_type = match network {
+        Network::Bitcoin => 0,
+        _ => 1,
+    };
+    Ok(DerivationPath::from(vec![
+        ChildNumber::from_hardened_idx(86)?,
+        ChildNumber::from_hardened_idx(coin_type)?,
+        ChildNumber::from_hardened_idx(account)?,
+        ChildNumber::from_normal_idx(0)?,
+        ChildNumber::from_normal_idx(index)?,
+    ]))
+}
+
+/// Derive the keypair at `path` from the seed of `mnemonic`.
+pub fn derive_keypair(
+    mnemonic: &Mnemonic,
+    passphrase: &str,
+    path: &DerivationPath,
+) -> Result<(UntweakedKeypair, Pubkey)> {
+    let secp = Secp256k1::new();
+    let seed = Zeroizing::new(mnemonic.to_seed_normalized(passphrase));
+
+    let mut master = Xpriv::new_master(Network::Bitcoin, seed.as_ref())?;
+    let mut child = master.derive_priv(&secp, path)?;
+    master.private_key.non_secure_erase();
+
+    let keypair = keypair_from_secret_key(child.private_key);
+    child.private_key.non_secure_erase();
+    Ok(keypair)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The key files of the tests use a cheap scrypt cost
+    fn keystore(password: &str) -> Keystore {
+        Keystore::with_password(password).with_scrypt_params(4, 8, 1)
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("keystore-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_encrypted_round_trip() {
+        let path = temp_path("encrypted");
+        let (keypair, pubkey) = keystore("hunter2").create(&path).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("\"encryption\": \"aes256_gcm\""));
+        assert!(!contents.contains(&hex::encode(keypair.secret_bytes())));
+
+        let (loaded, loaded_pubkey) = keystore("hunter2").load(&path).unwrap();
+        assert_eq!(loaded.secret_bytes(), keypair.secret_bytes());
+        assert_eq!(loaded_pubkey, pubkey);
+
+        assert!(keystore("wrong").load(&path).is_err());
+        assert!(keystore("").load(&path).is_err());
+        assert!(keystore("hunter2").create(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unencrypted_and_legacy_files() {
+        let path = temp_path("plain");
+        let (keypair, pubkey) = keystore("").create(&path).unwrap();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains(&hex::encode(keypair.secret_bytes())));
+        assert_eq!(keystore("").load(&path).unwrap().1, pubkey);
+        fs::remove_file(&path).unwrap();
+
+        fs::write(&path, hex::encode(keypair.secret_bytes())).unwrap();
+        assert_eq!(keystore("ignored").load(&path).unwrap().1, pubkey);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_file_is_an_error_unless_created() {
+        let path = temp_path("missing");
+        assert!(keystore("").load(&path).is_err());
+        assert!(!path.exists());
+
+        let (_, pubkey) = keystore("").load_or_create(&path).unwrap();
+        assert_eq!(keystore("").load_or_create(&path).unwrap().1, pubkey);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_pubkey_is_rejected() {
+        let path = temp_path("tampered");
+        keystore("").create(&path).unwrap();
+
+        let mut key_file: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        key_file["pubkey"] = hex::encode([2u8; 32]).into();
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, key_file.to_string()).unwrap();
+
+        assert!(keystore("").load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_bip86_derivation() {
+        // First receiving key of the BIP86 test vectors
+        let mnemonic = parse_mnemonic(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap();
+        let path = bip86_path(Network::Bitcoin, 0, 0).unwrap();
+        assert_eq!(path.to_string(), "m/86'/0'/0'/0/0");
+
+        let (_, pubkey) = derive_keypair(&mnemonic, "", &path).unwrap();
+        assert_eq!(
+            hex::encode(pubkey.serialize()),
+            "cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115"
+        );
+
+        let file = temp_path("mnemonic");
+        let (_, imported) = keystore("pw")
+            .import_mnemonic(&file, &mnemonic, "", &path)
+            .unwrap();
+        assert_eq!(imported, pubkey);
+        assert!(fs::read_to_string(&file)
+            .unwrap()
+            .contains("\"derivation_path\": \"m/86'/0'/0'/0/0\""));
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(generate_mnemonic(24).unwrap().word_count(), 24);
+        assert!(parse_mnemonic("abandon abandon").is_err());
+    }
+}
@@ -1,3 +1,16 @@
+pub mod bitcoin_backend;
+pub mod cluster;
+pub mod config;
+pub mod confirmation;
+pub mod decoder;
+pub mod deploy;
+pub mod fixture;
 pub mod helper;
+pub mod keystore;
+pub mod mock_server;
 pub mod models;
+pub mod regtest;
+pub mod subscription;
+pub mod transport;
+pub mod websocket;
 pub mod constants;
\ No newline at end of file
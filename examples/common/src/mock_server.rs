@@ -0,0 +1,527 @@
+//! A local node for tests without the stack. It serves the JSON-RPC methods
+//! reading accounts, transactions and blocks and, unless started with
+//! [`MockServer::without_push`], their subscriptions over WebSocket. Sent
+//! transactions are kept as processing until the test sets them with
+//! [`MockServer::set_transaction`], or until they are due if the server
+//! processes them itself, see [`MockServer::process_sent_after`].
+use std::collections::{BTreeMap, HashMap};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde_json::{json, Value};
+
+use sdk::arch_program::pubkey::Pubkey;
+use sdk::processed_transaction::{ProcessedTransaction, Status};
+use sdk::runtime_transaction::RuntimeTransaction;
+
+use crate::constants::{
+    ACCOUNT_NOTIFICATION, ACCOUNT_SUBSCRIBE, ALL_NODES_READY, GET_BEST_BLOCK_HASH, GET_BLOCK,
+    GET_PROCESSED_TRANSACTION, GET_PROGRAM_ACCOUNTS, IS_NODE_READY, PROGRAM_NOTIFICATION,
+    PROGRAM_SUBSCRIBE, READ_ACCOUNT_INFO, SEND_TRANSACTION, SEND_TRANSACTIONS,
+    TRANSACTION_NOTIFICATION, TRANSACTION_NOT_FOUND_CODE, TRANSACTION_SUBSCRIBE, UNSUBSCRIBE,
+};
+use crate::helper::{AccountInfoResult, Block, ProgramAccount};
+use crate::subscription::Subscription;
+use crate::websocket::{self, Message, WebSocket};
+
+pub struct MockServer {
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+    accept: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct State {
+    push: bool,
+    /// Answers of `is_node_ready` and `all_nodes_ready`, inverted so the
+    /// default is ready
+    not_ready: bool,
+    nodes_not_ready: bool,
+    /// Ids of the transactions sent to the server, in order
+    received: Vec<String>,
+    processing: Option<Processing>,
+    /// Sent transactions waiting to be processed, by the time they are due
+    pending: Vec<(Instant, String)>,
+    accounts: BTreeMap<Pubkey, AccountInfoResult>,
+    transactions: HashMap<String, ProcessedTransaction>,
+    /// Blocks by hash, and the hash of the last one
+    blocks: HashMap<String, Block>,
+    best_block_hash: Option<String>,
+    next_id: u64,
+    subscribers: HashMap<u64, Subscriber>,
+}
+
+/// Bitcoin txids produced by a transaction
+type BitcoinTxids = Box<dyn Fn(&RuntimeTransaction) -> Vec<String> + Send>;
+
+/// How the server processes the transactions sent to it
+struct Processing {
+    delay: Duration,
+    bitcoin_txids: BitcoinTxids,
+}
+
+struct Subscriber {
+    websocket: WebSocket,
+    subscriptions: HashMap<u64, Subscription>,
+}
+
+impl MockServer {
+    /// Starts a server with subscriptions on a free local port.
+    pub fn start() -> Result<Self> {
+        Self::bind(true)
+    }
+
+    /// Starts a server answering WebSocket requests with a 404, like a node
+    /// without subscriptions.
+    pub fn without_push() -> Result<Self> {
+        Self::bind(false)
+    }
+
+    fn bind(push: bool) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let mut server = Self {
+            address: listener.local_addr()?,
+            state: Arc::new(Mutex::new(State {
+                push,
+                ..Default::default()
+            })),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            accept: None,
+        };
+
+        let (state, shutdown) = (server.state.clone(), server.shutdown.clone());
+        server.accept = Some(thread::spawn(move || {
+            while !shutdown.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let state = state.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = serve(&state, stream) {
+                                debug!("Mock server connection failed: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(5))
+                    }
+                    Err(e) => debug!("Mock server failed to accept: {}", e),
+                }
+            }
+        }));
+        Ok(server)
+    }
+
+    /// URL of the JSON-RPC endpoint, which also takes WebSocket upgrades
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.address)
+    }
+
+    /// Sets an account, notifying its subscribers and those of its owner.
+    pub fn set_account(&self, pubkey: Pubkey, account: AccountInfoResult) {
+        let mut state = self.state.lock().unwrap();
+        state.accounts.insert(pubkey, account.clone());
+        state.notify(|subscription| match subscription {
+            Subscription::Account(key) if *key == pubkey => {
+                Some((ACCOUNT_NOTIFICATION, json!(account)))
+            }
+            Subscription::Program(program_id) if *program_id == account.owner => Some((
+                PROGRAM_NOTIFICATION,
+                json!(ProgramAccount {
+                    pubkey,
+                    account: account.clone(),
+                }),
+            )),
+            _ => None,
+        });
+    }
+
+    /// Sets a processed transaction, notifying its subscribers.
+    pub fn set_transaction(&self, processed_tx: ProcessedTransaction) {
+        self.state.lock().unwrap().set_transaction(processed_tx);
+    }
+
+    /// Ids of the transactions sent to the server, in order
+    pub fn received_transactions(&self) -> Vec<String> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// Marks transactions sent from now on as processed `delay` after they
+    /// are received, with the Bitcoin txids `bitcoin_txids` returns for
+    /// them. The change is seen by the next request after the delay, and
+    /// only then notified to subscribers.
+    pub fn process_sent_after(
+        &self,
+        delay: Duration,
+        bitcoin_txids: impl Fn(&RuntimeTransaction) -> Vec<String> + Send + 'static,
+    ) {
+        self.state.lock().unwrap().processing = Some(Processing {
+            delay,
+            bitcoin_txids: Box::new(bitcoin_txids),
+        });
+    }
+
+    /// Sets the answer of `is_node_ready`.
+    pub fn set_ready(&self, ready: bool) {
+        self.state.lock().unwrap().not_ready = !ready;
+    }
+
+    /// Sets the answer of `all_nodes_ready`.
+    pub fn set_all_nodes_ready(&self, ready: bool) {
+        self.state.lock().unwrap().nodes_not_ready = !ready;
+    }
+
+    /// Appends a block containing the given transactions, returning its hash.
+    pub fn add_block(&self, transactions: Vec<String>) -> String {
+        let mut state = self.state.lock().unwrap();
+        let previous_block_hash = state
+            .best_block_hash
+            .clone()
+            .unwrap_or_else(|| "0".repeat(64));
+        let hash = sha256::digest(format!("{}{}", previous_block_hash, transactions.concat()));
+        state.blocks.insert(
+            hash.clone(),
+            Block {
+                previous_block_hash,
+                transactions,
+            },
+        );
+        state.best_block_hash = Some(hash.clone());
+        hash
+    }
+
+    /// Number of active subscriptions over all connections
+    pub fn subscription_count(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .subscribers
+            .values()
+            .map(|subscriber| subscriber.subscriptions.len())
+            .sum()
+    }
+
+    /// Closes every WebSocket connection, as a restarting node would.
+    pub fn disconnect_subscribers(&self) {
+        let mut state = self.state.lock().unwrap();
+        for (_, mut subscriber) in state.subscribers.drain() {
+            subscriber.websocket.close();
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        // Once the listener is closed, connections are refused like by a
+        // stopped node
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+        self.disconnect_subscribers();
+    }
+}
+
+impl State {
+    fn set_transaction(&mut self, processed_tx: ProcessedTransaction) {
+        let txid = processed_tx.txid();
+        self.transactions.insert(txid.clone(), processed_tx.clone());
+        self.notify(|subscription| match subscription {
+            Subscription::Transaction(id) if *id == txid => {
+                Some((TRANSACTION_NOTIFICATION, json!(processed_tx)))
+            }
+            _ => None,
+        });
+    }
+
+    /// Keeps a sent transaction as processing, returning its id.
+    fn receive(&mut self, transaction: RuntimeTransaction) -> String {
+        let txid = transaction.txid();
+        self.received.push(txid.clone());
+        if !self.transactions.contains_key(&txid) {
+            if let Some(processing) = &self.processing {
+                self.pending
+                    .push((Instant::now() + processing.delay, txid.clone()));
+            }
+            self.set_transaction(ProcessedTransaction {
+                runtime_transaction: transaction,
+                status: Status::Processing,
+                bitcoin_txids: vec![],
+                logs: vec![],
+                compute_units_consumed: 0,
+                return_data: None,
+            });
+        }
+        txid
+    }
+
+    /// Sends the notification returned by `notification` for each
+    /// subscription, dropping subscribers that can't be written to.
+    fn notify(&mut self, notification: impl Fn(&Subscription) -> Option<(&'static str, Value)>) {
+        self.subscribers.retain(|_, subscriber| {
+            subscriber.subscriptions.iter().all(|(id, subscription)| {
+                let Some((method, result)) = notification(subscription) else {
+                    return true;
+                };
+                let message = json!({
+                    "jsonrpc": "2.0",
+                    "method": method,
+                    "params": { "subscription": id, "result": result },
+                });
+                subscriber.websocket.send_text(&message.to_string()).is_ok()
+            })
+        });
+    }
+
+    /// Processes the pending transactions that are due.
+    fn process_due(&mut self) {
+        let now = Instant::now();
+        let (due, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(at, _)| *at <= now);
+        self.pending = pending;
+
+        for (_, txid) in due {
+            let (Some(processing), Some(processed_tx)) =
+                (&self.processing, self.transactions.get(&txid))
+            else {
+                continue;
+            };
+            let processed_tx = ProcessedTransaction {
+                status: Status::Processed,
+                bitcoin_txids: (processing.bitcoin_txids)(&processed_tx.runtime_transaction),
+                ..processed_tx.clone()
+            };
+            self.set_transaction(processed_tx);
+        }
+    }
+
+    fn call(&mut self, method: &str, params: &Value) -> std::result::Result<Value, (i64, String)> {
+        self.process_due();
+        let not_found = |what: &str| (TRANSACTION_NOT_FOUND_CODE, format!("{} not found", what));
+        match method {
+            READ_ACCOUNT_INFO => {
+                let pubkey = parse::<Pubkey>(params)?;
+                let account = self
+                    .accounts
+                    .get(&pubkey)
+                    .ok_or_else(|| not_found("account"))?;
+                Ok(json!(account))
+            }
+            GET_PROGRAM_ACCOUNTS => {
+                let program_id = parse::<Pubkey>(params)?;
+                let accounts = self
+                    .accounts
+                    .iter()
+                    .filter(|(_, account)| account.owner == program_id)
+                    .map(|(pubkey, account)| ProgramAccount {
+                        pubkey: *pubkey,
+                        account: account.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                Ok(json!(accounts))
+            }
+            GET_PROCESSED_TRANSACTION => {
+                let txid = parse::<String>(params)?;
+                let processed_tx = self
+                    .transactions
+                    .get(&txid)
+                    .ok_or_else(|| not_found("transaction"))?;
+                Ok(json!(processed_tx))
+            }
+            GET_BEST_BLOCK_HASH => {
+                let hash = self
+                    .best_block_hash
+                    .as_ref()
+                    .ok_or_else(|| not_found("block"))?;
+                Ok(json!(hash))
+            }
+            GET_BLOCK => {
+                let hash = parse::<String>(params)?;
+                let block = self.blocks.get(&hash).ok_or_else(|| not_found("block"))?;
+                Ok(json!(block))
+            }
+            SEND_TRANSACTION => {
+                let transaction = parse::<RuntimeTransaction>(params)?;
+                Ok(json!(self.receive(transaction)))
+            }
+            SEND_TRANSACTIONS => {
+                let transactions = parse::<Vec<RuntimeTransaction>>(params)?;
+                let txids = transactions
+                    .into_iter()
+                    .map(|transaction| self.receive(transaction))
+                    .collect::<Vec<_>>();
+                Ok(json!(txids))
+            }
+            IS_NODE_READY => Ok(json!(!self.not_ready)),
+            ALL_NODES_READY => Ok(json!(!self.nodes_not_ready)),
+            method => Err((-32601, format!("method {} not found", method))),
+        }
+    }
+
+    /// Handles a request received over WebSocket, returning its response and
+    /// the notifications of the current state for new subscriptions.
+    fn call_subscription(&mut self, subscriber_id: u64, request: &Value) -> (Value, Vec<Value>) {
+        let params = &request["params"];
+        let subscription = match request["method"].as_str().unwrap_or_default() {
+            ACCOUNT_SUBSCRIBE => parse(params).map(Subscription::Account),
+            TRANSACTION_SUBSCRIBE => parse(params).map(Subscription::Transaction),
+            PROGRAM_SUBSCRIBE => parse(params).map(Subscription::Program),
+            UNSUBSCRIBE => {
+                let removed = parse::<u64>(params).map(|id| {
+                    self.subscribers
+                        .get_mut(&subscriber_id)
+                        .and_then(|subscriber| subscriber.subscriptions.remove(&id))
+                        .is_some()
+                });
+                return (response(request, removed.map(Value::Bool)), vec![]);
+            }
+            method => Err((-32601, format!("method {} not found", method))),
+        };
+        let subscription = match subscription {
+            Ok(subscription) => subscription,
+            Err(e) => return (response(request, Err(e)), vec![]),
+        };
+
+        self.next_id += 1;
+        let id = self.next_id;
+        let notify = |method: &str, result: Value| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": method,
+                "params": { "subscription": id, "result": result },
+            })
+        };
+        let notifications = match &subscription {
+            Subscription::Account(pubkey) => self
+                .accounts
+                .get(pubkey)
+                .map(|account| notify(ACCOUNT_NOTIFICATION, json!(account)))
+                .into_iter()
+                .collect(),
+            Subscription::Transaction(txid) => self
+                .transactions
+                .get(txid)
+                .map(|processed_tx| notify(TRANSACTION_NOTIFICATION, json!(processed_tx)))
+                .into_iter()
+                .collect(),
+            Subscription::Program(program_id) => self
+                .accounts
+                .iter()
+                .filter(|(_, account)| account.owner == *program_id)
+                .map(|(pubkey, account)| {
+                    let program_account = ProgramAccount {
+                        pubkey: *pubkey,
+                        account: account.clone(),
+                    };
+                    notify(PROGRAM_NOTIFICATION, json!(program_account))
+                })
+                .collect(),
+        };
+        if let Some(subscriber) = self.subscribers.get_mut(&subscriber_id) {
+            subscriber.subscriptions.insert(id, subscription);
+        }
+        (response(request, Ok(json!(id))), notifications)
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(params: &Value) -> std::result::Result<T, (i64, String)> {
+    serde_json::from_value(params.clone()).map_err(|e| (-32602, format!("invalid params: {}", e)))
+}
+
+fn response(request: &Value, result: std::result::Result<Value, (i64, String)>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+        Err((code, message)) => json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "error": { "code": code, "message": message },
+        }),
+    }
+}
+
+fn serve(state: &Mutex<State>, mut stream: TcpStream) -> Result<()> {
+    let (head, mut body) = websocket::read_http_head(&mut stream)?;
+
+    let is_upgrade = websocket::header(&head, "upgrade")
+        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+    if is_upgrade {
+        if !state.lock().unwrap().push {
+            stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")?;
+            return Ok(());
+        }
+        return serve_subscriber(state, websocket::accept(stream, &head, body)?);
+    }
+
+    let len = websocket::header(&head, "content-length")
+        .and_then(|len| len.parse::<usize>().ok())
+        .unwrap_or_default();
+    while body.len() < len {
+        let mut chunk = [0; 4096];
+        match stream.read(&mut chunk)? {
+            0 => return Err(anyhow!("connection closed before the end of the body")),
+            n => body.extend(&chunk[..n]),
+        }
+    }
+
+    let request = serde_json::from_slice::<Value>(&body)?;
+    let method = request["method"].as_str().unwrap_or_default();
+    let result = state.lock().unwrap().call(method, &request["params"]);
+    let body = response(&request, result).to_string();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+fn serve_subscriber(state: &Mutex<State>, mut websocket: WebSocket) -> Result<()> {
+    let subscriber_id = {
+        let mut state = state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+        let subscriber = Subscriber {
+            websocket: websocket.try_clone()?,
+            subscriptions: HashMap::new(),
+        };
+        state.subscribers.insert(id, subscriber);
+        id
+    };
+
+    let result = loop {
+        let text = match websocket.read_message() {
+            Ok(Some(Message::Text(text))) => text,
+            Ok(Some(Message::Close)) => break Ok(()),
+            Ok(None) => continue,
+            Err(e) => break Err(e),
+        };
+        let request = match serde_json::from_str::<Value>(&text) {
+            Ok(request) => request,
+            Err(e) => break Err(e.into()),
+        };
+
+        let mut locked = state.lock().unwrap();
+        let (response, notifications) = locked.call_subscription(subscriber_id, &request);
+        let Some(subscriber) = locked.subscribers.get_mut(&subscriber_id) else {
+            break Ok(());
+        };
+        let sent = std::iter::once(response)
+            .chain(notifications)
+            .try_for_each(|message| subscriber.websocket.send_text(&message.to_string()));
+        if let Err(e) = sent {
+            break Err(e);
+        }
+    };
+
+    state.lock().unwrap().subscribers.remove(&subscriber_id);
+    result
+}
@@ -5,17 +5,18 @@ use bitcoin::{
     self,
     address::Address,
     key::{Parity, UntweakedKeypair, XOnlyPublicKey},
-    secp256k1::{Secp256k1, SecretKey},
+    secp256k1::Secp256k1,
+    Network,
 };
 use sdk::signature::{Signature};
-use rand_core::OsRng;
 use serde::{Deserialize, Serialize};
 use serde_json::to_string;
 use sha256::digest;
-use std::fs;
-use std::str::FromStr;
 use sdk::arch_program::pubkey::Pubkey;
 
+use crate::config::active_profile;
+use crate::keystore::Keystore;
+
 /// Represents the parameters for deploying a program
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DeployProgramParams {
@@ -73,28 +74,23 @@ pub struct CallerInfo {
 }
 
 impl CallerInfo {
-    /// Create a [CallerInfo] from the specified file path
-    /// If the file does not exist, generate a random secret key
-    /// and use that instead.
+    /// Create a [CallerInfo] from the key file at the specified path,
+    /// failing if it doesn't exist
     pub fn with_secret_key_file(file_path: &str) -> Result<CallerInfo> {
+        let (key_pair, _) = Keystore::from_env().load(file_path)?;
+        Ok(CallerInfo::new(key_pair, active_profile()?.network))
+    }
+
+    /// Create a [CallerInfo] from its keypair, with its address on `network`
+    pub fn new(key_pair: UntweakedKeypair, network: Network) -> CallerInfo {
         let secp = Secp256k1::new();
-        let secret_key = match fs::read_to_string(file_path) {
-            Ok(key) => SecretKey::from_str(&key).unwrap(),
-            Err(_) => {
-                let (key, _) = secp.generate_keypair(&mut OsRng);
-                fs::write(file_path, &key.display_secret().to_string())
-                    .map_err(|_| anyhow!("Unable to write file"))?;
-                key
-            }
-        };
-        let key_pair = UntweakedKeypair::from_secret_key(&secp, &secret_key);
         let (public_key, parity) = XOnlyPublicKey::from_keypair(&key_pair);
-        let address = Address::p2tr(&secp, public_key, None, bitcoin::Network::Regtest);
-        Ok(CallerInfo {
+        let address = Address::p2tr(&secp, public_key, None, network);
+        CallerInfo {
             key_pair,
             public_key,
             parity,
             address,
-        })
+        }
     }
 }
@@ -0,0 +1,606 @@
+//! An in-memory regtest chain, so the helpers funding accounts can be tested
+//! without bitcoind or a network.
+//!
+//! [`RegtestSimulator`] keeps a UTXO set, a mempool and the blocks mined on
+//! demand, and pays from a wallet key of its own like bitcoind pays from its
+//! wallet. It implements [`BitcoinBackend`], so [`send_utxo_to`] and
+//! [`prepare_fees_with`] run against it unchanged.
+//!
+//! Transactions are checked for missing or spent inputs, immature coinbase
+//! spends and outputs worth more than their inputs, and their taproot key
+//! path signatures are verified. Other scripts are accepted without being run.
+//!
+//! [`send_utxo_to`]: crate::helper::send_utxo_to
+//! [`prepare_fees_with`]: crate::helper::prepare_fees_with
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::{anyhow, bail, Result};
+use bitcoin::absolute::LockTime;
+use bitcoin::block::{Header, Version as BlockVersion};
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::hashes::Hash;
+use bitcoin::key::UntweakedKeypair;
+use bitcoin::secp256k1::{self, Secp256k1};
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::taproot::Signature;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Address, Amount, Block, BlockHash, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn,
+    TxMerkleNode, TxOut, Txid, Witness, XOnlyPublicKey,
+};
+
+use crate::bitcoin_backend::{
+    build_payment, AddressUtxo, BitcoinBackend, COINBASE_MATURITY, DEFAULT_FEE_RATE,
+};
+
+/// Blocks between two halvings of the subsidy on regtest
+const SUBSIDY_HALVING_INTERVAL: u64 = 150;
+
+struct Coin {
+    output: TxOut,
+    /// Height of the block holding the output, `None` while in the mempool
+    height: Option<u64>,
+    is_coinbase: bool,
+}
+
+#[derive(Default)]
+struct Chain {
+    blocks: Vec<Block>,
+    /// Every transaction seen, with the height of its block once mined
+    transactions: HashMap<Txid, (Transaction, Option<u64>)>,
+    /// Unspent outputs, including those of mempool transactions
+    coins: HashMap<OutPoint, Coin>,
+    mempool: Vec<Txid>,
+}
+
+impl Chain {
+    fn height(&self) -> u64 {
+        self.blocks.len() as u64 - 1
+    }
+
+    fn add_outputs(&mut self, transaction: &Transaction, height: Option<u64>) {
+        let txid = transaction.txid();
+        for (vout, output) in transaction.output.iter().enumerate() {
+            self.coins.insert(
+                OutPoint::new(txid, vout as u32),
+                Coin {
+                    output: output.clone(),
+                    height,
+                    is_coinbase: transaction.is_coinbase(),
+                },
+            );
+        }
+    }
+
+    /// Check `transaction` against the UTXO set.
+    fn check(&self, transaction: &Transaction) -> Result<()> {
+        let txid = transaction.txid();
+        if self.transactions.contains_key(&txid) {
+            bail!("transaction {} is already known", txid);
+        }
+        if transaction.is_coinbase() {
+            bail!("coinbase transactions can't be broadcast");
+        }
+        if transaction.input.is_empty() || transaction.output.is_empty() {
+            bail!("transaction {} has no inputs or no outputs", txid);
+        }
+
+        let mut prevouts = vec![];
+        for input in &transaction.input {
+            let coin = self.coins.get(&input.previous_output).ok_or_else(|| {
+                anyhow!(
+                    "input {} of {} is missing or spent",
+                    input.previous_output,
+                    txid
+                )
+            })?;
+            // The transaction would be mined in the next block at best
+            let confirmations = coin.height.map_or(0, |height| self.height() + 1 - height);
+            if coin.is_coinbase && confirmations < COINBASE_MATURITY {
+                bail!(
+                    "input {} of {} spends an immature coinbase",
+                    input.previous_output,
+                    txid
+                );
+            }
+            prevouts.push(coin.output.clone());
+        }
+
+        let input_value = prevouts.iter().map(|prevout| prevout.value).sum::<Amount>();
+        let output_value = transaction
+            .output
+            .iter()
+            .map(|output| output.value)
+            .sum::<Amount>();
+        if output_value > input_value {
+            bail!(
+                "{} spends {} but its inputs are worth {}",
+                txid,
+                output_value,
+                input_value
+            );
+        }
+
+        verify_key_spends(transaction, &prevouts)
+    }
+
+    fn accept(&mut self, transaction: Transaction) -> Result<Txid> {
+        self.check(&transaction)?;
+
+        let txid = transaction.txid();
+        for input in &transaction.input {
+            self.coins.remove(&input.previous_output);
+        }
+        self.add_outputs(&transaction, None);
+        self.transactions.insert(txid, (transaction, None));
+        self.mempool.push(txid);
+        Ok(txid)
+    }
+
+    fn mine(&mut self, address: &Address) -> BlockHash {
+        let height = self.height() + 1;
+        let mempool = std::mem::take(&mut self.mempool);
+
+        let fees = mempool
+            .iter()
+            .map(|txid| {
+                let (transaction, _) = &self.transactions[txid];
+                let input_value = transaction
+                    .input
+                    .iter()
+                    .map(|input| self.spent_output(&input.previous_output).value)
+                    .sum::<Amount>();
+                input_value - transaction.output.iter().map(|output| output.value).sum()
+            })
+            .sum::<Amount>();
+        let subsidy = Amount::from_int_btc(50)
+            .checked_div(1 << (height / SUBSIDY_HALVING_INTERVAL).min(63))
+            .unwrap_or(Amount::ZERO);
+
+        let coinbase = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::builder().push_int(height as i64).into_script(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: subsidy + fees,
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+
+        let mut txdata = vec![coinbase];
+        txdata.extend(mempool.iter().map(|txid| self.transactions[txid].0.clone()));
+        let previous = self.blocks.last().expect("the chain has a genesis block");
+        let mut block = Block {
+            header: Header {
+                version: BlockVersion::TWO,
+                prev_blockhash: previous.block_hash(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: previous.header.time + 1,
+                bits: previous.header.bits,
+                nonce: 0,
+            },
+            txdata,
+        };
+        block.header.merkle_root = block
+            .compute_merkle_root()
+            .expect("the block has a coinbase");
+
+        for transaction in &block.txdata {
+            let txid = transaction.txid();
+            for (vout, _) in transaction.output.iter().enumerate() {
+                if let Some(coin) = self.coins.get_mut(&OutPoint::new(txid, vout as u32)) {
+                    coin.height = Some(height);
+                }
+            }
+            self.transactions
+                .insert(txid, (transaction.clone(), Some(height)));
+        }
+        self.add_outputs(&block.txdata[0], Some(height));
+
+        let hash = block.block_hash();
+        self.blocks.push(block);
+        hash
+    }
+
+    /// The output spent by `outpoint`, which was checked to exist when its
+    /// spending transaction was accepted.
+    fn spent_output(&self, outpoint: &OutPoint) -> &TxOut {
+        let (transaction, _) = &self.transactions[&outpoint.txid];
+        &transaction.output[outpoint.vout as usize]
+    }
+}
+
+/// Verify the signatures of the inputs spending taproot outputs through their
+/// key path.
+fn verify_key_spends(transaction: &Transaction, prevouts: &[TxOut]) -> Result<()> {
+    let secp = Secp256k1::verification_only();
+    let mut sighasher = SighashCache::new(transaction);
+
+    for (index, (input, prevout)) in transaction.input.iter().zip(prevouts).enumerate() {
+        if !prevout.script_pubkey.is_p2tr() || input.witness.len() != 1 {
+            continue;
+        }
+        let signature = Signature::from_slice(&input.witness[0])
+            .map_err(|e| anyhow!("input {} has an invalid signature: {}", index, e))?;
+        let sighash = sighasher.taproot_key_spend_signature_hash(
+            index,
+            &Prevouts::All(prevouts),
+            signature.hash_ty,
+        )?;
+        let output_key = XOnlyPublicKey::from_slice(&prevout.script_pubkey.as_bytes()[2..])?;
+        secp.verify_schnorr(
+            &signature.sig,
+            &secp256k1::Message::from(sighash),
+            &output_key,
+        )
+        .map_err(|_| {
+            anyhow!(
+                "input {} of {} has a wrong signature",
+                index,
+                transaction.txid()
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// A regtest chain held in memory.
+pub struct RegtestSimulator {
+    chain: Mutex<Chain>,
+    wallet_keypair: UntweakedKeypair,
+    wallet: Address,
+}
+
+impl Default for RegtestSimulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegtestSimulator {
+    /// A chain holding only the regtest genesis block, with a new wallet key.
+    pub fn new() -> Self {
+        let secp = Secp256k1::new();
+        let (secret_key, _) = secp.generate_keypair(&mut secp256k1::rand::thread_rng());
+        let wallet_keypair = UntweakedKeypair::from_secret_key(&secp, &secret_key);
+        let wallet = Address::p2tr(
+            &secp,
+            XOnlyPublicKey::from_keypair(&wallet_keypair).0,
+            None,
+            Network::Regtest,
+        );
+
+        let chain = Chain {
+            blocks: vec![genesis_block(Network::Regtest)],
+            ..Default::default()
+        };
+        Self {
+            chain: Mutex::new(chain),
+            wallet_keypair,
+            wallet,
+        }
+    }
+
+    /// A chain whose wallet can spend the coinbase of its first block, as
+    /// after `generatetoaddress 101` on a new bitcoind.
+    pub fn funded() -> Self {
+        let simulator = Self::new();
+        simulator.generate(COINBASE_MATURITY + 1);
+        simulator
+    }
+
+    fn chain(&self) -> MutexGuard<'_, Chain> {
+        self.chain.lock().expect("the chain isn't poisoned")
+    }
+
+    /// The taproot address the wallet receives and pays from.
+    pub fn wallet_address(&self) -> &Address {
+        &self.wallet
+    }
+
+    /// Mine `count` blocks paying their rewards to the wallet.
+    pub fn generate(&self, count: u64) -> Vec<BlockHash> {
+        self.generate_to_address(count, &self.wallet.clone())
+    }
+
+    /// Mine `count` blocks paying their rewards to `address`, the first one
+    /// holding the mempool transactions.
+    pub fn generate_to_address(&self, count: u64, address: &Address) -> Vec<BlockHash> {
+        let mut chain = self.chain();
+        (0..count).map(|_| chain.mine(address)).collect()
+    }
+
+    /// Ids of the transactions waiting to be mined, in arrival order.
+    pub fn mempool(&self) -> Vec<Txid> {
+        self.chain().mempool.clone()
+    }
+
+    pub fn block(&self, height: u64) -> Option<Block> {
+        self.chain().blocks.get(height as usize).cloned()
+    }
+
+    /// Height of the block holding `txid`, `None` while in the mempool.
+    pub fn transaction_height(&self, txid: &Txid) -> Result<Option<u64>> {
+        self.chain()
+            .transactions
+            .get(txid)
+            .map(|(_, height)| *height)
+            .ok_or_else(|| anyhow!("No such transaction {}", txid))
+    }
+
+    /// Total value of the UTXOs of `address`, including unconfirmed ones.
+    pub fn balance(&self, address: &Address) -> Amount {
+        let script_pubkey = address.script_pubkey();
+        self.chain()
+            .coins
+            .values()
+            .filter(|coin| coin.output.script_pubkey == script_pubkey)
+            .map(|coin| coin.output.value)
+            .sum()
+    }
+}
+
+impl BitcoinBackend for RegtestSimulator {
+    /// Pays from the wallet UTXOs that can be spent in the next block.
+    fn send_to_address(&self, address: &Address, amount: Amount) -> Result<Txid> {
+        let mut chain = self.chain();
+        let tip = chain.height();
+        let script_pubkey = self.wallet.script_pubkey();
+        let utxos = chain
+            .coins
+            .iter()
+            .filter(|(_, coin)| coin.output.script_pubkey == script_pubkey)
+            .filter(|(_, coin)| {
+                !coin.is_coinbase
+                    || coin
+                        .height
+                        .is_some_and(|height| tip + 1 - height >= COINBASE_MATURITY)
+            })
+            .map(|(outpoint, coin)| AddressUtxo {
+                txid: outpoint.txid,
+                vout: outpoint.vout,
+                value: coin.output.value,
+                block_height: coin.height,
+            })
+            .collect::<Vec<_>>();
+
+        let transaction = build_payment(
+            &self.wallet_keypair,
+            &self.wallet,
+            &utxos,
+            address,
+            amount,
+            DEFAULT_FEE_RATE,
+        )?;
+        chain.accept(transaction)
+    }
+
+    fn get_raw_transaction(&self, txid: &Txid) -> Result<Transaction> {
+        self.chain()
+            .transactions
+            .get(txid)
+            .map(|(transaction, _)| transaction.clone())
+            .ok_or_else(|| anyhow!("No such transaction {}", txid))
+    }
+
+    fn get_block_count(&self) -> Result<u64> {
+        Ok(self.chain().height())
+    }
+
+    fn get_address_utxos(&self, address: &Address) -> Result<Vec<AddressUtxo>> {
+        let script_pubkey = address.script_pubkey();
+        let mut utxos = self
+            .chain()
+            .coins
+            .iter()
+            .filter(|(_, coin)| coin.output.script_pubkey == script_pubkey)
+            .map(|(outpoint, coin)| AddressUtxo {
+                txid: outpoint.txid,
+                vout: outpoint.vout,
+                value: coin.output.value,
+                block_height: coin.height,
+            })
+            .collect::<Vec<_>>();
+        utxos.sort_by_key(|utxo| {
+            (
+                utxo.block_height.is_none(),
+                utxo.block_height,
+                utxo.outpoint(),
+            )
+        });
+        Ok(utxos)
+    }
+
+    fn send_raw_transaction(&self, transaction: &Transaction) -> Result<Txid> {
+        self.chain().accept(transaction.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helper::{get_address_utxos, prepare_fees_with, send_utxo_to};
+    use crate::models::CallerInfo;
+    use bitcoin::consensus::encode::deserialize;
+    use bitcoin::TapSighashType;
+
+    fn new_caller() -> CallerInfo {
+        let secp = Secp256k1::new();
+        let (secret_key, _) = secp.generate_keypair(&mut secp256k1::rand::thread_rng());
+        CallerInfo::new(
+            UntweakedKeypair::from_secret_key(&secp, &secret_key),
+            Network::Regtest,
+        )
+    }
+
+    #[test]
+    fn test_mining_and_maturity() {
+        let simulator = RegtestSimulator::new();
+        assert_eq!(simulator.get_block_count().unwrap(), 0);
+
+        simulator.generate(COINBASE_MATURITY - 1);
+        assert_eq!(simulator.get_block_count().unwrap(), 99);
+        assert_eq!(
+            simulator.balance(simulator.wallet_address()),
+            Amount::from_int_btc(99 * 50)
+        );
+        // The first coinbase can only be spent from block 101 on
+        assert!(simulator
+            .send_to_address(&new_caller().address, Amount::from_sat(3000))
+            .unwrap_err()
+            .to_string()
+            .contains("not enough"));
+
+        simulator.generate(1);
+        assert!(get_address_utxos(&simulator, simulator.wallet_address())
+            .unwrap()
+            .is_empty());
+        simulator.generate(1);
+        let wallet_utxos = get_address_utxos(&simulator, simulator.wallet_address()).unwrap();
+        assert_eq!(wallet_utxos.len(), 1);
+        assert_eq!(wallet_utxos[0].block_height, Some(1));
+        simulator
+            .send_to_address(&new_caller().address, Amount::from_sat(3000))
+            .unwrap();
+
+        // Regtest halves the subsidy every 150 blocks
+        simulator.generate(SUBSIDY_HALVING_INTERVAL);
+        let block = simulator.block(SUBSIDY_HALVING_INTERVAL).unwrap();
+        assert_eq!(block.txdata[0].output[0].value, Amount::from_int_btc(25));
+        assert_eq!(
+            block.header.prev_blockhash,
+            simulator.block(149).unwrap().block_hash()
+        );
+    }
+
+    #[test]
+    fn test_send_utxo() {
+        let simulator = RegtestSimulator::funded();
+        let caller = new_caller();
+
+        let (txid, vout) = send_utxo_to(&simulator, &caller.address).unwrap();
+        let txid: Txid = txid.parse().unwrap();
+        assert_eq!(simulator.mempool(), vec![txid]);
+        assert_eq!(simulator.transaction_height(&txid).unwrap(), None);
+
+        let sent_tx = simulator.get_raw_transaction(&txid).unwrap();
+        assert_eq!(sent_tx.output[vout as usize].value, Amount::from_sat(3000));
+        assert_eq!(simulator.balance(&caller.address), Amount::from_sat(3000));
+
+        // A second payment waits in the mempool with the first
+        send_utxo_to(&simulator, &caller.address).unwrap();
+
+        let [hash] = simulator.generate(1)[..] else {
+            panic!("one block is mined")
+        };
+        assert!(simulator.mempool().is_empty());
+        assert_eq!(simulator.transaction_height(&txid).unwrap(), Some(102));
+        let block = simulator.block(102).unwrap();
+        assert_eq!(block.block_hash(), hash);
+        assert_eq!(block.txdata.len(), 3);
+        assert_eq!(
+            block.header.merkle_root,
+            block.compute_merkle_root().unwrap()
+        );
+
+        let utxos = simulator.get_address_utxos(&caller.address).unwrap();
+        assert_eq!(utxos.len(), 2);
+        assert!(utxos.iter().all(|utxo| utxo.block_height == Some(102)));
+
+        // The fees go to the miner
+        let coinbase_value = block.txdata[0].output[0].value;
+        assert!(coinbase_value > Amount::from_int_btc(50));
+    }
+
+    #[test]
+    fn test_prepare_fees() {
+        let simulator = RegtestSimulator::funded();
+        let caller = new_caller();
+
+        let tx_hex = prepare_fees_with(&simulator, &caller).unwrap();
+        let mut transaction: Transaction = deserialize(&hex::decode(tx_hex).unwrap()).unwrap();
+        assert_eq!(transaction.input.len(), 1);
+        assert!(transaction.output.is_empty());
+
+        // The fee input is signed NONE|ANYONECANPAY, so outputs can be added
+        // before broadcasting it
+        let signature = Signature::from_slice(&transaction.input[0].witness[0]).unwrap();
+        assert_eq!(signature.hash_ty, TapSighashType::NonePlusAnyoneCanPay);
+        transaction.output.push(TxOut {
+            value: Amount::from_sat(2000),
+            script_pubkey: simulator.wallet_address().script_pubkey(),
+        });
+        let txid = simulator.send_raw_transaction(&transaction).unwrap();
+        assert!(simulator.mempool().contains(&txid));
+    }
+
+    #[test]
+    fn test_rejected_transactions() {
+        let simulator = RegtestSimulator::funded();
+        let caller = new_caller();
+        let (txid, vout) = send_utxo_to(&simulator, &caller.address).unwrap();
+        let outpoint = OutPoint::new(txid.parse().unwrap(), vout);
+
+        let spend = |value: u64, keypair: &UntweakedKeypair| {
+            let utxos = [AddressUtxo {
+                txid: outpoint.txid,
+                vout: outpoint.vout,
+                value: Amount::from_sat(value),
+                block_height: None,
+            }];
+            build_payment(
+                keypair,
+                &caller.address,
+                &utxos,
+                simulator.wallet_address(),
+                Amount::from_sat(1000),
+                1,
+            )
+            .unwrap()
+        };
+
+        // Signed by another key
+        assert!(simulator
+            .send_raw_transaction(&spend(3000, &new_caller().key_pair))
+            .unwrap_err()
+            .to_string()
+            .contains("wrong signature"));
+        // Signed over another prevout value
+        assert!(simulator
+            .send_raw_transaction(&spend(3001, &caller.key_pair))
+            .unwrap_err()
+            .to_string()
+            .contains("wrong signature"));
+
+        let valid = spend(3000, &caller.key_pair);
+        simulator.send_raw_transaction(&valid).unwrap();
+        assert!(simulator.send_raw_transaction(&valid).is_err());
+
+        // Double spend
+        let mut double_spend = valid.clone();
+        double_spend.output.pop();
+        assert!(simulator
+            .send_raw_transaction(&double_spend)
+            .unwrap_err()
+            .to_string()
+            .contains("missing or spent"));
+
+        // Spending more than the inputs
+        let mut overspend = Transaction {
+            input: vec![TxIn {
+                previous_output: OutPoint::new(valid.txid(), 1),
+                ..valid.input[0].clone()
+            }],
+            ..valid.clone()
+        };
+        overspend.input[0].witness = Witness::new();
+        overspend.output[0].value = Amount::from_int_btc(1);
+        assert!(simulator.send_raw_transaction(&overspend).is_err());
+    }
+}
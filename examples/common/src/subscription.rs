@@ -0,0 +1,622 @@
+//! Subscriptions to accounts, transactions and the accounts of programs.
+//!
+//! [`SubscriptionClient`] keeps a WebSocket open to the node and sends a
+//! JSON-RPC request for each subscription, `account_subscribe` with a pubkey,
+//! `transaction_subscribe` with a txid or `program_subscribe` with a program
+//! id, whose result is the id of the subscription on the node. The node then
+//! sends the current state followed by every change as notifications:
+//!
+//! ```json
+//! {"jsonrpc": "2.0", "method": "account_notification", "params": {"subscription": 1, "result": {...}}}
+//! ```
+//!
+//! with an [`AccountInfoResult`], a [`ProcessedTransaction`] or a
+//! [`ProgramAccount`] as result. The connection is reopened with a growing
+//! delay when it drops and the subscriptions are sent again. Nodes that don't
+//! accept WebSocket connections are polled with `read_account_info`,
+//! `get_processed_transaction` and `get_program_accounts` instead.
+//!
+//! Either way an update is only delivered when it differs from the last one
+//! of the same account or transaction.
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+
+use sdk::arch_program::pubkey::Pubkey;
+use sdk::processed_transaction::ProcessedTransaction;
+
+use crate::constants::{
+    ACCOUNT_NOTIFICATION, ACCOUNT_SUBSCRIBE, PROGRAM_NOTIFICATION, PROGRAM_SUBSCRIBE,
+    TRANSACTION_NOTIFICATION, TRANSACTION_SUBSCRIBE, UNSUBSCRIBE,
+};
+use crate::helper::{
+    get_program_accounts, get_transaction_status, read_account_info, AccountInfoResult,
+    ProgramAccount,
+};
+use crate::websocket::{self, ConnectError, Message, WebSocket};
+
+/// How often the worker checks for new subscriptions while connected
+const COMMAND_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Subscription {
+    Account(Pubkey),
+    /// A transaction by id
+    Transaction(String),
+    /// Every account owned by the program
+    Program(Pubkey),
+}
+
+impl Subscription {
+    fn request(&self, id: SubscriptionId) -> Value {
+        let (method, params) = match self {
+            Self::Account(pubkey) => (ACCOUNT_SUBSCRIBE, json!(pubkey)),
+            Self::Transaction(txid) => (TRANSACTION_SUBSCRIBE, json!(txid)),
+            Self::Program(program_id) => (PROGRAM_SUBSCRIBE, json!(program_id)),
+        };
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    /// Decodes the result of a notification for this subscription.
+    fn update(&self, method: &str, result: Value) -> Result<Update> {
+        match (self, method) {
+            (Self::Account(pubkey), ACCOUNT_NOTIFICATION) => Ok(Update::Account {
+                pubkey: *pubkey,
+                account: serde_json::from_value(result)?,
+            }),
+            (Self::Transaction(_), TRANSACTION_NOTIFICATION) => {
+                Ok(Update::Transaction(serde_json::from_value(result)?))
+            }
+            (Self::Program(program_id), PROGRAM_NOTIFICATION) => {
+                let ProgramAccount { pubkey, account } = serde_json::from_value(result)?;
+                Ok(Update::ProgramAccount {
+                    program_id: *program_id,
+                    pubkey,
+                    account,
+                })
+            }
+            (subscription, method) => bail!("unexpected {} for {:?}", method, subscription),
+        }
+    }
+
+    /// Reads the current state over JSON-RPC.
+    fn poll(&self, url: &str) -> Result<Vec<Update>> {
+        match self {
+            Self::Account(pubkey) => Ok(vec![Update::Account {
+                pubkey: *pubkey,
+                account: read_account_info(url, *pubkey)?,
+            }]),
+            Self::Transaction(txid) => Ok(get_transaction_status(url, txid)?
+                .map(Update::Transaction)
+                .into_iter()
+                .collect()),
+            Self::Program(program_id) => Ok(get_program_accounts(url, *program_id)?
+                .into_iter()
+                .map(
+                    |ProgramAccount { pubkey, account }| Update::ProgramAccount {
+                        program_id: *program_id,
+                        pubkey,
+                        account,
+                    },
+                )
+                .collect()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Update {
+    Account {
+        pubkey: Pubkey,
+        account: AccountInfoResult,
+    },
+    Transaction(ProcessedTransaction),
+    ProgramAccount {
+        program_id: Pubkey,
+        pubkey: Pubkey,
+        account: AccountInfoResult,
+    },
+}
+
+impl Update {
+    /// The account or transaction updated, to compare updates of the same one
+    fn key(&self) -> String {
+        match self {
+            Self::Account { pubkey, .. } | Self::ProgramAccount { pubkey, .. } => {
+                format!("{:x}", pubkey)
+            }
+            Self::Transaction(processed_tx) => processed_tx.txid(),
+        }
+    }
+}
+
+/// How updates currently reach the client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transport {
+    /// Waiting for the WebSocket to (re)open
+    Connecting,
+    Push,
+    Polling,
+}
+
+#[derive(Clone, Debug)]
+pub struct SubscriptionConfig {
+    /// Derived from the node URL when unset, `http://host/` giving `ws://host/`
+    pub websocket_url: Option<String>,
+    pub connect_timeout: Duration,
+    /// Delay before the first reconnection, doubled after each failure
+    pub reconnect_delay: Duration,
+    pub max_reconnect_delay: Duration,
+    /// Delay between polls when the node has no WebSocket
+    pub poll_interval: Duration,
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        Self {
+            websocket_url: None,
+            connect_timeout: Duration::from_secs(5),
+            reconnect_delay: Duration::from_millis(250),
+            max_reconnect_delay: Duration::from_secs(10),
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+pub type SubscriptionId = u64;
+
+enum Command {
+    Subscribe(SubscriptionId, Subscription),
+    Unsubscribe(SubscriptionId),
+    Shutdown,
+}
+
+/// Delivers the updates of subscriptions from a background thread.
+pub struct SubscriptionClient {
+    commands: Sender<Command>,
+    updates: Receiver<Update>,
+    transport: Arc<Mutex<Transport>>,
+    next_id: AtomicU64,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl SubscriptionClient {
+    pub fn connect(url: &str) -> Self {
+        Self::with_config(url, SubscriptionConfig::default())
+    }
+
+    pub fn with_config(url: &str, config: SubscriptionConfig) -> Self {
+        let (commands, command_receiver) = mpsc::channel();
+        let (update_sender, updates) = mpsc::channel();
+        let transport = Arc::new(Mutex::new(Transport::Connecting));
+
+        let worker = Worker {
+            url: url.to_string(),
+            config,
+            commands: command_receiver,
+            updates: update_sender,
+            transport: transport.clone(),
+            subscriptions: BTreeMap::new(),
+            delivered: HashMap::new(),
+            stopped: false,
+        };
+        Self {
+            commands,
+            updates,
+            transport,
+            next_id: AtomicU64::new(1),
+            worker: Some(thread::spawn(move || worker.run())),
+        }
+    }
+
+    pub fn subscribe(&self, subscription: Subscription) -> SubscriptionId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let _ = self.commands.send(Command::Subscribe(id, subscription));
+        id
+    }
+
+    pub fn unsubscribe(&self, id: SubscriptionId) {
+        let _ = self.commands.send(Command::Unsubscribe(id));
+    }
+
+    pub fn transport(&self) -> Transport {
+        *self.transport.lock().unwrap()
+    }
+
+    /// Waits for the next update.
+    pub fn recv(&self) -> Option<Update> {
+        self.updates.recv().ok()
+    }
+
+    /// Waits for the next update, up to `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Update> {
+        self.updates.recv_timeout(timeout).ok()
+    }
+
+    /// The updates received so far, without waiting.
+    pub fn try_iter(&self) -> impl Iterator<Item = Update> + '_ {
+        self.updates.try_iter()
+    }
+}
+
+impl Drop for SubscriptionClient {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Shutdown);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+struct Worker {
+    url: String,
+    config: SubscriptionConfig,
+    commands: Receiver<Command>,
+    updates: Sender<Update>,
+    transport: Arc<Mutex<Transport>>,
+    subscriptions: BTreeMap<SubscriptionId, Subscription>,
+    /// Last update delivered for each subscription and key
+    delivered: HashMap<(SubscriptionId, String), Update>,
+    stopped: bool,
+}
+
+impl Worker {
+    fn run(mut self) {
+        let websocket_url = self.config.websocket_url.clone().unwrap_or_else(|| {
+            self.url
+                .replacen("http://", "ws://", 1)
+                .replacen("https://", "wss://", 1)
+        });
+
+        let mut delay = self.config.reconnect_delay;
+        while !self.stopped {
+            match websocket::connect(&websocket_url, self.config.connect_timeout) {
+                Ok(websocket) => {
+                    delay = self.config.reconnect_delay;
+                    self.set_transport(Transport::Push);
+                    if let Err(e) = self.push(websocket) {
+                        warn!("Subscriptions to {} dropped: {}", websocket_url, e);
+                    }
+                    self.set_transport(Transport::Connecting);
+                }
+                Err(ConnectError::Unsupported(reason)) => {
+                    info!("{}, polling {} instead", reason, self.url);
+                    self.set_transport(Transport::Polling);
+                    return self.poll();
+                }
+                Err(ConnectError::Io(e)) => {
+                    debug!("Failed to connect to {}: {}", websocket_url, e);
+                    self.receive_commands(delay);
+                    delay = (delay * 2).min(self.config.max_reconnect_delay);
+                }
+            }
+        }
+    }
+
+    fn set_transport(&self, transport: Transport) {
+        *self.transport.lock().unwrap() = transport;
+    }
+
+    /// Waits up to `timeout` for commands and applies them to the
+    /// subscriptions, returning them for the transport to act on.
+    fn receive_commands(&mut self, timeout: Duration) -> Vec<Command> {
+        let first = match self.commands.recv_timeout(timeout) {
+            Ok(command) => command,
+            Err(RecvTimeoutError::Timeout) => return vec![],
+            Err(RecvTimeoutError::Disconnected) => {
+                self.stopped = true;
+                return vec![];
+            }
+        };
+
+        let commands = std::iter::once(first)
+            .chain(self.commands.try_iter())
+            .collect::<Vec<_>>();
+        for command in &commands {
+            match command {
+                Command::Subscribe(id, subscription) => {
+                    self.subscriptions.insert(*id, subscription.clone());
+                }
+                Command::Unsubscribe(id) => {
+                    self.subscriptions.remove(id);
+                    self.delivered
+                        .retain(|(delivered_id, _), _| delivered_id != id);
+                }
+                Command::Shutdown => self.stopped = true,
+            }
+        }
+        commands
+    }
+
+    /// Sends the update unless it's the same as the last one of its key.
+    fn deliver(&mut self, id: SubscriptionId, update: Update) {
+        let key = (id, update.key());
+        if self.delivered.get(&key) == Some(&update) {
+            return;
+        }
+        if self.updates.send(update.clone()).is_err() {
+            self.stopped = true;
+        }
+        self.delivered.insert(key, update);
+    }
+
+    /// Receives notifications until the connection drops or the client stops.
+    fn push(&mut self, mut websocket: WebSocket) -> Result<()> {
+        websocket.set_read_timeout(Some(COMMAND_INTERVAL))?;
+
+        // Ids of the subscriptions on the node, both ways
+        let mut local_ids = HashMap::<u64, SubscriptionId>::new();
+        let mut node_ids = HashMap::<SubscriptionId, u64>::new();
+
+        for (id, subscription) in &self.subscriptions {
+            websocket.send_text(&subscription.request(*id).to_string())?;
+        }
+
+        loop {
+            for command in self.receive_commands(Duration::ZERO) {
+                match command {
+                    Command::Subscribe(id, subscription) => {
+                        websocket.send_text(&subscription.request(id).to_string())?
+                    }
+                    Command::Unsubscribe(id) => {
+                        if let Some(node_id) = node_ids.remove(&id) {
+                            local_ids.remove(&node_id);
+                            send_unsubscribe(&mut websocket, node_id)?;
+                        }
+                    }
+                    Command::Shutdown => {}
+                }
+            }
+            if self.stopped {
+                websocket.close();
+                return Ok(());
+            }
+
+            let text = match websocket.read_message()? {
+                Some(Message::Text(text)) => text,
+                Some(Message::Close) => bail!("closed by the node"),
+                None => continue,
+            };
+            let message = serde_json::from_str::<Value>(&text)?;
+
+            if let Some(method) = message["method"].as_str() {
+                let params = &message["params"];
+                let Some(id) = params["subscription"]
+                    .as_u64()
+                    .and_then(|node_id| local_ids.get(&node_id))
+                    .copied()
+                else {
+                    continue;
+                };
+                let Some(subscription) = self.subscriptions.get(&id) else {
+                    continue;
+                };
+                match subscription.update(method, params["result"].clone()) {
+                    Ok(update) => self.deliver(id, update),
+                    Err(e) => warn!("Invalid {}: {}", method, e),
+                }
+            } else if let Some(id) = message["id"].as_u64() {
+                // Response to a subscription request
+                match message["result"].as_u64() {
+                    Some(node_id) if self.subscriptions.contains_key(&id) => {
+                        local_ids.insert(node_id, id);
+                        node_ids.insert(id, node_id);
+                    }
+                    Some(node_id) => send_unsubscribe(&mut websocket, node_id)?,
+                    None => warn!("Subscription {} failed: {}", id, message["error"]),
+                }
+            }
+        }
+    }
+
+    /// Polls every subscription until the client stops.
+    fn poll(&mut self) {
+        while !self.stopped {
+            for (id, subscription) in self.subscriptions.clone() {
+                match subscription.poll(&self.url) {
+                    Ok(updates) => {
+                        for update in updates {
+                            self.deliver(id, update);
+                        }
+                    }
+                    Err(e) => debug!("Failed to poll {:?}: {}", subscription, e),
+                }
+            }
+            self.receive_commands(self.config.poll_interval);
+        }
+    }
+}
+
+fn send_unsubscribe(websocket: &mut WebSocket, node_id: u64) -> Result<()> {
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": format!("unsubscribe-{}", node_id),
+        "method": UNSUBSCRIBE,
+        "params": node_id,
+    });
+    websocket
+        .send_text(&request.to_string())
+        .map_err(|e| anyhow!("failed to unsubscribe: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    use sdk::arch_program::message::Message;
+    use sdk::processed_transaction::Status;
+    use sdk::runtime_transaction::RuntimeTransaction;
+
+    use crate::mock_server::MockServer;
+
+    fn config() -> SubscriptionConfig {
+        SubscriptionConfig {
+            reconnect_delay: Duration::from_millis(10),
+            max_reconnect_delay: Duration::from_millis(50),
+            poll_interval: Duration::from_millis(10),
+            ..Default::default()
+        }
+    }
+
+    fn account(owner: Pubkey, data: &[u8]) -> AccountInfoResult {
+        AccountInfoResult {
+            owner,
+            data: data.to_vec(),
+            utxo: format!("{}:0", hex::encode([1; 32])),
+            is_executable: false,
+        }
+    }
+
+    fn processed_transaction(status: Status) -> ProcessedTransaction {
+        ProcessedTransaction {
+            runtime_transaction: RuntimeTransaction {
+                version: 0,
+                signatures: vec![],
+                message: Message {
+                    signers: vec![Pubkey::from_slice(&[7; 32])],
+                    instructions: vec![],
+                },
+            },
+            status,
+            bitcoin_txids: vec![],
+            logs: vec![],
+            compute_units_consumed: 0,
+            return_data: None,
+        }
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        let started = Instant::now();
+        while !condition() {
+            assert!(started.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn next(client: &SubscriptionClient) -> Update {
+        client
+            .recv_timeout(Duration::from_secs(5))
+            .expect("an update should arrive")
+    }
+
+    #[test]
+    fn test_push_updates() {
+        let server = MockServer::start().unwrap();
+        let (program_id, pubkey, other) = (
+            Pubkey::from_slice(&[1; 32]),
+            Pubkey::from_slice(&[2; 32]),
+            Pubkey::from_slice(&[3; 32]),
+        );
+        server.set_account(other, account(program_id, b"existing"));
+
+        let client = SubscriptionClient::with_config(&server.url(), config());
+        let processed_tx = processed_transaction(Status::Processing);
+        client.subscribe(Subscription::Account(pubkey));
+        client.subscribe(Subscription::Program(program_id));
+        client.subscribe(Subscription::Transaction(processed_tx.txid()));
+
+        // The current state comes first
+        assert_eq!(
+            next(&client),
+            Update::ProgramAccount {
+                program_id,
+                pubkey: other,
+                account: account(program_id, b"existing"),
+            }
+        );
+        wait_until(|| server.subscription_count() == 3);
+        assert_eq!(client.transport(), Transport::Push);
+
+        server.set_account(pubkey, account(program_id, b"new"));
+        let mut updates = vec![next(&client), next(&client)];
+        updates.sort_by_key(|update| matches!(update, Update::ProgramAccount { .. }));
+        assert_eq!(
+            updates,
+            vec![
+                Update::Account {
+                    pubkey,
+                    account: account(program_id, b"new"),
+                },
+                Update::ProgramAccount {
+                    program_id,
+                    pubkey,
+                    account: account(program_id, b"new"),
+                },
+            ]
+        );
+
+        server.set_transaction(processed_tx.clone());
+        assert_eq!(next(&client), Update::Transaction(processed_tx));
+    }
+
+    #[test]
+    fn test_reconnects() {
+        let server = MockServer::start().unwrap();
+        let pubkey = Pubkey::from_slice(&[2; 32]);
+        let client = SubscriptionClient::with_config(&server.url(), config());
+        let id = client.subscribe(Subscription::Account(pubkey));
+        wait_until(|| server.subscription_count() == 1);
+
+        server.disconnect_subscribers();
+        // Set while the client may be disconnected, and delivered once
+        // either way
+        server.set_account(pubkey, account(pubkey, b"after restart"));
+        assert_eq!(
+            next(&client),
+            Update::Account {
+                pubkey,
+                account: account(pubkey, b"after restart"),
+            }
+        );
+        wait_until(|| server.subscription_count() == 1);
+        assert_eq!(client.transport(), Transport::Push);
+
+        server.disconnect_subscribers();
+        wait_until(|| server.subscription_count() == 1);
+        assert_eq!(client.recv_timeout(Duration::from_millis(100)), None);
+
+        client.unsubscribe(id);
+        wait_until(|| server.subscription_count() == 0);
+    }
+
+    #[test]
+    fn test_polls_without_push() {
+        let server = MockServer::without_push().unwrap();
+        let pubkey = Pubkey::from_slice(&[2; 32]);
+        let client = SubscriptionClient::with_config(&server.url(), config());
+        client.subscribe(Subscription::Account(pubkey));
+        let processed_tx = processed_transaction(Status::Processing);
+        client.subscribe(Subscription::Transaction(processed_tx.txid()));
+
+        server.set_account(pubkey, account(pubkey, b"first"));
+        assert_eq!(
+            next(&client),
+            Update::Account {
+                pubkey,
+                account: account(pubkey, b"first"),
+            }
+        );
+        assert_eq!(client.transport(), Transport::Polling);
+
+        server.set_transaction(processed_tx.clone());
+        assert_eq!(next(&client), Update::Transaction(processed_tx.clone()));
+
+        let processed_tx = ProcessedTransaction {
+            status: Status::Processed,
+            ..processed_tx
+        };
+        server.set_transaction(processed_tx.clone());
+        assert_eq!(next(&client), Update::Transaction(processed_tx));
+
+        // Unchanged state isn't delivered again
+        assert_eq!(client.recv_timeout(Duration::from_millis(100)), None);
+    }
+}
@@ -0,0 +1,230 @@
+//! How JSON-RPC requests reach the nodes and bitcoind.
+//!
+//! The helpers, [`crate::cluster::Cluster`] and [`crate::bitcoin_backend::CoreRpcBackend`]
+//! send their requests through a [`Transport`]. It is HTTP unless the
+//! environment sets up a session:
+//!
+//! - `ARCH_RPC_RECORD=<file>` sends the requests over HTTP and appends each
+//!   request and its response to the fixture file, which is truncated first.
+//! - `ARCH_RPC_REPLAY=<file>` answers the requests from a fixture file
+//!   without any network, see [`Replayer`] for how requests are matched.
+//! - `ARCH_RPC_NORMALIZE` sets the params ignored when matching, see
+//!   [`Normalizer`].
+//!
+//! Subscriptions over WebSocket and the Esplora backend don't speak JSON-RPC
+//! over HTTP and aren't part of sessions.
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use bitcoincore_rpc::jsonrpc;
+use serde_json::Value;
+
+use crate::fixture::{Normalizer, Recorder, Replayer};
+
+/// Variables setting up a recording or replaying session
+pub const RECORD_VAR: &str = "ARCH_RPC_RECORD";
+pub const REPLAY_VAR: &str = "ARCH_RPC_REPLAY";
+pub const NORMALIZE_VAR: &str = "ARCH_RPC_NORMALIZE";
+
+/// A JSON-RPC request to an endpoint.
+#[derive(Clone, Copy, Debug)]
+pub struct Call<'a> {
+    pub url: &'a str,
+    /// The request object, or an array of them for a batch
+    pub body: &'a Value,
+    /// Basic authentication as user and password
+    pub auth: Option<(&'a str, &'a str)>,
+    /// Time after which the request is abandoned, the client default if
+    /// `None`
+    pub timeout: Option<Duration>,
+}
+
+impl<'a> Call<'a> {
+    pub fn new(url: &'a str, body: &'a Value) -> Self {
+        Self {
+            url,
+            body,
+            auth: None,
+            timeout: None,
+        }
+    }
+
+    /// Method of the request, `batch` for a batch
+    pub fn method(&self) -> &str {
+        match self.body {
+            Value::Array(_) => "batch",
+            body => body["method"].as_str().unwrap_or_default(),
+        }
+    }
+}
+
+/// Carries JSON-RPC requests to their endpoint.
+pub trait Transport: Send + Sync {
+    /// Sends the request, returning the response parsed as JSON. Fails when
+    /// the endpoint can't be reached or doesn't answer with JSON, while an
+    /// error answered over JSON-RPC is a response like any other. The error
+    /// is a [`ConnectError`] when the request was never delivered.
+    fn call(&self, call: &Call) -> Result<Value>;
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn call(&self, call: &Call) -> Result<Value> {
+        (**self).call(call)
+    }
+}
+
+/// Error of a request that wasn't delivered because no connection to the
+/// endpoint could be made, so the endpoint can't have acted on it.
+#[derive(Debug)]
+pub struct ConnectError(pub String);
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+/// [`Transport`] posting the requests over HTTP.
+#[derive(Clone, Default)]
+pub struct HttpTransport {
+    client: reqwest::blocking::Client,
+}
+
+impl Transport for HttpTransport {
+    fn call(&self, call: &Call) -> Result<Value> {
+        let mut request = self
+            .client
+            .post(call.url)
+            .header("content-type", "application/json")
+            .json(call.body);
+        if let Some((user, password)) = call.auth {
+            request = request.basic_auth(user, Some(password));
+        }
+        if let Some(timeout) = call.timeout {
+            request = request.timeout(timeout);
+        }
+
+        let res = request.send().map_err(|e| {
+            let message = format!("Unable to reach {}: {}", call.url, e);
+            match e.is_connect() {
+                true => ConnectError(message).into(),
+                false => anyhow!(message),
+            }
+        })?;
+        let status = res.status();
+        let body = res
+            .text()
+            .map_err(|e| anyhow!("Unable to read response from {}: {}", call.url, e))?;
+        // bitcoind answers errors with a server error status and a JSON body
+        serde_json::from_str(&body)
+            .map_err(|e| anyhow!("Invalid response from {} ({}): {}", call.url, status, e))
+    }
+}
+
+/// The session set up by the environment, if any.
+pub fn session() -> Result<Option<Arc<dyn Transport>>> {
+    static SESSION: OnceLock<Option<Arc<dyn Transport>>> = OnceLock::new();
+    if let Some(session) = SESSION.get() {
+        return Ok(session.clone());
+    }
+    let session = session_from(|name| std::env::var(name).ok())?;
+    Ok(SESSION.get_or_init(|| session).clone())
+}
+
+/// The transport of the session set up by the environment, or HTTP.
+pub fn current() -> Result<Arc<dyn Transport>> {
+    Ok(session()?.unwrap_or_else(|| Arc::new(HttpTransport::default())))
+}
+
+fn session_from(var: impl Fn(&str) -> Option<String>) -> Result<Option<Arc<dyn Transport>>> {
+    let normalizer = match var(NORMALIZE_VAR) {
+        Some(rules) => rules.parse()?,
+        None => Normalizer::default(),
+    };
+    match (var(RECORD_VAR), var(REPLAY_VAR)) {
+        (Some(_), Some(_)) => bail!("Only one of {} and {} can be set", RECORD_VAR, REPLAY_VAR),
+        (Some(path), None) => Ok(Some(Arc::new(Recorder::create(
+            path,
+            HttpTransport::default(),
+        )?))),
+        (None, Some(path)) => Ok(Some(Arc::new(Replayer::open(path, normalizer)?))),
+        (None, None) => Ok(None),
+    }
+}
+
+/// A [`jsonrpc::Transport`] over a [`Transport`], for the bitcoind client.
+pub struct BitcoindTransport {
+    url: String,
+    auth: Option<(String, String)>,
+    transport: Arc<dyn Transport>,
+}
+
+impl BitcoindTransport {
+    pub fn new(url: &str, auth: Option<(String, String)>, transport: Arc<dyn Transport>) -> Self {
+        Self {
+            url: url.to_string(),
+            auth,
+            transport,
+        }
+    }
+
+    fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        body: Value,
+    ) -> std::result::Result<T, jsonrpc::Error> {
+        let mut call = Call::new(&self.url, &body);
+        call.auth = self
+            .auth
+            .as_ref()
+            .map(|(user, password)| (user.as_str(), password.as_str()));
+        let response = self.transport.call(&call).map_err(|e| {
+            let e: Box<dyn std::error::Error + Send + Sync> = e.into();
+            jsonrpc::Error::Transport(e)
+        })?;
+        Ok(serde_json::from_value(response)?)
+    }
+}
+
+impl jsonrpc::Transport for BitcoindTransport {
+    fn send_request(
+        &self,
+        request: jsonrpc::Request,
+    ) -> std::result::Result<jsonrpc::Response, jsonrpc::Error> {
+        self.call(serde_json::to_value(request)?)
+    }
+
+    fn send_batch(
+        &self,
+        requests: &[jsonrpc::Request],
+    ) -> std::result::Result<Vec<jsonrpc::Response>, jsonrpc::Error> {
+        self.call(serde_json::to_value(requests)?)
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_from_env() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert!(session_from(env(&[])).unwrap().is_none());
+        assert!(session_from(env(&[(RECORD_VAR, "a"), (REPLAY_VAR, "b")])).is_err());
+        assert!(session_from(env(&[(REPLAY_VAR, "/nonexistent/fixture.jsonl")])).is_err());
+        assert!(session_from(env(&[(NORMALIZE_VAR, "@unknown")])).is_err());
+    }
+}
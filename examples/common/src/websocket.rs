@@ -0,0 +1,368 @@
+//! Minimal WebSocket (RFC 6455) connections, enough for the subscriptions of
+//! [`crate::subscription`] and [`crate::mock_server`]: text messages over
+//! plain TCP, without extensions or TLS.
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use bitcoin::hashes::{sha1, Hash};
+use rand_core::{OsRng, RngCore};
+
+/// Appended to the key of the handshake before hashing it
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest frame accepted, well above any account or transaction
+const MAX_FRAME_LEN: u64 = 16 * 1024 * 1024;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    /// The peer closed the connection
+    Close,
+}
+
+/// Why [`connect`] failed.
+#[derive(Debug)]
+pub enum ConnectError {
+    /// The server answered but doesn't speak WebSocket at this URL
+    Unsupported(String),
+    /// The server couldn't be reached or the handshake broke off
+    Io(anyhow::Error),
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported(reason) => write!(f, "WebSocket isn't supported: {}", reason),
+            Self::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+impl From<std::io::Error> for ConnectError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error.into())
+    }
+}
+
+impl From<anyhow::Error> for ConnectError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+pub struct WebSocket {
+    stream: TcpStream,
+    /// Bytes read but not yet parsed into frames
+    buffer: Vec<u8>,
+    /// Payload of a fragmented message being received
+    fragments: Option<Vec<u8>>,
+    /// Clients mask their frames, servers don't
+    is_client: bool,
+}
+
+/// Opens a WebSocket to a `ws://host:port/path` URL.
+pub fn connect(url: &str, timeout: Duration) -> std::result::Result<WebSocket, ConnectError> {
+    let Some(address) = url.strip_prefix("ws://") else {
+        return Err(ConnectError::Unsupported(format!(
+            "{} isn't a ws:// URL",
+            url
+        )));
+    };
+    let (host, path) = match address.find('/') {
+        Some(i) => (&address[..i], &address[i..]),
+        None => (address, "/"),
+    };
+    let socket_address = host
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("{} doesn't resolve", host))?;
+
+    let mut stream = TcpStream::connect_timeout(&socket_address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+
+    let mut key = [0; 16];
+    OsRng.fill_bytes(&mut key);
+    let key = base64::encode(key);
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        path, host, key
+    )?;
+
+    let (head, leftover) = read_http_head(&mut stream)?;
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("101") {
+        return Err(ConnectError::Unsupported(status.to_string()));
+    }
+    if header(&head, "sec-websocket-accept") != Some(accept_key(&key).as_str()) {
+        return Err(anyhow!("invalid WebSocket handshake from {}", host).into());
+    }
+
+    stream.set_read_timeout(None)?;
+    Ok(WebSocket {
+        stream,
+        buffer: leftover,
+        fragments: None,
+        is_client: true,
+    })
+}
+
+/// Completes the handshake of a client whose request head was read with
+/// [`read_http_head`].
+pub fn accept(mut stream: TcpStream, head: &str, leftover: Vec<u8>) -> Result<WebSocket> {
+    let key = header(head, "sec-websocket-key")
+        .ok_or_else(|| anyhow!("WebSocket request without a key"))?;
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )?;
+
+    Ok(WebSocket {
+        stream,
+        buffer: leftover,
+        fragments: None,
+        is_client: false,
+    })
+}
+
+/// Reads an HTTP head up to the empty line, returning it with the bytes
+/// read past it.
+pub fn read_http_head(stream: &mut TcpStream) -> Result<(String, Vec<u8>)> {
+    let mut data = vec![];
+    let mut chunk = [0; 1024];
+    loop {
+        if let Some(end) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            let leftover = data.split_off(end + 4);
+            return Ok((String::from_utf8(data)?, leftover));
+        }
+        if data.len() > 64 * 1024 {
+            bail!("HTTP head is too long");
+        }
+        match stream.read(&mut chunk)? {
+            0 => bail!("connection closed before the end of the HTTP head"),
+            n => data.extend(&chunk[..n]),
+        }
+    }
+}
+
+/// Value of a header of an HTTP head, matching its name in any case.
+pub fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then_some(value.trim())
+    })
+}
+
+fn accept_key(key: &str) -> String {
+    base64::encode(
+        sha1::Hash::hash(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()).to_byte_array(),
+    )
+}
+
+impl WebSocket {
+    /// Another handle to the connection, to write from one thread while
+    /// reading from another.
+    pub fn try_clone(&self) -> Result<Self> {
+        Ok(Self {
+            stream: self.stream.try_clone()?,
+            buffer: vec![],
+            fragments: None,
+            is_client: self.is_client,
+        })
+    }
+
+    /// Sets how long [`WebSocket::read_message`] waits for data.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        Ok(self.stream.set_read_timeout(timeout)?)
+    }
+
+    pub fn send_text(&mut self, text: &str) -> Result<()> {
+        self.send_frame(OPCODE_TEXT, text.as_bytes())
+    }
+
+    /// Closes the connection, telling the peer first when possible.
+    pub fn close(&mut self) {
+        let _ = self.send_frame(OPCODE_CLOSE, &[]);
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    /// Returns the next message, or `None` if none arrived before the read
+    /// timeout.
+    pub fn read_message(&mut self) -> Result<Option<Message>> {
+        loop {
+            while let Some((fin, opcode, payload)) = self.parse_frame()? {
+                match opcode {
+                    OPCODE_TEXT | OPCODE_CONTINUATION => {
+                        let message = match (opcode, self.fragments.take()) {
+                            (OPCODE_TEXT, None) => payload,
+                            (OPCODE_CONTINUATION, Some(mut message)) => {
+                                message.extend(payload);
+                                message
+                            }
+                            _ => bail!("unexpected WebSocket continuation frame"),
+                        };
+                        if fin {
+                            return Ok(Some(Message::Text(String::from_utf8(message)?)));
+                        }
+                        self.fragments = Some(message);
+                    }
+                    OPCODE_CLOSE => {
+                        self.close();
+                        return Ok(Some(Message::Close));
+                    }
+                    OPCODE_PING => self.send_frame(OPCODE_PONG, &payload)?,
+                    OPCODE_PONG => {}
+                    opcode => bail!("unsupported WebSocket opcode {:#x}", opcode),
+                }
+            }
+
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => bail!("WebSocket connection closed"),
+                Ok(n) => self.buffer.extend(&chunk[..n]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        let mut frame = vec![0x80 | opcode];
+        let mask_bit = if self.is_client { 0x80 } else { 0 };
+        match payload.len() {
+            len if len < 126 => frame.push(mask_bit | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(mask_bit | 126);
+                frame.extend((len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(mask_bit | 127);
+                frame.extend((len as u64).to_be_bytes());
+            }
+        }
+
+        if self.is_client {
+            let mut mask = [0; 4];
+            OsRng.fill_bytes(&mut mask);
+            frame.extend(mask);
+            frame.extend(
+                payload
+                    .iter()
+                    .enumerate()
+                    .map(|(i, byte)| byte ^ mask[i % 4]),
+            );
+        } else {
+            frame.extend(payload);
+        }
+
+        Ok(self.stream.write_all(&frame)?)
+    }
+
+    /// Takes the first frame out of the buffer once it's complete.
+    fn parse_frame(&mut self) -> Result<Option<(bool, u8, Vec<u8>)>> {
+        let buffer = &self.buffer;
+        if buffer.len() < 2 {
+            return Ok(None);
+        }
+        let fin = buffer[0] & 0x80 != 0;
+        let opcode = buffer[0] & 0x0f;
+        let masked = buffer[1] & 0x80 != 0;
+
+        let (len, mut offset) = match buffer[1] & 0x7f {
+            126 if buffer.len() >= 4 => (u16::from_be_bytes([buffer[2], buffer[3]]) as u64, 4),
+            127 if buffer.len() >= 10 => (u64::from_be_bytes(buffer[2..10].try_into()?), 10),
+            126 | 127 => return Ok(None),
+            len => (len as u64, 2),
+        };
+        if len > MAX_FRAME_LEN {
+            bail!("WebSocket frame of {} bytes is too large", len);
+        }
+
+        let mask = match masked {
+            true if buffer.len() >= offset + 4 => {
+                offset += 4;
+                Some([
+                    buffer[offset - 4],
+                    buffer[offset - 3],
+                    buffer[offset - 2],
+                    buffer[offset - 1],
+                ])
+            }
+            true => return Ok(None),
+            false => None,
+        };
+        let end = offset + len as usize;
+        if buffer.len() < end {
+            return Ok(None);
+        }
+
+        let mut payload = buffer[offset..end].to_vec();
+        if let Some(mask) = mask {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+        self.buffer.drain(..end);
+        Ok(Some((fin, opcode, payload)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_accept_key() {
+        // Example handshake of RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_echo() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (head, leftover) = read_http_head(&mut stream).unwrap();
+            let mut websocket = accept(stream, &head, leftover).unwrap();
+            while let Some(Message::Text(text)) = websocket.read_message().unwrap() {
+                websocket.send_text(&text.to_uppercase()).unwrap();
+            }
+        });
+
+        let mut websocket = connect(&url, Duration::from_secs(5)).unwrap();
+        for text in ["hello", &"x".repeat(70_000)] {
+            websocket.send_text(text).unwrap();
+            assert_eq!(
+                websocket.read_message().unwrap(),
+                Some(Message::Text(text.to_uppercase()))
+            );
+        }
+        websocket.close();
+        server.join().unwrap();
+    }
+}
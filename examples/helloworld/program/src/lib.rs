@@ -1,75 +1,62 @@
 use arch_program::{
     account::AccountInfo,
-    entrypoint,
+    context::Context,
+    entrypoint::ProgramResult,
     helper::get_state_transition_tx,
     input_to_sign::InputToSign,
-    instruction::Instruction,
     msg,
-    program::{
-        get_account_script_pubkey, get_bitcoin_tx, get_network_xonly_pubkey, invoke,
-        next_account_info, set_return_data, set_transaction_to_sign, validate_utxo_ownership,
-    },
+    program::{get_account_script_pubkey, set_transaction_to_sign},
     program_error::ProgramError,
-    pubkey::Pubkey,
-    system_instruction::SystemInstruction,
     transaction_to_sign::TransactionToSign,
-    utxo::UtxoMeta,
+    Accounts,
 };
 use bitcoin::{self, Transaction};
-use borsh::{BorshDeserialize, BorshSerialize};
 
-entrypoint!(process_instruction);
-pub fn process_instruction(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    instruction_data: &[u8],
-) -> Result<(), ProgramError> {
-    if accounts.len() != 1 {
-        return Err(ProgramError::Custom(501));
-    }
-
-    let account_iter = &mut accounts.iter();
-    let account = next_account_info(account_iter)?;
+#[derive(Accounts)]
+pub struct SayHello<'a, 'b> {
+    #[account(signer, writable, owner = program_id)]
+    pub caller: &'a AccountInfo<'b>,
+}
 
-    let params: HelloWorldParams = borsh::from_slice(instruction_data).unwrap();
-    let fees_tx: Transaction = bitcoin::consensus::deserialize(&params.tx_hex).unwrap();
+#[arch_program::program]
+pub mod hello_world {
+    use super::*;
 
-    let new_data = format!("Hello {}", params.name);
+    pub fn say_hello(ctx: Context<SayHello>, name: String, tx_hex: Vec<u8>) -> ProgramResult {
+        let account = ctx.accounts.caller;
 
-    let data_len = account.data.try_borrow().unwrap().len();
-    if new_data.as_bytes().len() > data_len {
-        account.realloc(new_data.len(), true)?;
-    }
+        let fees_tx: Transaction = bitcoin::consensus::deserialize(&tx_hex)
+            .map_err(|_| ProgramError::InvalidInstructionData)?;
 
-    let script_pubkey = get_account_script_pubkey(account.key);
-    msg!("script_pubkey {:?}", script_pubkey);
+        let new_data = format!("Hello {}", name);
 
-    account
-        .data
-        .try_borrow_mut()
-        .unwrap()
-        .copy_from_slice(new_data.as_bytes());
+        let data_len = account.data.try_borrow().unwrap().len();
+        if new_data.len() > data_len {
+            account.realloc(new_data.len(), true)?;
+        }
 
-    let mut tx = get_state_transition_tx(accounts);
-    tx.input.push(fees_tx.input[0].clone());
+        let script_pubkey = get_account_script_pubkey(account.key);
+        msg!("script_pubkey {:?}", script_pubkey);
 
-    let tx_to_sign = TransactionToSign {
-        tx_bytes: &bitcoin::consensus::serialize(&tx),
-        inputs_to_sign: &[InputToSign {
-            index: 0,
-            signer: account.key.clone(),
-        }],
-    };
+        account
+            .data
+            .try_borrow_mut()
+            .unwrap()
+            .copy_from_slice(new_data.as_bytes());
 
-    msg!("tx_to_sign{:?}", tx_to_sign);
+        let mut tx = get_state_transition_tx(std::slice::from_ref(account));
+        tx.input.push(fees_tx.input[0].clone());
 
-    set_transaction_to_sign(accounts, tx_to_sign);
+        let tx_to_sign = TransactionToSign {
+            tx_bytes: &bitcoin::consensus::serialize(&tx),
+            inputs_to_sign: &[InputToSign {
+                index: 0,
+                signer: *account.key,
+            }],
+        };
 
-    Ok(())
-}
+        msg!("tx_to_sign{:?}", tx_to_sign);
 
-#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
-pub struct HelloWorldParams {
-    pub name: String,
-    pub tx_hex: Vec<u8>,
+        set_transaction_to_sign(std::slice::from_ref(account), tx_to_sign)
+    }
 }
@@ -1,13 +1,16 @@
+/// Instruction builders of the Hello World program, generated from its IDL
+pub mod client {
+    include!(concat!(env!("OUT_DIR"), "/hello_world_client.rs"));
+}
+
 /// Running Tests
 #[cfg(test)]
 mod tests {
-    use arch_program::{
-        account::AccountMeta, instruction::Instruction, pubkey::Pubkey,
-        system_instruction::SystemInstruction, utxo::UtxoMeta,
-    };
+    use super::client::{say_hello, SayHelloAccounts};
+    use arch_program::{system_instruction::SystemInstruction, utxo::UtxoMeta};
     use bitcoincore_rpc::{Auth, Client};
-    use borsh::{BorshDeserialize, BorshSerialize};
     use common::constants::*;
+    use common::deploy::{ProgramDeployer, RpcNode};
     use common::helper::*;
     use common::models::*;
     use serial_test::serial;
@@ -22,13 +25,6 @@ mod tests {
         env_logger::init();
     }
 
-    /// Represents the parameters for running the Hello World process
-    #[derive(Clone, BorshSerialize, BorshDeserialize)]
-    pub struct HelloWorldParams {
-        name: String,
-        tx_hex: Vec<u8>,
-    }
-
     #[test]
     fn test_deploy_call() {
         setup();
@@ -88,33 +84,16 @@ mod tests {
             processed_tx
         );
 
-        // 5. Deploy program
-        let txids = deploy_program_txs(
-            program_keypair.clone(),
-            "program/target/sbf-solana-solana/release/helloworldprogram.so",
-        );
-        info!("Program deployed with transaction IDs: {:?}", txids);
-
-        // 6. Set program as executable
-        let (txid, instruction_hash) = sign_and_send_instruction(
-            Instruction {
-                program_id: Pubkey::system_program(),
-                accounts: vec![AccountMeta {
-                    pubkey: program_pubkey.clone(),
-                    is_signer: true,
-                    is_writable: true,
-                }],
-                data: vec![2],
-            },
-            vec![program_keypair],
-        )
-        .expect("Failed to sign and send set executable instruction");
-
-        let processed_tx = get_processed_transaction(NODE1_ADDRESS, txid.clone())
-            .expect("Failed to get processed transaction");
-        debug!(
-            "Processed transaction for setting executable: {:?}",
-            processed_tx
+        // 5. Deploy program, verify it and set it as executable
+        let elf = fs::read("program/target/sbf-solana-solana/release/helloworldprogram.so")
+            .expect("Failed to read program ELF");
+        let report = ProgramDeployer::new(RpcNode::new(NODE1_ADDRESS), program_keypair)
+            .on_progress(|progress| debug!("Deployment progress: {:?}", progress))
+            .deploy(&elf)
+            .expect("Failed to deploy program");
+        info!(
+            "Program deployed with sha256 {} and transaction IDs: {:?}",
+            report.hash, report.txids
         );
 
         // 7. Verify program is executable
@@ -153,19 +132,8 @@ mod tests {
 
         // 9. Assign ownership of caller account to program
 
-        let mut instruction_data = vec![3];
-        instruction_data.extend(program_pubkey.serialize());
-
         let (txid, instruction_hash) = sign_and_send_instruction(
-            Instruction {
-                program_id: Pubkey::system_program(),
-                accounts: vec![AccountMeta {
-                    pubkey: caller_pubkey.clone(),
-                    is_signer: true,
-                    is_writable: true
-                }],
-                data: instruction_data
-            },
+            SystemInstruction::new_assign_ownership_instruction(caller_pubkey, program_pubkey),
             vec![caller_keypair.clone()],
         )
         .expect("Failed to sign and send Assign ownership of caller account instruction");
@@ -186,18 +154,14 @@ mod tests {
 
         // 12. Call the program again
         let (txid, instruction_hash) = sign_and_send_instruction(
-            Instruction {
-                program_id: program_pubkey.clone(),
-                accounts: vec![AccountMeta {
-                    pubkey: caller_pubkey.clone(),
-                    is_signer: true,
-                    is_writable: true
-                }],
-                data: borsh::to_vec(&HelloWorldParams {
-                    name: hello_name.to_string(),
-                    tx_hex: hex::decode(prepare_fees()).unwrap()
-                }).unwrap()
-            },
+            say_hello(
+                program_pubkey,
+                SayHelloAccounts {
+                    caller: caller_pubkey,
+                },
+                hello_name.to_string(),
+                hex::decode(prepare_fees()).unwrap(),
+            ),
             vec![caller_keypair],
         )
         .expect("Failed to sign and send program call instruction");
@@ -221,18 +185,14 @@ mod tests {
 
         // 11. Call the program
         let (txid, instruction_hash) = sign_and_send_instruction(
-            Instruction {
-                program_id: program_pubkey.clone(),
-                accounts: vec![AccountMeta {
-                    pubkey: caller_pubkey.clone(),
-                    is_signer: true,
-                    is_writable: true
-                }],
-                data: borsh::to_vec(&HelloWorldParams {
-                    name: hello_name.to_string(),
-                    tx_hex: hex::decode(prepare_fees()).unwrap()
-                }).unwrap()
-            },
+            say_hello(
+                program_pubkey,
+                SayHelloAccounts {
+                    caller: caller_pubkey,
+                },
+                hello_name.to_string(),
+                hex::decode(prepare_fees()).unwrap(),
+            ),
             vec![caller_keypair],
         )
         .expect("Failed to sign and send program call instruction");
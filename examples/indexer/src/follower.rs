@@ -0,0 +1,240 @@
+//! Follows the blocks of a node into a [`Store`].
+//!
+//! Snapshots are read from the node when a block is indexed, so an indexer
+//! catching up on old blocks records the current state of the accounts
+//! rather than the state each transaction left them in. Only a following
+//! indexer sees every intermediate state.
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+
+use common::helper::{
+    get_best_block_hash, get_block, get_transaction_status, read_account_info, Block,
+};
+use sdk::processed_transaction::ProcessedTransaction;
+
+use crate::store::{IndexedBlock, Snapshot, Store};
+
+pub struct Follower {
+    url: String,
+}
+
+impl Follower {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    /// Indexes the blocks of the node newer than the last indexed one,
+    /// oldest first, returning how many were indexed.
+    pub fn sync(&self, store: &mut Store) -> Result<usize> {
+        let mut hash = get_best_block_hash(&self.url)?;
+        let mut pending = vec![];
+        while !store.contains_block(&hash) && !Block::is_genesis_parent(&hash) {
+            let block = get_block(&self.url, &hash)?;
+            let previous_block_hash = block.previous_block_hash.clone();
+            pending.push(IndexedBlock {
+                hash,
+                previous_block_hash: block.previous_block_hash,
+                transactions: block.transactions,
+            });
+            hash = previous_block_hash;
+        }
+
+        let count = pending.len();
+        for block in pending.into_iter().rev() {
+            self.index_block(store, block)?;
+        }
+        Ok(count)
+    }
+
+    fn index_block(&self, store: &mut Store, block: IndexedBlock) -> Result<()> {
+        for txid in &block.transactions {
+            let processed_tx = match store.transaction(txid) {
+                Some(processed_tx) => processed_tx.clone(),
+                None => get_transaction_status(&self.url, txid)?.ok_or_else(|| {
+                    anyhow!("Block {} has the unknown transaction {}", block.hash, txid)
+                })?,
+            };
+            store.add_transaction(&processed_tx)?;
+            self.snapshot_accounts(store, &processed_tx)?;
+        }
+
+        info!(
+            "Indexed block {} with {} transactions",
+            block.hash,
+            block.transactions.len()
+        );
+        store.add_block(block)
+    }
+
+    /// Snapshots the accounts the transaction could have written.
+    fn snapshot_accounts(
+        &self,
+        store: &mut Store,
+        processed_tx: &ProcessedTransaction,
+    ) -> Result<()> {
+        let mut written = processed_tx
+            .runtime_transaction
+            .message
+            .instructions
+            .iter()
+            .flat_map(|instruction| &instruction.accounts)
+            .filter(|meta| meta.is_writable)
+            .map(|meta| meta.pubkey)
+            .collect::<Vec<_>>();
+        written.sort();
+        written.dedup();
+
+        for pubkey in written {
+            // Failed account creations leave nothing to read
+            let account = match read_account_info(&self.url, pubkey) {
+                Ok(account) => account,
+                Err(e) => {
+                    debug!("No snapshot of {:x}: {}", pubkey, e);
+                    continue;
+                }
+            };
+            store.add_snapshot(Snapshot {
+                pubkey,
+                txid: processed_tx.txid(),
+                account,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::helper::AccountInfoResult;
+    use common::mock_server::MockServer;
+    use sdk::arch_program::program_error::ProgramError;
+    use sdk::arch_program::pubkey::Pubkey;
+    use sdk::arch_program::system_instruction::SystemInstruction;
+    use sdk::arch_program::utxo::UtxoMeta;
+    use sdk::processed_transaction::ProcessedTransaction;
+
+    use super::*;
+    use crate::query::UtxoSource;
+    use crate::store::tests::{account, temp_dir, transaction};
+
+    #[test]
+    fn test_follow_and_query() {
+        let dir = temp_dir("follow");
+        let server = MockServer::start().unwrap();
+        let follower = Follower::new(server.url());
+        let (program_id, pubkey) = (Pubkey::from_slice(&[9; 32]), Pubkey::from_slice(&[5; 32]));
+
+        // The account is created from a UTXO, then handed to the program
+        let create = transaction(
+            Pubkey::system_program(),
+            pubkey,
+            SystemInstruction::CreateAccount(UtxoMeta::from([1; 32], 0)).serialise(),
+            1,
+        );
+        let assign = transaction(
+            Pubkey::system_program(),
+            pubkey,
+            SystemInstruction::AssignOwnership(program_id).serialise(),
+            2,
+        );
+        let created = account(
+            Pubkey::system_program(),
+            &format!("{}:0", hex::encode([1; 32])),
+        );
+        server.set_transaction(create.clone());
+        server.set_transaction(assign.clone());
+        server.set_account(pubkey, created.clone());
+        let first_block = server.add_block(vec![create.txid()]);
+        let second_block = server.add_block(vec![assign.txid()]);
+
+        let mut store = Store::open(&dir).unwrap();
+        assert_eq!(follower.sync(&mut store).unwrap(), 2);
+        assert_eq!(follower.sync(&mut store).unwrap(), 0);
+        assert_eq!(store.best_block().unwrap().hash, second_block);
+
+        let origin = store.utxo_origin(&pubkey).unwrap();
+        assert_eq!(
+            (origin.txid, origin.source),
+            (create.txid(), UtxoSource::CreateAccount)
+        );
+
+        // The program moves the account to a UTXO it broadcasts, and a
+        // malformed system instruction touches it
+        let bitcoin_txid = hex::encode([2; 32]);
+        let call = ProcessedTransaction {
+            bitcoin_txids: vec![bitcoin_txid.clone()],
+            ..transaction(program_id, pubkey, vec![7], 3)
+        };
+        let malformed = transaction(Pubkey::system_program(), pubkey, vec![9, 9], 4);
+        let moved = AccountInfoResult {
+            owner: program_id,
+            utxo: format!("{}:0", bitcoin_txid),
+            ..created.clone()
+        };
+        server.set_transaction(call.clone());
+        server.set_transaction(malformed.clone());
+        server.set_account(pubkey, moved.clone());
+        server.add_block(vec![call.txid(), malformed.txid()]);
+
+        assert_eq!(follower.sync(&mut store).unwrap(), 1);
+        drop(store);
+        let store = Store::open(&dir).unwrap();
+
+        let history = store.account_history(&pubkey);
+        assert_eq!(
+            history
+                .iter()
+                .map(|change| &change.txid)
+                .collect::<Vec<_>>(),
+            vec![
+                &create.txid(),
+                &assign.txid(),
+                &call.txid(),
+                &malformed.txid()
+            ]
+        );
+        assert_eq!(history[0].block_hash.as_deref(), Some(first_block.as_str()));
+        assert_eq!(history[0].account, Some(created));
+        assert_eq!(history[1].account, None);
+        assert_eq!(history[2].account, Some(moved));
+        assert!(history.iter().all(|change| change.writable));
+
+        assert_eq!(store.program_transactions(&program_id), vec![&call]);
+
+        let origin = store.utxo_origin(&pubkey).unwrap();
+        assert_eq!(
+            (origin.txid, origin.source),
+            (call.txid(), UtxoSource::BitcoinTransaction)
+        );
+
+        let instructions = store.system_instructions(Some(&pubkey));
+        assert_eq!(
+            instructions
+                .iter()
+                .map(|record| record.instruction.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Ok(SystemInstruction::CreateAccount(UtxoMeta::from([1; 32], 0))),
+                Ok(SystemInstruction::AssignOwnership(program_id)),
+                Err(ProgramError::InvalidInstructionData),
+            ]
+        );
+        assert_eq!(store.system_instructions(None), instructions);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_transaction_is_not_indexed() {
+        let dir = temp_dir("unknown");
+        let server = MockServer::start().unwrap();
+        let follower = Follower::new(server.url());
+        server.add_block(vec![hex::encode([4; 32])]);
+
+        let mut store = Store::open(&dir).unwrap();
+        assert!(follower.sync(&mut store).is_err());
+        assert!(store.best_block().is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,13 @@
+//! An indexer following an Arch node into an embedded database, to answer
+//! questions about the history of accounts and programs that the node
+//! itself can't, such as which transaction changed an account.
+//!
+//! [`Follower`] indexes every block of the node into a [`Store`], with the
+//! transactions it contains and snapshots of the accounts they write. The
+//! [`query`] module answers questions from the store.
+pub mod follower;
+pub mod query;
+pub mod store;
+
+pub use follower::Follower;
+pub use store::Store;
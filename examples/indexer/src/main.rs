@@ -0,0 +1,180 @@
+//! `arch-indexer`, a daemon following a node into an embedded database and
+//! the queries answered from it.
+//!
+//! `arch-indexer run` indexes new blocks as the node produces them. The
+//! other commands read the database, which they can do while the daemon
+//! runs, and print a single JSON document on stdout, or `{"error": "..."}`
+//! with exit status 1.
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use log::error;
+use serde_json::{json, Value};
+
+use arch_indexer::{Follower, Store};
+use common::config::load_profile;
+use sdk::arch_program::pubkey::Pubkey;
+
+mod output;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "arch-indexer",
+    version,
+    about = "Index the history of an Arch node"
+)]
+struct Cli {
+    /// Directory of the database
+    #[arg(
+        long,
+        global = true,
+        env = "ARCH_INDEXER_DB",
+        default_value = ".arch-index"
+    )]
+    db: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Follow the node, indexing its blocks as they are produced
+    Run {
+        /// Network profile giving the node URL, see `arch.json`
+        #[arg(long, env = "ARCH_PROFILE")]
+        profile: Option<String>,
+        /// RPC endpoint of the node, the leader of the profile by default
+        #[arg(long, env = "ARCH_RPC_URL")]
+        rpc_url: Option<String>,
+        /// Seconds between polls of the node for new blocks
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+        /// Index the blocks produced so far and exit
+        #[arg(long)]
+        once: bool,
+    },
+    /// Show the transactions touching an account and how they changed it
+    Account { pubkey: String },
+    /// Show the transactions calling a program
+    Program { program_id: String },
+    /// Show the transaction that set the current UTXO of an account
+    UtxoOrigin { pubkey: String },
+    /// Show the decoded system instructions, of a single account if given
+    SystemInstructions {
+        #[arg(long)]
+        account: Option<String>,
+    },
+    /// Show an indexed transaction
+    Tx { txid: String },
+}
+
+fn run(cli: &Cli) -> Result<Value> {
+    let store = || Store::open_read_only(&cli.db);
+
+    match &cli.command {
+        Command::Run {
+            profile,
+            rpc_url,
+            interval,
+            once,
+        } => {
+            let url = match rpc_url {
+                Some(url) => url.clone(),
+                None => load_profile(profile.as_deref())?.leader_url()?.to_string(),
+            };
+            let mut store = Store::open(&cli.db)?;
+            let follower = Follower::new(url);
+            if *once {
+                let blocks = follower.sync(&mut store)?;
+                return Ok(json!({ "indexed_blocks": blocks }));
+            }
+            loop {
+                // A node restarting isn't a reason to stop following it
+                if let Err(e) = follower.sync(&mut store) {
+                    error!("Failed to index new blocks: {:#}", e);
+                }
+                thread::sleep(Duration::from_secs(*interval));
+            }
+        }
+        Command::Account { pubkey } => {
+            let history = store()?.account_history(&parse_pubkey(pubkey)?);
+            Ok(json!(history
+                .iter()
+                .map(output::account_change)
+                .collect::<Vec<_>>()))
+        }
+        Command::Program { program_id } => {
+            let store = store()?;
+            let transactions = store.program_transactions(&parse_pubkey(program_id)?);
+            Ok(json!(transactions
+                .iter()
+                .map(|processed_tx| output::transaction(&store, processed_tx))
+                .collect::<Vec<_>>()))
+        }
+        Command::UtxoOrigin { pubkey } => {
+            let pubkey = parse_pubkey(pubkey)?;
+            match store()?.utxo_origin(&pubkey) {
+                Some(origin) => Ok(output::utxo_origin(&origin)),
+                None => bail!("No indexed transaction set the UTXO of {:x}", pubkey),
+            }
+        }
+        Command::SystemInstructions { account } => {
+            let pubkey = account.as_deref().map(parse_pubkey).transpose()?;
+            let records = store()?.system_instructions(pubkey.as_ref());
+            Ok(json!(records
+                .iter()
+                .map(output::system_instruction)
+                .collect::<Vec<_>>()))
+        }
+        Command::Tx { txid } => {
+            let store = store()?;
+            match store.transaction(txid) {
+                Some(processed_tx) => Ok(output::transaction(&store, processed_tx)),
+                None => bail!("Transaction {} isn't indexed", txid),
+            }
+        }
+    }
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<Pubkey> {
+    let bytes = hex::decode(pubkey).context("Pubkey isn't valid hex")?;
+    if bytes.len() != 32 {
+        bail!("Pubkey should be 32 bytes, got {}", bytes.len());
+    }
+    Ok(Pubkey::from_slice(&bytes))
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+    let cli = Cli::parse();
+
+    match run(&cli) {
+        Ok(output) => {
+            println!("{}", serde_json::to_string_pretty(&output).unwrap());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json!({ "error": format!("{:#}", err) })).unwrap()
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+}
@@ -0,0 +1,104 @@
+//! JSON renderings of the query results, with keys and bytes as hex.
+use serde_json::{json, Value};
+
+use arch_indexer::query::{AccountChange, SystemInstructionRecord, UtxoOrigin, UtxoSource};
+use arch_indexer::Store;
+use common::helper::AccountInfoResult;
+use sdk::arch_program::pubkey::Pubkey;
+use sdk::arch_program::system_instruction::SystemInstruction;
+use sdk::processed_transaction::{ProcessedTransaction, Status};
+
+pub fn pubkey(pubkey: &Pubkey) -> Value {
+    json!(format!("{:x}", pubkey))
+}
+
+fn status(status: &Status) -> Value {
+    match status {
+        Status::Processing => json!("processing"),
+        Status::Processed => json!("processed"),
+        Status::Failed(error) => json!({ "failed": error.to_string() }),
+    }
+}
+
+fn account(account: &AccountInfoResult) -> Value {
+    json!({
+        "owner": pubkey(&account.owner),
+        "utxo": account.utxo,
+        "is_executable": account.is_executable,
+        "data": hex::encode(&account.data),
+    })
+}
+
+pub fn account_change(change: &AccountChange) -> Value {
+    json!({
+        "txid": change.txid,
+        "block_hash": change.block_hash,
+        "status": status(&change.status),
+        "writable": change.writable,
+        "account": change.account.as_ref().map(account),
+    })
+}
+
+pub fn transaction(store: &Store, processed_tx: &ProcessedTransaction) -> Value {
+    let txid = processed_tx.txid();
+    json!({
+        "block_hash": store.block_of(&txid),
+        "txid": txid,
+        "status": status(&processed_tx.status),
+        "bitcoin_txids": processed_tx.bitcoin_txids,
+        "instructions": processed_tx
+            .runtime_transaction
+            .message
+            .instructions
+            .iter()
+            .map(|instruction| json!({
+                "program_id": pubkey(&instruction.program_id),
+                "accounts": instruction
+                    .accounts
+                    .iter()
+                    .map(|meta| pubkey(&meta.pubkey))
+                    .collect::<Vec<_>>(),
+                "data": hex::encode(&instruction.data),
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+pub fn utxo_origin(origin: &UtxoOrigin) -> Value {
+    let source = match origin.source {
+        UtxoSource::CreateAccount => "create_account",
+        UtxoSource::BitcoinTransaction => "bitcoin_transaction",
+        UtxoSource::Snapshot => "snapshot",
+    };
+    json!({
+        "utxo": origin.utxo,
+        "txid": origin.txid,
+        "source": source,
+    })
+}
+
+pub fn system_instruction(record: &SystemInstructionRecord) -> Value {
+    let instruction = match &record.instruction {
+        Ok(SystemInstruction::CreateAccount(utxo)) => json!({
+            "create_account": { "txid": hex::encode(utxo.txid()), "vout": utxo.vout() },
+        }),
+        // Never decoded, `try_from_slice` reads its bytes as `WriteBytes`
+        #[allow(deprecated)]
+        Ok(SystemInstruction::ExtendBytes(data)) => json!({ "extend_bytes": hex::encode(data) }),
+        Ok(SystemInstruction::WriteBytes { offset, data }) => json!({
+            "write_bytes": { "offset": offset, "len": data.len(), "data": hex::encode(data) },
+        }),
+        Ok(SystemInstruction::MakeExecutable) => json!("make_executable"),
+        Ok(SystemInstruction::AssignOwnership(owner)) => json!({
+            "assign_ownership": pubkey(owner),
+        }),
+        Ok(SystemInstruction::Truncate(len)) => json!({ "truncate": len }),
+        Err(error) => json!({ "invalid": error.to_string() }),
+    };
+    json!({
+        "txid": record.txid,
+        "index": record.index,
+        "pubkey": record.pubkey.as_ref().map(pubkey),
+        "instruction": instruction,
+    })
+}
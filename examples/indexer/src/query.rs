@@ -0,0 +1,180 @@
+//! Questions dashboards ask about the indexed history.
+use std::collections::HashMap;
+
+use common::helper::AccountInfoResult;
+use sdk::arch_program::program_error::ProgramError;
+use sdk::arch_program::pubkey::Pubkey;
+use sdk::arch_program::system_instruction::SystemInstruction;
+use sdk::processed_transaction::{ProcessedTransaction, Status};
+
+use crate::store::Store;
+
+/// A transaction touching an account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountChange {
+    pub txid: String,
+    /// Hash of the block containing the transaction, `None` until the
+    /// block is fully indexed
+    pub block_hash: Option<String>,
+    pub status: Status,
+    /// Whether an instruction of the transaction could write the account
+    pub writable: bool,
+    /// The account as read after the transaction, if it differed from the
+    /// previous snapshot
+    pub account: Option<AccountInfoResult>,
+}
+
+/// How the transaction setting an account's UTXO was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UtxoSource {
+    /// A system instruction created the account from the UTXO
+    CreateAccount,
+    /// The transaction broadcast the Bitcoin transaction of the UTXO
+    BitcoinTransaction,
+    /// The account had the UTXO in the first snapshot taken after the
+    /// transaction
+    Snapshot,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtxoOrigin {
+    /// The UTXO, as `<txid>:<vout>`
+    pub utxo: String,
+    pub txid: String,
+    pub source: UtxoSource,
+}
+
+/// A system program instruction of an indexed transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemInstructionRecord {
+    pub txid: String,
+    /// Position of the instruction in the transaction
+    pub index: usize,
+    /// The account the instruction operates on
+    pub pubkey: Option<Pubkey>,
+    pub instruction: Result<SystemInstruction, ProgramError>,
+}
+
+impl Store {
+    /// Every transaction touching the account, oldest first, with the state
+    /// it left the account in.
+    pub fn account_history(&self, pubkey: &Pubkey) -> Vec<AccountChange> {
+        let snapshots = self
+            .snapshots(pubkey)
+            .iter()
+            .map(|snapshot| (snapshot.txid.as_str(), &snapshot.account))
+            .collect::<HashMap<_, _>>();
+
+        self.transactions_touching(pubkey)
+            .map(|processed_tx| {
+                let txid = processed_tx.txid();
+                AccountChange {
+                    block_hash: self.block_of(&txid).map(str::to_string),
+                    status: processed_tx.status.clone(),
+                    writable: writes(processed_tx, pubkey),
+                    account: snapshots.get(txid.as_str()).map(|&account| account.clone()),
+                    txid,
+                }
+            })
+            .collect()
+    }
+
+    /// Transactions with an instruction for the program, oldest first.
+    pub fn program_transactions(&self, program_id: &Pubkey) -> Vec<&ProcessedTransaction> {
+        self.transactions_calling(program_id).collect()
+    }
+
+    /// The transaction that set the current UTXO of the account, as known
+    /// from its latest snapshot.
+    pub fn utxo_origin(&self, pubkey: &Pubkey) -> Option<UtxoOrigin> {
+        let utxo = &self.latest_snapshot(pubkey)?.account.utxo;
+        let origin = |txid: String, source| UtxoOrigin {
+            utxo: utxo.clone(),
+            txid,
+            source,
+        };
+
+        let (bitcoin_txid, vout) = utxo.split_once(':')?;
+        let created = self
+            .system_instructions(Some(pubkey))
+            .into_iter()
+            .rev()
+            .find(|record| match &record.instruction {
+                Ok(SystemInstruction::CreateAccount(meta)) => {
+                    hex::encode(meta.txid()) == bitcoin_txid && meta.vout().to_string() == vout
+                }
+                _ => false,
+            });
+        if let Some(record) = created {
+            return Some(origin(record.txid, UtxoSource::CreateAccount));
+        }
+
+        if let Some(processed_tx) = self
+            .transaction_broadcasting(bitcoin_txid)
+            .filter(|processed_tx| touches(processed_tx, pubkey))
+        {
+            return Some(origin(processed_tx.txid(), UtxoSource::BitcoinTransaction));
+        }
+
+        // The first of the latest snapshots holding the UTXO
+        let snapshot = self
+            .snapshots(pubkey)
+            .iter()
+            .rev()
+            .take_while(|snapshot| &snapshot.account.utxo == utxo)
+            .last()?;
+        Some(origin(snapshot.txid.clone(), UtxoSource::Snapshot))
+    }
+
+    /// System program instructions, oldest first, only those operating on
+    /// `pubkey` if given. Instructions the system program would reject
+    /// are returned with their decoding error.
+    pub fn system_instructions(&self, pubkey: Option<&Pubkey>) -> Vec<SystemInstructionRecord> {
+        let system_program = Pubkey::system_program();
+        let transactions: Box<dyn Iterator<Item = &ProcessedTransaction>> = match pubkey {
+            Some(pubkey) => Box::new(self.transactions_touching(pubkey)),
+            None => Box::new(self.transactions_calling(&system_program)),
+        };
+
+        transactions
+            .flat_map(|processed_tx| {
+                let txid = processed_tx.txid();
+                processed_tx
+                    .runtime_transaction
+                    .message
+                    .instructions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, instruction)| instruction.program_id == system_program)
+                    .map(move |(index, instruction)| SystemInstructionRecord {
+                        txid: txid.clone(),
+                        index,
+                        pubkey: instruction.accounts.first().map(|meta| meta.pubkey),
+                        instruction: SystemInstruction::try_from_slice(&instruction.data),
+                    })
+            })
+            .filter(|record| pubkey.is_none() || record.pubkey.as_ref() == pubkey)
+            .collect()
+    }
+}
+
+fn touches(processed_tx: &ProcessedTransaction, pubkey: &Pubkey) -> bool {
+    let message = &processed_tx.runtime_transaction.message;
+    message.signers.contains(pubkey)
+        || message.instructions.iter().any(|instruction| {
+            instruction
+                .accounts
+                .iter()
+                .any(|meta| meta.pubkey == *pubkey)
+        })
+}
+
+fn writes(processed_tx: &ProcessedTransaction, pubkey: &Pubkey) -> bool {
+    processed_tx
+        .runtime_transaction
+        .message
+        .instructions
+        .iter()
+        .flat_map(|instruction| &instruction.accounts)
+        .any(|meta| meta.pubkey == *pubkey && meta.is_writable)
+}
@@ -0,0 +1,505 @@
+//! The embedded database of the indexer: an append-only log of records in a
+//! single file, whose indexes are rebuilt in memory when it is opened.
+//!
+//! Each record is a kind byte, the length of its payload as a little-endian
+//! `u64`, then the payload:
+//!
+//! | kind | record      | payload                                      |
+//! |------|-------------|----------------------------------------------|
+//! | 1    | transaction | [`ProcessedTransaction::to_vec`]             |
+//! | 2    | snapshot    | a [`Snapshot`] as JSON                       |
+//! | 3    | block       | an [`IndexedBlock`] as JSON                  |
+//!
+//! A block is written after its transactions and snapshots, so a crash
+//! while indexing it at worst leaves a truncated record, dropped on open,
+//! and records the block is indexed again on top of.
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use common::helper::AccountInfoResult;
+use sdk::arch_program::pubkey::Pubkey;
+use sdk::processed_transaction::ProcessedTransaction;
+
+/// Name of the log in the database directory
+pub const LOG_FILE: &str = "index.log";
+
+const TRANSACTION: u8 = 1;
+const SNAPSHOT: u8 = 2;
+const BLOCK: u8 = 3;
+
+/// Kind and payload length
+const HEADER_LEN: usize = 9;
+
+/// A block whose transactions are all indexed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedBlock {
+    pub hash: String,
+    pub previous_block_hash: String,
+    pub transactions: Vec<String>,
+}
+
+/// The state of an account, read after indexing a transaction writing it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub pubkey: Pubkey,
+    pub txid: String,
+    pub account: AccountInfoResult,
+}
+
+pub struct Store {
+    path: PathBuf,
+    file: File,
+    blocks: Vec<IndexedBlock>,
+    block_by_hash: HashMap<String, usize>,
+    block_of_transaction: HashMap<String, usize>,
+    /// Transactions in the order they were indexed, which the indexes below
+    /// point into
+    transactions: Vec<ProcessedTransaction>,
+    by_txid: HashMap<String, usize>,
+    by_account: HashMap<Pubkey, Vec<usize>>,
+    by_program: HashMap<Pubkey, Vec<usize>>,
+    by_bitcoin_txid: HashMap<String, usize>,
+    snapshots: HashMap<Pubkey, Vec<Snapshot>>,
+}
+
+impl Store {
+    /// Opens the database in `dir`, creating it if needed.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).with_context(|| format!("Can't create {}", dir.display()))?;
+        let path = dir.join(LOG_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .with_context(|| format!("Can't open {}", path.display()))?;
+        Self::load(path, file, true)
+    }
+
+    /// Opens the database in `dir` for queries, which can run while another
+    /// process indexes into it.
+    pub fn open_read_only(dir: impl AsRef<Path>) -> Result<Self> {
+        let path = dir.as_ref().join(LOG_FILE);
+        let file = File::open(&path).with_context(|| format!("Can't open {}", path.display()))?;
+        Self::load(path, file, false)
+    }
+
+    /// Replays the log into the indexes. A truncated last record is dropped
+    /// from the log if it is `writable`, and ignored otherwise as it may be
+    /// the one being appended.
+    fn load(path: PathBuf, file: File, writable: bool) -> Result<Self> {
+        let mut store = Self {
+            path,
+            file,
+            blocks: vec![],
+            block_by_hash: HashMap::new(),
+            block_of_transaction: HashMap::new(),
+            transactions: vec![],
+            by_txid: HashMap::new(),
+            by_account: HashMap::new(),
+            by_program: HashMap::new(),
+            by_bitcoin_txid: HashMap::new(),
+            snapshots: HashMap::new(),
+        };
+
+        let log = fs::read(&store.path)?;
+        let mut offset = 0;
+        while offset < log.len() {
+            let Some((kind, payload)) = record_at(&log, offset) else {
+                if writable {
+                    warn!(
+                        "Dropping the truncated record at offset {} of {}",
+                        offset,
+                        store.path.display()
+                    );
+                    store.file.set_len(offset as u64)?;
+                }
+                break;
+            };
+            store
+                .apply(kind, payload)
+                .with_context(|| format!("Corrupted record at offset {}", offset))?;
+            offset += HEADER_LEN + payload.len();
+        }
+        Ok(store)
+    }
+
+    fn apply(&mut self, kind: u8, payload: &[u8]) -> Result<()> {
+        match kind {
+            TRANSACTION => self.index_transaction(ProcessedTransaction::from_vec(payload)?),
+            SNAPSHOT => self.index_snapshot(serde_json::from_slice(payload)?),
+            BLOCK => self.index_block(serde_json::from_slice(payload)?),
+            kind => bail!("unknown record kind {}", kind),
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, kind: u8, payload: &[u8]) -> Result<()> {
+        let mut record = Vec::with_capacity(HEADER_LEN + payload.len());
+        record.push(kind);
+        record.extend((payload.len() as u64).to_le_bytes());
+        record.extend(payload);
+        self.file
+            .write_all(&record)
+            .with_context(|| format!("Can't write to {}", self.path.display()))
+    }
+
+    /// Stores a transaction, returning false if it was already stored.
+    pub fn add_transaction(&mut self, processed_tx: &ProcessedTransaction) -> Result<bool> {
+        if self.by_txid.contains_key(&processed_tx.txid()) {
+            return Ok(false);
+        }
+        self.append(TRANSACTION, &processed_tx.to_vec()?)?;
+        self.index_transaction(processed_tx.clone());
+        Ok(true)
+    }
+
+    /// Stores a snapshot, returning false if the account is unchanged since
+    /// its last one.
+    pub fn add_snapshot(&mut self, snapshot: Snapshot) -> Result<bool> {
+        if self
+            .latest_snapshot(&snapshot.pubkey)
+            .map(|last| &last.account)
+            == Some(&snapshot.account)
+        {
+            return Ok(false);
+        }
+        self.append(SNAPSHOT, &serde_json::to_vec(&snapshot)?)?;
+        self.index_snapshot(snapshot);
+        Ok(true)
+    }
+
+    /// Stores a block once its transactions are, making it durable.
+    pub fn add_block(&mut self, block: IndexedBlock) -> Result<()> {
+        if let Some(txid) = block
+            .transactions
+            .iter()
+            .find(|txid| !self.by_txid.contains_key(*txid))
+        {
+            return Err(anyhow!(
+                "Block {} has the unindexed transaction {}",
+                block.hash,
+                txid
+            ));
+        }
+        self.append(BLOCK, &serde_json::to_vec(&block)?)?;
+        self.file.sync_data()?;
+        self.index_block(block);
+        Ok(())
+    }
+
+    fn index_transaction(&mut self, processed_tx: ProcessedTransaction) {
+        let txid = processed_tx.txid();
+        if self.by_txid.contains_key(&txid) {
+            return;
+        }
+        let position = self.transactions.len();
+
+        let message = &processed_tx.runtime_transaction.message;
+        let accounts = message.signers.iter().chain(
+            message
+                .instructions
+                .iter()
+                .flat_map(|instruction| instruction.accounts.iter().map(|meta| &meta.pubkey)),
+        );
+        for pubkey in accounts {
+            push_once(self.by_account.entry(*pubkey).or_default(), position);
+        }
+        for instruction in &message.instructions {
+            push_once(
+                self.by_program.entry(instruction.program_id).or_default(),
+                position,
+            );
+        }
+        for bitcoin_txid in &processed_tx.bitcoin_txids {
+            self.by_bitcoin_txid.insert(bitcoin_txid.clone(), position);
+        }
+
+        self.by_txid.insert(txid, position);
+        self.transactions.push(processed_tx);
+    }
+
+    fn index_snapshot(&mut self, snapshot: Snapshot) {
+        self.snapshots
+            .entry(snapshot.pubkey)
+            .or_default()
+            .push(snapshot);
+    }
+
+    fn index_block(&mut self, block: IndexedBlock) {
+        if self.block_by_hash.contains_key(&block.hash) {
+            return;
+        }
+        let position = self.blocks.len();
+        for txid in &block.transactions {
+            self.block_of_transaction.insert(txid.clone(), position);
+        }
+        self.block_by_hash.insert(block.hash.clone(), position);
+        self.blocks.push(block);
+    }
+
+    /// The last indexed block
+    pub fn best_block(&self) -> Option<&IndexedBlock> {
+        self.blocks.last()
+    }
+
+    pub fn contains_block(&self, hash: &str) -> bool {
+        self.block_by_hash.contains_key(hash)
+    }
+
+    pub fn transaction(&self, txid: &str) -> Option<&ProcessedTransaction> {
+        self.by_txid
+            .get(txid)
+            .map(|&position| &self.transactions[position])
+    }
+
+    /// Hash of the block containing the transaction, once indexed
+    pub fn block_of(&self, txid: &str) -> Option<&str> {
+        self.block_of_transaction
+            .get(txid)
+            .map(|&position| self.blocks[position].hash.as_str())
+    }
+
+    /// Transactions signed by or passing the account to an instruction, in
+    /// the order they were indexed
+    pub fn transactions_touching(
+        &self,
+        pubkey: &Pubkey,
+    ) -> impl Iterator<Item = &ProcessedTransaction> {
+        self.positions(self.by_account.get(pubkey))
+    }
+
+    /// Transactions with an instruction for the program, in the order they
+    /// were indexed
+    pub fn transactions_calling(
+        &self,
+        program_id: &Pubkey,
+    ) -> impl Iterator<Item = &ProcessedTransaction> {
+        self.positions(self.by_program.get(program_id))
+    }
+
+    /// Transactions in the order they were indexed
+    pub fn transactions(&self) -> impl Iterator<Item = &ProcessedTransaction> {
+        self.transactions.iter()
+    }
+
+    /// The transaction that broadcast the Bitcoin transaction
+    pub fn transaction_broadcasting(&self, bitcoin_txid: &str) -> Option<&ProcessedTransaction> {
+        self.by_bitcoin_txid
+            .get(bitcoin_txid)
+            .map(|&position| &self.transactions[position])
+    }
+
+    /// Snapshots of the account, oldest first
+    pub fn snapshots(&self, pubkey: &Pubkey) -> &[Snapshot] {
+        self.snapshots.get(pubkey).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn latest_snapshot(&self, pubkey: &Pubkey) -> Option<&Snapshot> {
+        self.snapshots(pubkey).last()
+    }
+
+    fn positions<'a>(
+        &'a self,
+        positions: Option<&'a Vec<usize>>,
+    ) -> impl Iterator<Item = &'a ProcessedTransaction> {
+        positions
+            .into_iter()
+            .flatten()
+            .map(|&position| &self.transactions[position])
+    }
+}
+
+/// Returns the kind and payload of the record at `offset`, or `None` if the
+/// log ends before it does.
+fn record_at(log: &[u8], offset: usize) -> Option<(u8, &[u8])> {
+    let header = log.get(offset..offset + HEADER_LEN)?;
+    let len = u64::from_le_bytes(header[1..].try_into().unwrap());
+    let start = offset + HEADER_LEN;
+    let end = start.checked_add(usize::try_from(len).ok()?)?;
+    Some((header[0], log.get(start..end)?))
+}
+
+/// Pushes `position` unless it is already last, as positions are pushed in
+/// increasing order.
+fn push_once(positions: &mut Vec<usize>, position: usize) {
+    if positions.last() != Some(&position) {
+        positions.push(position);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use sdk::arch_program::account::AccountMeta;
+    use sdk::arch_program::instruction::Instruction;
+    use sdk::arch_program::message::Message;
+    use sdk::processed_transaction::Status;
+    use sdk::runtime_transaction::RuntimeTransaction;
+
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("arch-indexer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// A transaction calling `program_id` with `writable` as its writable
+    /// account, made unique by `nonce`
+    pub(crate) fn transaction(
+        program_id: Pubkey,
+        writable: Pubkey,
+        data: Vec<u8>,
+        nonce: u8,
+    ) -> ProcessedTransaction {
+        ProcessedTransaction {
+            runtime_transaction: RuntimeTransaction {
+                version: 0,
+                signatures: vec![],
+                message: Message {
+                    signers: vec![Pubkey::from_slice(&[nonce; 32])],
+                    instructions: vec![Instruction {
+                        program_id,
+                        accounts: vec![AccountMeta {
+                            pubkey: writable,
+                            is_signer: false,
+                            is_writable: true,
+                        }],
+                        data,
+                    }],
+                },
+            },
+            status: Status::Processed,
+            bitcoin_txids: vec![],
+            logs: vec![],
+            compute_units_consumed: 0,
+            return_data: None,
+        }
+    }
+
+    pub(crate) fn account(owner: Pubkey, utxo: &str) -> AccountInfoResult {
+        AccountInfoResult {
+            owner,
+            data: vec![1, 2],
+            utxo: utxo.to_string(),
+            is_executable: false,
+        }
+    }
+
+    #[test]
+    fn test_reopen_rebuilds_indexes() {
+        let dir = temp_dir("reopen");
+        let (program_id, pubkey) = (Pubkey::from_slice(&[9; 32]), Pubkey::from_slice(&[5; 32]));
+        let first = transaction(program_id, pubkey, vec![1], 1);
+        let second = ProcessedTransaction {
+            bitcoin_txids: vec![hex::encode([3; 32])],
+            ..transaction(program_id, pubkey, vec![2], 2)
+        };
+
+        {
+            let mut store = Store::open(&dir).unwrap();
+            assert!(store.add_transaction(&first).unwrap());
+            assert!(!store.add_transaction(&first).unwrap());
+            assert!(store.add_transaction(&second).unwrap());
+
+            let snapshot = Snapshot {
+                pubkey,
+                txid: second.txid(),
+                account: account(program_id, "00:0"),
+            };
+            assert!(store.add_snapshot(snapshot.clone()).unwrap());
+            assert!(!store
+                .add_snapshot(Snapshot {
+                    txid: first.txid(),
+                    ..snapshot
+                })
+                .unwrap());
+
+            store
+                .add_block(IndexedBlock {
+                    hash: "b1".to_string(),
+                    previous_block_hash: "0".repeat(64),
+                    transactions: vec![first.txid(), second.txid()],
+                })
+                .unwrap();
+            assert!(store
+                .add_block(IndexedBlock {
+                    hash: "b2".to_string(),
+                    previous_block_hash: "b1".to_string(),
+                    transactions: vec!["unknown".to_string()],
+                })
+                .is_err());
+        }
+
+        let store = Store::open(&dir).unwrap();
+        assert_eq!(store.best_block().unwrap().hash, "b1");
+        assert_eq!(store.block_of(&second.txid()), Some("b1"));
+        assert_eq!(
+            store.transactions_touching(&pubkey).collect::<Vec<_>>(),
+            vec![&first, &second]
+        );
+        assert_eq!(store.transactions_calling(&program_id).count(), 2);
+        assert_eq!(
+            store.transaction_broadcasting(&hex::encode([3; 32])),
+            Some(&second)
+        );
+        assert_eq!(store.snapshots(&pubkey).len(), 1);
+        assert_eq!(store.latest_snapshot(&pubkey).unwrap().txid, second.txid());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_record_is_dropped() {
+        let dir = temp_dir("truncated");
+        let pubkey = Pubkey::from_slice(&[5; 32]);
+        let processed_tx = transaction(Pubkey::system_program(), pubkey, vec![2], 1);
+        {
+            let mut store = Store::open(&dir).unwrap();
+            store.add_transaction(&processed_tx).unwrap();
+        }
+        let complete = fs::metadata(dir.join(LOG_FILE)).unwrap().len();
+
+        // A crash in the middle of appending the next record
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(LOG_FILE))
+            .unwrap();
+        file.write_all(&[SNAPSHOT, 200, 0, 0, 0, 0, 0, 0, 0, b'{'])
+            .unwrap();
+
+        let store = Store::open_read_only(&dir).unwrap();
+        assert_eq!(store.transaction(&processed_tx.txid()), Some(&processed_tx));
+        assert!(fs::metadata(dir.join(LOG_FILE)).unwrap().len() > complete);
+
+        let mut store = Store::open(&dir).unwrap();
+        assert_eq!(fs::metadata(dir.join(LOG_FILE)).unwrap().len(), complete);
+        assert_eq!(store.transaction(&processed_tx.txid()), Some(&processed_tx));
+
+        // Records are appended after the last complete one
+        let snapshot = Snapshot {
+            pubkey,
+            txid: processed_tx.txid(),
+            account: account(Pubkey::system_program(), "00:0"),
+        };
+        store.add_snapshot(snapshot.clone()).unwrap();
+        drop(store);
+        let store = Store::open(&dir).unwrap();
+        assert_eq!(store.snapshots(&pubkey), &[snapshot]);
+
+        // Anything else is an error rather than silently lost history
+        let mut log = fs::read(dir.join(LOG_FILE)).unwrap();
+        log[0] = 42;
+        fs::write(dir.join(LOG_FILE), log).unwrap();
+        assert!(Store::open(&dir).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
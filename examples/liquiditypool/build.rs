@@ -0,0 +1,12 @@
+use std::{env, fs, path::Path};
+
+/// Generates the liquidity pool client from the IDL checked in by the program.
+fn main() {
+    let idl = "program/idl.json";
+    println!("cargo:rerun-if-changed={}", idl);
+
+    let client = sdk::codegen::generate_client_from_file(idl).expect("IDL should be valid");
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    fs::write(Path::new(&out_dir).join("liquidity_pool_client.rs"), client)
+        .expect("writing the client should not fail");
+}
@@ -0,0 +1,32 @@
+use arch_program::program_error::ProgramError;
+
+/// Reasons a pool instruction fails, returned as [`ProgramError::Custom`]
+/// with the discriminant as code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum PoolError {
+    /// The fee is not below 10_000 basis points
+    InvalidFee,
+    /// An amount, or what it converts to, is zero
+    ZeroAmount,
+    /// The pool can't pay that much or would be left with nothing
+    InsufficientLiquidity,
+    /// The caller doesn't hold that many shares
+    InsufficientShares,
+    /// The price moved past the bound set by the caller
+    SlippageExceeded,
+    /// The amounts are too large for the pool math
+    Overflow,
+    /// The settlement is longer than a transaction to sign can be, see
+    /// [`MAX_TX_BYTES_LEN`](arch_program::transaction_to_sign::MAX_TX_BYTES_LEN)
+    TransactionTooLarge,
+    /// Runes are brought into the pool without the signature of its
+    /// attestor
+    UnattestedRunes,
+}
+
+impl From<PoolError> for ProgramError {
+    fn from(error: PoolError) -> Self {
+        ProgramError::Custom(error as u32)
+    }
+}
@@ -1,120 +1,266 @@
+//! A constant product pool between BTC and a rune.
+//!
+//! The pool account stores the [`LiquidityPool`](liquidity_pool::LiquidityPool)
+//! state and its UTXO holds the reserves, so every instruction updates both
+//! together: the state through the account data and the reserves through the
+//! transaction set with `set_transaction_to_sign`, see [`transition`].
+//!
+//! # Trust model
+//!
+//! Programs can't read the rune balance of an output, so the pool can't
+//! check the runes the inputs of a caller carry itself. Each pool names a
+//! rune attestor when it is opened, typically the operator of a rune
+//! indexer, and every instruction bringing runes into the pool must be
+//! signed by it, passed as the first account after the declared ones. By
+//! signing, the attestor vouches that the inputs of the caller carry at
+//! least the runes the instruction declares, the surplus going to the pool.
+//! Instructions bringing no runes in don't need it.
+//!
+//! Liquidity providers and traders trust the attestor of a pool like they
+//! would its indexer: an attestor signing for runes that aren't there lets
+//! the caller be paid for a deposit the pool never gets.
+use std::collections::BTreeMap;
+
 use arch_program::{
-    account::{AccountInfo},
-    entrypoint,
-    instruction::Instruction,
-    msg,
-    program::{
-        invoke, set_return_data, get_bitcoin_tx, 
-        validate_utxo_ownership, get_network_xonly_pubkey,
-        set_transaction_to_sign, next_account_info,
-    },
-    helper::get_state_transition_tx,
-    transaction_to_sign::TransactionToSign,
-    program_error::ProgramError,
-    input_to_sign::InputToSign,
+    account::{Account, AccountInfo},
+    context::Context,
+    entrypoint::ProgramResult,
     pubkey::Pubkey,
-    utxo::UtxoMeta,
-    system_instruction::SystemInstruction,
+    state, Accounts, IdlBuild,
 };
-use borsh::{BorshSerialize, BorshDeserialize};
-
-entrypoint!(process_instruction);
-pub fn process_instruction(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    instruction_data: &[u8],
-) -> Result<(), ProgramError> {
-    
-    let params: LiquidityPoolParams = borsh::from_slice(instruction_data).unwrap();
-
-    match params {
-        LiquidityPoolParams::InitializePool((
-            open_pool_params,
-            user_psbt,
-            param_utxos,
-            serialized_utxos
-        )) => {
-            let utxos = serialized_utxos.iter().map(|serialized_utxo| UtxoMeta::from_slice(serialized_utxo)).collect::<Vec<UtxoMeta>>();
-
-            if accounts.len() != 2 {
-                panic!("account length mismatch");
-            }
-        
-            let account_iter = &mut accounts.iter();
-            let liquidity_pool_account = next_account_info(account_iter)?;
-            assert!(liquidity_pool_account.is_writable, "liquidity_pool_account must be writable");
-            let mut pool: LiquidityPool = borsh::from_slice(&liquidity_pool_account.data.try_borrow().unwrap()).unwrap();
-            let caller_account = next_account_info(account_iter)?;
-            assert!(caller_account.is_signer, "caller_account must be signer");
-
-            initialize_pool(
-                &mut pool,
-                open_pool_params,
-                caller_account.key,
-                user_psbt,
-                param_utxos,
-                &utxos,
-            );
-
-            liquidity_pool_account.data.try_borrow_mut().unwrap().copy_from_slice(&borsh::to_vec(&pool).unwrap());
-            
-        },
-        _ => {}
-    }
+use borsh::{BorshDeserialize, BorshSerialize};
 
-    Ok(())
-}
+pub mod error;
+pub mod math;
+pub mod pool;
+pub mod transition;
 
-pub fn initialize_pool(
-    pool: &mut LiquidityPool,
-    params: OpenPoolParams,
-    caller: &Pubkey,
-    user_psbt: Vec<u8>,
-    param_utxos: OpenPoolUtxos,
-    utxos: &Vec<UtxoMeta>,
-) {
-    
-}
+use error::PoolError;
+use liquidity_pool::LiquidityPool;
+use transition::{settle, Transfer};
 
-#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
-pub enum LiquidityPoolParams {
-    InitializePool((OpenPoolParams, Vec<u8>, OpenPoolUtxos, Vec<[u8; 36]>)),
-    IncreaseLiquidity,
-    DecreaseLiquidity,
-    Swap,
+/// The accounts of every instruction, followed by the rune attestor of the
+/// pool when the instruction brings runes in, see the
+/// [trust model](crate#trust-model).
+#[derive(Accounts)]
+pub struct PoolAccounts<'a, 'b> {
+    #[account(writable, owner = program_id)]
+    pub pool: &'a AccountInfo<'b>,
+    /// Owner of the liquidity added or removed
+    #[account(signer)]
+    pub caller: &'a AccountInfo<'b>,
 }
 
-use std::collections::HashMap;
+#[arch_program::program]
+pub mod liquidity_pool {
+    use super::*;
 
-#[derive(Debug, BorshSerialize, BorshDeserialize, Default)]
-pub struct LiquidityPool {
-    pub balance_sats: u128,
-    pub balance_rune: u128,
-    pub fee: Option<u8>,
-    pub positions: HashMap<String, LiquidityPosition>,
-    pub rune_name: String,
-    pub rune_id: (u64, u32),
+    #[state]
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct LiquidityPool {
+        /// BTC held by the pool, in sats
+        pub balance_sats: u64,
+        pub balance_rune: u128,
+        /// Share of each swap input kept by the pool, in basis points
+        pub fee_bps: u16,
+        /// Shares of all positions plus the locked
+        /// [`MINIMUM_LIQUIDITY`](crate::math::MINIMUM_LIQUIDITY)
+        pub total_shares: u128,
+        /// Shares of each liquidity provider
+        pub positions: BTreeMap<Pubkey, u128>,
+        pub rune_name: String,
+        pub rune_id: (u64, u32),
+        /// Signer of the instructions bringing runes into the pool
+        pub rune_attestor: Pubkey,
+    }
+
+    /// Opens the pool with the first liquidity of the caller, which sets the
+    /// price.
+    pub fn initialize_pool(
+        ctx: Context<PoolAccounts>,
+        params: OpenPoolParams,
+        funding: Funding,
+    ) -> ProgramResult {
+        let PoolAccounts { pool, caller } = ctx.accounts;
+        let (state, transfer) = LiquidityPool::open(*caller.key, &params)?;
+        check_attested(&params.rune_attestor, &transfer, ctx.remaining_accounts)?;
+        Account::init(pool, ctx.program_id, state)?.exit()?;
+        settle(pool, &funding, transfer, params.rune_id)
+    }
+
+    /// Deposits BTC and runes at the price of the pool, at most `max_sats`
+    /// and `max_rune`, for at least `min_shares`.
+    pub fn increase_liquidity(
+        ctx: Context<PoolAccounts>,
+        max_sats: u64,
+        max_rune: u128,
+        min_shares: u128,
+        funding: Funding,
+    ) -> ProgramResult {
+        update(ctx, &funding, |pool, caller| {
+            pool.increase_liquidity(caller, max_sats, max_rune, min_shares)
+        })
+    }
+
+    /// Burns `shares` of the caller for at least `min_sats` and `min_rune`.
+    pub fn decrease_liquidity(
+        ctx: Context<PoolAccounts>,
+        shares: u128,
+        min_sats: u64,
+        min_rune: u128,
+        funding: Funding,
+    ) -> ProgramResult {
+        update(ctx, &funding, |pool, caller| {
+            pool.decrease_liquidity(caller, shares, min_sats, min_rune)
+        })
+    }
+
+    /// Swaps `amount_in` for at least `min_amount_out`, in the direction
+    /// given.
+    pub fn swap(
+        ctx: Context<PoolAccounts>,
+        direction: SwapDirection,
+        amount_in: u128,
+        min_amount_out: u128,
+        funding: Funding,
+    ) -> ProgramResult {
+        update(ctx, &funding, |pool, _| {
+            pool.swap(direction, amount_in, min_amount_out)
+        })
+    }
 }
 
-#[derive(Debug, BorshSerialize, BorshDeserialize, Clone, Copy)]
-pub struct LiquidityPosition(pub f64);
+/// Applies an instruction to the state of an open pool, then settles what it
+/// exchanges with the caller.
+fn update(
+    ctx: Context<PoolAccounts>,
+    funding: &Funding,
+    instruction: impl FnOnce(&mut LiquidityPool, Pubkey) -> Result<Transfer, PoolError>,
+) -> ProgramResult {
+    let PoolAccounts { pool, caller } = ctx.accounts;
+    let mut account = Account::<LiquidityPool>::try_from(pool, ctx.program_id)?;
+    let state = account.get_mut()?;
+    let transfer = instruction(state, *caller.key)?;
+    check_attested(&state.rune_attestor, &transfer, ctx.remaining_accounts)?;
+    let rune_id = state.rune_id;
+    account.exit()?;
+    settle(pool, funding, transfer, rune_id)
+}
+
+/// Fails unless the runes `transfer` brings into the pool are vouched for by
+/// `attestor`, signing as the first of `remaining_accounts`.
+fn check_attested(
+    attestor: &Pubkey,
+    transfer: &Transfer,
+    remaining_accounts: &[AccountInfo],
+) -> ProgramResult {
+    if transfer.rune_in == 0 {
+        return Ok(());
+    }
+    match remaining_accounts.first() {
+        Some(account) if account.key == attestor && account.is_signer => Ok(()),
+        _ => Err(PoolError::UnattestedRunes.into()),
+    }
+}
 
-#[derive(Debug, BorshSerialize, BorshDeserialize, Default, Clone)]
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize, IdlBuild)]
 pub struct OpenPoolParams {
     /// The initial balance of BTC in the pool (in sats)
     pub balance_sats: u64,
     /// The initial balance of runes in the pool
-    pub balance_rune: u64,
+    pub balance_rune: u128,
     /// The trading fee that is incurred during swaps, in basis points
-    pub fee: Option<u8>,
+    pub fee_bps: u16,
     /// Name of the rune that will be held in this pool
     pub rune_name: String,
     /// Id of the rune that will be held in this pool
     pub rune_id: (u64, u32),
+    /// Signer vouching for the runes brought into the pool, see the
+    /// [trust model](crate#trust-model)
+    pub rune_attestor: Pubkey,
+}
+
+/// The side of the caller in the transaction settling an instruction.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize, IdlBuild)]
+pub struct Funding {
+    /// A transaction whose inputs are added to the settlement as they are,
+    /// so they must be signed with `SIGHASH_NONE | SIGHASH_ANYONECANPAY`.
+    /// They pay the BTC and carry the runes the caller deposits, and the fee.
+    pub tx_hex: Vec<u8>,
+    /// Mining fee of the settlement, in sats
+    pub fee_sats: u64,
+    /// Script of the output receiving the change and what the pool pays out
+    pub payout_script: Vec<u8>,
 }
 
-#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
-pub struct OpenPoolUtxos {
-    pub btc_utxo: String,
-    pub rune_utxo: String,
-}
\ No newline at end of file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize, IdlBuild)]
+pub enum SwapDirection {
+    /// Pay BTC, receive runes
+    SatsForRune,
+    /// Pay runes, receive BTC
+    RuneForSats,
+}
+
+#[cfg(test)]
+mod tests {
+    use arch_program::{program_error::ProgramError, utxo::UtxoMeta};
+
+    use super::*;
+
+    #[test]
+    fn test_check_attested() {
+        let (attestor, impostor, owner) = (
+            Pubkey::from([9; 32]),
+            Pubkey::from([8; 32]),
+            Pubkey::default(),
+        );
+        let utxo = UtxoMeta::from([0; 32], 0);
+        let (mut data, mut other_data, mut unsigned_data) = (vec![], vec![], vec![]);
+        let signed = AccountInfo::new(&attestor, &mut data, &owner, &utxo, true, false, false);
+        let other = AccountInfo::new(
+            &impostor,
+            &mut other_data,
+            &owner,
+            &utxo,
+            true,
+            false,
+            false,
+        );
+        let unsigned = AccountInfo::new(
+            &attestor,
+            &mut unsigned_data,
+            &owner,
+            &utxo,
+            false,
+            false,
+            false,
+        );
+
+        let deposit = Transfer {
+            sats_in: 1_000,
+            rune_in: 1,
+            ..Transfer::default()
+        };
+        let unattested = Err(ProgramError::from(PoolError::UnattestedRunes));
+        assert_eq!(
+            check_attested(&attestor, &deposit, std::slice::from_ref(&signed)),
+            Ok(())
+        );
+        assert_eq!(check_attested(&attestor, &deposit, &[]), unattested);
+        assert_eq!(
+            check_attested(&attestor, &deposit, std::slice::from_ref(&other)),
+            unattested
+        );
+        assert_eq!(
+            check_attested(&attestor, &deposit, std::slice::from_ref(&unsigned)),
+            unattested
+        );
+
+        // Only runes coming in need the attestor
+        let withdrawal = Transfer {
+            rune_out: 1,
+            ..Transfer::default()
+        };
+        assert_eq!(check_attested(&attestor, &withdrawal, &[]), Ok(()));
+    }
+}
@@ -0,0 +1,150 @@
+//! Integer constant product math of the pool.
+//!
+//! Every result is rounded in favor of the pool, so rounding can only grow
+//! the product of the reserves and never lets value leave it.
+use crate::error::PoolError;
+
+/// Denominator of fees given in basis points
+pub const BASIS_POINTS: u16 = 10_000;
+
+/// Shares minted on initialization that belong to no one, so the pool can
+/// never be emptied and a share can't be inflated to an unusable price.
+pub const MINIMUM_LIQUIDITY: u128 = 1_000;
+
+/// `a * b / c` rounded down.
+pub fn mul_div(a: u128, b: u128, c: u128) -> Result<u128, PoolError> {
+    a.checked_mul(b)
+        .and_then(|product| product.checked_div(c))
+        .ok_or(PoolError::Overflow)
+}
+
+/// `a * b / c` rounded up.
+pub fn mul_div_ceil(a: u128, b: u128, c: u128) -> Result<u128, PoolError> {
+    let product = a.checked_mul(b).ok_or(PoolError::Overflow)?;
+    if c == 0 {
+        return Err(PoolError::Overflow);
+    }
+    Ok(product.div_ceil(c))
+}
+
+/// Largest integer whose square is at most `n`.
+pub fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    // Newton's method from above converges to the floor of the root
+    let mut x = n;
+    let mut y = x / 2 + x % 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+/// Shares minted for the first deposit: the geometric mean of the amounts,
+/// of which [`MINIMUM_LIQUIDITY`] are locked.
+pub fn initial_shares(sats: u64, rune: u128) -> Result<u128, PoolError> {
+    let product = (sats as u128)
+        .checked_mul(rune)
+        .ok_or(PoolError::Overflow)?;
+    let shares = isqrt(product);
+    if shares <= MINIMUM_LIQUIDITY {
+        return Err(PoolError::InsufficientLiquidity);
+    }
+    Ok(shares)
+}
+
+/// Shares minted for a deposit of at most `max_sats` and `max_rune` into a
+/// pool holding `reserve_sats` and `reserve_rune` for `total_shares`, along
+/// with the amounts actually taken at the price of the pool.
+pub fn deposit(
+    (reserve_sats, reserve_rune, total_shares): (u64, u128, u128),
+    max_sats: u64,
+    max_rune: u128,
+) -> Result<(u128, u64, u128), PoolError> {
+    let reserve_sats = reserve_sats as u128;
+    let shares = mul_div(max_sats as u128, total_shares, reserve_sats)?.min(mul_div(
+        max_rune,
+        total_shares,
+        reserve_rune,
+    )?);
+    if shares == 0 {
+        return Err(PoolError::ZeroAmount);
+    }
+
+    let sats = mul_div_ceil(shares, reserve_sats, total_shares)?;
+    let rune = mul_div_ceil(shares, reserve_rune, total_shares)?;
+    Ok((shares, sats as u64, rune))
+}
+
+/// Amounts paid out for burning `shares` of `total_shares`.
+pub fn withdrawal(
+    (reserve_sats, reserve_rune, total_shares): (u64, u128, u128),
+    shares: u128,
+) -> Result<(u64, u128), PoolError> {
+    let sats = mul_div(shares, reserve_sats as u128, total_shares)?;
+    let rune = mul_div(shares, reserve_rune, total_shares)?;
+    if sats == 0 && rune == 0 {
+        return Err(PoolError::ZeroAmount);
+    }
+    Ok((sats as u64, rune))
+}
+
+/// Amount out of a swap of `amount_in`, keeping `fee_bps` of it in the pool.
+pub fn swap_output(
+    reserve_in: u128,
+    reserve_out: u128,
+    amount_in: u128,
+    fee_bps: u16,
+) -> Result<u128, PoolError> {
+    let basis_points = BASIS_POINTS as u128;
+    let amount_in_after_fee = amount_in
+        .checked_mul(basis_points - fee_bps as u128)
+        .ok_or(PoolError::Overflow)?;
+    let denominator = reserve_in
+        .checked_mul(basis_points)
+        .and_then(|reserve| reserve.checked_add(amount_in_after_fee))
+        .ok_or(PoolError::Overflow)?;
+    let amount_out = mul_div(amount_in_after_fee, reserve_out, denominator)?;
+    if amount_out == 0 {
+        return Err(PoolError::ZeroAmount);
+    }
+    Ok(amount_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_math() {
+        assert_eq!(
+            (0..=17).map(isqrt).collect::<Vec<_>>(),
+            vec![0, 1, 1, 1, 2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3, 4, 4]
+        );
+        assert_eq!(isqrt(u128::MAX), u64::MAX as u128);
+
+        assert_eq!(initial_shares(10_000, 40_000), Ok(20_000));
+        assert_eq!(
+            initial_shares(1_000, 1_000),
+            Err(PoolError::InsufficientLiquidity)
+        );
+
+        // The amounts are taken at the price of the pool, rounded up
+        let pool = (10_000, 40_000, 20_000);
+        assert_eq!(deposit(pool, 1_000, 100_000), Ok((2_000, 1_000, 4_000)));
+        assert_eq!(deposit(pool, 1_000, 2_001), Ok((1_000, 500, 2_000)));
+        assert_eq!(deposit(pool, 0, 2_001), Err(PoolError::ZeroAmount));
+        assert_eq!(withdrawal(pool, 2_000), Ok((1_000, 4_000)));
+
+        // 0.3% of 1_000 stays in the pool: 997 * 40_000 / (10_000 + 997)
+        assert_eq!(swap_output(10_000, 40_000, 1_000, 30), Ok(3_626));
+        assert_eq!(swap_output(10_000, 40_000, 1_000, 0), Ok(3_636));
+        assert_eq!(
+            swap_output(40_000, 10_000, 1, 30),
+            Err(PoolError::ZeroAmount)
+        );
+        assert_eq!(mul_div(u128::MAX, 2, 1), Err(PoolError::Overflow));
+    }
+}
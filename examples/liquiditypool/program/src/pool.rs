@@ -0,0 +1,277 @@
+//! The pool instructions applied to the state of the pool.
+use std::collections::BTreeMap;
+
+use arch_program::pubkey::Pubkey;
+
+use crate::{
+    error::PoolError,
+    liquidity_pool::LiquidityPool,
+    math::{self, BASIS_POINTS, MINIMUM_LIQUIDITY},
+    transition::Transfer,
+    OpenPoolParams, SwapDirection,
+};
+
+impl LiquidityPool {
+    /// A pool holding the amounts of `params`, all owned by `owner` but for
+    /// the locked [`MINIMUM_LIQUIDITY`].
+    pub fn open(owner: Pubkey, params: &OpenPoolParams) -> Result<(Self, Transfer), PoolError> {
+        if params.fee_bps >= BASIS_POINTS {
+            return Err(PoolError::InvalidFee);
+        }
+        let shares = math::initial_shares(params.balance_sats, params.balance_rune)?;
+
+        let pool = Self {
+            balance_sats: params.balance_sats,
+            balance_rune: params.balance_rune,
+            fee_bps: params.fee_bps,
+            total_shares: shares,
+            positions: BTreeMap::from([(owner, shares - MINIMUM_LIQUIDITY)]),
+            rune_name: params.rune_name.clone(),
+            rune_id: params.rune_id,
+            rune_attestor: params.rune_attestor,
+        };
+        let transfer = Transfer {
+            sats_in: params.balance_sats,
+            rune_in: params.balance_rune,
+            ..Transfer::default()
+        };
+        Ok((pool, transfer))
+    }
+
+    pub fn shares_of(&self, owner: &Pubkey) -> u128 {
+        self.positions.get(owner).copied().unwrap_or_default()
+    }
+
+    pub fn increase_liquidity(
+        &mut self,
+        owner: Pubkey,
+        max_sats: u64,
+        max_rune: u128,
+        min_shares: u128,
+    ) -> Result<Transfer, PoolError> {
+        let (shares, sats, rune) = math::deposit(self.reserves(), max_sats, max_rune)?;
+        if shares < min_shares {
+            return Err(PoolError::SlippageExceeded);
+        }
+
+        self.balance_sats = self
+            .balance_sats
+            .checked_add(sats)
+            .ok_or(PoolError::Overflow)?;
+        self.balance_rune = self
+            .balance_rune
+            .checked_add(rune)
+            .ok_or(PoolError::Overflow)?;
+        self.total_shares = self
+            .total_shares
+            .checked_add(shares)
+            .ok_or(PoolError::Overflow)?;
+        *self.positions.entry(owner).or_default() += shares;
+        Ok(Transfer {
+            sats_in: sats,
+            rune_in: rune,
+            ..Transfer::default()
+        })
+    }
+
+    pub fn decrease_liquidity(
+        &mut self,
+        owner: Pubkey,
+        shares: u128,
+        min_sats: u64,
+        min_rune: u128,
+    ) -> Result<Transfer, PoolError> {
+        let held = self.shares_of(&owner);
+        if shares == 0 {
+            return Err(PoolError::ZeroAmount);
+        }
+        if shares > held {
+            return Err(PoolError::InsufficientShares);
+        }
+        let (sats, rune) = math::withdrawal(self.reserves(), shares)?;
+        if sats < min_sats || rune < min_rune {
+            return Err(PoolError::SlippageExceeded);
+        }
+
+        // The locked shares keep both reserves above zero
+        self.balance_sats -= sats;
+        self.balance_rune -= rune;
+        self.total_shares -= shares;
+        match held - shares {
+            0 => self.positions.remove(&owner),
+            left => self.positions.insert(owner, left),
+        };
+        Ok(Transfer {
+            sats_out: sats,
+            rune_out: rune,
+            ..Transfer::default()
+        })
+    }
+
+    /// Swaps `amount_in`, leaving the fee in the reserves so it accrues to
+    /// the liquidity providers.
+    pub fn swap(
+        &mut self,
+        direction: SwapDirection,
+        amount_in: u128,
+        min_amount_out: u128,
+    ) -> Result<Transfer, PoolError> {
+        let transfer = match direction {
+            SwapDirection::SatsForRune => {
+                let sats_in = u64::try_from(amount_in).map_err(|_| PoolError::Overflow)?;
+                let rune_out = math::swap_output(
+                    self.balance_sats as u128,
+                    self.balance_rune,
+                    amount_in,
+                    self.fee_bps,
+                )?;
+                if rune_out < min_amount_out {
+                    return Err(PoolError::SlippageExceeded);
+                }
+                self.balance_sats = self
+                    .balance_sats
+                    .checked_add(sats_in)
+                    .ok_or(PoolError::Overflow)?;
+                self.balance_rune -= rune_out;
+                Transfer {
+                    sats_in,
+                    rune_out,
+                    ..Transfer::default()
+                }
+            }
+            SwapDirection::RuneForSats => {
+                let sats_out = math::swap_output(
+                    self.balance_rune,
+                    self.balance_sats as u128,
+                    amount_in,
+                    self.fee_bps,
+                )?;
+                if sats_out < min_amount_out {
+                    return Err(PoolError::SlippageExceeded);
+                }
+                self.balance_rune = self
+                    .balance_rune
+                    .checked_add(amount_in)
+                    .ok_or(PoolError::Overflow)?;
+                // Below the reserve, which is a u64
+                let sats_out = sats_out as u64;
+                self.balance_sats -= sats_out;
+                Transfer {
+                    sats_out,
+                    rune_in: amount_in,
+                    ..Transfer::default()
+                }
+            }
+        };
+        Ok(transfer)
+    }
+
+    fn reserves(&self) -> (u64, u128, u128) {
+        (self.balance_sats, self.balance_rune, self.total_shares)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> OpenPoolParams {
+        OpenPoolParams {
+            balance_sats: 100_000,
+            balance_rune: 400_000,
+            fee_bps: 30,
+            rune_name: "ARCH•POOL".to_string(),
+            rune_id: (840_000, 1),
+            rune_attestor: Pubkey::from([9; 32]),
+        }
+    }
+
+    fn product(pool: &LiquidityPool) -> u128 {
+        pool.balance_sats as u128 * pool.balance_rune
+    }
+
+    #[test]
+    fn test_liquidity() {
+        let (alice, bob) = (Pubkey::from([1; 32]), Pubkey::from([2; 32]));
+        let (mut pool, transfer) = LiquidityPool::open(alice, &params()).unwrap();
+        assert_eq!((transfer.sats_in, transfer.rune_in), (100_000, 400_000));
+        assert_eq!(pool.total_shares, 200_000);
+        assert_eq!(pool.shares_of(&alice), 200_000 - MINIMUM_LIQUIDITY);
+        assert_eq!(
+            LiquidityPool::open(
+                alice,
+                &OpenPoolParams {
+                    fee_bps: BASIS_POINTS,
+                    ..params()
+                }
+            ),
+            Err(PoolError::InvalidFee)
+        );
+
+        assert_eq!(
+            pool.increase_liquidity(bob, 10_000, 100_000, 20_001),
+            Err(PoolError::SlippageExceeded)
+        );
+        let transfer = pool
+            .increase_liquidity(bob, 10_000, 100_000, 20_000)
+            .unwrap();
+        assert_eq!((transfer.sats_in, transfer.rune_in), (10_000, 40_000));
+        assert_eq!(pool.shares_of(&bob), 20_000);
+        assert_eq!(pool.total_shares, 220_000);
+
+        assert_eq!(
+            pool.decrease_liquidity(bob, 20_001, 0, 0),
+            Err(PoolError::InsufficientShares)
+        );
+        assert_eq!(
+            pool.decrease_liquidity(bob, 20_000, 10_001, 0),
+            Err(PoolError::SlippageExceeded)
+        );
+        let transfer = pool
+            .decrease_liquidity(bob, 20_000, 10_000, 40_000)
+            .unwrap();
+        assert_eq!((transfer.sats_out, transfer.rune_out), (10_000, 40_000));
+        assert!(!pool.positions.contains_key(&bob));
+
+        // Everyone leaving keeps the locked liquidity in the pool
+        pool.decrease_liquidity(alice, pool.shares_of(&alice), 0, 0)
+            .unwrap();
+        assert!(pool.positions.is_empty());
+        assert_eq!(pool.total_shares, MINIMUM_LIQUIDITY);
+        assert_eq!((pool.balance_sats, pool.balance_rune), (500, 2_000));
+    }
+
+    #[test]
+    fn test_swap() {
+        let alice = Pubkey::from([1; 32]);
+        let (mut pool, _) = LiquidityPool::open(alice, &params()).unwrap();
+
+        let before = product(&pool);
+        assert_eq!(
+            pool.swap(SwapDirection::SatsForRune, 10_000, 36_265),
+            Err(PoolError::SlippageExceeded)
+        );
+        let transfer = pool
+            .swap(SwapDirection::SatsForRune, 10_000, 36_264)
+            .unwrap();
+        assert_eq!((transfer.sats_in, transfer.rune_out), (10_000, 36_264));
+        assert_eq!((pool.balance_sats, pool.balance_rune), (110_000, 363_736));
+        assert!(product(&pool) > before);
+
+        let before = product(&pool);
+        let transfer = pool.swap(SwapDirection::RuneForSats, 36_264, 0).unwrap();
+        assert_eq!((transfer.rune_in, transfer.sats_out), (36_264, 9_945));
+        assert!(product(&pool) > before);
+        // The round trip leaves the fees with the liquidity providers
+        assert_eq!((pool.balance_sats, pool.balance_rune), (100_055, 400_000));
+
+        assert_eq!(
+            pool.swap(SwapDirection::SatsForRune, u64::MAX as u128 + 1, 0),
+            Err(PoolError::Overflow)
+        );
+        assert_eq!(
+            pool.swap(SwapDirection::RuneForSats, 1, 0),
+            Err(PoolError::ZeroAmount)
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! The Bitcoin transaction settling a pool instruction.
+//!
+//! The UTXO of the pool account holds the BTC and the runes of the pool. Each
+//! instruction spends it along with the inputs of the caller, and creates:
+//!
+//! 0. the new UTXO of the pool, its value moved by the BTC exchanged,
+//! 1. the output of the caller, their change plus the BTC the pool pays out,
+//! 2. when the pool pays out runes, a runestone moving them to output 1.
+//!
+//! Runes the runestone doesn't move, those of the pool and those the caller
+//! deposits, go to the first output, the pool.
+//!
+//! The node takes the transaction with a two byte length, so it can't be
+//! longer than [`MAX_TX_BYTES_LEN`].
+use arch_program::{
+    account::AccountInfo,
+    entrypoint::ProgramResult,
+    helper::get_state_transition_tx,
+    input_to_sign::InputToSign,
+    msg,
+    program::{get_utxo_value, set_transaction_to_sign},
+    program_error::ProgramError,
+    transaction_to_sign::{TransactionToSign, MAX_TX_BYTES_LEN},
+    utxo::UtxoMeta,
+};
+use bitcoin::{
+    opcodes::all::{OP_PUSHNUM_13, OP_RETURN},
+    script::{Builder, PushBytesBuf},
+    Amount, ScriptBuf, Transaction, TxIn, TxOut,
+};
+
+use crate::{error::PoolError, Funding};
+
+/// Smallest value of the output of the caller, which carries the runes paid
+/// out
+pub const DUST_LIMIT: u64 = 546;
+
+/// Runestone tag preceding the edicts
+const TAG_BODY: u128 = 0;
+
+/// What the pool takes from and pays to the caller in BTC and runes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Transfer {
+    pub sats_in: u64,
+    /// Runes the inputs of the caller carry into the pool, which only the
+    /// pool state accounts for
+    pub rune_in: u128,
+    pub sats_out: u64,
+    pub rune_out: u128,
+}
+
+/// Builds the transaction of `transfer` and has the pool sign its input.
+pub fn settle(
+    pool: &AccountInfo,
+    funding: &Funding,
+    transfer: Transfer,
+    rune_id: (u64, u32),
+) -> ProgramResult {
+    let caller_tx: Transaction = bitcoin::consensus::deserialize(&funding.tx_hex)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    let caller_value = caller_tx.input.iter().try_fold(0u64, |total, input| {
+        let utxo = UtxoMeta::from_outpoint(input.previous_output.txid, input.previous_output.vout);
+        get_utxo_value(&utxo)
+            .and_then(|value| total.checked_add(value))
+            .ok_or(ProgramError::InvalidArgument)
+    })?;
+
+    let tx = build(
+        get_state_transition_tx(std::slice::from_ref(pool)),
+        caller_tx.input,
+        caller_value,
+        funding,
+        transfer,
+        rune_id,
+    )?;
+    msg!("pool transition {:?}", transfer);
+
+    set_transaction_to_sign(
+        std::slice::from_ref(pool),
+        TransactionToSign {
+            tx_bytes: &bitcoin::consensus::serialize(&tx),
+            inputs_to_sign: &[InputToSign {
+                index: 0,
+                signer: *pool.key,
+            }],
+        },
+    )
+}
+
+/// Completes the state transition `tx` of the pool, spending its UTXO into
+/// its first output, with the inputs of the caller worth `caller_value`.
+/// Fails if the result is too long to be signed.
+pub fn build(
+    mut tx: Transaction,
+    caller_inputs: Vec<TxIn>,
+    caller_value: u64,
+    funding: &Funding,
+    transfer: Transfer,
+    rune_id: (u64, u32),
+) -> Result<Transaction, ProgramError> {
+    let pool_value = tx.output[0]
+        .value
+        .to_sat()
+        .checked_add(transfer.sats_in)
+        .ok_or(PoolError::Overflow)?
+        .checked_sub(transfer.sats_out)
+        .ok_or(PoolError::InsufficientLiquidity)?;
+    let caller_change = caller_value
+        .checked_sub(transfer.sats_in)
+        .and_then(|value| value.checked_sub(funding.fee_sats))
+        .ok_or(ProgramError::InsufficientFunds)?;
+    let caller_output = caller_change
+        .checked_add(transfer.sats_out)
+        .ok_or(PoolError::Overflow)?;
+    if caller_output < DUST_LIMIT {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    tx.output[0].value = Amount::from_sat(pool_value);
+    tx.input.extend(caller_inputs);
+    tx.output.push(TxOut {
+        value: Amount::from_sat(caller_output),
+        script_pubkey: ScriptBuf::from_bytes(funding.payout_script.clone()),
+    });
+    if transfer.rune_out > 0 {
+        tx.output.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: runestone(rune_id, transfer.rune_out, 1),
+        });
+    }
+    if bitcoin::consensus::serialize(&tx).len() > MAX_TX_BYTES_LEN {
+        return Err(PoolError::TransactionTooLarge.into());
+    }
+    Ok(tx)
+}
+
+/// A runestone with a single edict moving `amount` of `rune_id` to `output`.
+pub fn runestone((block, tx): (u64, u32), amount: u128, output: u32) -> ScriptBuf {
+    let mut payload = vec![];
+    for value in [TAG_BODY, block as u128, tx as u128, amount, output as u128] {
+        encode_varint(value, &mut payload);
+    }
+    Builder::new()
+        .push_opcode(OP_RETURN)
+        .push_opcode(OP_PUSHNUM_13)
+        .push_slice(PushBytesBuf::try_from(payload).expect("a single edict fits in a push"))
+        .into_script()
+}
+
+/// LEB128, the integer encoding of runestones.
+fn encode_varint(mut value: u128, buffer: &mut Vec<u8>) {
+    while value >> 7 > 0 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arch_program::pubkey::Pubkey;
+    use bitcoin::{absolute::LockTime, transaction::Version, OutPoint, Sequence, Witness};
+
+    use crate::{liquidity_pool::LiquidityPool, OpenPoolParams, SwapDirection};
+
+    fn input(vout: u32) -> TxIn {
+        TxIn {
+            previous_output: OutPoint {
+                vout,
+                ..OutPoint::null()
+            },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }
+    }
+
+    fn p2tr_script() -> ScriptBuf {
+        ScriptBuf::from_bytes([&[0x51, 0x20][..], &[7; 32]].concat())
+    }
+
+    /// The settlement of `transfer` for a caller spending a key path input,
+    /// once the node decoded it from a [`TransactionToSign`].
+    fn settle_through_node(transfer: Transfer) -> Result<Transaction, ProgramError> {
+        let state_transition = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![input(0)],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000_000),
+                script_pubkey: p2tr_script(),
+            }],
+        };
+        let caller_input = TxIn {
+            witness: Witness::from_slice(&[[1; 65]]),
+            ..input(1)
+        };
+        let funding = Funding {
+            tx_hex: vec![],
+            fee_sats: 500,
+            payout_script: p2tr_script().into_bytes(),
+        };
+        let tx = build(
+            state_transition,
+            vec![caller_input],
+            1_000_000,
+            &funding,
+            transfer,
+            (840_000, 1),
+        )?;
+
+        let tx_bytes = bitcoin::consensus::serialize(&tx);
+        let inputs_to_sign = [InputToSign {
+            index: 0,
+            signer: Pubkey::from([1; 32]),
+        }];
+        let serialized = TransactionToSign {
+            tx_bytes: &tx_bytes,
+            inputs_to_sign: &inputs_to_sign,
+        }
+        .serialise();
+        let decoded =
+            sdk::transaction_to_sign::TransactionToSign::try_from_slice(&serialized).unwrap();
+        assert_eq!(decoded.inputs_to_sign, inputs_to_sign);
+        Ok(bitcoin::consensus::deserialize(&decoded.tx_bytes).unwrap())
+    }
+
+    #[test]
+    fn test_settlements_reach_the_node() {
+        let alice = Pubkey::from([1; 32]);
+        let params = OpenPoolParams {
+            balance_sats: 100_000,
+            balance_rune: 400_000,
+            fee_bps: 30,
+            rune_name: "ARCH•POOL".to_string(),
+            rune_id: (840_000, 1),
+            rune_attestor: Pubkey::from([9; 32]),
+        };
+        let (mut pool, open) = LiquidityPool::open(alice, &params).unwrap();
+        let increase = pool.increase_liquidity(alice, 10_000, 40_000, 0).unwrap();
+        let rune_for_sats = pool.swap(SwapDirection::RuneForSats, 4_000, 0).unwrap();
+        for transfer in [open, increase, rune_for_sats] {
+            let tx = settle_through_node(transfer).unwrap();
+            assert_eq!(tx.output.len(), 2);
+        }
+
+        // Paying out runes adds a runestone moving them to the caller
+        let decrease = pool.decrease_liquidity(alice, 20_000, 0, 0).unwrap();
+        let sats_for_rune = pool.swap(SwapDirection::SatsForRune, 1_000, 0).unwrap();
+        for transfer in [decrease, sats_for_rune] {
+            assert!(transfer.rune_out > 0);
+            let tx = settle_through_node(transfer).unwrap();
+            assert!(bitcoin::consensus::serialize(&tx).len() > u8::MAX as usize);
+            assert_eq!(tx.output.len(), 3);
+            assert_eq!(
+                tx.output[0].value.to_sat(),
+                1_000_000 + transfer.sats_in - transfer.sats_out
+            );
+            assert_eq!(
+                tx.output[1].value.to_sat(),
+                1_000_000 - 500 - transfer.sats_in + transfer.sats_out
+            );
+            assert_eq!(
+                tx.output[2].script_pubkey,
+                runestone((840_000, 1), transfer.rune_out, 1)
+            );
+        }
+    }
+
+    #[test]
+    fn test_build() {
+        let mut buffer = vec![];
+        encode_varint(300, &mut buffer);
+        assert_eq!(buffer, vec![0xac, 0x02]);
+        assert_eq!(
+            runestone((840_000, 1), 300, 1).as_bytes(),
+            &[0x6a, 0x5d, 0x08, 0x00, 0xc0, 0xa2, 0x33, 0x01, 0xac, 0x02, 0x01]
+        );
+
+        let state_transition = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![input(0)],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let funding = Funding {
+            tx_hex: vec![],
+            fee_sats: 500,
+            payout_script: vec![0x51],
+        };
+
+        // Withdrawing BTC and runes
+        let transfer = Transfer {
+            sats_out: 4_000,
+            rune_out: 300,
+            ..Transfer::default()
+        };
+        let tx = build(
+            state_transition.clone(),
+            vec![input(1)],
+            1_000,
+            &funding,
+            transfer,
+            (840_000, 1),
+        )
+        .unwrap();
+        assert_eq!(tx.input, vec![input(0), input(1)]);
+        assert_eq!(tx.output[0].value.to_sat(), 6_000);
+        assert_eq!(tx.output[1].value.to_sat(), 4_500);
+        assert_eq!(tx.output[1].script_pubkey.as_bytes(), &[0x51]);
+        assert_eq!(tx.output[2].script_pubkey, runestone((840_000, 1), 300, 1));
+
+        // Depositing BTC, the runes follow the inputs into the pool
+        let transfer = Transfer {
+            sats_in: 2_000,
+            ..Transfer::default()
+        };
+        let tx = build(
+            state_transition.clone(),
+            vec![input(1)],
+            3_046,
+            &funding,
+            transfer,
+            (840_000, 1),
+        )
+        .unwrap();
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.output[0].value.to_sat(), 12_000);
+        assert_eq!(tx.output[1].value.to_sat(), DUST_LIMIT);
+        assert_eq!(
+            build(
+                state_transition,
+                vec![input(1)],
+                3_045,
+                &funding,
+                transfer,
+                (840_000, 1)
+            ),
+            Err(ProgramError::InsufficientFunds)
+        );
+    }
+}
@@ -0,0 +1,22 @@
+//! Keeps `idl.json` in sync with the program. Run with `UPDATE_IDL=1` to
+//! regenerate it after changing the instructions.
+
+use std::{env, fs, path::Path};
+
+#[test]
+fn idl_is_up_to_date() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("idl.json");
+    let idl = serde_json::to_string_pretty(&liquiditypoolprogram::idl()).unwrap() + "\n";
+
+    if env::var_os("UPDATE_IDL").is_some() {
+        fs::write(&path, idl).unwrap();
+        return;
+    }
+
+    let checked_in = fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        checked_in == idl,
+        "{} is out of date, rerun the tests with UPDATE_IDL=1",
+        path.display()
+    );
+}
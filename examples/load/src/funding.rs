@@ -0,0 +1,106 @@
+//! Creating the accounts sending the load.
+use anyhow::{anyhow, bail, Result};
+use bitcoin::key::UntweakedKeypair;
+use bitcoin::secp256k1::{self, Secp256k1};
+use bitcoin::XOnlyPublicKey;
+use log::info;
+
+use common::bitcoin_backend::BitcoinBackend;
+use common::cluster::Cluster;
+use common::deploy::DeployNode;
+use common::helper::{send_utxo_with, sign_transaction};
+use sdk::arch_program::pubkey::Pubkey;
+use sdk::arch_program::system_instruction::SystemInstruction;
+
+/// Where the UTXOs anchoring the accounts come from.
+pub trait Funder {
+    /// Pays a UTXO to the account of `pubkey`, returning its txid and output
+    /// index.
+    fn fund(&self, pubkey: Pubkey) -> Result<(String, u32)>;
+}
+
+/// [`Funder`] paying from a Bitcoin backend.
+pub struct BitcoinFunder {
+    backend: Box<dyn BitcoinBackend>,
+}
+
+impl BitcoinFunder {
+    pub fn new(backend: Box<dyn BitcoinBackend>) -> Self {
+        Self { backend }
+    }
+}
+
+impl Funder for BitcoinFunder {
+    fn fund(&self, pubkey: Pubkey) -> Result<(String, u32)> {
+        send_utxo_with(&self.backend, pubkey)
+    }
+}
+
+/// [`Funder`] making up UTXOs without paying them, for a mock node that
+/// doesn't check them.
+pub struct UnpaidFunder;
+
+impl Funder for UnpaidFunder {
+    fn fund(&self, pubkey: Pubkey) -> Result<(String, u32)> {
+        Ok((sha256::digest(pubkey.serialize().as_slice()), 0))
+    }
+}
+
+/// An account of the load test and its key.
+#[derive(Clone, Copy)]
+pub struct Account {
+    pub keypair: UntweakedKeypair,
+    pub pubkey: Pubkey,
+}
+
+/// Creates `count` accounts from UTXOs paid by `funder`, sending the
+/// creations in batches of `batch_size` and waiting until all are processed.
+pub fn create_accounts(
+    cluster: &Cluster,
+    funder: &dyn Funder,
+    count: usize,
+    batch_size: usize,
+) -> Result<Vec<Account>> {
+    let secp = Secp256k1::new();
+    let mut accounts = Vec::with_capacity(count);
+    let mut transactions = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (secret_key, _) = secp.generate_keypair(&mut secp256k1::rand::thread_rng());
+        let keypair = UntweakedKeypair::from_secret_key(&secp, &secret_key);
+        let pubkey = Pubkey::from_slice(&XOnlyPublicKey::from_keypair(&keypair).0.serialize());
+
+        let (txid, vout) = funder.fund(pubkey)?;
+        let txid: [u8; 32] = hex::decode(&txid)
+            .ok()
+            .and_then(|txid| txid.try_into().ok())
+            .ok_or_else(|| anyhow!("Funding txid {} isn't 32 hex encoded bytes", txid))?;
+        transactions.push(sign_transaction(
+            vec![SystemInstruction::new_create_account_instruction(
+                txid, vout, pubkey,
+            )],
+            vec![keypair],
+        ));
+        accounts.push(Account { keypair, pubkey });
+    }
+
+    let mut txids = vec![];
+    for batch in transactions.chunks(batch_size.max(1)) {
+        txids.extend(cluster.send_transactions(batch.to_vec())?);
+    }
+    let failed = cluster
+        .confirm_all(&txids)
+        .into_iter()
+        .filter_map(Result::err)
+        .collect::<Vec<_>>();
+    if let Some(e) = failed.first() {
+        bail!(
+            "{} of {} accounts weren't created, first error: {:#}",
+            failed.len(),
+            count,
+            e
+        );
+    }
+
+    info!("Created {} accounts", count);
+    Ok(accounts)
+}
@@ -0,0 +1,10 @@
+//! Load tests of an Arch node: funded accounts sending a mix of system and
+//! program instructions at a target rate, and the latency until the node has
+//! processed them.
+pub mod funding;
+pub mod mix;
+pub mod report;
+pub mod runner;
+
+pub use report::Report;
+pub use runner::{run, LoadConfig};
@@ -0,0 +1,136 @@
+//! `arch-load`, sending load to the nodes of a profile and printing what it
+//! measured.
+//!
+//! The report is a single JSON document on stdout, or `{"error": "..."}` with
+//! exit status 1. With `--mock` the load goes to an in-process mock node
+//! instead, processing every transaction after a short delay and producing a
+//! Bitcoin transaction for each program instruction, as a quick check of the
+//! tool itself.
+use std::process::ExitCode;
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+use serde_json::json;
+
+use arch_load::funding::{BitcoinFunder, UnpaidFunder};
+use arch_load::mix::{Mix, ProgramCall};
+use arch_load::{run, LoadConfig, Report};
+use common::cluster::Cluster;
+use common::config::load_profile;
+use common::mock_server::MockServer;
+
+/// Delay after which the mock node processes a transaction
+const MOCK_PROCESSING_DELAY: Duration = Duration::from_millis(50);
+
+#[derive(Parser, Debug)]
+#[command(name = "arch-load", version, about = "Load test Arch nodes")]
+struct Cli {
+    /// Network profile giving the nodes and the Bitcoin backend funding the
+    /// accounts, see `arch.json`
+    #[arg(long, env = "ARCH_PROFILE")]
+    profile: Option<String>,
+    /// RPC endpoints of the nodes, the leader first, those of the profile
+    /// by default
+    #[arg(long = "rpc-url")]
+    rpc_urls: Vec<String>,
+    /// Send the load to an in-process mock node
+    #[arg(long, conflicts_with_all = ["profile", "rpc_urls"])]
+    mock: bool,
+
+    /// Number of funded accounts sending the transactions
+    #[arg(long, default_value_t = 10)]
+    accounts: usize,
+    /// Number of transactions to send
+    #[arg(long, default_value_t = 1000)]
+    transactions: usize,
+    /// Transactions submitted per second
+    #[arg(long, default_value_t = 100.0)]
+    rate: f64,
+    /// Transactions per send_transactions request
+    #[arg(long, default_value_t = 10)]
+    batch_size: usize,
+    /// Proportions of the kinds of instructions, as `system=<weight>` and
+    /// `program=<weight>` separated by commas
+    #[arg(long, default_value = "system=1")]
+    mix: Mix,
+    /// Program called by program instructions, as
+    /// `<program_id>[:<hex data>]`. The sequence number of the transaction
+    /// is appended to the data, as a little endian u64, so each transaction
+    /// has its own id
+    #[arg(long)]
+    program: Option<ProgramCall>,
+    /// Milliseconds between the status polls of a transaction
+    #[arg(long, default_value_t = 100)]
+    poll_interval: u64,
+    /// Seconds after which a transaction not yet processed is given up on
+    #[arg(long, default_value_t = 60)]
+    timeout: u64,
+}
+
+fn load(cli: &Cli) -> Result<Report> {
+    let config = LoadConfig {
+        accounts: cli.accounts,
+        transactions: cli.transactions,
+        rate: cli.rate,
+        batch_size: cli.batch_size,
+        mix: cli.mix.clone(),
+        program: cli.program.clone(),
+        poll_interval: Duration::from_millis(cli.poll_interval),
+        timeout: Duration::from_secs(cli.timeout),
+    };
+
+    if cli.mock {
+        let server = MockServer::start()?;
+        let program_id = config.program.as_ref().map(|program| program.program_id);
+        server.process_sent_after(MOCK_PROCESSING_DELAY, move |transaction| {
+            transaction
+                .message
+                .instructions
+                .iter()
+                .filter(|instruction| Some(instruction.program_id) == program_id)
+                .enumerate()
+                .map(|(index, _)| sha256::digest(format!("{}{}", transaction.txid(), index)))
+                .collect()
+        });
+        return run(&Cluster::new([server.url()])?, &UnpaidFunder, &config);
+    }
+
+    let profile = load_profile(cli.profile.as_deref())?;
+    let cluster = match cli.rpc_urls.is_empty() {
+        true => Cluster::from_profile(&profile)?,
+        false => Cluster::new(cli.rpc_urls.iter().cloned())?,
+    };
+    let funder = BitcoinFunder::new(profile.bitcoin_backend()?);
+    run(&cluster, &funder, &config)
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+    let cli = Cli::parse();
+
+    match load(&cli) {
+        Ok(report) => {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json!({ "error": format!("{:#}", err) })).unwrap()
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+}
@@ -0,0 +1,217 @@
+//! The instructions a load test sends.
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+
+use sdk::arch_program::account::AccountMeta;
+use sdk::arch_program::instruction::Instruction;
+use sdk::arch_program::pubkey::Pubkey;
+use sdk::arch_program::system_instruction::SystemInstruction;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstructionKind {
+    /// A system program write of the sequence number into the account data
+    System,
+    /// A call of the program given with [`ProgramCall`]
+    Program,
+}
+
+impl FromStr for InstructionKind {
+    type Err = anyhow::Error;
+
+    fn from_str(kind: &str) -> Result<Self> {
+        match kind {
+            "system" => Ok(Self::System),
+            "program" => Ok(Self::Program),
+            _ => bail!(
+                "Unknown instruction kind {}, expected system or program",
+                kind
+            ),
+        }
+    }
+}
+
+impl fmt::Display for InstructionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::System => write!(f, "system"),
+            Self::Program => write!(f, "program"),
+        }
+    }
+}
+
+/// Proportions of each kind of instruction, written like `system=3,program=1`.
+///
+/// Transactions get their kind from their sequence number, in runs following
+/// the order of the weights, so any stretch of the sum of the weights holds
+/// exactly the proportions asked for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mix {
+    weights: Vec<(InstructionKind, u32)>,
+}
+
+impl Mix {
+    /// Kind of the transaction numbered `sequence`
+    pub fn kind(&self, sequence: u64) -> InstructionKind {
+        let total = self
+            .weights
+            .iter()
+            .map(|(_, weight)| *weight as u64)
+            .sum::<u64>();
+        let mut position = sequence % total;
+        for (kind, weight) in &self.weights {
+            if position < *weight as u64 {
+                return *kind;
+            }
+            position -= *weight as u64;
+        }
+        unreachable!("the position is below the sum of the weights")
+    }
+
+    /// Whether any transaction is of `kind`
+    pub fn contains(&self, kind: InstructionKind) -> bool {
+        self.weights
+            .iter()
+            .any(|(mixed, weight)| *mixed == kind && *weight > 0)
+    }
+}
+
+impl Default for Mix {
+    fn default() -> Self {
+        Self {
+            weights: vec![(InstructionKind::System, 1)],
+        }
+    }
+}
+
+impl FromStr for Mix {
+    type Err = anyhow::Error;
+
+    fn from_str(mix: &str) -> Result<Self> {
+        let mut weights: Vec<(InstructionKind, u32)> = vec![];
+        for entry in mix.split(',').map(str::trim) {
+            let (kind, weight) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Mix entry {} should be <kind>=<weight>", entry))?;
+            let kind = kind.trim().parse::<InstructionKind>()?;
+            let weight = weight
+                .trim()
+                .parse::<u32>()
+                .with_context(|| format!("Weight of {} should be a number", kind))?;
+            if weights.iter().any(|(mixed, _)| *mixed == kind) {
+                bail!("{} is given twice in the mix", kind);
+            }
+            weights.push((kind, weight));
+        }
+        if weights.iter().all(|(_, weight)| *weight == 0) {
+            bail!("The mix needs a weight above 0");
+        }
+        Ok(Self { weights })
+    }
+}
+
+/// The program called by program instructions, written like
+/// `<program_id>[:<hex data>]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProgramCall {
+    pub program_id: Pubkey,
+    pub data: Vec<u8>,
+}
+
+impl FromStr for ProgramCall {
+    type Err = anyhow::Error;
+
+    fn from_str(call: &str) -> Result<Self> {
+        let (program_id, data) = call.split_once(':').unwrap_or((call, ""));
+        let program_id = hex::decode(program_id).context("Program id isn't valid hex")?;
+        if program_id.len() != 32 {
+            bail!("Program id should be 32 bytes, got {}", program_id.len());
+        }
+        Ok(Self {
+            program_id: Pubkey::from_slice(&program_id),
+            data: hex::decode(data).context("Instruction data isn't valid hex")?,
+        })
+    }
+}
+
+/// The instruction of the transaction numbered `sequence`, sent by
+/// `account`.
+///
+/// The sequence number ends the data of every instruction so no two
+/// transactions share an id: system instructions write it into the account,
+/// and program instructions carry it after the data of the [`ProgramCall`].
+pub fn instruction(
+    kind: InstructionKind,
+    sequence: u64,
+    account: Pubkey,
+    program: Option<&ProgramCall>,
+) -> Result<Instruction> {
+    let sequence = sequence.to_le_bytes().to_vec();
+    match kind {
+        InstructionKind::System => Ok(SystemInstruction::new_write_bytes_instruction(
+            0, sequence, account,
+        )),
+        InstructionKind::Program => {
+            let program =
+                program.ok_or_else(|| anyhow!("Program instructions need a program to call"))?;
+            Ok(Instruction {
+                program_id: program.program_id,
+                accounts: vec![AccountMeta {
+                    pubkey: account,
+                    is_signer: true,
+                    is_writable: true,
+                }],
+                data: [program.data.as_slice(), &sequence].concat(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mix() {
+        let mix = "system=3, program=1".parse::<Mix>().unwrap();
+        let kinds = (0..8)
+            .map(|sequence| mix.kind(sequence))
+            .collect::<Vec<_>>();
+        use InstructionKind::*;
+        assert_eq!(
+            kinds,
+            vec![System, System, System, Program, System, System, System, Program]
+        );
+        assert!(mix.contains(Program));
+        assert!(!"system=1,program=0"
+            .parse::<Mix>()
+            .unwrap()
+            .contains(Program));
+
+        assert!("system".parse::<Mix>().is_err());
+        assert!("system=0".parse::<Mix>().is_err());
+        assert!("system=1,system=2".parse::<Mix>().is_err());
+        assert!("bitcoin=1".parse::<Mix>().is_err());
+
+        let call = format!("{}:0102", hex::encode([7; 32]))
+            .parse::<ProgramCall>()
+            .unwrap();
+        let account = Pubkey::from_slice(&[1; 32]);
+        let program = instruction(Program, 5, account, Some(&call)).unwrap();
+        assert_eq!(program.program_id, call.program_id);
+        assert_eq!(program.data, [&[1, 2][..], &5u64.to_le_bytes()].concat());
+        assert!(instruction(Program, 5, account, None).is_err());
+
+        let system = instruction(System, 5, account, None).unwrap();
+        assert_eq!(
+            SystemInstruction::try_from_slice(&system.data).unwrap(),
+            SystemInstruction::WriteBytes {
+                offset: 0,
+                data: 5u64.to_le_bytes().to_vec()
+            }
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! What a load test measured.
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::mix::InstructionKind;
+
+/// Results of a load test, printed as JSON.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Report {
+    pub accounts: usize,
+    /// Transactions accepted by the node, and those it refused or couldn't
+    /// be sent to
+    pub submitted: usize,
+    pub submit_errors: usize,
+    /// Transactions accepted by the node, by the kind of their instruction
+    pub by_kind: BTreeMap<InstructionKind, usize>,
+    /// Outcomes of the submitted transactions: processed, failed by the
+    /// runtime, or of unknown status when the wait timed out
+    pub processed: usize,
+    pub failed: usize,
+    pub unconfirmed: usize,
+    /// Distinct Bitcoin transactions produced by the processed ones
+    pub bitcoin_txids: usize,
+    /// Rates in transactions per second: asked for, achieved by the
+    /// submission, and processed over the whole test
+    pub target_rate: f64,
+    pub submit_rate: f64,
+    pub throughput: f64,
+    pub elapsed_secs: f64,
+    /// Time from submission to the status read as processed, `None` if no
+    /// transaction was processed
+    pub latency_ms: Option<Latency>,
+}
+
+/// Distribution of latencies, in milliseconds.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Latency {
+    pub min: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Latency {
+    /// The distribution of `samples`, `None` if there is none.
+    pub fn from_samples(mut samples: Vec<Duration>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        // Nearest rank: the smallest sample with at least p% of them at or
+        // below it
+        let percentile = |p: f64| {
+            let rank = (p / 100.0 * samples.len() as f64).ceil() as usize;
+            millis(samples[rank.clamp(1, samples.len()) - 1])
+        };
+        let total = samples.iter().sum::<Duration>();
+        Some(Self {
+            min: millis(samples[0]),
+            mean: millis(total) / samples.len() as f64,
+            p50: percentile(50.0),
+            p90: percentile(90.0),
+            p99: percentile(99.0),
+            max: millis(samples[samples.len() - 1]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency() {
+        assert_eq!(Latency::from_samples(vec![]), None);
+
+        let samples = (1..=100).rev().map(Duration::from_millis).collect();
+        let latency = Latency::from_samples(samples).unwrap();
+        assert_eq!(
+            latency,
+            Latency {
+                min: 1.0,
+                mean: 50.5,
+                p50: 50.0,
+                p90: 90.0,
+                p99: 99.0,
+                max: 100.0,
+            }
+        );
+
+        let latency = Latency::from_samples(vec![Duration::from_millis(7)]).unwrap();
+        assert_eq!((latency.p50, latency.p99), (7.0, 7.0));
+    }
+}
@@ -0,0 +1,335 @@
+//! Sending the load at the target rate and waiting for its outcome.
+use std::collections::{BTreeMap, HashSet};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use log::{info, warn};
+use tokio::runtime::Builder;
+use tokio::sync::mpsc;
+
+use common::cluster::Cluster;
+use common::confirmation::{Backoff, ConfirmationTracker};
+use common::helper::sign_transaction;
+use sdk::processed_transaction::Status;
+use sdk::runtime_transaction::RuntimeTransaction;
+
+use crate::funding::{create_accounts, Funder};
+use crate::mix::{instruction, InstructionKind, Mix, ProgramCall};
+use crate::report::{Latency, Report};
+
+/// Number of status requests in flight at once while waiting for the load
+const MAX_CONCURRENT_POLLS: usize = 64;
+
+#[derive(Clone, Debug)]
+pub struct LoadConfig {
+    /// Number of accounts sending the transactions, in turn
+    pub accounts: usize,
+    pub transactions: usize,
+    /// Transactions submitted per second
+    pub rate: f64,
+    /// Transactions per `send_transactions` request
+    pub batch_size: usize,
+    pub mix: Mix,
+    /// Program called by the program instructions of the mix
+    pub program: Option<ProgramCall>,
+    /// Delay between the status polls of a transaction, which bounds the
+    /// precision of the latencies
+    pub poll_interval: Duration,
+    /// Time after which a transaction not yet processed is given up on
+    pub timeout: Duration,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self {
+            accounts: 10,
+            transactions: 1000,
+            rate: 100.0,
+            batch_size: 10,
+            mix: Mix::default(),
+            program: None,
+            poll_interval: Duration::from_millis(100),
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A transaction accepted by the node
+struct Submitted {
+    txid: String,
+    kind: InstructionKind,
+    at: Instant,
+}
+
+enum Outcome {
+    Processed {
+        latency: Duration,
+        bitcoin_txids: Vec<String>,
+    },
+    Failed,
+    Unconfirmed,
+}
+
+/// Creates the accounts, then sends the transactions at the configured rate
+/// and waits for each to be processed.
+///
+/// Transactions are signed before the clock starts so signing doesn't slow
+/// the submission down. Batches are sent at the times the rate sets, right
+/// away when the submission falls behind, and each transaction is waited for
+/// as soon as the node accepts it.
+pub fn run(cluster: &Cluster, funder: &dyn Funder, config: &LoadConfig) -> Result<Report> {
+    if config.accounts == 0 || config.batch_size == 0 {
+        bail!("A load test needs at least one account and a batch size above 0");
+    }
+    if !(config.rate > 0.0 && config.rate.is_finite()) {
+        bail!("The rate should be a positive number of transactions per second");
+    }
+    if config.mix.contains(InstructionKind::Program) && config.program.is_none() {
+        bail!("The mix has program instructions but no program to call");
+    }
+
+    let accounts = create_accounts(cluster, funder, config.accounts, config.batch_size)?;
+    let transactions = (0..config.transactions as u64)
+        .map(|sequence| {
+            let kind = config.mix.kind(sequence);
+            let account = accounts[sequence as usize % accounts.len()];
+            let instruction = instruction(kind, sequence, account.pubkey, config.program.as_ref())?;
+            Ok((
+                kind,
+                sign_transaction(vec![instruction], vec![account.keypair]),
+            ))
+        })
+        .collect::<Result<Vec<(InstructionKind, RuntimeTransaction)>>>()?;
+    info!(
+        "Sending {} transactions at {} per second",
+        transactions.len(),
+        config.rate
+    );
+
+    let (sender, confirmations) = confirm(cluster, config);
+    let start = Instant::now();
+    let mut submit_errors = 0;
+    let mut by_kind = BTreeMap::new();
+    let mut sent = 0;
+    // Transactions sent before the last batch and when it started, the
+    // span over which the submit rate is measured
+    let mut last_batch = (0, start);
+    for batch in transactions.chunks(config.batch_size) {
+        let due = start + Duration::from_secs_f64(sent as f64 / config.rate);
+        thread::sleep(due.saturating_duration_since(Instant::now()));
+        let at = Instant::now();
+        last_batch = (sent, at);
+        sent += batch.len();
+
+        let txids = cluster.send_transactions(
+            batch
+                .iter()
+                .map(|(_, transaction)| transaction.clone())
+                .collect(),
+        );
+        match txids {
+            Ok(txids) if txids.len() == batch.len() => {
+                for (txid, (kind, _)) in txids.into_iter().zip(batch) {
+                    *by_kind.entry(*kind).or_default() += 1;
+                    // The receiver only stops once every sender is dropped
+                    let _ = sender.send(Submitted {
+                        txid,
+                        kind: *kind,
+                        at,
+                    });
+                }
+            }
+            Ok(txids) => {
+                warn!(
+                    "send_transactions returned {} txids for {} transactions",
+                    txids.len(),
+                    batch.len()
+                );
+                submit_errors += batch.len();
+            }
+            Err(e) => {
+                warn!("Failed to send a batch: {:#}", e);
+                submit_errors += batch.len();
+            }
+        }
+    }
+    drop(sender);
+
+    let outcomes = confirmations
+        .join()
+        .expect("the confirmation thread shouldn't panic")?;
+    let elapsed = start.elapsed();
+
+    let mut latencies = vec![];
+    let mut bitcoin_txids = HashSet::new();
+    let (mut failed, mut unconfirmed) = (0, 0);
+    for outcome in &outcomes {
+        match outcome {
+            Outcome::Processed {
+                latency,
+                bitcoin_txids: txids,
+            } => {
+                latencies.push(*latency);
+                bitcoin_txids.extend(txids);
+            }
+            Outcome::Failed => failed += 1,
+            Outcome::Unconfirmed => unconfirmed += 1,
+        }
+    }
+
+    let submitted = outcomes.len();
+    let processed = latencies.len();
+    let per_second = |count: usize, duration: Duration| match duration.as_secs_f64() {
+        secs if secs > 0.0 => count as f64 / secs,
+        _ => 0.0,
+    };
+    Ok(Report {
+        accounts: accounts.len(),
+        submitted,
+        submit_errors,
+        by_kind,
+        processed,
+        failed,
+        unconfirmed,
+        bitcoin_txids: bitcoin_txids.len(),
+        target_rate: config.rate,
+        submit_rate: match last_batch {
+            (0, _) => per_second(sent, elapsed),
+            (before, at) => per_second(before, at - start),
+        },
+        throughput: per_second(processed, elapsed),
+        elapsed_secs: elapsed.as_secs_f64(),
+        latency_ms: Latency::from_samples(latencies),
+    })
+}
+
+/// Starts a thread waiting for every transaction sent through the returned
+/// sender, until it is dropped, and returning their outcomes.
+fn confirm(
+    cluster: &Cluster,
+    config: &LoadConfig,
+) -> (
+    mpsc::UnboundedSender<Submitted>,
+    thread::JoinHandle<Result<Vec<Outcome>>>,
+) {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Submitted>();
+    let tracker = ConfirmationTracker::new(cluster.clone())
+        .with_backoff(Backoff {
+            initial: config.poll_interval,
+            multiplier: 1,
+            max_delay: config.poll_interval,
+            timeout: config.timeout,
+        })
+        .with_max_concurrent_polls(MAX_CONCURRENT_POLLS);
+    let cluster = cluster.clone();
+
+    let handle = thread::spawn(move || {
+        let runtime = Builder::new_current_thread().enable_time().build()?;
+        Ok(runtime.block_on(async move {
+            let mut waits = vec![];
+            while let Some(submitted) = receiver.recv().await {
+                let wait = tracker.wait_for(&submitted.txid, Status::Processed);
+                let cluster = cluster.clone();
+                waits.push(tokio::spawn(async move {
+                    match wait.await {
+                        Ok(processed_tx) => Outcome::Processed {
+                            latency: submitted.at.elapsed(),
+                            bitcoin_txids: processed_tx.bitcoin_txids,
+                        },
+                        // Tell a failed transaction from a wait that
+                        // timed out or couldn't reach the nodes
+                        Err(e) => {
+                            let status = tokio::task::spawn_blocking(move || {
+                                cluster.get_transaction_status(&submitted.txid)
+                            })
+                            .await;
+                            match status {
+                                Ok(Ok(Some(processed_tx)))
+                                    if matches!(processed_tx.status, Status::Failed(_)) =>
+                                {
+                                    Outcome::Failed
+                                }
+                                _ => {
+                                    warn!("{:?} transaction unconfirmed: {:#}", submitted.kind, e);
+                                    Outcome::Unconfirmed
+                                }
+                            }
+                        }
+                    }
+                }));
+            }
+
+            let mut outcomes = Vec::with_capacity(waits.len());
+            for wait in waits {
+                outcomes.push(wait.await.unwrap_or(Outcome::Unconfirmed));
+            }
+            outcomes
+        }))
+    });
+    (sender, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::mock_server::MockServer;
+    use sdk::arch_program::pubkey::Pubkey;
+
+    use crate::funding::UnpaidFunder;
+
+    #[test]
+    fn test_run_against_mock_server() {
+        let server = MockServer::start().unwrap();
+        let program_id = Pubkey::from_slice(&[7; 32]);
+        // Program calls produce a Bitcoin transaction each
+        server.process_sent_after(Duration::from_millis(20), move |transaction| {
+            transaction
+                .message
+                .instructions
+                .iter()
+                .filter(|instruction| instruction.program_id == program_id)
+                .map(|_| sha256::digest(transaction.txid()))
+                .collect()
+        });
+        let cluster = Cluster::new([server.url()]).unwrap();
+
+        let config = LoadConfig {
+            accounts: 4,
+            transactions: 40,
+            rate: 400.0,
+            batch_size: 5,
+            mix: "system=3,program=1".parse().unwrap(),
+            program: Some(ProgramCall {
+                program_id,
+                data: vec![1],
+            }),
+            poll_interval: Duration::from_millis(10),
+            timeout: Duration::from_secs(10),
+        };
+        let report = run(&cluster, &UnpaidFunder, &config).unwrap();
+
+        assert_eq!(report.accounts, 4);
+        assert_eq!((report.submitted, report.submit_errors), (40, 0));
+        assert_eq!(report.by_kind[&InstructionKind::System], 30);
+        assert_eq!(report.by_kind[&InstructionKind::Program], 10);
+        assert_eq!(
+            (report.processed, report.failed, report.unconfirmed),
+            (40, 0, 0)
+        );
+        assert_eq!(report.bitcoin_txids, 10);
+        // 4 account creations then the load
+        assert_eq!(server.received_transactions().len(), 44);
+
+        let latency = report.latency_ms.unwrap();
+        assert!(latency.min >= 20.0, "{:?}", latency);
+        assert!(latency.p50 <= latency.p99 && latency.p99 <= latency.max);
+        assert!(report.throughput > 0.0);
+
+        let config = LoadConfig {
+            program: None,
+            ..config
+        };
+        assert!(run(&cluster, &UnpaidFunder, &config).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    spanned::Spanned, Attribute, Data, DeriveInput, Error, Expr, Fields, Lifetime, Result, Type,
+};
+
+/// Checks requested on an account with `#[account(...)]`.
+#[derive(Default)]
+pub(crate) struct Constraints {
+    pub signer: bool,
+    pub writable: bool,
+    pub executable: bool,
+    pub owner: Option<Expr>,
+    pub address: Option<Expr>,
+}
+
+impl Constraints {
+    pub fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut constraints = Self::default();
+
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("account")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("signer") {
+                    constraints.signer = true;
+                } else if meta.path.is_ident("writable") {
+                    constraints.writable = true;
+                } else if meta.path.is_ident("executable") {
+                    constraints.executable = true;
+                } else if meta.path.is_ident("owner") {
+                    constraints.owner = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("address") {
+                    constraints.address = Some(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error(
+                        "expected one of `signer`, `writable`, `executable`, `owner = ...` or `address = ...`",
+                    ));
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(constraints)
+    }
+
+    /// Generate the checks on the `AccountInfo` bound to `ident`.
+    fn checks(&self, ident: &TokenStream) -> TokenStream {
+        let error = quote! { arch_program::program_error::ProgramError };
+        let mut checks = vec![];
+
+        if self.signer {
+            checks.push(quote! {
+                if !#ident.is_signer {
+                    return Err(#error::MissingRequiredSignature);
+                }
+            });
+        }
+        if self.writable {
+            checks.push(quote! {
+                if !#ident.is_writable {
+                    return Err(#error::Immutable);
+                }
+            });
+        }
+        if self.executable {
+            checks.push(quote! {
+                if !#ident.is_executable {
+                    return Err(#error::IncorrectProgramId);
+                }
+            });
+        }
+        // `AsRef<[u8]>` lets the expressions be either a `Pubkey` or a
+        // reference to one, like the `program_id` argument.
+        if let Some(owner) = &self.owner {
+            checks.push(quote! {
+                if ::core::convert::AsRef::<[u8]>::as_ref(#ident.owner)
+                    != ::core::convert::AsRef::<[u8]>::as_ref(&(#owner))
+                {
+                    return Err(#error::IncorrectProgramId);
+                }
+            });
+        }
+        if let Some(address) = &self.address {
+            checks.push(quote! {
+                if ::core::convert::AsRef::<[u8]>::as_ref(#ident.key)
+                    != ::core::convert::AsRef::<[u8]>::as_ref(&(#address))
+                {
+                    return Err(#error::InvalidArgument);
+                }
+            });
+        }
+
+        quote! { #(#checks)* }
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.signer
+            && !self.writable
+            && !self.executable
+            && self.owner.is_none()
+            && self.address.is_none()
+    }
+}
+
+pub fn expand(input: DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.span(),
+                    "Accounts can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "Accounts can only be derived for structs",
+            ))
+        }
+    };
+
+    // Fields are `&'a AccountInfo<'b>`. A single lifetime can't be used for
+    // both as `AccountInfo` is invariant over its lifetime.
+    let lifetimes = input
+        .generics
+        .lifetimes()
+        .map(|param| param.lifetime.clone())
+        .collect::<Vec<Lifetime>>();
+    let [outer, inner] = lifetimes.as_slice() else {
+        return Err(Error::new(
+            input.generics.span(),
+            "Accounts structs take two lifetimes, e.g. `Foo<'a, 'b>` with `&'a AccountInfo<'b>` fields",
+        ));
+    };
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let takes = fields
+        .iter()
+        .map(|field| {
+            let ident = field.ident.as_ref().expect("fields are named");
+            let constraints = Constraints::parse(&field.attrs)?;
+
+            match &field.ty {
+                Type::Reference(_) => {
+                    let checks = constraints.checks(&quote! { #ident });
+                    Ok(quote! {
+                        let #ident = arch_program::program::next_account_info(accounts)?;
+                        #checks
+                    })
+                }
+                _ if !constraints.is_empty() => Err(Error::new(
+                    field.span(),
+                    "#[account(...)] constraints only apply to `&AccountInfo` fields",
+                )),
+                ty => Ok(quote! {
+                    let #ident = <#ty as arch_program::context::Accounts<#outer, #inner>>::try_accounts(
+                        program_id,
+                        accounts,
+                    )?;
+                }),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let idents = fields.iter().map(|field| &field.ident);
+
+    let idl_accounts = fields
+        .iter()
+        .map(|field| {
+            let ident = field.ident.as_ref().expect("fields are named");
+            let name = ident.to_string();
+            let constraints = Constraints::parse(&field.attrs)?;
+
+            Ok(match &field.ty {
+                Type::Reference(_) => {
+                    let Constraints {
+                        signer, writable, ..
+                    } = constraints;
+                    quote! {
+                        accounts.push(arch_program::idl::IdlAccount {
+                            name: #name.to_string(),
+                            is_signer: #signer,
+                            is_writable: #writable,
+                        });
+                    }
+                }
+                ty => quote! {
+                    accounts.extend(
+                        <#ty as arch_program::idl::IdlAccounts>::idl_accounts()
+                            .into_iter()
+                            .map(|account| account.prefixed(#name)),
+                    );
+                },
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        impl #impl_generics arch_program::context::Accounts<#outer, #inner> for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn try_accounts(
+                program_id: &arch_program::pubkey::Pubkey,
+                accounts: &mut ::std::slice::Iter<#outer, arch_program::account::AccountInfo<#inner>>,
+            ) -> ::core::result::Result<Self, arch_program::program_error::ProgramError> {
+                #(#takes)*
+
+                Ok(Self { #(#idents),* })
+            }
+        }
+
+        impl #impl_generics arch_program::idl::IdlAccounts for #name #ty_generics #where_clause {
+            #[allow(clippy::vec_init_then_push)]
+            fn idl_accounts() -> ::std::vec::Vec<arch_program::idl::IdlAccount> {
+                let mut accounts = ::std::vec::Vec::new();
+                #(#idl_accounts)*
+                accounts
+            }
+        }
+    })
+}
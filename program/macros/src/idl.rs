@@ -0,0 +1,120 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use sha2::{Digest, Sha256};
+use syn::{spanned::Spanned, Data, DeriveInput, Error, Fields, Result};
+
+/// Describe `fields` as an `IdlFields`, collecting their types in `types`.
+fn idl_fields(fields: &Fields, types: &mut Vec<syn::Type>) -> TokenStream {
+    types.extend(fields.iter().map(|field| field.ty.clone()));
+
+    match fields {
+        Fields::Unnamed(fields) => {
+            let tys = fields.unnamed.iter().map(|field| &field.ty);
+            quote! {
+                arch_program::idl::IdlFields::Tuple(vec![
+                    #(<#tys as arch_program::idl::IdlBuild>::idl_type()),*
+                ])
+            }
+        }
+        fields => {
+            let named = fields.iter().map(|field| {
+                let name = field.ident.as_ref().expect("fields are named").to_string();
+                let ty = &field.ty;
+                quote! {
+                    arch_program::idl::IdlField::new(
+                        #name,
+                        <#ty as arch_program::idl::IdlBuild>::idl_type(),
+                    )
+                }
+            });
+            quote! { arch_program::idl::IdlFields::Named(vec![#(#named),*]) }
+        }
+    }
+}
+
+pub fn expand_derive(input: DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+    let name_str = name.to_string();
+
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "IdlBuild can't be derived for generic types",
+        ));
+    }
+
+    let mut field_types = vec![];
+    let definition = match &input.data {
+        Data::Struct(data) => {
+            let fields = idl_fields(&data.fields, &mut field_types);
+            quote! { arch_program::idl::IdlTypeDefTy::Struct { fields: #fields } }
+        }
+        Data::Enum(data) => {
+            let variants = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let name = variant.ident.to_string();
+                    let fields = idl_fields(&variant.fields, &mut field_types);
+                    quote! {
+                        arch_program::idl::IdlVariant {
+                            name: #name.to_string(),
+                            fields: #fields,
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+            quote! { arch_program::idl::IdlTypeDefTy::Enum { variants: vec![#(#variants),*] } }
+        }
+        Data::Union(_) => {
+            return Err(Error::new(
+                input.span(),
+                "IdlBuild can't be derived for unions",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        impl arch_program::idl::IdlBuild for #name {
+            fn idl_type() -> arch_program::idl::IdlType {
+                arch_program::idl::IdlType::Defined(#name_str.to_string())
+            }
+
+            fn idl_definitions(types: &mut ::std::vec::Vec<arch_program::idl::IdlTypeDef>) {
+                if types.iter().any(|def| def.name == #name_str) {
+                    return;
+                }
+                types.push(arch_program::idl::IdlTypeDef {
+                    name: #name_str.to_string(),
+                    discriminator: None,
+                    ty: #definition,
+                });
+                #(<#field_types as arch_program::idl::IdlBuild>::idl_definitions(types);)*
+            }
+        }
+    })
+}
+
+/// Expand `#[state]` or `#[event]`, whose discriminators are derived from
+/// `"<namespace>:<TypeName>"`.
+pub fn expand_attribute(namespace: &str, input: DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let hash = Sha256::digest(format!("{}:{}", namespace, name).as_bytes());
+    let discriminator = &hash[..8];
+
+    Ok(quote! {
+        #[derive(
+            arch_program::borsh::BorshSerialize,
+            arch_program::borsh::BorshDeserialize,
+            arch_program::IdlBuild,
+        )]
+        #[borsh(crate = "arch_program::borsh")]
+        #input
+
+        impl #impl_generics arch_program::account::Discriminator for #name #ty_generics #where_clause {
+            const DISCRIMINATOR: [u8; 8] = [#(#discriminator),*];
+        }
+    })
+}
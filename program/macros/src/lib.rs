@@ -0,0 +1,144 @@
+//! Procedural macros for writing Arch programs, re-exported by `arch_program`.
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, ItemMod};
+
+mod accounts;
+mod idl;
+// Shared with `arch_program::idl` so generated clients name things the same
+#[path = "../../src/idl/naming.rs"]
+mod naming;
+mod program;
+
+/// Turns an inline module of instruction handlers into a program.
+///
+/// Every `pub fn` of the module is an instruction handler taking a
+/// [`Context`] over an [`Accounts`] struct followed by the instruction
+/// arguments:
+///
+/// ```ignore
+/// #[program]
+/// pub mod counter {
+///     use super::*;
+///
+///     pub fn increment(ctx: Context<Increment>, amount: u64) -> ProgramResult {
+///         // ...
+///     }
+/// }
+/// ```
+///
+/// The macro generates a borsh encoded `CounterInstruction` enum with one
+/// variant per handler, a `process_instruction` function decoding it and
+/// routing to the handler, and registers that function with `entrypoint!`
+/// when building for the SBF target.
+///
+/// It also generates an `idl()` function describing the instructions, their
+/// accounts and arguments. Types marked with `#[state]` or `#[event]` inside
+/// the module are listed as the account and event types of the program.
+/// Argument types other than primitives, `String`, `Pubkey` and their
+/// `Option`, `Vec`, array and tuple combinations need `#[derive(IdlBuild)]`.
+///
+/// [`Context`]: ../arch_program/context/struct.Context.html
+/// [`Accounts`]: ../arch_program/context/trait.Accounts.html
+#[proc_macro_attribute]
+pub fn program(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "#[program] takes no arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let module = parse_macro_input!(input as ItemMod);
+    program::expand(module)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `Accounts` for a struct of `&'a AccountInfo<'b>` fields, taking
+/// one account per field in declaration order. Fields of other types are
+/// taken as nested `Accounts` structs.
+///
+/// Each account can be validated with `#[account(...)]`:
+///
+/// | constraint        | error when violated        |
+/// |-------------------|----------------------------|
+/// | `signer`          | `MissingRequiredSignature` |
+/// | `writable`        | `Immutable`                |
+/// | `executable`      | `IncorrectProgramId`       |
+/// | `owner = <expr>`  | `IncorrectProgramId`       |
+/// | `address = <expr>`| `InvalidArgument`          |
+///
+/// `owner` and `address` take a `Pubkey` or a reference to one. The
+/// `program_id` of the running program is in scope:
+///
+/// ```ignore
+/// #[derive(Accounts)]
+/// pub struct Increment<'a, 'b> {
+///     #[account(writable, owner = program_id)]
+///     pub counter: &'a AccountInfo<'b>,
+///     #[account(signer)]
+///     pub authority: &'a AccountInfo<'b>,
+/// }
+/// ```
+///
+/// A missing account fails with `NotEnoughAccountKeys`.
+#[proc_macro_derive(Accounts, attributes(account))]
+pub fn derive_accounts(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    accounts::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `IdlBuild` for a struct or enum, describing its borsh layout in
+/// the program IDL. All field types must implement `IdlBuild` as well.
+#[proc_macro_derive(IdlBuild)]
+pub fn derive_idl_build(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    idl::expand_derive(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Marks a type stored in program owned accounts.
+///
+/// Derives borsh serialization and `IdlBuild`, and implements
+/// `Discriminator` with the first 8 bytes of `sha256("account:<TypeName>")`
+/// so the type can be used with `Account`.
+#[proc_macro_attribute]
+pub fn state(args: TokenStream, input: TokenStream) -> TokenStream {
+    attribute_with_discriminator("state", "account", args, input)
+}
+
+/// Marks a type logged with `arch_program::log::emit`.
+///
+/// Same as `#[state]` with the discriminator taken from
+/// `sha256("event:<TypeName>")`.
+#[proc_macro_attribute]
+pub fn event(args: TokenStream, input: TokenStream) -> TokenStream {
+    attribute_with_discriminator("event", "event", args, input)
+}
+
+fn attribute_with_discriminator(
+    attribute: &str,
+    namespace: &str,
+    args: TokenStream,
+    input: TokenStream,
+) -> TokenStream {
+    if !args.is_empty() {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            format!("#[{}] takes no arguments", attribute),
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let input = parse_macro_input!(input as DeriveInput);
+    idl::expand_attribute(namespace, input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
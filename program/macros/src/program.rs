@@ -0,0 +1,242 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    spanned::Spanned, Attribute, Error, FnArg, GenericArgument, Ident, Item, ItemFn, ItemMod, Pat,
+    PathArguments, Result, Type, Visibility,
+};
+
+use crate::naming::to_camel_case;
+
+/// An instruction handler of a `#[program]` module.
+struct Handler {
+    name: Ident,
+    variant: Ident,
+    accounts: Type,
+    args: Vec<(Ident, Type)>,
+}
+
+impl Handler {
+    fn parse(function: &ItemFn) -> Result<Self> {
+        let mut inputs = function.sig.inputs.iter();
+
+        let accounts = match inputs.next() {
+            Some(FnArg::Typed(ctx)) => context_accounts(&ctx.ty)?,
+            _ => {
+                return Err(Error::new(
+                    function.sig.span(),
+                    "instruction handlers take a `Context<Accounts>` as first argument",
+                ))
+            }
+        };
+
+        let args = inputs
+            .map(|input| match input {
+                FnArg::Typed(arg) => match &*arg.pat {
+                    Pat::Ident(pat) => Ok((pat.ident.clone(), (*arg.ty).clone())),
+                    pat => Err(Error::new(
+                        pat.span(),
+                        "instruction arguments must be plain identifiers",
+                    )),
+                },
+                FnArg::Receiver(receiver) => Err(Error::new(
+                    receiver.span(),
+                    "instruction handlers can't take self",
+                )),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let name = function.sig.ident.clone();
+        Ok(Self {
+            variant: Ident::new(&to_camel_case(&name.to_string()), name.span()),
+            name,
+            accounts,
+            args,
+        })
+    }
+}
+
+/// Extract `T` from a `Context<T>` type.
+fn context_accounts(ty: &Type) -> Result<Type> {
+    if let Type::Path(path) = ty {
+        if let Some(segment) = path.path.segments.last() {
+            if segment.ident == "Context" {
+                if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                    let accounts = args.args.iter().find_map(|arg| match arg {
+                        GenericArgument::Type(ty) => Some(ty.clone()),
+                        _ => None,
+                    });
+                    if let Some(accounts) = accounts {
+                        return Ok(accounts);
+                    }
+                }
+            }
+        }
+    }
+
+    Err(Error::new(
+        ty.span(),
+        "expected `Context<Accounts>` as first argument",
+    ))
+}
+
+/// Whether `attrs` has `#[<name>]`, possibly through a path like
+/// `#[arch_program::<name>]`.
+fn has_attribute(attrs: &[Attribute], name: &str) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path()
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == name)
+    })
+}
+
+pub fn expand(mut module: ItemMod) -> Result<TokenStream> {
+    let mod_name = module.ident.clone();
+    let Some((_, items)) = module.content.as_mut() else {
+        return Err(Error::new(
+            module.span(),
+            "#[program] needs an inline module",
+        ));
+    };
+
+    let handlers = items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Fn(function) if matches!(function.vis, Visibility::Public(_)) => Some(function),
+            _ => None,
+        })
+        .map(Handler::parse)
+        .collect::<Result<Vec<_>>>()?;
+
+    if handlers.is_empty() {
+        return Err(Error::new(
+            module.span(),
+            "#[program] module has no `pub fn` instruction handlers",
+        ));
+    }
+
+    let marked = |attribute: &str| {
+        items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Struct(item) if has_attribute(&item.attrs, attribute) => Some(&item.ident),
+                Item::Enum(item) if has_attribute(&item.attrs, attribute) => Some(&item.ident),
+                _ => None,
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let states = marked("state");
+    let events = marked("event");
+
+    let instruction_enum = format_ident!("{}Instruction", to_camel_case(&mod_name.to_string()));
+
+    let variants = handlers.iter().map(|handler| {
+        let variant = &handler.variant;
+        if handler.args.is_empty() {
+            quote! { #variant }
+        } else {
+            let fields = handler.args.iter().map(|(name, ty)| quote! { #name: #ty });
+            quote! { #variant { #(#fields),* } }
+        }
+    });
+
+    let arms = handlers.iter().map(|handler| {
+        let Handler {
+            name,
+            variant,
+            accounts,
+            args,
+        } = handler;
+        let arg_names = args.iter().map(|(name, _)| name).collect::<Vec<_>>();
+        let pattern = if args.is_empty() {
+            quote! { #instruction_enum::#variant }
+        } else {
+            quote! { #instruction_enum::#variant { #(#arg_names),* } }
+        };
+
+        quote! {
+            #pattern => {
+                let mut accounts_iter = accounts.iter();
+                let ctx_accounts = <#accounts as arch_program::context::Accounts>::try_accounts(
+                    program_id,
+                    &mut accounts_iter,
+                )?;
+                #name(
+                    arch_program::context::Context::new(
+                        program_id,
+                        ctx_accounts,
+                        accounts_iter.as_slice(),
+                    ),
+                    #(#arg_names),*
+                )
+            }
+        }
+    });
+
+    let idl_instructions = handlers.iter().map(|handler| {
+        let name = handler.name.to_string();
+        let accounts = &handler.accounts;
+        let args = handler.args.iter().map(|(name, ty)| {
+            let name = name.to_string();
+            quote! { arch_program::idl::IdlField::new(#name, idl.add_type::<#ty>()) }
+        });
+
+        quote! {
+            let args = vec![#(#args),*];
+            idl.instructions.push(arch_program::idl::IdlInstruction {
+                name: #name.to_string(),
+                accounts: <#accounts as arch_program::idl::IdlAccounts>::idl_accounts(),
+                args,
+            });
+        }
+    });
+    let mod_name_str = mod_name.to_string();
+
+    let generated: Vec<Item> = vec![
+        syn::parse_quote! {
+            /// Instruction data of this program, one variant per handler.
+            #[derive(arch_program::borsh::BorshSerialize, arch_program::borsh::BorshDeserialize)]
+            #[borsh(crate = "arch_program::borsh")]
+            pub enum #instruction_enum {
+                #(#variants),*
+            }
+        },
+        syn::parse_quote! {
+            /// Decode the instruction data and route it to its handler.
+            pub fn process_instruction(
+                program_id: &arch_program::pubkey::Pubkey,
+                accounts: &[arch_program::account::AccountInfo],
+                instruction_data: &[u8],
+            ) -> arch_program::entrypoint::ProgramResult {
+                let instruction = <#instruction_enum as arch_program::borsh::BorshDeserialize>::try_from_slice(
+                    instruction_data,
+                )
+                .map_err(|_| arch_program::program_error::ProgramError::InvalidInstructionData)?;
+
+                match instruction {
+                    #(#arms)*
+                }
+            }
+        },
+        syn::parse_quote! {
+            /// Describe the instructions, accounts and events of this program.
+            pub fn idl() -> arch_program::idl::Idl {
+                let mut idl = arch_program::idl::Idl::new(#mod_name_str, env!("CARGO_PKG_VERSION"));
+                #(#idl_instructions)*
+                #(idl.add_account::<#states>();)*
+                #(idl.add_event::<#events>();)*
+                idl
+            }
+        },
+    ];
+    items.extend(generated);
+
+    Ok(quote! {
+        #module
+
+        pub use #mod_name::{idl, process_instruction, #instruction_enum};
+
+        arch_program::program_entrypoint!(process_instruction);
+    })
+}
@@ -45,7 +45,7 @@ impl AccountMeta {
 
 use core::fmt;
 use std::{
-    cell::{Ref, RefCell, RefMut},
+    cell::{OnceCell, Ref, RefCell, RefMut},
     mem::size_of,
     rc::Rc,
     slice::from_raw_parts_mut,
@@ -99,7 +99,7 @@ impl<'a> AccountInfo<'a> {
         self.data.borrow().len()
     }
 
-    pub fn try_borrow_data(&self) -> Result<Ref<&mut [u8]>, ProgramError> {
+    pub fn try_borrow_data(&self) -> Result<Ref<'_, &mut [u8]>, ProgramError> {
         self.data
             .try_borrow()
             .map_err(|_| ProgramError::AccountBorrowFailed)
@@ -109,7 +109,7 @@ impl<'a> AccountInfo<'a> {
         self.data.borrow().is_empty()
     }
 
-    pub fn try_borrow_mut_data(&self) -> Result<RefMut<&'a mut [u8]>, ProgramError> {
+    pub fn try_borrow_mut_data(&self) -> Result<RefMut<'_, &'a mut [u8]>, ProgramError> {
         self.data
             .try_borrow_mut()
             .map_err(|_| ProgramError::AccountBorrowFailed)
@@ -212,3 +212,311 @@ impl<'a> AccountInfo<'a> {
         }
     }
 }
+
+/// Length of the type discriminator stored at the start of account data
+/// managed through [`Account`].
+pub const DISCRIMINATOR_LENGTH: usize = 8;
+
+/// A type that can be stored in account data through [`Account`].
+///
+/// The discriminator is written in front of the borsh encoding so an account
+/// holding one type can never be loaded as another. By convention it is the
+/// first 8 bytes of `sha256("account:<TypeName>")`.
+pub trait Discriminator {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LENGTH];
+}
+
+/// Typed view of the data of an account owned by the running program.
+///
+/// The data is only deserialized on first access, and changes are written
+/// back by [`Account::exit`], growing the account with
+/// [`AccountInfo::realloc`] if the new encoding doesn't fit.
+pub struct Account<'a, 'b, T>
+where
+    T: BorshSerialize + BorshDeserialize + Discriminator,
+{
+    info: &'a AccountInfo<'b>,
+    state: OnceCell<T>,
+    is_dirty: bool,
+}
+
+impl<'a, 'b, T> Account<'a, 'b, T>
+where
+    T: BorshSerialize + BorshDeserialize + Discriminator,
+{
+    /// Load an initialized account, checking its owner and discriminator.
+    pub fn try_from(info: &'a AccountInfo<'b>, program_id: &Pubkey) -> Result<Self, ProgramError> {
+        if info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let data = info.try_borrow_data()?;
+        if data.len() < DISCRIMINATOR_LENGTH || data[..DISCRIMINATOR_LENGTH] == [0; 8] {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if data[..DISCRIMINATOR_LENGTH] != T::DISCRIMINATOR {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        drop(data);
+
+        Ok(Self {
+            info,
+            state: OnceCell::new(),
+            is_dirty: false,
+        })
+    }
+
+    /// Take an uninitialized account and set its state to `state`.
+    ///
+    /// Nothing is written until [`Account::exit`] is called.
+    pub fn init(
+        info: &'a AccountInfo<'b>,
+        program_id: &Pubkey,
+        state: T,
+    ) -> Result<Self, ProgramError> {
+        if info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if !info.is_writable {
+            return Err(ProgramError::Immutable);
+        }
+
+        let data = info.try_borrow_data()?;
+        if data.len() >= DISCRIMINATOR_LENGTH && data[..DISCRIMINATOR_LENGTH] != [0; 8] {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+        drop(data);
+
+        Ok(Self {
+            info,
+            state: OnceCell::from(state),
+            is_dirty: true,
+        })
+    }
+
+    pub fn info(&self) -> &'a AccountInfo<'b> {
+        self.info
+    }
+
+    pub fn key(&self) -> &'b Pubkey {
+        self.info.key
+    }
+
+    /// Returns the account state, deserializing it on first access.
+    pub fn get(&self) -> Result<&T, ProgramError> {
+        if let Some(state) = self.state.get() {
+            return Ok(state);
+        }
+
+        let data = self.info.try_borrow_data()?;
+        let state = T::deserialize(&mut &data[DISCRIMINATOR_LENGTH..])
+            .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
+        drop(data);
+
+        Ok(self.state.get_or_init(|| state))
+    }
+
+    /// Returns the account state for modification. The account is written
+    /// back on [`Account::exit`].
+    pub fn get_mut(&mut self) -> Result<&mut T, ProgramError> {
+        self.get()?;
+        self.is_dirty = true;
+        Ok(self.state.get_mut().expect("account state was just loaded"))
+    }
+
+    /// Replace the account state without deserializing the current one.
+    pub fn set(&mut self, state: T) {
+        self.state = OnceCell::from(state);
+        self.is_dirty = true;
+    }
+
+    /// Write the state back to the account data if it was modified.
+    pub fn exit(&mut self) -> Result<(), ProgramError> {
+        if !self.is_dirty {
+            return Ok(());
+        }
+        if !self.info.is_writable {
+            return Err(ProgramError::Immutable);
+        }
+
+        let state = self
+            .state
+            .get()
+            .expect("a dirty account always has a state");
+        let mut serialized = T::DISCRIMINATOR.to_vec();
+        state
+            .serialize(&mut serialized)
+            .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
+
+        if serialized.len() > self.info.data_len() {
+            self.info.realloc(serialized.len(), true)?;
+        }
+
+        let mut data = self.info.try_borrow_mut_data()?;
+        data[..serialized.len()].copy_from_slice(&serialized);
+        // Clear whatever an older, longer encoding left behind
+        data[serialized.len()..].fill(0);
+
+        self.is_dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::{BorshDeserialize, BorshSerialize};
+
+    use super::{Account, AccountInfo, Discriminator};
+    use crate::{program_error::ProgramError, pubkey::Pubkey, utxo::UtxoMeta};
+
+    #[derive(Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+    struct Counter {
+        count: u64,
+    }
+
+    impl Discriminator for Counter {
+        const DISCRIMINATOR: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+    }
+
+    #[derive(Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+    struct Log {
+        entries: Vec<u8>,
+    }
+
+    impl Discriminator for Log {
+        const DISCRIMINATOR: [u8; 8] = [8, 7, 6, 5, 4, 3, 2, 1];
+    }
+
+    /// A key followed by the original data length, where `realloc` reads it
+    /// in the input the runtime serializes
+    #[repr(C)]
+    struct RuntimeKey {
+        key: Pubkey,
+        original_data_len: u64,
+    }
+
+    fn log_data(entries: &[u8]) -> Vec<u8> {
+        let mut data = Log::DISCRIMINATOR.to_vec();
+        data.extend((entries.len() as u32).to_le_bytes());
+        data.extend(entries);
+        data
+    }
+
+    /// `realloc` logs through the runtime, which the host tests don't have
+    #[no_mangle]
+    extern "C" fn sol_log_(_message: *const u8, _len: u64) {}
+
+    fn counter_data(count: u64) -> Vec<u8> {
+        let mut data = Counter::DISCRIMINATOR.to_vec();
+        data.extend(count.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_load_checks_owner_and_discriminator() {
+        let key = Pubkey::system_program();
+        let program_id = Pubkey::from([7; 32]);
+        let utxo = UtxoMeta::from([0; 32], 0);
+
+        let mut data = counter_data(42);
+        let info = AccountInfo::new(&key, &mut data, &program_id, &utxo, false, true, false);
+        let account = Account::<Counter>::try_from(&info, &program_id).unwrap();
+        assert_eq!(account.get().unwrap(), &Counter { count: 42 });
+
+        assert_eq!(
+            Account::<Counter>::try_from(&info, &Pubkey::default()).err(),
+            Some(ProgramError::IncorrectProgramId)
+        );
+
+        let mut data = counter_data(42);
+        data[0] = 9;
+        let info = AccountInfo::new(&key, &mut data, &program_id, &utxo, false, true, false);
+        assert_eq!(
+            Account::<Counter>::try_from(&info, &program_id).err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+
+        let mut data = vec![];
+        let info = AccountInfo::new(&key, &mut data, &program_id, &utxo, false, true, false);
+        assert_eq!(
+            Account::<Counter>::try_from(&info, &program_id).err(),
+            Some(ProgramError::UninitializedAccount)
+        );
+    }
+
+    #[test]
+    fn test_init_rejects_initialized_account() {
+        let key = Pubkey::system_program();
+        let program_id = Pubkey::from([7; 32]);
+        let utxo = UtxoMeta::from([0; 32], 0);
+
+        let mut data = counter_data(1);
+        let info = AccountInfo::new(&key, &mut data, &program_id, &utxo, false, true, false);
+        assert_eq!(
+            Account::init(&info, &program_id, Counter { count: 0 }).err(),
+            Some(ProgramError::AccountAlreadyInitialized)
+        );
+
+        let mut data = vec![0; 16];
+        let info = AccountInfo::new(&key, &mut data, &program_id, &utxo, false, false, false);
+        assert_eq!(
+            Account::init(&info, &program_id, Counter { count: 0 }).err(),
+            Some(ProgramError::Immutable)
+        );
+    }
+
+    #[test]
+    fn test_exit_writes_back_the_state() {
+        let program_id = Pubkey::from([7; 32]);
+        let utxo = UtxoMeta::from([0; 32], 0);
+        let initial = log_data(&[1]);
+        let key = RuntimeKey {
+            key: Pubkey::system_program(),
+            original_data_len: initial.len() as u64,
+        };
+        // The data length, then the data with room to grow
+        let mut buffer = vec![0u64; 9];
+        buffer[0] = initial.len() as u64;
+        let data = unsafe {
+            std::slice::from_raw_parts_mut(buffer.as_mut_ptr().add(1) as *mut u8, initial.len())
+        };
+        data.copy_from_slice(&initial);
+        let info = AccountInfo::new(&key.key, data, &program_id, &utxo, false, true, false);
+        let serialized_len =
+            || unsafe { *(info.try_borrow_data().unwrap().as_ptr().offset(-8) as *const u64) };
+
+        // Nothing is written back unless the state changed
+        let mut account = Account::<Log>::try_from(&info, &program_id).unwrap();
+        account.get().unwrap();
+        account.exit().unwrap();
+        assert_eq!(&info.try_borrow_data().unwrap()[..], &initial[..]);
+
+        // A longer encoding grows the account
+        let mut account = Account::<Log>::try_from(&info, &program_id).unwrap();
+        account.get_mut().unwrap().entries = vec![1, 2, 3, 4, 5];
+        account.exit().unwrap();
+        assert_eq!(
+            &info.try_borrow_data().unwrap()[..],
+            &log_data(&[1, 2, 3, 4, 5])[..]
+        );
+        assert_eq!(serialized_len(), 17);
+
+        // A shorter one keeps the length and zeroes what is left of the old one
+        let mut account = Account::<Log>::try_from(&info, &program_id).unwrap();
+        account.set(Log { entries: vec![9] });
+        account.exit().unwrap();
+        assert_eq!(
+            &info.try_borrow_data().unwrap()[..],
+            &[log_data(&[9]), vec![0; 4]].concat()[..]
+        );
+        assert_eq!(serialized_len(), 17);
+        let account = Account::<Log>::try_from(&info, &program_id).unwrap();
+        assert_eq!(account.get().unwrap(), &Log { entries: vec![9] });
+
+        let mut data = log_data(&[1]);
+        let info = AccountInfo::new(&key.key, &mut data, &program_id, &utxo, false, false, false);
+        let mut account = Account::<Log>::try_from(&info, &program_id).unwrap();
+        account.set(Log { entries: vec![] });
+        assert_eq!(account.exit(), Err(ProgramError::Immutable));
+    }
+}
@@ -0,0 +1,213 @@
+//! Zero-copy access to fixed-layout account data.
+//!
+//! [`Account`](crate::account::Account) deserializes the whole account onto
+//! the heap, which is wasteful for large accounts like order books given the
+//! program heap is only [`HEAP_LENGTH`](crate::entrypoint::HEAP_LENGTH) bytes
+//! and never freed. [`AccountLoader`] instead casts the account data in place
+//! to a `#[repr(C)]` [`Pod`] struct and hands out [`Ref`]/[`RefMut`] guards,
+//! so the usual `RefCell` borrow tracking of [`AccountInfo::data`] still
+//! applies.
+
+use std::{
+    cell::{Ref, RefMut},
+    marker::PhantomData,
+    mem::size_of,
+};
+
+pub use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    account::{AccountInfo, Discriminator, DISCRIMINATOR_LENGTH},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Loader for accounts whose data is a discriminator followed by a `T`.
+pub struct AccountLoader<'a, 'b, T: Pod + Discriminator> {
+    info: &'a AccountInfo<'b>,
+    phantom: PhantomData<T>,
+}
+
+impl<'a, 'b, T: Pod + Discriminator> AccountLoader<'a, 'b, T> {
+    /// Create a loader for an account owned by `program_id`.
+    ///
+    /// The discriminator is only checked when the data is loaded, so the
+    /// account can still be initialized with [`AccountLoader::load_init`].
+    pub fn try_from(info: &'a AccountInfo<'b>, program_id: &Pubkey) -> Result<Self, ProgramError> {
+        if info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        Ok(Self {
+            info,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn info(&self) -> &'a AccountInfo<'b> {
+        self.info
+    }
+
+    /// Borrow the account data as a `T`.
+    pub fn load(&self) -> Result<Ref<'_, T>, ProgramError> {
+        let data = self.info.try_borrow_data()?;
+        check_layout::<T>(&data)?;
+        if data[..DISCRIMINATOR_LENGTH] != T::DISCRIMINATOR {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Ref::map(data, |data| {
+            bytemuck::from_bytes(&data[DISCRIMINATOR_LENGTH..DISCRIMINATOR_LENGTH + size_of::<T>()])
+        }))
+    }
+
+    /// Mutably borrow the account data as a `T`.
+    pub fn load_mut(&self) -> Result<RefMut<'_, T>, ProgramError> {
+        if !self.info.is_writable {
+            return Err(ProgramError::Immutable);
+        }
+
+        let data = self.info.try_borrow_mut_data()?;
+        check_layout::<T>(&data)?;
+        if data[..DISCRIMINATOR_LENGTH] != T::DISCRIMINATOR {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(RefMut::map(data, |data| {
+            bytemuck::from_bytes_mut(
+                &mut data[DISCRIMINATOR_LENGTH..DISCRIMINATOR_LENGTH + size_of::<T>()],
+            )
+        }))
+    }
+
+    /// Mutably borrow an uninitialized account as a `T`, writing the
+    /// discriminator.
+    ///
+    /// The account must already be large enough; its remaining data is
+    /// expected to be zeroed.
+    pub fn load_init(&self) -> Result<RefMut<'_, T>, ProgramError> {
+        if !self.info.is_writable {
+            return Err(ProgramError::Immutable);
+        }
+
+        let mut data = self.info.try_borrow_mut_data()?;
+        check_layout::<T>(&data)?;
+        if data[..DISCRIMINATOR_LENGTH] != [0; DISCRIMINATOR_LENGTH] {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+        data[..DISCRIMINATOR_LENGTH].copy_from_slice(&T::DISCRIMINATOR);
+
+        Ok(RefMut::map(data, |data| {
+            bytemuck::from_bytes_mut(
+                &mut data[DISCRIMINATOR_LENGTH..DISCRIMINATOR_LENGTH + size_of::<T>()],
+            )
+        }))
+    }
+}
+
+/// Check that `data` is large enough and aligned for a `T` after the
+/// discriminator, so the casts above can't panic.
+fn check_layout<T: Pod>(data: &[u8]) -> Result<(), ProgramError> {
+    if data.len() < DISCRIMINATOR_LENGTH + size_of::<T>() {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+
+    let ptr = data[DISCRIMINATOR_LENGTH..].as_ptr();
+    if ptr.align_offset(std::mem::align_of::<T>()) != 0 {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::{Pod, Zeroable};
+
+    use super::AccountLoader;
+    use crate::{
+        account::{AccountInfo, Discriminator},
+        program_error::ProgramError,
+        pubkey::Pubkey,
+        utxo::UtxoMeta,
+    };
+
+    #[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+    #[repr(C)]
+    struct OrderBook {
+        best_bid: u64,
+        best_ask: u64,
+    }
+
+    impl Discriminator for OrderBook {
+        const DISCRIMINATOR: [u8; 8] = [8, 7, 6, 5, 4, 3, 2, 1];
+    }
+
+    #[test]
+    fn test_load_and_modify_in_place() {
+        let key = Pubkey::system_program();
+        let program_id = Pubkey::from([7; 32]);
+        let utxo = UtxoMeta::from([0; 32], 0);
+
+        // u64 backing storage keeps the data 8-byte aligned
+        let mut storage = [0u64; 3];
+        let data: &mut [u8] = bytemuck::cast_slice_mut(&mut storage);
+        let info = AccountInfo::new(&key, data, &program_id, &utxo, false, true, false);
+        let loader = AccountLoader::<OrderBook>::try_from(&info, &program_id).unwrap();
+
+        assert_eq!(loader.load().err(), Some(ProgramError::InvalidAccountData));
+
+        loader.load_init().unwrap().best_bid = 10;
+        {
+            let mut book = loader.load_mut().unwrap();
+            book.best_ask = 12;
+            assert_eq!(loader.load().err(), Some(ProgramError::AccountBorrowFailed));
+        }
+        assert_eq!(
+            *loader.load().unwrap(),
+            OrderBook {
+                best_bid: 10,
+                best_ask: 12
+            }
+        );
+        assert_eq!(
+            loader.load_init().err(),
+            Some(ProgramError::AccountAlreadyInitialized)
+        );
+        assert_eq!(storage[1], 10);
+    }
+
+    #[test]
+    fn test_layout_checks() {
+        let key = Pubkey::system_program();
+        let program_id = Pubkey::from([7; 32]);
+        let utxo = UtxoMeta::from([0; 32], 0);
+
+        let mut storage = [0u64; 4];
+        let data: &mut [u8] = bytemuck::cast_slice_mut(&mut storage);
+
+        let info = AccountInfo::new(
+            &key,
+            &mut data[..16],
+            &program_id,
+            &utxo,
+            false,
+            true,
+            false,
+        );
+        let loader = AccountLoader::<OrderBook>::try_from(&info, &program_id).unwrap();
+        assert_eq!(
+            loader.load_init().err(),
+            Some(ProgramError::AccountDataTooSmall)
+        );
+
+        let info = AccountInfo::new(&key, &mut data[1..], &program_id, &utxo, false, true, false);
+        let loader = AccountLoader::<OrderBook>::try_from(&info, &program_id).unwrap();
+        assert_eq!(
+            loader.load_init().err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+
+        assert!(AccountLoader::<OrderBook>::try_from(&info, &Pubkey::default()).is_err());
+    }
+}
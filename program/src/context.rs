@@ -0,0 +1,41 @@
+//! Support types for programs written with the [`program`](crate::program)
+//! attribute macro.
+
+use std::slice::Iter;
+
+use crate::{account::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+
+/// A set of accounts an instruction expects, usually implemented with
+/// `#[derive(Accounts)]`.
+pub trait Accounts<'a, 'b>: Sized {
+    /// Take the accounts from the front of `accounts`, failing with
+    /// [`ProgramError::NotEnoughAccountKeys`] if it runs out.
+    fn try_accounts(
+        program_id: &Pubkey,
+        accounts: &mut Iter<'a, AccountInfo<'b>>,
+    ) -> Result<Self, ProgramError>;
+}
+
+/// The input of an instruction handler.
+pub struct Context<'a, 'b, T> {
+    /// Id of the currently executing program
+    pub program_id: &'a Pubkey,
+    /// The validated accounts of the instruction
+    pub accounts: T,
+    /// Accounts passed after the ones declared in `T`
+    pub remaining_accounts: &'a [AccountInfo<'b>],
+}
+
+impl<'a, 'b, T> Context<'a, 'b, T> {
+    pub fn new(
+        program_id: &'a Pubkey,
+        accounts: T,
+        remaining_accounts: &'a [AccountInfo<'b>],
+    ) -> Self {
+        Self {
+            program_id,
+            accounts,
+            remaining_accounts,
+        }
+    }
+}
@@ -168,6 +168,24 @@ macro_rules! entrypoint {
     };
 }
 
+/// Declare the program entrypoint when building for the SBF target, and
+/// nothing otherwise so programs can be unit tested on the host.
+#[cfg(target_os = "solana")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! program_entrypoint {
+    ($process_instruction:ident) => {
+        $crate::entrypoint!($process_instruction);
+    };
+}
+
+#[cfg(not(target_os = "solana"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! program_entrypoint {
+    ($process_instruction:ident) => {};
+}
+
 #[macro_export]
 macro_rules! custom_heap_default {
     () => {
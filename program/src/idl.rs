@@ -0,0 +1,335 @@
+//! Interface description of programs written with the
+//! [`program`](crate::program) attribute macro.
+//!
+//! `#[program]` generates an `idl()` function returning an [`Idl`] that lists
+//! the instructions of the program with their accounts and argument layouts,
+//! along with the account and event types declared in the program module with
+//! [`state`](crate::state) and [`event`](crate::event). The IDL serializes to
+//! JSON and is meant to be checked in next to the program so clients can be
+//! generated from it.
+
+mod naming;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{account::Discriminator, pubkey::Pubkey};
+
+pub use naming::to_camel_case;
+
+/// Interface of a program.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Idl {
+    /// Name of the `#[program]` module
+    pub name: String,
+    /// Version of the crate declaring the program
+    pub version: String,
+    /// Instructions, in the order of the instruction enum variants
+    pub instructions: Vec<IdlInstruction>,
+    /// Types stored in program owned accounts
+    #[serde(default)]
+    pub accounts: Vec<IdlTypeDef>,
+    /// Types logged with [`emit`](crate::log::emit)
+    #[serde(default)]
+    pub events: Vec<IdlTypeDef>,
+    /// Other types used by the instruction arguments, accounts and events
+    #[serde(default)]
+    pub types: Vec<IdlTypeDef>,
+}
+
+impl Idl {
+    pub fn new(name: &str, version: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            instructions: vec![],
+            accounts: vec![],
+            events: vec![],
+            types: vec![],
+        }
+    }
+
+    /// Register the definitions needed by `T` and return its [`IdlType`].
+    pub fn add_type<T: IdlBuild>(&mut self) -> IdlType {
+        T::idl_definitions(&mut self.types);
+        T::idl_type()
+    }
+
+    /// Register `T` as an account type.
+    pub fn add_account<T: IdlBuild + Discriminator>(&mut self) {
+        let definition = self.take_definition::<T>();
+        self.accounts.extend(definition);
+    }
+
+    /// Register `T` as an event type.
+    pub fn add_event<T: IdlBuild + Discriminator>(&mut self) {
+        let definition = self.take_definition::<T>();
+        self.events.extend(definition);
+    }
+
+    fn take_definition<T: IdlBuild + Discriminator>(&mut self) -> Option<IdlTypeDef> {
+        let IdlType::Defined(name) = self.add_type::<T>() else {
+            return None;
+        };
+        let index = self.types.iter().position(|def| def.name == name)?;
+
+        let mut definition = self.types.remove(index);
+        definition.discriminator = Some(T::DISCRIMINATOR);
+        Some(definition)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdlInstruction {
+    pub name: String,
+    /// Accounts in the order the instruction expects them
+    pub accounts: Vec<IdlAccount>,
+    pub args: Vec<IdlField>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdlAccount {
+    pub name: String,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+impl IdlAccount {
+    /// Prefix the name with the field holding a nested `Accounts` struct.
+    pub fn prefixed(mut self, prefix: &str) -> Self {
+        self.name = format!("{}_{}", prefix, self.name);
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdlField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: IdlType,
+}
+
+impl IdlField {
+    pub fn new(name: &str, ty: IdlType) -> Self {
+        Self {
+            name: name.to_string(),
+            ty,
+        }
+    }
+}
+
+/// Borsh layout of a value.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdlType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    String,
+    Pubkey,
+    Option(Box<IdlType>),
+    Vec(Box<IdlType>),
+    Array(Box<IdlType>, usize),
+    Tuple(Vec<IdlType>),
+    /// A type listed in the IDL under this name
+    Defined(String),
+}
+
+/// A named struct or enum.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdlTypeDef {
+    pub name: String,
+    /// Set for account and event types
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discriminator: Option<[u8; 8]>,
+    #[serde(rename = "type")]
+    pub ty: IdlTypeDefTy,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum IdlTypeDefTy {
+    Struct { fields: IdlFields },
+    Enum { variants: Vec<IdlVariant> },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdlVariant {
+    pub name: String,
+    pub fields: IdlFields,
+}
+
+/// Fields of a struct or enum variant. Unit structs and variants have no
+/// named fields.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdlFields {
+    Named(Vec<IdlField>),
+    Tuple(Vec<IdlType>),
+}
+
+/// A type that can appear in an IDL, usually implemented with
+/// `#[derive(IdlBuild)]`.
+pub trait IdlBuild {
+    fn idl_type() -> IdlType;
+
+    /// Add the definitions of this type and of the types it refers to,
+    /// skipping the ones already present.
+    fn idl_definitions(_types: &mut Vec<IdlTypeDef>) {}
+}
+
+/// The accounts of an instruction, implemented by `#[derive(Accounts)]`.
+pub trait IdlAccounts {
+    fn idl_accounts() -> Vec<IdlAccount>;
+}
+
+macro_rules! impl_idl_build {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl IdlBuild for $ty {
+                fn idl_type() -> IdlType {
+                    IdlType::$variant
+                }
+            }
+        )*
+    };
+}
+
+impl_idl_build!(
+    bool => Bool,
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+    u128 => U128,
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    i128 => I128,
+    String => String,
+    Pubkey => Pubkey,
+);
+
+impl<T: IdlBuild> IdlBuild for Option<T> {
+    fn idl_type() -> IdlType {
+        IdlType::Option(Box::new(T::idl_type()))
+    }
+
+    fn idl_definitions(types: &mut Vec<IdlTypeDef>) {
+        T::idl_definitions(types)
+    }
+}
+
+impl<T: IdlBuild> IdlBuild for Vec<T> {
+    fn idl_type() -> IdlType {
+        IdlType::Vec(Box::new(T::idl_type()))
+    }
+
+    fn idl_definitions(types: &mut Vec<IdlTypeDef>) {
+        T::idl_definitions(types)
+    }
+}
+
+impl<T: IdlBuild, const N: usize> IdlBuild for [T; N] {
+    fn idl_type() -> IdlType {
+        IdlType::Array(Box::new(T::idl_type()), N)
+    }
+
+    fn idl_definitions(types: &mut Vec<IdlTypeDef>) {
+        T::idl_definitions(types)
+    }
+}
+
+impl<T: IdlBuild> IdlBuild for Box<T> {
+    fn idl_type() -> IdlType {
+        T::idl_type()
+    }
+
+    fn idl_definitions(types: &mut Vec<IdlTypeDef>) {
+        T::idl_definitions(types)
+    }
+}
+
+/// Borsh encodes a map like a vector of its entries sorted by key.
+impl<K: IdlBuild, V: IdlBuild> IdlBuild for BTreeMap<K, V> {
+    fn idl_type() -> IdlType {
+        IdlType::Vec(Box::new(IdlType::Tuple(vec![K::idl_type(), V::idl_type()])))
+    }
+
+    fn idl_definitions(types: &mut Vec<IdlTypeDef>) {
+        K::idl_definitions(types);
+        V::idl_definitions(types);
+    }
+}
+
+macro_rules! impl_idl_build_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: IdlBuild),+> IdlBuild for ($($name,)+) {
+            fn idl_type() -> IdlType {
+                IdlType::Tuple(vec![$($name::idl_type()),+])
+            }
+
+            fn idl_definitions(types: &mut Vec<IdlTypeDef>) {
+                $($name::idl_definitions(types);)+
+            }
+        }
+    };
+}
+
+impl_idl_build_tuple!(A);
+impl_idl_build_tuple!(A, B);
+impl_idl_build_tuple!(A, B, C);
+impl_idl_build_tuple!(A, B, C, D);
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{Idl, IdlFields, IdlType, IdlTypeDef, IdlTypeDefTy};
+    use crate::pubkey::Pubkey;
+
+    #[test]
+    fn test_idl_type_json() {
+        let mut idl = Idl::new("test", "0.1.0");
+        let ty = idl.add_type::<Vec<(Option<Pubkey>, [u8; 4])>>();
+
+        assert_eq!(
+            ty,
+            IdlType::Vec(Box::new(IdlType::Tuple(vec![
+                IdlType::Option(Box::new(IdlType::Pubkey)),
+                IdlType::Array(Box::new(IdlType::U8), 4),
+            ])))
+        );
+        assert_eq!(
+            serde_json::to_string(&ty).unwrap(),
+            r#"{"vec":{"tuple":[{"option":"pubkey"},{"array":["u8",4]}]}}"#
+        );
+        assert!(idl.types.is_empty());
+        assert_eq!(
+            idl.add_type::<BTreeMap<Pubkey, u128>>(),
+            idl.add_type::<Vec<(Pubkey, u128)>>()
+        );
+
+        let def = IdlTypeDef {
+            name: "Empty".to_string(),
+            discriminator: None,
+            ty: IdlTypeDefTy::Struct {
+                fields: IdlFields::Named(vec![]),
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&def).unwrap(),
+            r#"{"name":"Empty","type":{"kind":"struct","fields":{"named":[]}}}"#
+        );
+    }
+}
@@ -0,0 +1,19 @@
+//! Names derived from the snake case names of a program.
+//!
+//! The `#[program]` macro names the instruction enum and its variants with
+//! [`to_camel_case`] and the client generator derives the same names from the
+//! IDL, so both include this one file.
+
+/// `say_hello` to `SayHello`, the name of an instruction variant.
+pub fn to_camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
@@ -93,7 +93,9 @@ mod tests {
     }
 }
 
-#[derive(Debug, Error, PartialEq, Eq, Clone)]
+#[derive(
+    Debug, Error, PartialEq, Eq, Clone, Serialize, Deserialize, BorshSerialize, BorshDeserialize,
+)]
 pub enum InstructionError {
     /// Deprecated! Use CustomError instead!
     /// The program instruction returned an error
@@ -1,7 +1,10 @@
 pub mod account;
+pub mod account_loader;
+pub mod context;
 pub mod debug_account_data;
 pub mod entrypoint;
 pub mod helper;
+pub mod idl;
 pub mod input_to_sign;
 pub mod instruction;
 pub mod log;
@@ -15,3 +18,6 @@ pub mod syscalls;
 pub mod system_instruction;
 pub mod transaction_to_sign;
 pub mod utxo;
+
+pub use arch_program_macros::{event, program, state, Accounts, IdlBuild};
+pub use borsh;
@@ -33,7 +33,9 @@
 //! [`Pubkey`]: crate::pubkey::Pubkey
 //! [`Pubkey::log`]: crate::pubkey::Pubkey::log
 
-use crate::account::AccountInfo;
+use borsh::BorshSerialize;
+
+use crate::account::{AccountInfo, Discriminator};
 
 /// Print a message to the log.
 #[macro_export]
@@ -109,6 +111,14 @@ pub fn sol_log_data(data: &[&[u8]]) {
     unsafe { crate::syscalls::sol_log_data(data as *const _ as *const u8, data.len() as u64) };
 }
 
+/// Log an event as its discriminator followed by its borsh encoding, the
+/// layout described for it in the program IDL.
+pub fn emit<T: BorshSerialize + Discriminator>(event: &T) {
+    if let Ok(data) = borsh::to_vec(event) {
+        sol_log_data(&[&T::DISCRIMINATOR, &data]);
+    }
+}
+
 /// Print the hexadecimal representation of a slice.
 #[allow(dead_code)]
 pub fn sol_log_slice(slice: &[u8]) {
@@ -6,7 +6,7 @@ use crate::program_error::ProgramError;
 use crate::stable_layout::stable_ins::StableInstruction;
 
 use crate::transaction_to_sign::TransactionToSign;
-use crate::utxo::UtxoMeta;
+use crate::utxo::{UtxoInfo, UtxoMeta};
 use crate::{account::AccountInfo, entrypoint::ProgramResult, pubkey::Pubkey};
 
 pub fn invoke(instruction: &Instruction, account_infos: &[AccountInfo]) -> ProgramResult {
@@ -180,3 +180,79 @@ pub fn get_account_script_pubkey(pubkey: &Pubkey) -> [u8; 34] {
     let _ = unsafe { crate::syscalls::arch_get_account_script_pubkey(buf.as_mut_ptr(), pubkey) };
     buf
 }
+
+/// Maximum size of a script pubkey that can be read with [`get_utxo_script_pubkey`].
+pub const MAX_SCRIPT_PUBKEY_SIZE: usize = 128;
+
+/// Get the value in satoshis of the output referenced by `utxo`.
+///
+/// Returns `None` if the node doesn't know the output.
+pub fn get_utxo_value(utxo: &UtxoMeta) -> Option<u64> {
+    let mut value = 0u64;
+    let result = unsafe { crate::syscalls::arch_get_utxo_value(utxo, &mut value) };
+    match result {
+        crate::entrypoint::SUCCESS => Some(value),
+        _ => None,
+    }
+}
+
+/// Get the script pubkey of the output referenced by `utxo`.
+///
+/// Returns `None` if the node doesn't know the output or if the script is
+/// longer than [`MAX_SCRIPT_PUBKEY_SIZE`].
+pub fn get_utxo_script_pubkey(utxo: &UtxoMeta) -> Option<Vec<u8>> {
+    let mut buf = [0u8; MAX_SCRIPT_PUBKEY_SIZE];
+
+    let size = unsafe {
+        crate::syscalls::arch_get_utxo_script_pubkey(buf.as_mut_ptr(), buf.len() as u64, utxo)
+    };
+
+    if size == 0 || size as usize > MAX_SCRIPT_PUBKEY_SIZE {
+        None
+    } else {
+        Some(buf[..size as usize].to_vec())
+    }
+}
+
+/// Get the number of confirmations of the transaction that created `utxo`.
+///
+/// An output that is still in the mempool has zero confirmations.
+pub fn get_utxo_confirmations(utxo: &UtxoMeta) -> Option<u32> {
+    let mut confirmations = 0u32;
+    let result = unsafe { crate::syscalls::arch_get_utxo_confirmations(utxo, &mut confirmations) };
+    match result {
+        crate::entrypoint::SUCCESS => Some(confirmations),
+        _ => None,
+    }
+}
+
+/// Check whether the output referenced by `utxo` has already been spent.
+pub fn is_utxo_spent(utxo: &UtxoMeta) -> Option<bool> {
+    let mut is_spent = 0u8;
+    let result = unsafe { crate::syscalls::arch_get_utxo_spent_status(utxo, &mut is_spent) };
+    match result {
+        crate::entrypoint::SUCCESS => Some(is_spent != 0),
+        _ => None,
+    }
+}
+
+/// Get everything the node knows about the output referenced by `utxo`.
+pub fn get_utxo_info(utxo: &UtxoMeta) -> Option<UtxoInfo> {
+    Some(UtxoInfo {
+        value: get_utxo_value(utxo)?,
+        script_pubkey: get_utxo_script_pubkey(utxo)?,
+        confirmations: get_utxo_confirmations(utxo)?,
+        is_spent: is_utxo_spent(utxo)?,
+    })
+}
+
+/// Check that `utxo` is unspent and pays to the script of the `account`.
+///
+/// Use this before crediting a deposit: unlike [`validate_utxo_ownership`] the
+/// caller can then read the exact amount with [`get_utxo_value`].
+pub fn utxo_pays_account(utxo: &UtxoMeta, account: &Pubkey) -> bool {
+    match get_utxo_info(utxo) {
+        Some(info) => info.pays_to(&get_account_script_pubkey(account)),
+        None => false,
+    }
+}
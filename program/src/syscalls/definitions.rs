@@ -2,28 +2,49 @@
 
 use crate::{pubkey::Pubkey, utxo::UtxoMeta};
 
-macro_rules! define_syscall {
-	(fn $name:ident($($arg:ident: $typ:ty),*) -> $ret:ty) => {
+/// Declare the syscalls provided by the runtime, along with [`SYSCALLS`]
+/// listing their names.
+macro_rules! define_syscalls {
+	($(fn $name:ident($($arg:ident: $typ:ty),*) $(-> $ret:ty)?;)*) => {
 		extern "C" {
-			pub fn $name($($arg: $typ),*) -> $ret;
+			$(pub fn $name($($arg: $typ),*) $(-> $ret)?;)*
 		}
+
+		/// Names of the syscalls a program can import, checked against the
+		/// imports of program ELFs before they are deployed.
+		pub const SYSCALLS: &[&str] = &[$(stringify!($name)),*];
 	};
-	(fn $name:ident($($arg:ident: $typ:ty),*)) => {
-		define_syscall!(fn $name($($arg: $typ),*) -> ());
-	}
 }
 
-define_syscall!(fn sol_invoke_signed_rust(instruction_addr: *const u8, account_infos_addr: *const u8, account_infos_len: u64) -> u64);
-define_syscall!(fn sol_set_return_data(data: *const u8, length: u64));
-define_syscall!(fn sol_get_return_data(data: *mut u8, length: u64, program_id: *mut Pubkey) -> u64);
+/// Symbols the runtime resolves for every program besides [`SYSCALLS`]: the
+/// panic handler and the memory builtins the compiler emits calls to.
+pub const RUNTIME_BUILTINS: &[&str] = &[
+    "abort",
+    "sol_panic_",
+    "sol_memcpy_",
+    "sol_memset_",
+    "sol_memcmp_",
+    "sol_memmove_",
+];
+
+define_syscalls! {
+    fn sol_invoke_signed_rust(instruction_addr: *const u8, account_infos_addr: *const u8, account_infos_len: u64) -> u64;
+    fn sol_set_return_data(data: *const u8, length: u64);
+    fn sol_get_return_data(data: *mut u8, length: u64, program_id: *mut Pubkey) -> u64;
 
-define_syscall!(fn arch_set_transaction_to_sign(transaction_to_sign: *const u8, length: u64) -> u64);
-define_syscall!(fn arch_get_bitcoin_tx(data: *mut u8, length: u64, txid: &[u8; 32]) -> u64);
-define_syscall!(fn arch_get_network_xonly_pubkey(data: *mut u8) -> u64);
-define_syscall!(fn arch_validate_utxo_ownership(utxo: *const UtxoMeta, owner: *const Pubkey) -> u64);
-define_syscall!(fn arch_get_account_script_pubkey(script: *mut u8, pubkey: *const Pubkey) -> u64);
-// logs
-define_syscall!(fn sol_log_(message: *const u8, len: u64));
-define_syscall!(fn sol_log_64_(arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64));
-define_syscall!(fn sol_log_pubkey(pubkey_addr: *const u8));
-define_syscall!(fn sol_log_data(data: *const u8, data_len: u64));
+    fn arch_set_transaction_to_sign(transaction_to_sign: *const u8, length: u64) -> u64;
+    fn arch_get_bitcoin_tx(data: *mut u8, length: u64, txid: &[u8; 32]) -> u64;
+    fn arch_get_network_xonly_pubkey(data: *mut u8) -> u64;
+    fn arch_validate_utxo_ownership(utxo: *const UtxoMeta, owner: *const Pubkey) -> u64;
+    fn arch_get_account_script_pubkey(script: *mut u8, pubkey: *const Pubkey) -> u64;
+    // utxos
+    fn arch_get_utxo_value(utxo: *const UtxoMeta, value: *mut u64) -> u64;
+    fn arch_get_utxo_script_pubkey(data: *mut u8, length: u64, utxo: *const UtxoMeta) -> u64;
+    fn arch_get_utxo_confirmations(utxo: *const UtxoMeta, confirmations: *mut u32) -> u64;
+    fn arch_get_utxo_spent_status(utxo: *const UtxoMeta, is_spent: *mut u8) -> u64;
+    // logs
+    fn sol_log_(message: *const u8, len: u64);
+    fn sol_log_64_(arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64);
+    fn sol_log_pubkey(pubkey_addr: *const u8);
+    fn sol_log_data(data: *const u8, data_len: u64);
+}
@@ -1,15 +1,40 @@
 use crate::account::AccountMeta;
 use crate::instruction::Instruction;
+use crate::program_error::ProgramError;
 use crate::pubkey::Pubkey;
 use crate::utxo::UtxoMeta;
 
+/// Instructions of the system program, each taking the account it operates
+/// on as its only, signing and writable, account.
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum SystemInstruction {
+    /// Create an account anchored to the given UTXO
     CreateAccount(UtxoMeta),
+    /// Bytes sent after the tag of `WriteBytes` as is, the caller having put
+    /// the offset and length in front of them. Decoding yields `WriteBytes`.
+    #[deprecated(note = "use `WriteBytes`, which encodes the offset and length")]
     ExtendBytes(Vec<u8>),
+    /// Write `data` into the account data at `offset`, growing the account
+    /// if it is too small.
+    ///
+    /// This replaces `ExtendBytes`, which has the same tag and whose clients
+    /// put the offset and length in front of the data themselves, so the
+    /// bytes sent are unchanged.
+    WriteBytes { offset: u32, data: Vec<u8> },
+    /// Mark the account as an executable program
+    MakeExecutable,
+    /// Transfer ownership of the account to another program
+    AssignOwnership(Pubkey),
+    /// Shrink the account data to the given length.
+    ///
+    /// Tag 4 is only understood by nodes whose system program implements
+    /// truncation, older ones fail the transaction, so it should only be
+    /// sent to nodes known to support it.
+    Truncate(u32),
 }
 
 impl SystemInstruction {
+    #[allow(deprecated)]
     pub fn serialise(&self) -> Vec<u8> {
         let mut serialized = vec![];
 
@@ -22,26 +47,77 @@ impl SystemInstruction {
                 serialized.push(1);
                 serialized.extend(bytes);
             }
+            Self::WriteBytes { offset, data } => {
+                serialized.push(1);
+                serialized.extend(offset.to_le_bytes());
+                serialized.extend((data.len() as u32).to_le_bytes());
+                serialized.extend(data);
+            }
+            Self::MakeExecutable => {
+                serialized.push(2);
+            }
+            Self::AssignOwnership(owner) => {
+                serialized.push(3);
+                serialized.extend(owner.serialize());
+            }
+            Self::Truncate(len) => {
+                serialized.push(4);
+                serialized.extend(len.to_le_bytes());
+            }
         }
 
         serialized
     }
 
+    /// Decode an instruction, panicking on data that isn't one.
+    #[deprecated(note = "use `try_from_slice`, which returns an error on invalid data")]
     pub fn from_slice(data: &[u8]) -> Self {
-        match data[0] {
-            0 => Self::CreateAccount(UtxoMeta::from_slice(&data[1..])),
-            1 => Self::ExtendBytes(data[1..].to_vec()),
-            _ => {
-                unreachable!("error deserializing system instruction")
+        Self::try_from_slice(data).expect("error deserializing system instruction")
+    }
+
+    /// Decode an instruction, rejecting truncated data, trailing bytes and
+    /// unknown tags.
+    pub fn try_from_slice(data: &[u8]) -> Result<Self, ProgramError> {
+        let (tag, rest) = data
+            .split_first()
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        let exact = |len: usize| {
+            if rest.len() == len {
+                Ok(rest)
+            } else {
+                Err(ProgramError::InvalidInstructionData)
             }
-        }
+        };
+        let read_u32 = |bytes: &[u8]| u32::from_le_bytes(bytes[..4].try_into().unwrap());
+
+        Ok(match tag {
+            0 => Self::CreateAccount(UtxoMeta::from_slice(exact(36)?)),
+            1 => {
+                if rest.len() < 8 {
+                    return Err(ProgramError::InvalidInstructionData);
+                }
+                let offset = read_u32(&rest[..4]);
+                let len = read_u32(&rest[4..8]) as usize;
+                if rest.len() - 8 != len {
+                    return Err(ProgramError::InvalidInstructionData);
+                }
+                Self::WriteBytes {
+                    offset,
+                    data: rest[8..].to_vec(),
+                }
+            }
+            2 => {
+                exact(0)?;
+                Self::MakeExecutable
+            }
+            3 => Self::AssignOwnership(Pubkey::from_slice(exact(32)?)),
+            4 => Self::Truncate(read_u32(exact(4)?)),
+            _ => return Err(ProgramError::InvalidInstructionData),
+        })
     }
 
-    pub fn new_create_account_instruction(
-        txid: [u8; 32],
-        vout: u32,
-        pubkey: Pubkey,
-    ) -> Instruction {
+    /// Wrap the instruction for the system program, signed by `pubkey`.
+    fn into_instruction(self, pubkey: Pubkey) -> Instruction {
         Instruction {
             program_id: Pubkey::system_program(),
             accounts: vec![AccountMeta {
@@ -49,28 +125,49 @@ impl SystemInstruction {
                 is_signer: true,
                 is_writable: true,
             }],
-            data: SystemInstruction::CreateAccount(UtxoMeta::from(txid, vout)).serialise(),
+            data: self.serialise(),
         }
     }
 
+    pub fn new_create_account_instruction(
+        txid: [u8; 32],
+        vout: u32,
+        pubkey: Pubkey,
+    ) -> Instruction {
+        SystemInstruction::CreateAccount(UtxoMeta::from(txid, vout)).into_instruction(pubkey)
+    }
+
+    pub fn new_write_bytes_instruction(offset: u32, data: Vec<u8>, pubkey: Pubkey) -> Instruction {
+        SystemInstruction::WriteBytes { offset, data }.into_instruction(pubkey)
+    }
+
+    /// Send `data` with the tag of [`SystemInstruction::WriteBytes`] as is,
+    /// the caller having put the offset and length in front of it.
+    #[deprecated(note = "use `new_write_bytes_instruction`, which encodes the offset and length")]
+    #[allow(deprecated)]
     pub fn new_extend_bytes_instruction(data: Vec<u8>, pubkey: Pubkey) -> Instruction {
-        Instruction {
-            program_id: Pubkey::system_program(),
-            accounts: vec![AccountMeta {
-                pubkey,
-                is_signer: true,
-                is_writable: true,
-            }],
-            data: SystemInstruction::ExtendBytes(data).serialise(),
-        }
+        SystemInstruction::ExtendBytes(data).into_instruction(pubkey)
+    }
+
+    pub fn new_make_executable_instruction(pubkey: Pubkey) -> Instruction {
+        SystemInstruction::MakeExecutable.into_instruction(pubkey)
+    }
+
+    pub fn new_assign_ownership_instruction(pubkey: Pubkey, owner: Pubkey) -> Instruction {
+        SystemInstruction::AssignOwnership(owner).into_instruction(pubkey)
+    }
+
+    pub fn new_truncate_instruction(len: u32, pubkey: Pubkey) -> Instruction {
+        SystemInstruction::Truncate(len).into_instruction(pubkey)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::utxo::UtxoMeta;
+    use crate::{pubkey::Pubkey, utxo::UtxoMeta};
 
     use super::SystemInstruction;
+    use crate::program_error::ProgramError;
 
     #[test]
     fn test_serialize_and_back() {
@@ -83,13 +180,121 @@ mod tests {
         ));
         assert_eq!(
             system_instruction,
-            SystemInstruction::from_slice(&system_instruction.serialise())
+            SystemInstruction::try_from_slice(&system_instruction.serialise()).unwrap()
         );
 
-        let system_instruction = SystemInstruction::ExtendBytes(vec![0, 4, 5, 5, 8, 9]);
+        let system_instruction = SystemInstruction::WriteBytes {
+            offset: 1024,
+            data: vec![0, 4, 5, 5, 8, 9],
+        };
         assert_eq!(
             system_instruction,
-            SystemInstruction::from_slice(&system_instruction.serialise())
+            SystemInstruction::try_from_slice(&system_instruction.serialise()).unwrap()
+        );
+
+        let system_instruction = SystemInstruction::MakeExecutable;
+        assert_eq!(
+            system_instruction,
+            SystemInstruction::try_from_slice(&system_instruction.serialise()).unwrap()
+        );
+
+        let system_instruction = SystemInstruction::AssignOwnership(Pubkey::from([7; 32]));
+        assert_eq!(
+            system_instruction,
+            SystemInstruction::try_from_slice(&system_instruction.serialise()).unwrap()
+        );
+
+        let system_instruction = SystemInstruction::Truncate(300);
+        assert_eq!(
+            system_instruction,
+            SystemInstruction::try_from_slice(&system_instruction.serialise()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_wire_format() {
+        let owner = Pubkey::from([7; 32]);
+
+        assert_eq!(
+            SystemInstruction::WriteBytes {
+                offset: 2,
+                data: vec![9, 9, 9],
+            }
+            .serialise(),
+            vec![1, 2, 0, 0, 0, 3, 0, 0, 0, 9, 9, 9]
+        );
+        assert_eq!(SystemInstruction::MakeExecutable.serialise(), vec![2]);
+
+        let mut assign = vec![3];
+        assign.extend(owner.serialize());
+        assert_eq!(
+            SystemInstruction::AssignOwnership(owner).serialise(),
+            assign
+        );
+        assert_eq!(
+            SystemInstruction::Truncate(258).serialise(),
+            vec![4, 2, 1, 0, 0]
+        );
+
+        let instruction =
+            SystemInstruction::new_assign_ownership_instruction(Pubkey::from([1; 32]), owner);
+        assert_eq!(instruction.program_id, Pubkey::system_program());
+        assert_eq!(instruction.accounts[0].pubkey, Pubkey::from([1; 32]));
+        assert!(instruction.accounts[0].is_signer && instruction.accounts[0].is_writable);
+        assert_eq!(instruction.data, assign);
+
+        // Clients of `ExtendBytes` sent the same bytes as `WriteBytes`
+        let pubkey = Pubkey::from([1; 32]);
+        #[allow(deprecated)]
+        let extend = SystemInstruction::new_extend_bytes_instruction(
+            vec![2, 0, 0, 0, 3, 0, 0, 0, 9, 9, 9],
+            pubkey,
+        );
+        assert_eq!(
+            extend,
+            SystemInstruction::new_write_bytes_instruction(2, vec![9, 9, 9], pubkey)
+        );
+        assert_eq!(
+            SystemInstruction::try_from_slice(&extend.data),
+            Ok(SystemInstruction::WriteBytes {
+                offset: 2,
+                data: vec![9, 9, 9],
+            })
+        );
+    }
+
+    #[test]
+    fn test_try_from_slice() {
+        for instruction in [
+            SystemInstruction::CreateAccount(UtxoMeta::from([3; 32], 7)),
+            SystemInstruction::WriteBytes {
+                offset: 12,
+                data: vec![1, 2, 3],
+            },
+            SystemInstruction::MakeExecutable,
+            SystemInstruction::AssignOwnership(Pubkey::from([7; 32])),
+            SystemInstruction::Truncate(9),
+        ] {
+            let data = instruction.serialise();
+            assert_eq!(SystemInstruction::try_from_slice(&data), Ok(instruction));
+
+            let mut trailing = data.clone();
+            trailing.push(0);
+            assert_eq!(
+                SystemInstruction::try_from_slice(&trailing),
+                Err(ProgramError::InvalidInstructionData)
+            );
+            for len in 0..data.len() {
+                assert_eq!(
+                    SystemInstruction::try_from_slice(&data[..len]),
+                    Err(ProgramError::InvalidInstructionData)
+                );
+            }
+        }
+
+        assert_eq!(
+            SystemInstruction::try_from_slice(&[5]),
+            Err(ProgramError::InvalidInstructionData)
         );
     }
 }
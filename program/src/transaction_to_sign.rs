@@ -1,5 +1,9 @@
 use crate::input_to_sign::InputToSign;
 
+/// Largest `tx_bytes` [`TransactionToSign::serialise`] can encode, as their
+/// length is written in two little endian bytes
+pub const MAX_TX_BYTES_LEN: usize = u16::MAX as usize;
+
 #[repr(C)]
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct TransactionToSign<'a> {
@@ -11,7 +15,7 @@ impl<'a> TransactionToSign<'a> {
     pub fn serialise(&self) -> Vec<u8> {
         let mut serialized = vec![];
 
-        serialized.push(self.tx_bytes.len() as u8);
+        serialized.extend_from_slice(&(self.tx_bytes.len() as u16).to_le_bytes());
         serialized.extend_from_slice(self.tx_bytes);
         serialized.push(self.inputs_to_sign.len() as u8);
         for input_to_sign in self.inputs_to_sign.iter() {
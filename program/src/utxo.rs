@@ -54,6 +54,41 @@ impl UtxoMeta {
     }
 }
 
+/// The output referenced by a [`UtxoMeta`], as reported by the node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UtxoInfo {
+    /// Value of the output in satoshis
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+    /// Zero while the creating transaction is still in the mempool
+    pub confirmations: u32,
+    pub is_spent: bool,
+}
+
+impl UtxoInfo {
+    /// Returns true if the output is unspent and locked by `script_pubkey`.
+    pub fn pays_to(&self, script_pubkey: &[u8]) -> bool {
+        !self.is_spent && self.script_pubkey == script_pubkey
+    }
+}
+
+#[test]
+fn test_utxo_info_pays_to() {
+    let script_pubkey = [0x51, 0x20, 0xab].to_vec();
+    let mut info = UtxoInfo {
+        value: 3000,
+        script_pubkey: script_pubkey.clone(),
+        confirmations: 1,
+        is_spent: false,
+    };
+
+    assert!(info.pays_to(&script_pubkey));
+    assert!(!info.pays_to(&[0x51, 0x20, 0xac]));
+
+    info.is_spent = true;
+    assert!(!info.pays_to(&script_pubkey));
+}
+
 #[test]
 fn test_outpoint() {
     assert_eq!(
@@ -0,0 +1,106 @@
+use std::slice::Iter;
+
+use arch_program::{
+    account::AccountInfo, context::Accounts, program_error::ProgramError, pubkey::Pubkey,
+    utxo::UtxoMeta, Accounts,
+};
+
+const ORACLE: Pubkey = Pubkey([9; 32]);
+
+#[derive(Accounts)]
+pub struct Transfer<'a, 'b> {
+    #[account(writable, owner = program_id)]
+    pub vault: &'a AccountInfo<'b>,
+    #[account(signer)]
+    pub authority: &'a AccountInfo<'b>,
+    #[account(executable, address = Pubkey::system_program())]
+    pub system_program: &'a AccountInfo<'b>,
+    pub oracle: Oracle<'a, 'b>,
+}
+
+#[derive(Accounts)]
+pub struct Oracle<'a, 'b> {
+    #[account(address = ORACLE, owner = ORACLE)]
+    pub feed: &'a AccountInfo<'b>,
+}
+
+fn try_transfer<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+) -> Result<Transfer<'a, 'b>, ProgramError> {
+    let mut iter: Iter<'a, AccountInfo<'b>> = accounts.iter();
+    Transfer::try_accounts(program_id, &mut iter)
+}
+
+#[test]
+fn test_constraints() {
+    let program_id = Pubkey::from([7; 32]);
+    let keys = [Pubkey::from([1; 32]), Pubkey::from([2; 32])];
+    let system_program = Pubkey::system_program();
+    let utxo = UtxoMeta::from([0; 32], 0);
+    let mut data = [[0u8; 0]; 4];
+    let [vault_data, authority_data, system_data, oracle_data] = &mut data;
+
+    let mut accounts = vec![
+        AccountInfo::new(&keys[0], vault_data, &program_id, &utxo, false, true, false),
+        AccountInfo::new(
+            &keys[1],
+            authority_data,
+            &program_id,
+            &utxo,
+            true,
+            false,
+            false,
+        ),
+        AccountInfo::new(
+            &system_program,
+            system_data,
+            &system_program,
+            &utxo,
+            false,
+            false,
+            true,
+        ),
+        AccountInfo::new(&ORACLE, oracle_data, &ORACLE, &utxo, false, false, false),
+    ];
+
+    let transfer = try_transfer(&program_id, &accounts).unwrap();
+    assert_eq!(transfer.vault.key, &keys[0]);
+    assert_eq!(transfer.oracle.feed.key, &ORACLE);
+
+    assert_eq!(
+        try_transfer(&Pubkey::default(), &accounts).err(),
+        Some(ProgramError::IncorrectProgramId)
+    );
+    assert_eq!(
+        try_transfer(&program_id, &accounts[..3]).err(),
+        Some(ProgramError::NotEnoughAccountKeys)
+    );
+
+    accounts[0].is_writable = false;
+    assert_eq!(
+        try_transfer(&program_id, &accounts).err(),
+        Some(ProgramError::Immutable)
+    );
+    accounts[0].is_writable = true;
+
+    accounts[1].is_signer = false;
+    assert_eq!(
+        try_transfer(&program_id, &accounts).err(),
+        Some(ProgramError::MissingRequiredSignature)
+    );
+    accounts[1].is_signer = true;
+
+    accounts[2].is_executable = false;
+    assert_eq!(
+        try_transfer(&program_id, &accounts).err(),
+        Some(ProgramError::IncorrectProgramId)
+    );
+    accounts[2].is_executable = true;
+
+    accounts[3].key = &keys[1];
+    assert_eq!(
+        try_transfer(&program_id, &accounts).err(),
+        Some(ProgramError::InvalidArgument)
+    );
+}
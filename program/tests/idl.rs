@@ -0,0 +1,150 @@
+use arch_program::{
+    account::{AccountInfo, Discriminator},
+    borsh::{BorshDeserialize, BorshSerialize},
+    context::Context,
+    entrypoint::ProgramResult,
+    idl::{IdlAccount, IdlField, IdlFields, IdlInstruction, IdlType, IdlTypeDefTy, IdlVariant},
+    pubkey::Pubkey,
+    Accounts, IdlBuild,
+};
+
+#[derive(Accounts)]
+pub struct Place<'a, 'b> {
+    #[account(writable, owner = program_id)]
+    pub book: &'a AccountInfo<'b>,
+    #[account(signer)]
+    pub trader: &'a AccountInfo<'b>,
+    pub market: Market<'a, 'b>,
+}
+
+#[derive(Accounts)]
+pub struct Market<'a, 'b> {
+    pub config: &'a AccountInfo<'b>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, IdlBuild)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, IdlBuild)]
+pub struct Order {
+    pub side: Side,
+    pub price: u64,
+    pub owner: Option<Pubkey>,
+}
+
+#[arch_program::program]
+pub mod order_book {
+    use super::*;
+
+    #[arch_program::state]
+    pub struct Book {
+        pub orders: Vec<Order>,
+    }
+
+    #[arch_program::event]
+    pub struct Placed(pub Order);
+
+    pub fn place(_ctx: Context<Place>, order: Order, tags: [u8; 4]) -> ProgramResult {
+        let _ = (order, tags);
+        Ok(())
+    }
+
+    pub fn clear(_ctx: Context<Market>) -> ProgramResult {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_idl() {
+    let idl = idl();
+
+    assert_eq!(idl.name, "order_book");
+    assert_eq!(idl.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(
+        idl.instructions,
+        vec![
+            IdlInstruction {
+                name: "place".to_string(),
+                accounts: vec![
+                    IdlAccount {
+                        name: "book".to_string(),
+                        is_signer: false,
+                        is_writable: true,
+                    },
+                    IdlAccount {
+                        name: "trader".to_string(),
+                        is_signer: true,
+                        is_writable: false,
+                    },
+                    IdlAccount {
+                        name: "market_config".to_string(),
+                        is_signer: false,
+                        is_writable: false,
+                    },
+                ],
+                args: vec![
+                    IdlField::new("order", IdlType::Defined("Order".to_string())),
+                    IdlField::new("tags", IdlType::Array(Box::new(IdlType::U8), 4)),
+                ],
+            },
+            IdlInstruction {
+                name: "clear".to_string(),
+                accounts: vec![IdlAccount {
+                    name: "config".to_string(),
+                    is_signer: false,
+                    is_writable: false,
+                }],
+                args: vec![],
+            },
+        ]
+    );
+
+    assert_eq!(idl.accounts.len(), 1);
+    assert_eq!(idl.accounts[0].name, "Book");
+    assert_eq!(
+        idl.accounts[0].discriminator,
+        Some(order_book::Book::DISCRIMINATOR)
+    );
+    assert_eq!(idl.events.len(), 1);
+    assert_eq!(idl.events[0].name, "Placed");
+    assert_eq!(
+        idl.events[0].ty,
+        IdlTypeDefTy::Struct {
+            fields: IdlFields::Tuple(vec![IdlType::Defined("Order".to_string())]),
+        }
+    );
+
+    let names = idl.types.iter().map(|def| &def.name).collect::<Vec<_>>();
+    assert_eq!(names, ["Order", "Side"]);
+    assert_eq!(
+        idl.types[1].ty,
+        IdlTypeDefTy::Enum {
+            variants: vec![
+                IdlVariant {
+                    name: "Bid".to_string(),
+                    fields: IdlFields::Named(vec![]),
+                },
+                IdlVariant {
+                    name: "Ask".to_string(),
+                    fields: IdlFields::Named(vec![]),
+                },
+            ],
+        }
+    );
+}
+
+#[test]
+fn test_discriminators() {
+    // first 8 bytes of sha256("account:Book") and sha256("event:Placed")
+    assert_ne!(
+        order_book::Book::DISCRIMINATOR,
+        order_book::Placed::DISCRIMINATOR
+    );
+    assert_eq!(
+        order_book::Book::DISCRIMINATOR.to_vec(),
+        hex::decode(&sha256::digest("account:Book")[..16]).unwrap()
+    );
+}
@@ -0,0 +1,123 @@
+use arch_program::{
+    account::AccountInfo, context::Context, entrypoint::ProgramResult, program,
+    program_error::ProgramError, pubkey::Pubkey, utxo::UtxoMeta, Accounts,
+};
+
+#[derive(Accounts)]
+pub struct Increment<'a, 'b> {
+    pub counter: &'a AccountInfo<'b>,
+    pub authority: &'a AccountInfo<'b>,
+}
+
+#[derive(Accounts)]
+pub struct Reset<'a, 'b> {
+    pub counter: &'a AccountInfo<'b>,
+}
+
+#[program]
+pub mod counter {
+    use super::*;
+
+    pub fn increment(ctx: Context<Increment>, amount: u8) -> ProgramResult {
+        let mut data = ctx.accounts.counter.try_borrow_mut_data()?;
+        data[0] = data[0]
+            .checked_add(amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        data[1] = ctx.remaining_accounts.len() as u8;
+        Ok(())
+    }
+
+    pub fn reset(ctx: Context<Reset>) -> ProgramResult {
+        ctx.accounts.counter.try_borrow_mut_data()?.fill(0);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_dispatch_to_handlers() {
+    let program_id = Pubkey::from([7; 32]);
+    let keys = [
+        Pubkey::from([1; 32]),
+        Pubkey::from([2; 32]),
+        Pubkey::from([3; 32]),
+    ];
+    let utxo = UtxoMeta::from([0; 32], 0);
+    let mut counter_data = [0u8; 2];
+    let mut empty = [[0u8; 0]; 2];
+    let [authority_data, extra_data] = &mut empty;
+
+    let accounts = [
+        AccountInfo::new(
+            &keys[0],
+            &mut counter_data,
+            &program_id,
+            &utxo,
+            false,
+            true,
+            false,
+        ),
+        AccountInfo::new(
+            &keys[1],
+            authority_data,
+            &program_id,
+            &utxo,
+            true,
+            false,
+            false,
+        ),
+        AccountInfo::new(
+            &keys[2],
+            extra_data,
+            &program_id,
+            &utxo,
+            false,
+            false,
+            false,
+        ),
+    ];
+
+    let data = borsh::to_vec(&CounterInstruction::Increment { amount: 5 }).unwrap();
+    process_instruction(&program_id, &accounts, &data).unwrap();
+    assert_eq!(*accounts[0].data.borrow(), [5, 1]);
+
+    let data = borsh::to_vec(&CounterInstruction::Reset).unwrap();
+    process_instruction(&program_id, &accounts[..1], &data).unwrap();
+    assert_eq!(*accounts[0].data.borrow(), [0, 0]);
+}
+
+#[test]
+fn test_dispatch_errors() {
+    let program_id = Pubkey::from([7; 32]);
+    let key = Pubkey::from([1; 32]);
+    let utxo = UtxoMeta::from([0; 32], 0);
+    let mut data = [0u8; 2];
+    let accounts = [AccountInfo::new(
+        &key,
+        &mut data,
+        &program_id,
+        &utxo,
+        false,
+        true,
+        false,
+    )];
+
+    assert_eq!(
+        process_instruction(&program_id, &accounts, &[]),
+        Err(ProgramError::InvalidInstructionData)
+    );
+    assert_eq!(
+        process_instruction(&program_id, &accounts, &[9]),
+        Err(ProgramError::InvalidInstructionData)
+    );
+    // Trailing bytes are rejected as well
+    assert_eq!(
+        process_instruction(&program_id, &accounts, &[0, 1, 2]),
+        Err(ProgramError::InvalidInstructionData)
+    );
+
+    let data = borsh::to_vec(&CounterInstruction::Increment { amount: 1 }).unwrap();
+    assert_eq!(
+        process_instruction(&program_id, &accounts, &data),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+}
@@ -0,0 +1,352 @@
+//! Generate a Rust client module from a program [`Idl`].
+//!
+//! The client has the instruction enum of the program, its types and, for
+//! every instruction, an accounts struct and a builder returning the
+//! [`Instruction`](arch_program::instruction::Instruction) with the
+//! `AccountMeta`s the program expects. It is meant to be generated from the
+//! checked in IDL by a build script and brought in with `include!`:
+//!
+//! ```ignore
+//! // build.rs
+//! let client = sdk::codegen::generate_client_from_file("program/idl.json")?;
+//! std::fs::write(Path::new(&env::var("OUT_DIR")?).join("client.rs"), client)?;
+//!
+//! // src/lib.rs
+//! pub mod client {
+//!     include!(concat!(env!("OUT_DIR"), "/client.rs"));
+//! }
+//! ```
+//!
+//! Changing the instructions of the program and regenerating its IDL then
+//! breaks the build of clients using them the old way.
+
+use std::{fmt::Write, fs, path::Path};
+
+use anyhow::{Context, Result};
+use arch_program::idl::{
+    to_camel_case, Idl, IdlFields, IdlInstruction, IdlType, IdlTypeDef, IdlTypeDefTy,
+};
+
+const BORSH_DERIVES: &str = "#[derive(Clone, Debug, PartialEq, arch_program::borsh::BorshSerialize, arch_program::borsh::BorshDeserialize)]\n#[borsh(crate = \"arch_program::borsh\")]\n";
+
+/// Read the JSON IDL at `path` and generate its client.
+pub fn generate_client_from_file(path: impl AsRef<Path>) -> Result<String> {
+    let path = path.as_ref();
+    let json = fs::read_to_string(path)
+        .with_context(|| format!("failed to read IDL {}", path.display()))?;
+    let idl: Idl = serde_json::from_str(&json)
+        .with_context(|| format!("failed to parse IDL {}", path.display()))?;
+
+    Ok(generate_client(&idl))
+}
+
+/// Generate the source of a client module for the program described by `idl`.
+pub fn generate_client(idl: &Idl) -> String {
+    let mut out = String::new();
+    let instruction_enum = format!("{}Instruction", to_camel_case(&idl.name));
+
+    writeln!(
+        out,
+        "// Client of the `{}` program {}, generated from its IDL. Do not edit.\n",
+        idl.name, idl.version
+    )
+    .unwrap();
+
+    writeln!(out, "/// Instruction data of the program.").unwrap();
+    out.push_str(BORSH_DERIVES);
+    writeln!(out, "pub enum {} {{", instruction_enum).unwrap();
+    for instruction in &idl.instructions {
+        let variant = to_camel_case(&instruction.name);
+        if instruction.args.is_empty() {
+            writeln!(out, "    {},", variant).unwrap();
+        } else {
+            writeln!(out, "    {} {{", variant).unwrap();
+            for arg in &instruction.args {
+                writeln!(out, "        {}: {},", arg.name, rust_type(&arg.ty)).unwrap();
+            }
+            writeln!(out, "    }},").unwrap();
+        }
+    }
+    writeln!(out, "}}\n").unwrap();
+
+    for instruction in &idl.instructions {
+        write_instruction(&mut out, &instruction_enum, instruction);
+    }
+
+    for definition in &idl.types {
+        write_type(&mut out, definition);
+    }
+    for definition in &idl.accounts {
+        write_type(&mut out, definition);
+        write_discriminator(&mut out, definition, "account");
+    }
+    for definition in &idl.events {
+        write_type(&mut out, definition);
+        write_discriminator(&mut out, definition, "event");
+    }
+
+    out
+}
+
+fn write_instruction(out: &mut String, instruction_enum: &str, instruction: &IdlInstruction) {
+    let name = &instruction.name;
+    let variant = to_camel_case(name);
+    let accounts = format!("{}Accounts", variant);
+
+    writeln!(out, "/// Accounts of the `{}` instruction.", name).unwrap();
+    writeln!(out, "#[derive(Clone, Debug, PartialEq)]").unwrap();
+    writeln!(out, "pub struct {} {{", accounts).unwrap();
+    for account in &instruction.accounts {
+        writeln!(
+            out,
+            "    pub {}: arch_program::pubkey::Pubkey,",
+            account.name
+        )
+        .unwrap();
+    }
+    writeln!(out, "}}\n").unwrap();
+
+    writeln!(out, "impl {} {{", accounts).unwrap();
+    writeln!(
+        out,
+        "    pub fn to_account_metas(&self) -> Vec<arch_program::account::AccountMeta> {{"
+    )
+    .unwrap();
+    writeln!(out, "        vec![").unwrap();
+    for account in &instruction.accounts {
+        writeln!(
+            out,
+            "            arch_program::account::AccountMeta {{ pubkey: self.{}, is_signer: {}, is_writable: {} }},",
+            account.name, account.is_signer, account.is_writable
+        )
+        .unwrap();
+    }
+    writeln!(out, "        ]").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}\n").unwrap();
+
+    let params = instruction
+        .args
+        .iter()
+        .map(|arg| format!(", {}: {}", arg.name, rust_type(&arg.ty)))
+        .collect::<String>();
+    let data = if instruction.args.is_empty() {
+        format!("{}::{}", instruction_enum, variant)
+    } else {
+        let fields = instruction
+            .args
+            .iter()
+            .map(|arg| arg.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{}::{} {{ {} }}", instruction_enum, variant, fields)
+    };
+
+    writeln!(out, "/// Build a `{}` instruction.", name).unwrap();
+    writeln!(out, "#[allow(clippy::too_many_arguments)]").unwrap();
+    writeln!(
+        out,
+        "pub fn {}(program_id: arch_program::pubkey::Pubkey, accounts: {}{}) -> arch_program::instruction::Instruction {{",
+        name, accounts, params
+    )
+    .unwrap();
+    writeln!(out, "    arch_program::instruction::Instruction {{").unwrap();
+    writeln!(out, "        program_id,").unwrap();
+    writeln!(out, "        accounts: accounts.to_account_metas(),").unwrap();
+    writeln!(
+        out,
+        "        data: arch_program::borsh::to_vec(&{}).expect(\"instruction data should serialize\"),",
+        data
+    )
+    .unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}\n").unwrap();
+}
+
+fn write_type(out: &mut String, definition: &IdlTypeDef) {
+    out.push_str(BORSH_DERIVES);
+    match &definition.ty {
+        IdlTypeDefTy::Struct {
+            fields: IdlFields::Tuple(fields),
+        } => {
+            let fields = fields
+                .iter()
+                .map(|ty| format!("pub {}", rust_type(ty)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(out, "pub struct {}({});\n", definition.name, fields).unwrap();
+        }
+        IdlTypeDefTy::Struct {
+            fields: IdlFields::Named(fields),
+        } => {
+            writeln!(out, "pub struct {} {{", definition.name).unwrap();
+            for field in fields {
+                writeln!(out, "    pub {}: {},", field.name, rust_type(&field.ty)).unwrap();
+            }
+            writeln!(out, "}}\n").unwrap();
+        }
+        IdlTypeDefTy::Enum { variants } => {
+            writeln!(out, "pub enum {} {{", definition.name).unwrap();
+            for variant in variants {
+                match &variant.fields {
+                    IdlFields::Named(fields) if fields.is_empty() => {
+                        writeln!(out, "    {},", variant.name).unwrap();
+                    }
+                    IdlFields::Named(fields) => {
+                        writeln!(out, "    {} {{", variant.name).unwrap();
+                        for field in fields {
+                            writeln!(out, "        {}: {},", field.name, rust_type(&field.ty))
+                                .unwrap();
+                        }
+                        writeln!(out, "    }},").unwrap();
+                    }
+                    IdlFields::Tuple(fields) => {
+                        let fields = fields.iter().map(rust_type).collect::<Vec<_>>().join(", ");
+                        writeln!(out, "    {}({}),", variant.name, fields).unwrap();
+                    }
+                }
+            }
+            writeln!(out, "}}\n").unwrap();
+        }
+    }
+}
+
+/// Implement `Discriminator` and a decoder of the discriminator prefixed
+/// encoding, as stored in accounts or logged for events.
+fn write_discriminator(out: &mut String, definition: &IdlTypeDef, kind: &str) {
+    let name = &definition.name;
+    let discriminator = definition.discriminator.unwrap_or_default();
+
+    writeln!(
+        out,
+        "impl arch_program::account::Discriminator for {} {{",
+        name
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "    const DISCRIMINATOR: [u8; 8] = {:?};",
+        discriminator
+    )
+    .unwrap();
+    writeln!(out, "}}\n").unwrap();
+
+    writeln!(out, "impl {} {{", name).unwrap();
+    writeln!(
+        out,
+        "    /// Decode the {} data of a `{}`, checking its discriminator.",
+        kind, name
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "    pub fn try_from_{}_data(data: &[u8]) -> std::io::Result<Self> {{",
+        kind
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "        if data.len() < 8 || data[..8] != <Self as arch_program::account::Discriminator>::DISCRIMINATOR {{"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, \"discriminator mismatch\"));"
+    )
+    .unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(
+        out,
+        "        <Self as arch_program::borsh::BorshDeserialize>::deserialize(&mut &data[8..])"
+    )
+    .unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}\n").unwrap();
+}
+
+fn rust_type(ty: &IdlType) -> String {
+    match ty {
+        IdlType::Bool => "bool".to_string(),
+        IdlType::U8 => "u8".to_string(),
+        IdlType::U16 => "u16".to_string(),
+        IdlType::U32 => "u32".to_string(),
+        IdlType::U64 => "u64".to_string(),
+        IdlType::U128 => "u128".to_string(),
+        IdlType::I8 => "i8".to_string(),
+        IdlType::I16 => "i16".to_string(),
+        IdlType::I32 => "i32".to_string(),
+        IdlType::I64 => "i64".to_string(),
+        IdlType::I128 => "i128".to_string(),
+        IdlType::String => "String".to_string(),
+        IdlType::Pubkey => "arch_program::pubkey::Pubkey".to_string(),
+        IdlType::Option(ty) => format!("Option<{}>", rust_type(ty)),
+        IdlType::Vec(ty) => format!("Vec<{}>", rust_type(ty)),
+        IdlType::Array(ty, len) => format!("[{}; {}]", rust_type(ty), len),
+        IdlType::Tuple(tys) if tys.len() == 1 => format!("({},)", rust_type(&tys[0])),
+        IdlType::Tuple(tys) => format!(
+            "({})",
+            tys.iter().map(rust_type).collect::<Vec<_>>().join(", ")
+        ),
+        IdlType::Defined(name) => name.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arch_program::idl::{
+        Idl, IdlAccount, IdlField, IdlFields, IdlInstruction, IdlType, IdlTypeDef, IdlTypeDefTy,
+    };
+
+    use super::generate_client;
+
+    #[test]
+    fn test_generate_client() {
+        let mut idl = Idl::new("counter", "0.1.0");
+        idl.instructions.push(IdlInstruction {
+            name: "increment".to_string(),
+            accounts: vec![
+                IdlAccount {
+                    name: "counter".to_string(),
+                    is_signer: false,
+                    is_writable: true,
+                },
+                IdlAccount {
+                    name: "authority".to_string(),
+                    is_signer: true,
+                    is_writable: false,
+                },
+            ],
+            args: vec![IdlField::new(
+                "amount",
+                IdlType::Option(Box::new(IdlType::U64)),
+            )],
+        });
+        idl.instructions.push(IdlInstruction {
+            name: "reset".to_string(),
+            accounts: vec![],
+            args: vec![],
+        });
+        idl.accounts.push(IdlTypeDef {
+            name: "Counter".to_string(),
+            discriminator: Some([1, 2, 3, 4, 5, 6, 7, 8]),
+            ty: IdlTypeDefTy::Struct {
+                fields: IdlFields::Named(vec![IdlField::new("count", IdlType::U64)]),
+            },
+        });
+
+        let client = generate_client(&idl);
+
+        assert!(client.contains("pub enum CounterInstruction {\n    Increment {\n        amount: Option<u64>,\n    },\n    Reset,\n}"));
+        assert!(client.contains("pub struct IncrementAccounts {\n    pub counter: arch_program::pubkey::Pubkey,\n    pub authority: arch_program::pubkey::Pubkey,\n}"));
+        assert!(client
+            .contains("AccountMeta { pubkey: self.counter, is_signer: false, is_writable: true }"));
+        assert!(client.contains(
+            "AccountMeta { pubkey: self.authority, is_signer: true, is_writable: false }"
+        ));
+        assert!(client.contains("pub fn increment(program_id: arch_program::pubkey::Pubkey, accounts: IncrementAccounts, amount: Option<u64>)"));
+        assert!(client.contains("borsh::to_vec(&CounterInstruction::Increment { amount })"));
+        assert!(client.contains("borsh::to_vec(&CounterInstruction::Reset)"));
+        assert!(client.contains("const DISCRIMINATOR: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];"));
+        assert!(client.contains("pub fn try_from_account_data(data: &[u8])"));
+    }
+}
@@ -0,0 +1,540 @@
+//! Checks run on a program ELF before paying for its deployment.
+//!
+//! The validator makes sure the program can be loaded by the runtime: it must
+//! be an SBF shared object exporting `entrypoint`, import only syscalls listed
+//! in [`SYSCALLS`] or [`RUNTIME_BUILTINS`] and use relocations the loader
+//! supports. Stack frames larger than the VM allows are reported as warnings
+//! since the compiler only warns about them and they fail at run time.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use arch_program::syscalls::{RUNTIME_BUILTINS, SYSCALLS};
+use object::elf::{
+    FileHeader64, EM_BPF, SHF_EXECINSTR, SHN_UNDEF, SHT_DYNSYM, SHT_SYMTAB, STT_FUNC,
+};
+use object::read::elf::{FileHeader, SectionHeader, SectionTable, Sym};
+use object::Endianness;
+
+/// Machine of ELFs produced by the SBF toolchain when it isn't `EM_BPF`
+pub const EM_SBF: u16 = 263;
+
+/// Size of the stack frame of a function in the VM
+pub const MAX_STACK_FRAME_SIZE: i64 = 4096;
+
+/// Relocation types the program loader can apply
+pub const SUPPORTED_RELOCATIONS: &[(u32, &str)] = &[
+    (0, "R_BPF_NONE"),
+    (1, "R_BPF_64_64"),
+    (8, "R_BPF_64_RELATIVE"),
+    (10, "R_BPF_64_32"),
+];
+
+const FRAME_POINTER: u8 = 10;
+const LDDW: u8 = 0x18;
+const CLASS_LDX: u8 = 0x01;
+const CLASS_ST: u8 = 0x02;
+const CLASS_STX: u8 = 0x03;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ElfError {
+    NotSbf { machine: u16 },
+    MissingEntrypoint,
+    UnknownSyscall(String),
+    UnsupportedRelocation { offset: u64, r_type: u32 },
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotSbf { machine } => write!(f, "not an SBF program (machine {})", machine),
+            Self::MissingEntrypoint => write!(f, "no `entrypoint` symbol"),
+            Self::UnknownSyscall(name) => write!(f, "imports unknown syscall `{}`", name),
+            Self::UnsupportedRelocation { offset, r_type } => {
+                write!(f, "unsupported relocation type {} at {:#x}", r_type, offset)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ElfWarning {
+    /// A function accesses its stack below the frame size, `depth` bytes
+    /// under the frame pointer
+    StackFrameTooLarge {
+        function: Option<String>,
+        depth: i64,
+    },
+}
+
+impl fmt::Display for ElfWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StackFrameTooLarge { function, depth } => write!(
+                f,
+                "{} uses {} bytes of stack, more than the {} bytes of a frame",
+                function.as_deref().unwrap_or("unknown function"),
+                depth,
+                MAX_STACK_FRAME_SIZE
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ElfReport {
+    pub errors: Vec<ElfError>,
+    pub warnings: Vec<ElfWarning>,
+    /// Syscalls imported by the program
+    pub syscalls: Vec<String>,
+}
+
+impl ElfReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Validate a program ELF, failing only if it can't be parsed at all.
+pub fn validate_program(elf: &[u8]) -> Result<ElfReport> {
+    let header =
+        FileHeader64::<Endianness>::parse(elf).map_err(|e| anyhow!("invalid ELF header: {}", e))?;
+    let endian = header
+        .endian()
+        .map_err(|e| anyhow!("invalid ELF header: {}", e))?;
+    let sections = header
+        .sections(endian, elf)
+        .map_err(|e| anyhow!("invalid ELF sections: {}", e))?;
+
+    let mut report = ElfReport::default();
+
+    let machine = header.e_machine(endian);
+    if machine != EM_BPF && machine != EM_SBF {
+        report.errors.push(ElfError::NotSbf { machine });
+        return Ok(report);
+    }
+
+    check_symbols(&mut report, endian, elf, &sections)?;
+    check_relocations(&mut report, endian, elf, &sections)?;
+    check_stack_frames(&mut report, endian, elf, &sections)?;
+
+    Ok(report)
+}
+
+fn check_symbols(
+    report: &mut ElfReport,
+    endian: Endianness,
+    elf: &[u8],
+    sections: &SectionTable<FileHeader64<Endianness>>,
+) -> Result<()> {
+    let mut has_entrypoint = false;
+
+    for sh_type in [SHT_DYNSYM, SHT_SYMTAB] {
+        let symbols = sections
+            .symbols(endian, elf, sh_type)
+            .map_err(|e| anyhow!("invalid symbol table: {}", e))?;
+
+        for symbol in symbols.iter() {
+            let name = symbols
+                .symbol_name(endian, symbol)
+                .map_err(|e| anyhow!("invalid symbol name: {}", e))?;
+            let name = String::from_utf8_lossy(name);
+            let defined = symbol.st_shndx(endian) != SHN_UNDEF;
+
+            if defined && name == "entrypoint" {
+                has_entrypoint = true;
+            }
+            // the runtime resolves the undefined dynamic symbols as syscalls
+            if !defined && sh_type == SHT_DYNSYM && !name.is_empty() {
+                let known =
+                    SYSCALLS.contains(&name.as_ref()) || RUNTIME_BUILTINS.contains(&name.as_ref());
+                if !known {
+                    report
+                        .errors
+                        .push(ElfError::UnknownSyscall(name.to_string()));
+                }
+                report.syscalls.push(name.to_string());
+            }
+        }
+    }
+
+    if !has_entrypoint {
+        report.errors.push(ElfError::MissingEntrypoint);
+    }
+
+    Ok(())
+}
+
+fn check_relocations(
+    report: &mut ElfReport,
+    endian: Endianness,
+    elf: &[u8],
+    sections: &SectionTable<FileHeader64<Endianness>>,
+) -> Result<()> {
+    for section in sections.iter() {
+        let Some((relocations, _)) = section
+            .rel(endian, elf)
+            .map_err(|e| anyhow!("invalid relocations: {}", e))?
+        else {
+            continue;
+        };
+
+        for relocation in relocations {
+            let r_type = relocation.r_type(endian);
+            if !SUPPORTED_RELOCATIONS.iter().any(|(ty, _)| *ty == r_type) {
+                report.errors.push(ElfError::UnsupportedRelocation {
+                    offset: relocation.r_offset.get(endian),
+                    r_type,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Look for stack accesses through the frame pointer `r10` further than a
+/// frame, reporting the deepest one of each function.
+fn check_stack_frames(
+    report: &mut ElfReport,
+    endian: Endianness,
+    elf: &[u8],
+    sections: &SectionTable<FileHeader64<Endianness>>,
+) -> Result<()> {
+    let symbols = sections
+        .symbols(endian, elf, SHT_SYMTAB)
+        .map_err(|e| anyhow!("invalid symbol table: {}", e))?;
+    let functions = symbols
+        .iter()
+        .filter(|symbol| symbol.st_type() == STT_FUNC && symbol.st_size(endian) > 0)
+        .filter_map(|symbol| {
+            let name = symbols.symbol_name(endian, symbol).ok()?;
+            let start = symbol.st_value(endian);
+            Some((
+                start..start + symbol.st_size(endian),
+                String::from_utf8_lossy(name).to_string(),
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    let mut deepest = BTreeMap::<Option<String>, i64>::new();
+
+    for section in sections.iter() {
+        if section.sh_flags(endian) & u64::from(SHF_EXECINSTR) == 0 {
+            continue;
+        }
+        let text = section
+            .data(endian, elf)
+            .map_err(|e| anyhow!("invalid code section: {}", e))?;
+        let address = section.sh_addr(endian);
+
+        let mut pc = 0;
+        while pc + 8 <= text.len() {
+            let opcode = text[pc];
+            let dst = text[pc + 1] & 0x0f;
+            let src = text[pc + 1] >> 4;
+            let offset = i16::from_le_bytes([text[pc + 2], text[pc + 3]]) as i64;
+
+            let base = match opcode & 0x07 {
+                CLASS_LDX => src,
+                CLASS_ST | CLASS_STX => dst,
+                _ => 0,
+            };
+            if opcode != LDDW && base == FRAME_POINTER && -offset > MAX_STACK_FRAME_SIZE {
+                let at = address + pc as u64;
+                let function = functions
+                    .iter()
+                    .find(|(range, _)| range.contains(&at))
+                    .map(|(_, name)| name.clone());
+                let depth = deepest.entry(function).or_default();
+                *depth = (*depth).max(-offset);
+            }
+
+            pc += if opcode == LDDW { 16 } else { 8 };
+        }
+    }
+
+    report.warnings.extend(
+        deepest
+            .into_iter()
+            .map(|(function, depth)| ElfWarning::StackFrameTooLarge { function, depth }),
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use object::elf::{EM_BPF, EM_X86_64, SHF_ALLOC, SHF_EXECINSTR, STB_GLOBAL, STT_FUNC};
+
+    use super::{validate_program, ElfError, ElfWarning};
+
+    struct Section {
+        name: &'static str,
+        sh_type: u32,
+        flags: u64,
+        link: u32,
+        entsize: u64,
+        data: Vec<u8>,
+    }
+
+    /// Build a minimal ELF64 with `.text`, `.dynsym`/`.dynstr`, `.rel.dyn`
+    /// and `.symtab`/`.strtab` sections.
+    fn build_elf(
+        machine: u16,
+        text: &[u8],
+        exports: &[&str],
+        imports: &[&str],
+        relocations: &[u32],
+    ) -> Vec<u8> {
+        fn symbol(name: u32, info: u8, shndx: u16, value: u64, size: u64) -> Vec<u8> {
+            let mut sym = vec![];
+            sym.extend(name.to_le_bytes());
+            sym.push(info);
+            sym.push(0);
+            sym.extend(shndx.to_le_bytes());
+            sym.extend(value.to_le_bytes());
+            sym.extend(size.to_le_bytes());
+            sym
+        }
+
+        let mut dynstr = vec![0];
+        let mut dynsym = vec![0; 24];
+        for (i, name) in exports.iter().chain(imports).enumerate() {
+            let shndx = if i < exports.len() { 1 } else { 0 };
+            dynsym.extend(symbol(
+                dynstr.len() as u32,
+                (STB_GLOBAL << 4) | STT_FUNC,
+                shndx,
+                0,
+                0,
+            ));
+            dynstr.extend(name.as_bytes());
+            dynstr.push(0);
+        }
+
+        let mut rel = vec![];
+        for r_type in relocations {
+            rel.extend(0u64.to_le_bytes());
+            rel.extend(
+                (u64::from(exports.len() as u32 + 1) << 32 | u64::from(*r_type)).to_le_bytes(),
+            );
+        }
+
+        let strtab = b"\0big_frame\0".to_vec();
+        let mut symtab = vec![0; 24];
+        symtab.extend(symbol(
+            1,
+            (STB_GLOBAL << 4) | STT_FUNC,
+            1,
+            0,
+            text.len() as u64,
+        ));
+
+        let mut sections = vec![
+            Section {
+                name: ".text",
+                sh_type: 1,
+                flags: u64::from(SHF_ALLOC | SHF_EXECINSTR),
+                link: 0,
+                entsize: 0,
+                data: text.to_vec(),
+            },
+            Section {
+                name: ".dynsym",
+                sh_type: 11,
+                flags: u64::from(SHF_ALLOC),
+                link: 4,
+                entsize: 24,
+                data: dynsym,
+            },
+            Section {
+                name: ".rel.dyn",
+                sh_type: 9,
+                flags: u64::from(SHF_ALLOC),
+                link: 2,
+                entsize: 16,
+                data: rel,
+            },
+            Section {
+                name: ".dynstr",
+                sh_type: 3,
+                flags: u64::from(SHF_ALLOC),
+                link: 0,
+                entsize: 0,
+                data: dynstr,
+            },
+            Section {
+                name: ".symtab",
+                sh_type: 2,
+                flags: 0,
+                link: 6,
+                entsize: 24,
+                data: symtab,
+            },
+            Section {
+                name: ".strtab",
+                sh_type: 3,
+                flags: 0,
+                link: 0,
+                entsize: 0,
+                data: strtab,
+            },
+        ];
+
+        let mut shstrtab = vec![0];
+        let mut names = vec![];
+        for section in &sections {
+            names.push(shstrtab.len() as u32);
+            shstrtab.extend(section.name.as_bytes());
+            shstrtab.push(0);
+        }
+        names.push(shstrtab.len() as u32);
+        shstrtab.extend(b".shstrtab\0");
+        sections.push(Section {
+            name: ".shstrtab",
+            sh_type: 3,
+            flags: 0,
+            link: 0,
+            entsize: 0,
+            data: shstrtab,
+        });
+
+        let mut body = vec![];
+        let mut offsets = vec![];
+        for section in &sections {
+            while body.len() % 8 != 0 {
+                body.push(0);
+            }
+            offsets.push(64 + body.len() as u64);
+            body.extend(&section.data);
+        }
+        while body.len() % 8 != 0 {
+            body.push(0);
+        }
+        let shoff = 64 + body.len() as u64;
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        elf.extend(3u16.to_le_bytes()); // ET_DYN
+        elf.extend(machine.to_le_bytes());
+        elf.extend(1u32.to_le_bytes());
+        elf.extend(0u64.to_le_bytes()); // entry
+        elf.extend(0u64.to_le_bytes()); // phoff
+        elf.extend(shoff.to_le_bytes());
+        elf.extend(0u32.to_le_bytes()); // flags
+        elf.extend(64u16.to_le_bytes());
+        elf.extend(56u16.to_le_bytes());
+        elf.extend(0u16.to_le_bytes());
+        elf.extend(64u16.to_le_bytes());
+        elf.extend((sections.len() as u16 + 1).to_le_bytes());
+        elf.extend((sections.len() as u16).to_le_bytes());
+        elf.extend(body);
+
+        elf.extend([0; 64]);
+        for (i, section) in sections.iter().enumerate() {
+            elf.extend(names[i].to_le_bytes());
+            elf.extend(section.sh_type.to_le_bytes());
+            elf.extend(section.flags.to_le_bytes());
+            elf.extend(0u64.to_le_bytes()); // addr
+            elf.extend(offsets[i].to_le_bytes());
+            elf.extend((section.data.len() as u64).to_le_bytes());
+            elf.extend(section.link.to_le_bytes());
+            elf.extend(0u32.to_le_bytes()); // info
+            elf.extend(8u64.to_le_bytes());
+            elf.extend(section.entsize.to_le_bytes());
+        }
+
+        elf
+    }
+
+    /// `stxdw [r10 + offset], r1` followed by `exit`
+    fn store_to_stack(offset: i16) -> Vec<u8> {
+        let mut text = vec![0x7b, 0x1a];
+        text.extend(offset.to_le_bytes());
+        text.extend([0; 4]);
+        text.extend([0x95, 0, 0, 0, 0, 0, 0, 0]);
+        text
+    }
+
+    #[test]
+    fn test_valid_program() {
+        let elf = build_elf(
+            EM_BPF,
+            &store_to_stack(-8),
+            &["entrypoint"],
+            &["sol_log_", "arch_set_transaction_to_sign", "sol_memcpy_"],
+            &[10, 8],
+        );
+        let report = validate_program(&elf).unwrap();
+
+        assert_eq!(report.errors, vec![]);
+        assert_eq!(report.warnings, vec![]);
+        assert_eq!(
+            report.syscalls,
+            vec![
+                "sol_log_".to_string(),
+                "arch_set_transaction_to_sign".to_string(),
+                "sol_memcpy_".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_program() {
+        let elf = build_elf(
+            EM_BPF,
+            &store_to_stack(-4104),
+            &["process"],
+            &["sol_log_", "sol_alloc_free_"],
+            &[10, 3],
+        );
+        let report = validate_program(&elf).unwrap();
+
+        assert!(!report.is_valid());
+        assert_eq!(
+            report.errors,
+            vec![
+                ElfError::UnknownSyscall("sol_alloc_free_".to_string()),
+                ElfError::MissingEntrypoint,
+                ElfError::UnsupportedRelocation {
+                    offset: 0,
+                    r_type: 3
+                },
+            ]
+        );
+        assert_eq!(
+            report.warnings,
+            vec![ElfWarning::StackFrameTooLarge {
+                function: Some("big_frame".to_string()),
+                depth: 4104
+            }]
+        );
+    }
+
+    #[test]
+    fn test_not_sbf() {
+        let elf = build_elf(EM_X86_64, &[], &["entrypoint"], &[], &[]);
+        assert_eq!(
+            validate_program(&elf).unwrap().errors,
+            vec![ElfError::NotSbf { machine: EM_X86_64 }]
+        );
+        assert!(validate_program(b"not an elf").is_err());
+    }
+
+    /// Validates the helloworld example built with `cargo build-sbf`, whose
+    /// imports come from the real toolchain rather than a hand-made ELF.
+    #[test]
+    #[ignore = "needs `cargo build-sbf` in examples/helloworld/program"]
+    fn test_helloworld_build() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../examples/helloworld/program/target/deploy/helloworldprogram.so"
+        );
+        let elf = std::fs::read(path).expect("helloworld should be built with `cargo build-sbf`");
+        let report = validate_program(&elf).unwrap();
+
+        assert_eq!(report.errors, vec![]);
+        assert!(report.syscalls.contains(&"sol_log_".to_string()));
+    }
+}
@@ -1,5 +1,7 @@
 pub use arch_program;
 
+pub mod codegen;
+pub mod elf;
 pub mod processed_transaction;
 pub mod runtime_transaction;
 pub mod signature;
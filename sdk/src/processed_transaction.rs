@@ -1,20 +1,109 @@
-use anyhow::Result;
+//! Transactions as reported by the nodes once executed.
+//!
+//! # Binary format
+//!
+//! [`ProcessedTransaction::to_vec`] writes version 1 of the format below,
+//! with integers in little endian. [`ProcessedTransaction::from_vec`] reads it
+//! as well as the unversioned format written before, whose first byte is the
+//! status and so never has the `0x80` bit set.
+//!
+//! | Field | Encoding |
+//! |---|---|
+//! | header | `u8`, `0x80 \| version` |
+//! | status | `u8`: 0 processing, 1 processed, 2 failed |
+//! | error | only when failed, `u64` length and the Borsh encoding of the [`TransactionError`] |
+//! | runtime transaction | `u64` length and [`RuntimeTransaction::serialize`] |
+//! | bitcoin txids | `u64` count and 32 bytes for each |
+//! | logs | `u64` count and for each a `u64` length and UTF-8 bytes |
+//! | compute units consumed | `u64` |
+//! | return data | `u8` 0 when absent, or 1 followed by the 32 byte program id, a `u64` length and the data |
+//!
+//! The unversioned format only holds the status, 0 or 1, the runtime
+//! transaction and the bitcoin txids, in the same encodings.
+//!
+//! Decoding fails on unknown versions or statuses, truncated data and trailing
+//! bytes. New variants of [`TransactionError`] and the errors it wraps must be
+//! appended so the Borsh encoding of existing ones doesn't change, and any
+//! other change to the layout needs a new version.
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use arch_program::instruction::InstructionError;
+use arch_program::program_error::ProgramError;
+use arch_program::pubkey::Pubkey;
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 
 use crate::runtime_transaction::RuntimeTransaction;
 
-#[derive(Clone, Debug, Deserialize, Serialize, BorshDeserialize, BorshSerialize)]
+/// Why a transaction failed.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, BorshDeserialize, BorshSerialize)]
+pub enum TransactionError {
+    /// Returned by a program
+    Program(ProgramError),
+    /// Raised by the runtime while executing an instruction
+    Instruction(InstructionError),
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Program(error) => write!(f, "{}", error),
+            Self::Instruction(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+impl From<ProgramError> for TransactionError {
+    fn from(error: ProgramError) -> Self {
+        Self::Program(error)
+    }
+}
+
+impl From<InstructionError> for TransactionError {
+    fn from(error: InstructionError) -> Self {
+        Self::Instruction(error)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, BorshDeserialize, BorshSerialize)]
 pub enum Status {
     Processing,
     Processed,
+    Failed(TransactionError),
+}
+
+impl Status {
+    fn tag(&self) -> u8 {
+        match self {
+            Self::Processing => 0,
+            Self::Processed => 1,
+            Self::Failed(_) => 2,
+        }
+    }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
+/// Data set by the last program calling `set_return_data`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, BorshDeserialize, BorshSerialize)]
+pub struct ReturnData {
+    pub program_id: Pubkey,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
 pub struct ProcessedTransaction {
     pub runtime_transaction: RuntimeTransaction,
     pub status: Status,
     pub bitcoin_txids: Vec<String>,
+    /// Messages logged by the programs, in order
+    #[serde(default)]
+    pub logs: Vec<String>,
+    #[serde(default)]
+    pub compute_units_consumed: u64,
+    #[serde(default)]
+    pub return_data: Option<ReturnData>,
 }
 
 impl ProcessedTransaction {
@@ -22,45 +111,589 @@ impl ProcessedTransaction {
         self.runtime_transaction.txid()
     }
 
+    /// The error the transaction failed with, if it did.
+    pub fn error(&self) -> Option<&TransactionError> {
+        match &self.status {
+            Status::Failed(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Encodes the transaction in the current version of the
+    /// [binary format](self#binary-format).
     pub fn to_vec(&self) -> Result<Vec<u8>> {
-        let mut serialized = vec![];
+        let mut writer = Writer::default();
 
-        serialized.push(self.status.clone() as u8);
-        serialized.extend((self.runtime_transaction.serialize().len() as u64).to_le_bytes());
-        serialized.extend(self.runtime_transaction.serialize());
+        writer.write_u8(VERSION_FLAG | FORMAT_VERSION);
+        writer.write_u8(self.status.tag());
+        if let Status::Failed(error) = &self.status {
+            writer.write_bytes(&borsh::to_vec(error)?);
+        }
+        writer.write_runtime_transaction(&self.runtime_transaction)?;
 
-        serialized.extend((self.bitcoin_txids.len() as u64).to_le_bytes());
+        writer.write_len(self.bitcoin_txids.len());
         for bitcoin_txid in &self.bitcoin_txids {
-            serialized.extend(hex::decode(bitcoin_txid)?);
+            let bitcoin_txid = hex::decode(bitcoin_txid)?;
+            if bitcoin_txid.len() != 32 {
+                return Err(anyhow!("bitcoin txid should be 32 bytes"));
+            }
+            writer.0.extend(bitcoin_txid);
+        }
+
+        writer.write_len(self.logs.len());
+        for log in &self.logs {
+            writer.write_bytes(log.as_bytes());
+        }
+
+        writer.write_u64(self.compute_units_consumed);
+
+        match &self.return_data {
+            None => writer.write_u8(0),
+            Some(return_data) => {
+                writer.write_u8(1);
+                writer.0.extend(return_data.program_id.serialize());
+                writer.write_bytes(&return_data.data);
+            }
         }
-        Ok(serialized)
+        Ok(writer.0)
     }
 
+    /// Decodes a transaction encoded in any version of the
+    /// [binary format](self#binary-format).
     pub fn from_vec(data: &[u8]) -> Result<Self> {
-        let status = match data[0] {
-            0 => Status::Processing,
-            1 => Status::Processed,
-            _ => unreachable!("status doesn't exist"),
+        let mut reader = Reader::new(data);
+
+        let header = *data
+            .first()
+            .ok_or_else(|| anyhow!("processed transaction is empty"))?;
+        let processed_tx = match header {
+            header if header & VERSION_FLAG == 0 => reader.read_unversioned()?,
+            header => match header & !VERSION_FLAG {
+                FORMAT_VERSION => {
+                    reader.read_u8()?;
+                    reader.read_v1()?
+                }
+                version => {
+                    return Err(anyhow!(
+                        "unknown processed transaction format version {}",
+                        version
+                    ))
+                }
+            },
         };
-        let runtime_transaction_len = u64::from_le_bytes(data[1..9].try_into().unwrap()) as usize;
-        let mut size = 9;
-        let runtime_transaction =
-            RuntimeTransaction::from_slice(&data[9..(9 + runtime_transaction_len)])?;
-        size += runtime_transaction_len;
 
-        let bitcoin_txids_len =
-            u64::from_le_bytes(data[size..(size + 8)].try_into().unwrap()) as usize;
-        size += 8;
-        let mut bitcoin_txids = vec![];
-        for _ in 0..bitcoin_txids_len {
-            bitcoin_txids.push(hex::encode(&data[(size + 32)..(size + 64)]));
-            size += 64;
+        if reader.offset != data.len() {
+            return Err(anyhow!(
+                "{} trailing bytes after processed transaction",
+                data.len() - reader.offset
+            ));
         }
+        Ok(processed_tx)
+    }
+}
+
+/// Set in the first byte of versioned encodings, which unversioned ones
+/// start with a status
+const VERSION_FLAG: u8 = 0x80;
+
+/// Version of the binary format written by [`ProcessedTransaction::to_vec`]
+pub const FORMAT_VERSION: u8 = 1;
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn write_u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    fn write_len(&mut self, len: usize) {
+        self.write_u64(len as u64);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_len(bytes.len());
+        self.0.extend(bytes);
+    }
+
+    /// Checks the counts fit [`RuntimeTransaction::serialize`] before writing it.
+    fn write_runtime_transaction(
+        &mut self,
+        runtime_transaction: &RuntimeTransaction,
+    ) -> Result<()> {
+        let message = &runtime_transaction.message;
+        if runtime_transaction.signatures.len() > u8::MAX as usize
+            || message.signers.len() > u8::MAX as usize
+            || message.instructions.len() > u8::MAX as usize
+            || message
+                .instructions
+                .iter()
+                .any(|instruction| instruction.accounts.len() > u8::MAX as usize)
+        {
+            return Err(anyhow!(
+                "runtime transaction has more than 255 signatures, signers, instructions or accounts"
+            ));
+        }
+        if runtime_transaction
+            .signatures
+            .iter()
+            .any(|signature| signature.0.len() != 64)
+        {
+            return Err(anyhow!("signatures should be 64 bytes"));
+        }
+
+        self.write_bytes(&runtime_transaction.serialize());
+        Ok(())
+    }
+}
+
+/// Reads the fields of serialized transactions in order, failing instead of
+/// panicking on malformed data.
+pub(crate) struct Reader<'a> {
+    pub(crate) data: &'a [u8],
+    pub(crate) offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    pub(crate) fn read(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| anyhow!("transaction data is truncated"))?;
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read(1)?[0])
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.read(8)?.try_into()?))
+    }
+
+    fn read_len(&mut self) -> Result<usize> {
+        Ok(usize::try_from(self.read_u64()?)?)
+    }
+
+    pub(crate) fn read_bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.read_len()?;
+        self.read(len)
+    }
+
+    pub(crate) fn read_pubkey(&mut self) -> Result<Pubkey> {
+        Ok(Pubkey::from_slice(self.read(32)?))
+    }
+
+    fn read_status(&mut self) -> Result<Status> {
+        match self.read_u8()? {
+            0 => Ok(Status::Processing),
+            1 => Ok(Status::Processed),
+            2 => Ok(Status::Failed(borsh::from_slice(self.read_bytes()?)?)),
+            status => Err(anyhow!("unknown transaction status {}", status)),
+        }
+    }
+
+    fn read_bitcoin_txids(&mut self) -> Result<Vec<String>> {
+        let len = self.read_len()?;
+        (0..len).map(|_| Ok(hex::encode(self.read(32)?))).collect()
+    }
+
+    /// Reads a length prefixed [`RuntimeTransaction::serialize`].
+    fn read_runtime_transaction(&mut self) -> Result<RuntimeTransaction> {
+        RuntimeTransaction::try_from_slice(self.read_bytes()?)
+    }
+
+    fn read_unversioned(&mut self) -> Result<ProcessedTransaction> {
+        let status = match self.read_status()? {
+            Status::Failed(_) => return Err(anyhow!("unknown transaction status 2")),
+            status => status,
+        };
+        Ok(ProcessedTransaction {
+            status,
+            runtime_transaction: self.read_runtime_transaction()?,
+            bitcoin_txids: self.read_bitcoin_txids()?,
+            logs: vec![],
+            compute_units_consumed: 0,
+            return_data: None,
+        })
+    }
+
+    fn read_v1(&mut self) -> Result<ProcessedTransaction> {
+        let status = self.read_status()?;
+        let runtime_transaction = self.read_runtime_transaction()?;
+        let bitcoin_txids = self.read_bitcoin_txids()?;
+
+        let logs = (0..self.read_len()?)
+            .map(|_| Ok(String::from_utf8(self.read_bytes()?.to_vec())?))
+            .collect::<Result<Vec<_>>>()?;
+
+        let compute_units_consumed = self.read_u64()?;
+
+        let return_data = match self.read_u8()? {
+            0 => None,
+            1 => Some(ReturnData {
+                program_id: self.read_pubkey()?,
+                data: self.read_bytes()?.to_vec(),
+            }),
+            flag => return Err(anyhow!("invalid return data flag {}", flag)),
+        };
 
         Ok(ProcessedTransaction {
             runtime_transaction,
             status,
             bitcoin_txids,
+            logs,
+            compute_units_consumed,
+            return_data,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::Signature;
+    use arch_program::account::AccountMeta;
+    use arch_program::instruction::Instruction;
+    use arch_program::message::Message;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+
+    fn processed_transaction(status: Status) -> ProcessedTransaction {
+        ProcessedTransaction {
+            runtime_transaction: RuntimeTransaction {
+                version: 1,
+                signatures: vec![Signature(vec![0x11; 64])],
+                message: Message {
+                    signers: vec![Pubkey::from_slice(&[0x22; 32])],
+                    instructions: vec![Instruction {
+                        program_id: Pubkey::from_slice(&[0x33; 32]),
+                        accounts: vec![AccountMeta {
+                            pubkey: Pubkey::from_slice(&[0x44; 32]),
+                            is_signer: true,
+                            is_writable: false,
+                        }],
+                        data: vec![1, 2, 3],
+                    }],
+                },
+            },
+            status,
+            bitcoin_txids: vec![hex::encode([0x55; 32])],
+            logs: vec!["hello".to_string()],
+            compute_units_consumed: 1_000,
+            return_data: Some(ReturnData {
+                program_id: Pubkey::from_slice(&[0x66; 32]),
+                data: vec![0xbe, 0xef],
+            }),
+        }
+    }
+
+    /// Encoding of the runtime transaction of [`processed_transaction`]
+    fn golden_runtime_transaction() -> String {
+        [
+            "b500000000000000", // length, 181 bytes
+            "01000000",         // version
+            "01",               // signature count
+            &"11".repeat(64),
+            "01", // signer count
+            &"22".repeat(32),
+            "01", // instruction count
+            &"33".repeat(32),
+            "01", // account count
+            &"44".repeat(32),
+            "01",               // is_signer
+            "00",               // is_writable
+            "0300000000000000", // data length
+            "010203",
+        ]
+        .concat()
+    }
+
+    /// Version 1 encoding of [`processed_transaction`] with the given status
+    /// and error fields
+    fn golden_v1(status: &[&str]) -> String {
+        [
+            &["81"], // version 1
+            status,
+            &[
+                &golden_runtime_transaction(),
+                "0100000000000000", // bitcoin txid count
+                &"55".repeat(32),
+                "0100000000000000", // log count
+                "0500000000000000", // log length
+                "68656c6c6f",       // hello
+                "e803000000000000", // compute units consumed
+                "01",               // return data
+                &"66".repeat(32),
+                "0200000000000000", // return data length
+                "beef",
+            ],
+        ]
+        .concat()
+        .concat()
+    }
+
+    fn golden_unversioned() -> String {
+        [
+            "01", // processed
+            &golden_runtime_transaction(),
+            "0100000000000000", // bitcoin txid count
+            &"55".repeat(32),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_golden_vectors() {
+        for (status, golden) in [
+            (Status::Processing, golden_v1(&["00"])),
+            (Status::Processed, golden_v1(&["01"])),
+            (
+                Status::Failed(ProgramError::Custom(42).into()),
+                golden_v1(&[
+                    "02",               // failed
+                    "0600000000000000", // error length
+                    "00",               // TransactionError::Program
+                    "00",               // ProgramError::Custom
+                    "2a000000",         // 42
+                ]),
+            ),
+        ] {
+            let processed_tx = processed_transaction(status);
+            assert_eq!(hex::encode(processed_tx.to_vec().unwrap()), golden);
+            assert_eq!(
+                ProcessedTransaction::from_vec(&hex::decode(golden).unwrap()).unwrap(),
+                processed_tx
+            );
+        }
+
+        let processed = ProcessedTransaction {
+            logs: vec![],
+            compute_units_consumed: 0,
+            return_data: None,
+            ..processed_transaction(Status::Processed)
+        };
+        assert_eq!(
+            ProcessedTransaction::from_vec(&hex::decode(golden_unversioned()).unwrap()).unwrap(),
+            processed
+        );
+    }
+
+    #[test]
+    fn test_rejects_malformed_data() {
+        let encoded = processed_transaction(Status::Processed).to_vec().unwrap();
+        for len in 0..encoded.len() {
+            assert!(ProcessedTransaction::from_vec(&encoded[..len]).is_err());
+        }
+
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(ProcessedTransaction::from_vec(&trailing).is_err());
+
+        let mut unknown_version = encoded.clone();
+        unknown_version[0] = VERSION_FLAG | (FORMAT_VERSION + 1);
+        assert_eq!(
+            ProcessedTransaction::from_vec(&unknown_version)
+                .unwrap_err()
+                .to_string(),
+            "unknown processed transaction format version 2"
+        );
+
+        let mut unknown_status = encoded;
+        unknown_status[1] = 3;
+        assert!(ProcessedTransaction::from_vec(&unknown_status).is_err());
+
+        let mut unversioned_failed = hex::decode(golden_unversioned()).unwrap();
+        unversioned_failed[0] = 2;
+        assert!(ProcessedTransaction::from_vec(&unversioned_failed).is_err());
+    }
+
+    #[test]
+    fn test_rejects_unencodable_transactions() {
+        let mut processed_tx = processed_transaction(Status::Processed);
+        processed_tx.bitcoin_txids.push("abcd".to_string());
+        assert!(processed_tx.to_vec().is_err());
+
+        let mut processed_tx = processed_transaction(Status::Processed);
+        processed_tx.runtime_transaction.signatures = vec![Signature(vec![0; 64]); 256];
+        assert!(processed_tx.to_vec().is_err());
+
+        let mut processed_tx = processed_transaction(Status::Processed);
+        processed_tx.runtime_transaction.signatures = vec![Signature(vec![0; 63])];
+        assert!(processed_tx.to_vec().is_err());
+    }
+
+    #[test]
+    fn test_json_without_execution_details() {
+        let mut json = serde_json::to_value(processed_transaction(Status::Processed)).unwrap();
+        for field in ["logs", "compute_units_consumed", "return_data"] {
+            json.as_object_mut().unwrap().remove(field);
+        }
+
+        let decoded: ProcessedTransaction = serde_json::from_value(json).unwrap();
+        assert!(decoded.logs.is_empty());
+        assert_eq!(decoded.compute_units_consumed, 0);
+        assert_eq!(decoded.return_data, None);
+    }
+
+    fn pubkey() -> impl Strategy<Value = Pubkey> {
+        any::<[u8; 32]>().prop_map(|bytes| Pubkey::from_slice(&bytes))
+    }
+
+    fn instruction() -> impl Strategy<Value = Instruction> {
+        let account = (pubkey(), any::<bool>(), any::<bool>()).prop_map(
+            |(pubkey, is_signer, is_writable)| AccountMeta {
+                pubkey,
+                is_signer,
+                is_writable,
+            },
+        );
+        (pubkey(), vec(account, 0..5), vec(any::<u8>(), 0..=300)).prop_map(
+            |(program_id, accounts, data)| Instruction {
+                program_id,
+                accounts,
+                data,
+            },
+        )
+    }
+
+    fn runtime_transaction() -> impl Strategy<Value = RuntimeTransaction> {
+        (
+            any::<u32>(),
+            vec(vec(any::<u8>(), 64).prop_map(Signature), 0..4),
+            vec(pubkey(), 0..4),
+            vec(instruction(), 0..4),
+        )
+            .prop_map(
+                |(version, signatures, signers, instructions)| RuntimeTransaction {
+                    version,
+                    signatures,
+                    message: Message {
+                        signers,
+                        instructions,
+                    },
+                },
+            )
+    }
+
+    impl Arbitrary for TransactionError {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            prop_oneof![
+                any::<u32>().prop_map(|code| ProgramError::Custom(code).into()),
+                (1..=26_u64).prop_map(|code| ProgramError::from(code << 32).into()),
+                any::<u32>().prop_map(|code| InstructionError::Custom(code).into()),
+                ".{0,20}".prop_map(|message| InstructionError::BorshIoError(message).into()),
+            ]
+            .boxed()
+        }
+    }
+
+    impl Arbitrary for Status {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            prop_oneof![
+                Just(Status::Processing),
+                Just(Status::Processed),
+                any::<TransactionError>().prop_map(Status::Failed),
+            ]
+            .boxed()
+        }
+    }
+
+    impl Arbitrary for ProcessedTransaction {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            let return_data = (pubkey(), vec(any::<u8>(), 0..=64))
+                .prop_map(|(program_id, data)| ReturnData { program_id, data });
+            (
+                runtime_transaction(),
+                any::<Status>(),
+                vec(any::<[u8; 32]>().prop_map(hex::encode), 0..4),
+                vec(".{0,20}", 0..4),
+                any::<u64>(),
+                proptest::option::of(return_data),
+            )
+                .prop_map(
+                    |(
+                        runtime_transaction,
+                        status,
+                        bitcoin_txids,
+                        logs,
+                        compute_units_consumed,
+                        return_data,
+                    )| ProcessedTransaction {
+                        runtime_transaction,
+                        status,
+                        bitcoin_txids,
+                        logs,
+                        compute_units_consumed,
+                        return_data,
+                    },
+                )
+                .boxed()
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(processed_tx in any::<ProcessedTransaction>()) {
+            let encoded = processed_tx.to_vec().unwrap();
+            prop_assert_eq!(
+                ProcessedTransaction::from_vec(&encoded).unwrap(),
+                processed_tx.clone()
+            );
+
+            // The lenient and strict decoders agree on valid transactions
+            let runtime_tx = &processed_tx.runtime_transaction;
+            let mut encoded = runtime_tx.serialize();
+            prop_assert_eq!(&RuntimeTransaction::from_slice(&encoded).unwrap(), runtime_tx);
+            prop_assert_eq!(&RuntimeTransaction::try_from_slice(&encoded).unwrap(), runtime_tx);
+            encoded.push(0);
+            prop_assert!(RuntimeTransaction::try_from_slice(&encoded).is_err());
+
+            let json = serde_json::to_string(&processed_tx).unwrap();
+            prop_assert_eq!(
+                serde_json::from_str::<ProcessedTransaction>(&json).unwrap(),
+                processed_tx
+            );
+        }
+
+        #[test]
+        fn test_corrupted_transactions_dont_panic(
+            processed_tx in any::<ProcessedTransaction>(),
+            corruptions in vec((any::<Index>(), any::<u8>()), 1..4),
+            len in any::<Index>(),
+        ) {
+            let mut encoded = processed_tx.to_vec().unwrap();
+            for (i, byte) in corruptions {
+                let i = i.index(encoded.len());
+                encoded[i] = byte;
+            }
+            let len = len.index(encoded.len() + 1);
+
+            if let Ok(processed_tx) = ProcessedTransaction::from_vec(&encoded[..len]) {
+                // Whatever decodes can be encoded again
+                processed_tx.to_vec().unwrap();
+            }
+        }
+    }
+}
@@ -1,9 +1,12 @@
 use anyhow::{anyhow, Result};
+use arch_program::account::AccountMeta;
+use arch_program::instruction::Instruction;
 use arch_program::message::Message;
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 use sha256::digest;
 
+use crate::processed_transaction::Reader;
 use crate::signature::Signature;
 
 pub const RUNTIME_TX_SIZE_LIMIT: usize = 1024;
@@ -52,6 +55,52 @@ impl RuntimeTransaction {
         })
     }
 
+    /// Decodes [`RuntimeTransaction::serialize`] like
+    /// [`RuntimeTransaction::from_slice`], but fails instead of panicking on
+    /// truncated data and rejects trailing bytes.
+    pub fn try_from_slice(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+
+        let version = u32::from_le_bytes(reader.read(4)?.try_into()?);
+        let signatures = (0..reader.read_u8()?)
+            .map(|_| Ok(Signature(reader.read(64)?.to_vec())))
+            .collect::<Result<Vec<_>>>()?;
+        let signers = (0..reader.read_u8()?)
+            .map(|_| reader.read_pubkey())
+            .collect::<Result<Vec<_>>>()?;
+        let instructions = (0..reader.read_u8()?)
+            .map(|_| {
+                let program_id = reader.read_pubkey()?;
+                let accounts = (0..reader.read_u8()?)
+                    .map(|_| {
+                        Ok(AccountMeta {
+                            pubkey: reader.read_pubkey()?,
+                            is_signer: reader.read_u8()? != 0,
+                            is_writable: reader.read_u8()? != 0,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Instruction {
+                    program_id,
+                    accounts,
+                    data: reader.read_bytes()?.to_vec(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if reader.offset != data.len() {
+            return Err(anyhow!("trailing bytes after runtime transaction"));
+        }
+        Ok(Self {
+            version,
+            signatures,
+            message: Message {
+                signers,
+                instructions,
+            },
+        })
+    }
+
     pub fn hash(&self) -> String {
         digest(digest(self.serialize()))
     }
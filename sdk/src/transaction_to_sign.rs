@@ -1,5 +1,8 @@
+use anyhow::{anyhow, Result};
 use arch_program::{input_to_sign::InputToSign, pubkey::Pubkey};
 
+use crate::processed_transaction::Reader;
+
 #[derive(Debug, Clone, Default)]
 pub struct TransactionToSign {
     pub tx_bytes: Vec<u8>,
@@ -8,10 +11,10 @@ pub struct TransactionToSign {
 
 impl TransactionToSign {
     pub fn from_slice(data: &[u8]) -> Self {
-        let mut size = data[0] as usize + 1;
+        let mut size = u16::from_le_bytes([data[0], data[1]]) as usize + 2;
         let inputs_to_sign_length: usize = data[size] as usize;
         let mut program_return = TransactionToSign {
-            tx_bytes: data[1..size].to_vec(),
+            tx_bytes: data[2..size].to_vec(),
             inputs_to_sign: vec![],
         };
 
@@ -32,4 +35,67 @@ impl TransactionToSign {
 
         program_return
     }
+
+    /// Like [`TransactionToSign::from_slice`], but fails on truncated data
+    /// and trailing bytes instead of panicking.
+    pub fn try_from_slice(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+
+        let tx_len = u16::from_le_bytes(reader.read(2)?.try_into()?) as usize;
+        let tx_bytes = reader.read(tx_len)?.to_vec();
+        let inputs_to_sign = (0..reader.read_u8()?)
+            .map(|_| {
+                Ok(InputToSign {
+                    index: u32::from_le_bytes(reader.read(4)?.try_into()?),
+                    signer: reader.read_pubkey()?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if reader.offset != data.len() {
+            return Err(anyhow!("trailing bytes after transaction to sign"));
+        }
+        Ok(Self {
+            tx_bytes,
+            inputs_to_sign,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arch_program::input_to_sign::InputToSign;
+    use arch_program::pubkey::Pubkey;
+    use arch_program::transaction_to_sign::TransactionToSign as ProgramTransactionToSign;
+
+    use super::TransactionToSign;
+
+    #[test]
+    fn test_try_from_slice() {
+        let inputs_to_sign = [InputToSign {
+            index: 1,
+            signer: Pubkey::from_slice(&[4; 32]),
+        }];
+        let serialized = ProgramTransactionToSign {
+            tx_bytes: &[7; 300],
+            inputs_to_sign: &inputs_to_sign,
+        }
+        .serialise();
+
+        let decoded = TransactionToSign::try_from_slice(&serialized).unwrap();
+        assert_eq!(serialized[..2], 300u16.to_le_bytes());
+        assert_eq!(decoded.tx_bytes, vec![7; 300]);
+        assert_eq!(decoded.inputs_to_sign, inputs_to_sign);
+        assert_eq!(
+            TransactionToSign::from_slice(&serialized).inputs_to_sign,
+            inputs_to_sign
+        );
+
+        for len in 0..serialized.len() {
+            assert!(TransactionToSign::try_from_slice(&serialized[..len]).is_err());
+        }
+        let mut trailing = serialized;
+        trailing.push(0);
+        assert!(TransactionToSign::try_from_slice(&trailing).is_err());
+    }
 }